@@ -0,0 +1,65 @@
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use acquiesce::json::PartialJson;
+
+const ITERATIONS: u32 = 200;
+
+fn tool_call(argument_len: usize) -> String {
+    let code = "fn main() { println!(\"hello\"); }\n".repeat(argument_len / 34 + 1);
+
+    serde_json::json!({
+        "name": "write_file",
+        "arguments": { "path": "src/main.rs", "contents": code },
+    })
+    .to_string()
+}
+
+fn bench(label: &str, input: &str, f: impl Fn(&str)) {
+    let mut total = Duration::ZERO;
+
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f(black_box(input));
+        total += start.elapsed();
+    }
+
+    let per_iter = total / ITERATIONS;
+    let throughput = input.len() as f64 / per_iter.as_secs_f64() / (1 << 20) as f64;
+
+    println!("{label:<24} {per_iter:>12?}/iter {throughput:>10.1} MiB/s");
+}
+
+fn main() {
+    for argument_len in [1 << 10, 64 << 10, 1 << 20] {
+        let input = tool_call(argument_len);
+        println!("--- {} byte tool call", input.len());
+
+        bench("consume_char", &input, |input| {
+            let mut state = PartialJson::default();
+            for c in input.chars() {
+                black_box(state.consume_char(c));
+            }
+        });
+
+        bench("consume_str (64B chunks)", &input, |input| {
+            let mut state = PartialJson::default();
+            let mut rest = input;
+            while !rest.is_empty() {
+                let mut end = rest.len().min(64);
+                while !rest.is_char_boundary(end) {
+                    end += 1;
+                }
+                black_box(state.consume_str(&rest[..end]));
+                rest = &rest[end..];
+            }
+        });
+
+        bench("consume_str (whole)", &input, |input| {
+            let mut state = PartialJson::default();
+            black_box(state.consume_str(input));
+        });
+    }
+}
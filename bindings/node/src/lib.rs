@@ -1,20 +1,357 @@
 use std::sync::{Arc, Mutex};
 
 use acquiesce::{
-    AcquiesceRepr,
+    AcquiesceRepr, HarmonyChannel, OrderedLexemes, ThinkingBudget, ThinkingLimit, ThinkingMode,
+    ThinkingToggle, ToolCall,
     parse::{ParseResult, Parser},
-    render::RenderResult,
+    render::{ReasoningEffort, RenderResult, schema::ChatResponseFormat},
 };
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 
 #[napi]
-pub struct Acquiesce(acquiesce::Acquiesce);
+pub struct Acquiesce(acquiesce::Acquiesce, AcquiesceSnapshot);
+
+/// Plain-object form of an `Acquiesce`, which (unlike the class instance)
+/// survives `structuredClone` and `postMessage` to worker threads.
+#[napi(object)]
+#[derive(Clone)]
+pub struct AcquiesceSnapshot {
+    pub source: String,
+    pub chat_template: String,
+    pub bos_token: Option<String>,
+    pub eos_token: Option<String>,
+}
+
+/// Names of the built-in configs that can be passed as `source`.
+#[napi]
+pub fn list_presets() -> Vec<String> {
+    acquiesce::configs::list_presets()
+        .map(str::to_string)
+        .collect()
+}
+
+/// A built-in config rendered as an `acquiesce.json` document.
+#[napi]
+pub fn get_preset(name: String) -> Result<String> {
+    acquiesce::configs::get_preset(&name)
+        .map(|preset| preset.to_string())
+        .ok_or_else(|| Error::new(Status::InvalidArg, format!("Unknown preset: {name}")))
+}
+
+/// Builds a config step by step, like the Rust crate's `AcquiesceBuilder`.
+/// Lexemes are passed as JSON: `{"token": "<think>"}` for a special token,
+/// `"\n"` for text, or an array of them. Methods update the builder in place.
+#[napi]
+#[derive(Default)]
+pub struct AcquiesceBuilder(acquiesce::builder::AcquiesceBuilder);
+
+impl AcquiesceBuilder {
+    fn update(
+        &mut self,
+        f: impl FnOnce(acquiesce::builder::AcquiesceBuilder) -> acquiesce::builder::AcquiesceBuilder,
+    ) {
+        let AcquiesceBuilder(builder) = self;
+        *builder = f(std::mem::take(builder));
+    }
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(name: &str, json: &str) -> Result<T> {
+    serde_json::from_str(json)
+        .map_err(|e| Error::new(Status::InvalidArg, format!("Invalid {name} JSON: {e}")))
+}
+
+#[napi]
+impl AcquiesceBuilder {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[napi]
+    pub fn thinking(&mut self, prefix_json: String, suffix_json: String) -> Result<()> {
+        let prefix = from_json::<OrderedLexemes>("prefix", &prefix_json)?;
+        let suffix = from_json::<OrderedLexemes>("suffix", &suffix_json)?;
+
+        self.update(|builder| builder.thinking(prefix, suffix));
+        Ok(())
+    }
+
+    /// Only applies after `thinking`.
+    #[napi]
+    pub fn thinking_budget(&mut self, budget_json: String) -> Result<()> {
+        let budget = from_json::<ThinkingBudget>("budget", &budget_json)?;
+
+        self.update(|builder| builder.thinking_budget(budget));
+        Ok(())
+    }
+
+    /// Only applies after `thinking`.
+    #[napi]
+    pub fn thinking_mode(&mut self, mode: String) -> Result<()> {
+        let mode = serde_json::from_value::<ThinkingMode>(mode.clone().into()).map_err(|_| {
+            Error::new(Status::InvalidArg, format!("Invalid thinking mode: {mode}"))
+        })?;
+
+        self.update(|builder| builder.thinking_mode(mode));
+        Ok(())
+    }
+
+    /// Only applies after `thinking`.
+    #[napi]
+    pub fn interleaved_thinking(&mut self) {
+        self.update(|builder| builder.interleaved_thinking());
+    }
+
+    /// Only applies after `thinking`.
+    #[napi]
+    pub fn thinking_toggle(&mut self, toggle_json: String) -> Result<()> {
+        let toggle = from_json::<ThinkingToggle>("toggle", &toggle_json)?;
+
+        self.update(|builder| builder.thinking_toggle(toggle));
+        Ok(())
+    }
+
+    #[napi]
+    pub fn tool_section(&mut self, prefix_json: String) -> Result<()> {
+        let prefix = from_json::<OrderedLexemes>("prefix", &prefix_json)?;
+
+        self.update(|builder| builder.tool_section(prefix));
+        Ok(())
+    }
+
+    /// Only applies after `toolSection`.
+    #[napi]
+    pub fn section_suffix(&mut self, suffix_json: String) -> Result<()> {
+        let suffix = from_json::<OrderedLexemes>("suffix", &suffix_json)?;
+
+        self.update(|builder| builder.section_suffix(suffix));
+        Ok(())
+    }
+
+    /// Only applies after `toolSection`.
+    #[napi]
+    pub fn section_per_call(&mut self) {
+        self.update(|builder| builder.section_per_call());
+    }
+
+    #[napi]
+    pub fn tool_call(&mut self, tool_call_json: String) -> Result<()> {
+        let tool_call = from_json::<ToolCall>("tool_call", &tool_call_json)?;
+
+        self.update(|builder| builder.tool_call(tool_call));
+        Ok(())
+    }
+
+    #[napi]
+    pub fn json_object(&mut self, name_key: String, argument_key: String) {
+        self.update(|builder| builder.json_object(&name_key, &argument_key));
+    }
+
+    #[napi]
+    pub fn json_array(&mut self, name_key: String, argument_key: String) {
+        self.update(|builder| builder.json_array(&name_key, &argument_key));
+    }
+
+    #[napi]
+    pub fn named_parameters(
+        &mut self,
+        prefix_json: Option<String>,
+        delimiter_json: Option<String>,
+        suffix_json: Option<String>,
+    ) -> Result<()> {
+        let lexemes = |name, json: Option<String>| {
+            json.map(|json| from_json::<OrderedLexemes>(name, &json))
+                .transpose()
+        };
+        let prefix = lexemes("prefix", prefix_json)?;
+        let delimiter = lexemes("delimiter", delimiter_json)?;
+        let suffix = lexemes("suffix", suffix_json)?;
+
+        self.update(|builder| builder.named_parameters(prefix, delimiter, suffix));
+        Ok(())
+    }
+
+    #[napi]
+    pub fn stop(&mut self, stop: String) {
+        self.update(|builder| builder.stop(stop));
+    }
+
+    /// Renames messages with role `from` to `to` for the template.
+    #[napi]
+    pub fn role(&mut self, from: String, to: String) {
+        self.update(|builder| builder.role(from, to));
+    }
+
+    /// Passes messages with role `role` to the template as is.
+    #[napi]
+    pub fn custom_role(&mut self, role: String) {
+        self.update(|builder| builder.custom_role(role));
+    }
+
+    /// The config as an `acquiesce.json` document, to pass as `source`.
+    #[napi]
+    pub fn build(&self) -> String {
+        let AcquiesceBuilder(builder) = self;
+        builder.clone().build().to_string()
+    }
+}
 
 #[napi]
 pub enum GrammarSyntax {
     Lark,
     GBNF,
+    Ebnf,
+    LLGuidance,
+    StructuralTags,
+}
+
+/// Per-request render settings. Omitted fields keep the core defaults.
+#[napi(object)]
+#[derive(Default)]
+pub struct RenderOptions {
+    pub parallel_tool_calls: Option<bool>,
+    pub max_tool_calls: Option<u32>,
+    pub mixed_content_tool_calls: Option<bool>,
+    pub grammar_syntax: Option<GrammarSyntax>,
+    pub reasoning_effort: Option<String>,
+    pub thinking: Option<String>,
+    pub max_thinking_tokens: Option<u32>,
+    pub max_thinking_chars: Option<u32>,
+    pub response_format_json: Option<String>,
+    pub add_generation_prompt: Option<bool>,
+    pub continue_final_message: Option<bool>,
+    pub prompt_prefix: Option<bool>,
+    pub reasoning_only: Option<bool>,
+    pub validate_grammar: Option<bool>,
+    pub kwargs_json: Option<String>,
+    pub documents_json: Option<String>,
+    pub knowledge_cutoff: Option<String>,
+    pub harmony_channels: Option<Vec<String>>,
+}
+
+impl RenderOptions {
+    fn resolve(self) -> Result<acquiesce::render::RenderOptions> {
+        let mut options = acquiesce::render::RenderOptions::default();
+
+        if let Some(parallel_tool_calls) = self.parallel_tool_calls {
+            options.parallel_tool_calls = parallel_tool_calls;
+        }
+
+        options.max_tool_calls = self.max_tool_calls.map(|max| max as usize);
+
+        if let Some(mixed_content_tool_calls) = self.mixed_content_tool_calls {
+            options.mixed_content_tool_calls = mixed_content_tool_calls;
+        }
+
+        if let Some(grammar_syntax) = self.grammar_syntax {
+            options.grammar_syntax = match grammar_syntax {
+                GrammarSyntax::Lark => acquiesce::render::GrammarSyntax::Lark,
+                GrammarSyntax::GBNF => acquiesce::render::GrammarSyntax::GBNF,
+                GrammarSyntax::Ebnf => acquiesce::render::GrammarSyntax::Ebnf,
+                GrammarSyntax::LLGuidance => acquiesce::render::GrammarSyntax::LLGuidance,
+                GrammarSyntax::StructuralTags => acquiesce::render::GrammarSyntax::StructuralTags,
+            };
+        }
+
+        if let Some(reasoning_effort) = self.reasoning_effort {
+            options.reasoning_effort = Some(
+                serde_json::from_value::<ReasoningEffort>(reasoning_effort.clone().into())
+                    .map_err(|_| {
+                        Error::new(
+                            Status::InvalidArg,
+                            format!("Invalid reasoning effort: {reasoning_effort}"),
+                        )
+                    })?,
+            );
+        }
+
+        if let Some(thinking) = self.thinking {
+            options.thinking = Some(
+                serde_json::from_value::<ThinkingMode>(thinking.clone().into()).map_err(|_| {
+                    Error::new(
+                        Status::InvalidArg,
+                        format!("Invalid thinking mode: {thinking}"),
+                    )
+                })?,
+            );
+        }
+
+        options.thinking_limit = match (self.max_thinking_tokens, self.max_thinking_chars) {
+            (Some(_), Some(_)) => {
+                return Err(Error::new(
+                    Status::InvalidArg,
+                    "Only one of maxThinkingTokens and maxThinkingChars can be set",
+                ));
+            }
+            (Some(max_tokens), None) => Some(ThinkingLimit::Tokens(max_tokens as usize)),
+            (None, Some(max_chars)) => Some(ThinkingLimit::Characters(max_chars as usize)),
+            (None, None) => None,
+        };
+
+        if let Some(response_format_json) = self.response_format_json {
+            options.response_format =
+                serde_json::from_str::<ChatResponseFormat>(&response_format_json).map_err(|e| {
+                    Error::new(
+                        Status::InvalidArg,
+                        format!("Invalid response_format JSON: {e}"),
+                    )
+                })?;
+        }
+
+        if let Some(add_generation_prompt) = self.add_generation_prompt {
+            options.add_generation_prompt = add_generation_prompt;
+        }
+
+        if let Some(continue_final_message) = self.continue_final_message {
+            options.continue_final_message = continue_final_message;
+        }
+
+        if let Some(prompt_prefix) = self.prompt_prefix {
+            options.prompt_prefix = prompt_prefix;
+        }
+
+        if let Some(reasoning_only) = self.reasoning_only {
+            options.reasoning_only = reasoning_only;
+        }
+
+        if let Some(validate_grammar) = self.validate_grammar {
+            options.validate_grammar = validate_grammar;
+        }
+
+        if let Some(kwargs_json) = self.kwargs_json {
+            options.kwargs = serde_json::from_str(&kwargs_json)
+                .map_err(|e| Error::new(Status::InvalidArg, format!("Invalid kwargs JSON: {e}")))?;
+        }
+
+        if let Some(documents_json) = self.documents_json {
+            options.documents = serde_json::from_str(&documents_json).map_err(|e| {
+                Error::new(Status::InvalidArg, format!("Invalid documents JSON: {e}"))
+            })?;
+        }
+
+        options.knowledge_cutoff = self.knowledge_cutoff;
+
+        if let Some(harmony_channels) = self.harmony_channels {
+            options.harmony_channels = Some(
+                harmony_channels
+                    .into_iter()
+                    .map(|channel| {
+                        serde_json::from_value::<HarmonyChannel>(channel.clone().into()).map_err(
+                            |_| {
+                                Error::new(
+                                    Status::InvalidArg,
+                                    format!("Invalid Harmony channel: {channel}"),
+                                )
+                            },
+                        )
+                    })
+                    .collect::<Result<_>>()?,
+            );
+        }
+
+        Ok(options)
+    }
 }
 
 #[napi]
@@ -26,36 +363,81 @@ impl Acquiesce {
         bos_token: Option<String>,
         eos_token: Option<String>,
     ) -> Result<Self> {
-        let repr = serde_json::from_str::<AcquiesceRepr>(&source)
-            .or(AcquiesceRepr::infer_default(source.as_str()))
+        Self::from_snapshot(AcquiesceSnapshot {
+            source,
+            chat_template,
+            bos_token,
+            eos_token,
+        })
+    }
+
+    #[napi(factory)]
+    pub fn from_snapshot(snapshot: AcquiesceSnapshot) -> Result<Self> {
+        let repr = snapshot
+            .source
+            .parse::<AcquiesceRepr>()
+            .or(serde_json::from_str::<AcquiesceRepr>(&snapshot.source))
+            .or(AcquiesceRepr::infer_default(snapshot.source.as_str()))
+            .or(AcquiesceRepr::infer_from_template(&snapshot.chat_template))
             .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
 
         Ok(Self(
-            repr.resolve_from_options(chat_template, bos_token, eos_token, false, true)
-                .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?,
+            repr.resolve_from_options(
+                snapshot.chat_template.clone(),
+                snapshot.bos_token.clone(),
+                snapshot.eos_token.clone(),
+                false,
+            )
+            .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?,
+            snapshot,
         ))
     }
 
+    #[napi]
+    pub fn snapshot(&self) -> AcquiesceSnapshot {
+        let Acquiesce(_, snapshot) = self;
+        snapshot.clone()
+    }
+
+    #[napi(js_name = "toJSON")]
+    pub fn to_json(&self) -> AcquiesceSnapshot {
+        self.snapshot()
+    }
+
     #[napi(ts_return_type = "Promise<RenderTaskResult>")]
-    pub fn render<'a>(
-        &'a self,
+    pub fn render(
+        &self,
         messages_json: String,
         tools_json: String,
         tool_choice_json: String,
-        parallel_tool_calls: bool,
-        mixed_content_tool_calls: bool,
-        grammar_syntax: GrammarSyntax,
-    ) -> AsyncTask<RenderTask<'a>> {
-        let Acquiesce(inner) = self;
-        AsyncTask::new(RenderTask {
-            inner,
+        options: Option<RenderOptions>,
+    ) -> Result<AsyncTask<RenderTask>> {
+        Ok(AsyncTask::new(self.render_task(
             messages_json,
             tools_json,
             tool_choice_json,
-            parallel_tool_calls,
-            mixed_content_tool_calls,
-            grammar_syntax,
-        })
+            options,
+        )?))
+    }
+
+    /// Like `render`, but returns the prompt and grammar as UTF-8 `Buffer`s
+    /// that take over the rendered allocations instead of copying them into
+    /// JS strings. Prefer this for long prompts that are written straight
+    /// back out to a socket.
+    #[napi(ts_return_type = "Promise<RenderBufferTaskResult>")]
+    pub fn render_buffer(
+        &self,
+        messages_json: String,
+        tools_json: String,
+        tool_choice_json: String,
+        options: Option<RenderOptions>,
+    ) -> Result<AsyncTask<RenderBufferTask>> {
+        Ok(AsyncTask::new(RenderBufferTask(self.render_task(
+            messages_json,
+            tools_json,
+            tool_choice_json,
+            options,
+        )?)))
     }
 
     #[napi(ts_return_type = "Promise<ParseTaskResult>")]
@@ -66,14 +448,57 @@ impl Acquiesce {
     }
 }
 
-pub struct RenderTask<'a> {
-    inner: &'a acquiesce::Acquiesce,
+impl Acquiesce {
+    fn render_task(
+        &self,
+        messages_json: String,
+        tools_json: String,
+        tool_choice_json: String,
+        options: Option<RenderOptions>,
+    ) -> Result<RenderTask> {
+        let Acquiesce(inner, _) = self;
+        Ok(RenderTask {
+            inner: inner.clone(),
+            messages_json,
+            tools_json,
+            tool_choice_json,
+            options: options.unwrap_or_default().resolve()?,
+        })
+    }
+}
+
+fn external_parser(
+    env: &Env,
+    parser: Option<Parser>,
+) -> Result<Option<ExternalRef<Arc<Mutex<Parser>>>>> {
+    parser
+        .map(|p| ExternalRef::new(env, Arc::new(Mutex::new(p))))
+        .transpose()
+}
+
+/// The part of the prompt shared with the conversation before its last user
+/// message. `key` is a hex-encoded 64-bit hash.
+#[napi(object)]
+pub struct PromptPrefix {
+    pub key: String,
+    pub len: u32,
+}
+
+impl From<acquiesce::render::PromptPrefix> for PromptPrefix {
+    fn from(acquiesce::render::PromptPrefix { key, len }: acquiesce::render::PromptPrefix) -> Self {
+        Self {
+            key: format!("{key:016x}"),
+            len: len as u32,
+        }
+    }
+}
+
+pub struct RenderTask {
+    inner: acquiesce::Acquiesce,
     messages_json: String,
     tools_json: String,
     tool_choice_json: String,
-    parallel_tool_calls: bool,
-    mixed_content_tool_calls: bool,
-    grammar_syntax: GrammarSyntax,
+    options: acquiesce::render::RenderOptions,
 }
 
 #[napi(object)]
@@ -81,10 +506,13 @@ pub struct RenderTaskResult {
     pub prompt: String,
     pub grammar: Option<String>,
     pub parser: Option<ExternalRef<Arc<Mutex<Parser>>>>,
+    pub warnings: Vec<String>,
+    pub stop: Vec<String>,
+    pub prefix: Option<PromptPrefix>,
 }
 
 #[napi]
-impl<'a> Task for RenderTask<'a> {
+impl Task for RenderTask {
     type Output = RenderResult;
     type JsValue = RenderTaskResult;
 
@@ -93,6 +521,9 @@ impl<'a> Task for RenderTask<'a> {
             prompt: self.messages_json.clone(),
             grammar: None,
             parser: None,
+            warnings: Vec::new(),
+            stop: Vec::new(),
+            prefix: None,
         })
     }
 
@@ -103,14 +534,65 @@ impl<'a> Task for RenderTask<'a> {
             prompt,
             grammar,
             parser,
+            warnings,
+            stop,
+            prefix,
         }: Self::Output,
     ) -> Result<Self::JsValue> {
         Ok(RenderTaskResult {
             prompt,
             grammar,
-            parser: parser
-                .map(|p| ExternalRef::new(&env, Arc::new(Mutex::new(p))))
-                .transpose()?,
+            parser: external_parser(&env, parser)?,
+            warnings: warnings.iter().map(ToString::to_string).collect(),
+            stop,
+            prefix: prefix.map(PromptPrefix::from),
+        })
+    }
+}
+
+pub struct RenderBufferTask(RenderTask);
+
+#[napi(object)]
+pub struct RenderBufferTaskResult {
+    pub prompt: Buffer,
+    pub grammar: Option<Buffer>,
+    pub parser: Option<ExternalRef<Arc<Mutex<Parser>>>>,
+    pub warnings: Vec<String>,
+    pub stop: Vec<String>,
+    pub prefix: Option<PromptPrefix>,
+}
+
+#[napi]
+impl Task for RenderBufferTask {
+    type Output = RenderResult;
+    type JsValue = RenderBufferTaskResult;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let RenderBufferTask(task) = self;
+        task.compute()
+    }
+
+    fn resolve(
+        &mut self,
+        env: Env,
+        RenderResult {
+            prompt,
+            grammar,
+            parser,
+            warnings,
+            stop,
+            prefix,
+        }: Self::Output,
+    ) -> Result<Self::JsValue> {
+        // `Buffer::from(Vec<u8>)` hands the allocation to V8 as external
+        // memory, so the rendered output is never copied.
+        Ok(RenderBufferTaskResult {
+            prompt: prompt.into_bytes().into(),
+            grammar: grammar.map(|grammar| grammar.into_bytes().into()),
+            parser: external_parser(&env, parser)?,
+            warnings: warnings.iter().map(ToString::to_string).collect(),
+            stop,
+            prefix: prefix.map(PromptPrefix::from),
         })
     }
 }
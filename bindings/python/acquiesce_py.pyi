@@ -7,12 +7,115 @@ import typing
 @typing.final
 class Acquiesce:
     @classmethod
-    def from_repo(cls, hf_cache_path:builtins.str, model_id:builtins.str, revision:typing.Optional[builtins.str]) -> Acquiesce: ...
-    def render(self, messages_json:builtins.str, tools_json:builtins.str, tool_choice_json:builtins.str, parallel_tool_calls:builtins.bool) -> RenderResult: ...
+    def new(cls, source:builtins.str, chat_template:builtins.str, bos_token:typing.Optional[builtins.str], eos_token:typing.Optional[builtins.str], multimodal:builtins.bool) -> Acquiesce: ...
+    def __reduce__(self) -> tuple[typing.Any, tuple[builtins.str, builtins.str, typing.Optional[builtins.str], typing.Optional[builtins.str], builtins.bool, builtins.bool]]: ...
+    def render(self, messages_json:builtins.str, tools_json:builtins.str, tool_choice_json:builtins.str, options:typing.Optional[RenderOptions]=None) -> RenderResult: ...
+    def render_batch(self, requests:typing.Sequence[tuple[builtins.str, builtins.str, builtins.str, typing.Optional[RenderOptions]]], parallel:builtins.bool=False) -> builtins.list[RenderResult]:
+        r"""
+        Renders many requests with the GIL released once for the whole batch.
+        Each request takes the same arguments as `render`. With `parallel`, the
+        requests are spread across a thread pool; results keep request order.
+        """
+
+@typing.final
+class AcquiesceBuilder:
+    r"""
+    Builds a config step by step, like the Rust crate's `AcquiesceBuilder`.
+    Lexemes are passed as JSON: `{"token": "<think>"}` for a special token,
+    `"\n"` for text, or a list of them. Every method returns the builder, so
+    calls chain.
+    """
+    def __new__(cls) -> AcquiesceBuilder: ...
+    def thinking(self, prefix_json:builtins.str, suffix_json:builtins.str) -> AcquiesceBuilder: ...
+    def thinking_budget(self, budget_json:builtins.str) -> AcquiesceBuilder:
+        r"""
+        Only applies after `thinking`.
+        """
+    def thinking_mode(self, mode:builtins.str) -> AcquiesceBuilder:
+        r"""
+        Only applies after `thinking`.
+        """
+    def interleaved_thinking(self) -> AcquiesceBuilder:
+        r"""
+        Only applies after `thinking`.
+        """
+    def thinking_toggle(self, toggle_json:builtins.str) -> AcquiesceBuilder:
+        r"""
+        Only applies after `thinking`.
+        """
+    def tool_section(self, prefix_json:builtins.str) -> AcquiesceBuilder: ...
+    def section_suffix(self, suffix_json:builtins.str) -> AcquiesceBuilder:
+        r"""
+        Only applies after `tool_section`.
+        """
+    def section_per_call(self) -> AcquiesceBuilder:
+        r"""
+        Only applies after `tool_section`.
+        """
+    def tool_call(self, tool_call_json:builtins.str) -> AcquiesceBuilder: ...
+    def json_object(self, name_key:builtins.str, argument_key:builtins.str) -> AcquiesceBuilder: ...
+    def json_array(self, name_key:builtins.str, argument_key:builtins.str) -> AcquiesceBuilder: ...
+    def named_parameters(self, prefix_json:typing.Optional[builtins.str]=None, delimiter_json:typing.Optional[builtins.str]=None, suffix_json:typing.Optional[builtins.str]=None) -> AcquiesceBuilder: ...
+    def stop(self, stop:builtins.str) -> AcquiesceBuilder: ...
+    def role(self, from_role:builtins.str, to:builtins.str) -> AcquiesceBuilder:
+        r"""
+        Renames messages with role `from_role` to `to` for the template.
+        """
+    def custom_role(self, role:builtins.str) -> AcquiesceBuilder:
+        r"""
+        Passes messages with role `role` to the template as is.
+        """
+    def build(self) -> builtins.str:
+        r"""
+        The config as an `acquiesce.json` document, to pass as `source`.
+        """
+
+@typing.final
+class ParseResult:
+    r"""
+    One result of `Parser.parse`.
+    """
+    @property
+    def kind(self) -> builtins.str:
+        r"""
+        `content`, `reasoning`, `tool_call`, `tool_call_id`, `tool_call_name`,
+        `rejected` or `complete`.
+        """
+    @property
+    def index(self) -> typing.Optional[builtins.int]:
+        r"""
+        The thinking block or call the text belongs to.
+        """
+    @property
+    def text(self) -> typing.Optional[builtins.str]:
+        r"""
+        The streamed text, or the text that was rejected.
+        """
+    @property
+    def expected(self) -> typing.Optional[builtins.str]:
+        r"""
+        What the parser expected instead of rejected text.
+        """
 
 @typing.final
 class Parser:
-    def parse(self, _text:builtins.str) -> builtins.list[builtins.str]: ...
+    r"""
+    Parsers wrap opaque state machines, so a snapshot is the config they were
+    built from plus the text fed so far; unpickling replays the text. Past
+    `MAX_REPLAY_LEN` bytes the text is dropped and the parser can't be pickled.
+    """
+    def parse(self, text:builtins.str) -> builtins.list[ParseResult]: ...
+    @staticmethod
+    def _restore(args:tuple[builtins.str, builtins.str, typing.Optional[builtins.str], typing.Optional[builtins.str], builtins.bool, builtins.bool], history:builtins.str) -> Parser: ...
+    def __reduce__(self) -> tuple[typing.Any, tuple[tuple[builtins.str, builtins.str, typing.Optional[builtins.str], typing.Optional[builtins.str], builtins.bool, builtins.bool], builtins.str]]: ...
+
+@typing.final
+class RenderOptions:
+    r"""
+    Per-request render settings. Arguments left as `None` keep the core
+    defaults.
+    """
+    def __new__(cls, parallel_tool_calls:typing.Optional[builtins.bool]=None, max_tool_calls:typing.Optional[builtins.int]=None, mixed_content_tool_calls:typing.Optional[builtins.bool]=None, grammar_syntax:typing.Optional[builtins.str]=None, reasoning_effort:typing.Optional[builtins.str]=None, thinking:typing.Optional[builtins.str]=None, max_thinking_tokens:typing.Optional[builtins.int]=None, max_thinking_chars:typing.Optional[builtins.int]=None, response_format_json:typing.Optional[builtins.str]=None, add_generation_prompt:typing.Optional[builtins.bool]=None, continue_final_message:typing.Optional[builtins.bool]=None, prompt_prefix:typing.Optional[builtins.bool]=None, reasoning_only:typing.Optional[builtins.bool]=None, validate_grammar:typing.Optional[builtins.bool]=None, kwargs_json:typing.Optional[builtins.str]=None, documents_json:typing.Optional[builtins.str]=None, knowledge_cutoff:typing.Optional[builtins.str]=None, harmony_channels:typing.Optional[typing.Sequence[builtins.str]]=None) -> RenderOptions: ...
 
 @typing.final
 class RenderResult:
@@ -22,4 +125,25 @@ class RenderResult:
     def grammar(self) -> typing.Optional[builtins.str]: ...
     @property
     def parser(self) -> typing.Optional[Parser]: ...
+    @property
+    def warnings(self) -> builtins.list[builtins.str]: ...
+    @property
+    def stop(self) -> builtins.list[builtins.str]: ...
+    @property
+    def prefix_key(self) -> typing.Optional[builtins.int]:
+        r"""
+        Hash of the prompt prefix shared with the conversation before its last
+        user message, if `prompt_prefix` was requested.
+        """
+    @property
+    def prefix_len(self) -> typing.Optional[builtins.int]: ...
+
+def get_preset(name:builtins.str) -> builtins.str:
+    r"""
+    A built-in config rendered as an `acquiesce.json` document.
+    """
 
+def list_presets() -> builtins.list[builtins.str]:
+    r"""
+    Names of the built-in configs that can be passed as `source`.
+    """
@@ -1,8 +1,11 @@
+use std::sync::Arc;
+
 use acquiesce::{
-    AcquiesceRepr,
+    AcquiesceRepr, HarmonyChannel, OrderedLexemes, ThinkingBudget, ThinkingLimit, ThinkingMode,
+    ThinkingToggle, ToolCall,
     render::{
-        GrammarSyntax,
-        schema::{ChatMessages, ChatTool, ChatToolChoice},
+        GrammarSyntax, ReasoningEffort,
+        schema::{ChatMessages, ChatResponseFormat, ChatTool, ChatToolChoice},
     },
 };
 use pyo3::exceptions::{PyIOError, PyRuntimeError, PyValueError};
@@ -10,19 +13,144 @@ use pyo3::prelude::*;
 use pyo3::types::PyType;
 use pyo3_stub_gen::define_stub_info_gatherer;
 use pyo3_stub_gen::derive::*;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 
 pyo3::create_exception!(acquiesce_py, InitError, PyValueError);
 pyo3::create_exception!(acquiesce_py, RenderError, PyRuntimeError);
 pyo3::create_exception!(acquiesce_py, ParseError, PyIOError);
 
+/// The arguments an `Acquiesce` was constructed from, kept around so the
+/// resolved config can be rebuilt on the other side of a pickle.
+#[derive(Clone)]
+struct AcquiesceArgs {
+    source: String,
+    chat_template: String,
+    bos_token: Option<String>,
+    eos_token: Option<String>,
+    multimodal: bool,
+}
+
+type AcquiesceArgsTuple = (String, String, Option<String>, Option<String>, bool);
+
+/// `(messages_json, tools_json, tool_choice_json, options)`, as accepted by
+/// `render`.
+type RenderRequestTuple = (String, String, String, Option<RenderOptions>);
+
+impl AcquiesceArgs {
+    fn resolve(&self) -> PyResult<acquiesce::Acquiesce> {
+        let repr = self
+            .source
+            .parse::<AcquiesceRepr>()
+            .or(serde_json::from_str::<AcquiesceRepr>(&self.source))
+            .or(AcquiesceRepr::infer_default(self.source.as_str()))
+            .or(AcquiesceRepr::infer_from_template(&self.chat_template))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        repr.resolve_from_options(
+            self.chat_template.clone(),
+            self.bos_token.clone(),
+            self.eos_token.clone(),
+            self.multimodal,
+        )
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn into_tuple(self) -> AcquiesceArgsTuple {
+        (
+            self.source,
+            self.chat_template,
+            self.bos_token,
+            self.eos_token,
+            self.multimodal,
+        )
+    }
+
+    fn from_tuple(
+        (source, chat_template, bos_token, eos_token, multimodal): AcquiesceArgsTuple,
+    ) -> Self {
+        Self {
+            source,
+            chat_template,
+            bos_token,
+            eos_token,
+            multimodal,
+        }
+    }
+}
+
 #[gen_stub_pyclass]
-#[pyclass]
-pub struct Acquiesce(acquiesce::Acquiesce);
+#[pyclass(module = "acquiesce_py")]
+pub struct Acquiesce(acquiesce::Acquiesce, Arc<AcquiesceArgs>);
 
+/// Parsers wrap opaque state machines, so a snapshot is the config they were
+/// built from plus the text fed so far; unpickling replays the text. Past
+/// `MAX_REPLAY_LEN` bytes the text is dropped and the parser can't be pickled.
 #[gen_stub_pyclass]
-#[pyclass]
+#[pyclass(module = "acquiesce_py")]
+#[derive(Clone)]
+pub struct Parser(acquiesce::parse::Parser, Arc<AcquiesceArgs>, Option<String>);
+
+const MAX_REPLAY_LEN: usize = 1 << 20;
+
+/// One result of `Parser.parse`.
+#[gen_stub_pyclass]
+#[pyclass(module = "acquiesce_py")]
 #[derive(Clone)]
-pub struct Parser(acquiesce::parse::Parser);
+pub struct ParseResult {
+    /// `content`, `reasoning`, `tool_call`, `tool_call_id`, `tool_call_name`,
+    /// `rejected` or `complete`.
+    #[pyo3(get)]
+    pub kind: String,
+    /// The thinking block or call the text belongs to.
+    #[pyo3(get)]
+    pub index: Option<usize>,
+    /// The streamed text, or the text that was rejected.
+    #[pyo3(get)]
+    pub text: Option<String>,
+    /// What the parser expected instead of rejected text.
+    #[pyo3(get)]
+    pub expected: Option<String>,
+}
+
+impl From<acquiesce::parse::ParseResult> for ParseResult {
+    fn from(result: acquiesce::parse::ParseResult) -> Self {
+        use acquiesce::parse::{ParseResult, ReasoningDelta, ToolCallDelta};
+
+        let (kind, index, text, expected) = match result {
+            ParseResult::Content(text) => ("content", None, Some(text), None),
+            ParseResult::Reasoning(ReasoningDelta { index, delta }) => {
+                ("reasoning", Some(index), Some(delta), None)
+            }
+            ParseResult::ToolCall(ToolCallDelta { index, delta }) => {
+                ("tool_call", Some(index), Some(delta), None)
+            }
+            ParseResult::ToolCallId(ToolCallDelta { index, delta }) => {
+                ("tool_call_id", Some(index), Some(delta), None)
+            }
+            ParseResult::ToolCallName(ToolCallDelta { index, delta }) => {
+                ("tool_call_name", Some(index), Some(delta), None)
+            }
+            ParseResult::Rejected(text, expected) => {
+                ("rejected", None, Some(text), Some(expected.to_string()))
+            }
+            ParseResult::Complete => ("complete", None, None, None),
+        };
+
+        Self {
+            kind: kind.to_string(),
+            index,
+            text,
+            expected,
+        }
+    }
+}
+
+/// Per-request render settings. Arguments left as `None` keep the core
+/// defaults.
+#[gen_stub_pyclass]
+#[pyclass(module = "acquiesce_py")]
+#[derive(Clone, Default)]
+pub struct RenderOptions(acquiesce::render::RenderOptions);
 
 #[gen_stub_pyclass]
 #[pyclass]
@@ -34,6 +162,16 @@ pub struct RenderResult {
     pub grammar: Option<String>,
     #[pyo3(get)]
     pub parser: Option<Parser>,
+    #[pyo3(get)]
+    pub warnings: Vec<String>,
+    #[pyo3(get)]
+    pub stop: Vec<String>,
+    /// Hash of the prompt prefix shared with the conversation before its last
+    /// user message, if `prompt_prefix` was requested.
+    #[pyo3(get)]
+    pub prefix_key: Option<u64>,
+    #[pyo3(get)]
+    pub prefix_len: Option<usize>,
 }
 
 #[gen_stub_pymethods]
@@ -47,91 +185,515 @@ impl Acquiesce {
         bos_token: Option<String>,
         eos_token: Option<String>,
         multimodal: bool,
-        add_generation_prompt: bool,
     ) -> PyResult<Self> {
-        let repr = serde_json::from_str::<AcquiesceRepr>(&source)
-            .or(AcquiesceRepr::infer_default(source.as_str()))
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let args = AcquiesceArgs {
+            source,
+            chat_template,
+            bos_token,
+            eos_token,
+            multimodal,
+        };
 
-        Ok(Self(
-            repr.resolve_from_options(
-                chat_template,
-                bos_token,
-                eos_token,
-                multimodal,
-                add_generation_prompt,
-            )
-            .map_err(|e| PyValueError::new_err(e.to_string()))?,
+        Ok(Self(args.resolve()?, Arc::new(args)))
+    }
+
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(Bound<'py, PyAny>, AcquiesceArgsTuple)> {
+        let Acquiesce(_, args) = &*slf.borrow();
+
+        Ok((
+            slf.get_type().getattr("new")?,
+            args.as_ref().clone().into_tuple(),
         ))
     }
 
+    #[pyo3(signature = (messages_json, tools_json, tool_choice_json, options = None))]
     fn render(
         &self,
         py: Python,
         messages_json: String,
         tools_json: String,
         tool_choice_json: String,
-        parallel_tool_calls: bool,
-        mixed_content_tool_calls: bool,
-        grammar_syntax: String,
+        options: Option<RenderOptions>,
     ) -> PyResult<RenderResult> {
-        let Acquiesce(inner) = self;
-        py.detach(|| {
-            let messages = serde_json::from_str::<ChatMessages>(&messages_json)
-                .map_err(|e| PyValueError::new_err(format!("Invalid messages JSON: {e}")))?;
-            let tools = serde_json::from_str::<Vec<ChatTool>>(&tools_json)
-                .map_err(|e| PyValueError::new_err(format!("Invalid tools JSON: {e}")))?;
-            let tool_choice = serde_json::from_str::<ChatToolChoice>(&tool_choice_json)
-                .map_err(|e| PyValueError::new_err(format!("Invalid tool_choice JSON: {e}")))?;
-
-            let grammar_syntax = match grammar_syntax.as_str() {
+        py.detach(|| self.render_request((messages_json, tools_json, tool_choice_json, options)))
+    }
+
+    /// Renders many requests with the GIL released once for the whole batch.
+    /// Each request takes the same arguments as `render`. With `parallel`, the
+    /// requests are spread across a thread pool; results keep request order.
+    #[pyo3(signature = (requests, parallel = false))]
+    fn render_batch(
+        &self,
+        py: Python,
+        requests: Vec<RenderRequestTuple>,
+        parallel: bool,
+    ) -> PyResult<Vec<RenderResult>> {
+        let results = py.detach(|| {
+            let render_indexed =
+                |(index, request)| self.render_request(request).map_err(|e| (index, e));
+
+            if parallel {
+                requests
+                    .into_par_iter()
+                    .enumerate()
+                    .map(render_indexed)
+                    .collect::<Result<Vec<_>, _>>()
+            } else {
+                requests
+                    .into_iter()
+                    .enumerate()
+                    .map(render_indexed)
+                    .collect::<Result<Vec<_>, _>>()
+            }
+        });
+
+        results.map_err(|(index, e)| {
+            PyErr::from_type(e.get_type(py), format!("request {index}: {}", e.value(py)))
+        })
+    }
+}
+
+impl Acquiesce {
+    fn render_request(
+        &self,
+        (messages_json, tools_json, tool_choice_json, options): RenderRequestTuple,
+    ) -> PyResult<RenderResult> {
+        let Acquiesce(inner, args) = self;
+
+        let messages = serde_json::from_str::<ChatMessages>(&messages_json)
+            .map_err(|e| PyValueError::new_err(format!("Invalid messages JSON: {e}")))?;
+        let tools = serde_json::from_str::<Vec<ChatTool>>(&tools_json)
+            .map_err(|e| PyValueError::new_err(format!("Invalid tools JSON: {e}")))?;
+        let tool_choice = serde_json::from_str::<ChatToolChoice>(&tool_choice_json)
+            .map_err(|e| PyValueError::new_err(format!("Invalid tool_choice JSON: {e}")))?;
+
+        let RenderOptions(options) = options.unwrap_or_default();
+
+        let result = inner
+            .render(messages, tools, tool_choice, &options)
+            .map_err(|e| RenderError::new_err(e.to_string()))?;
+
+        Ok(RenderResult {
+            prompt: result.prompt,
+            grammar: result.grammar,
+            parser: result
+                .parser
+                .map(|parser| Parser(parser, args.clone(), Some(String::new()))),
+            warnings: result.warnings.iter().map(ToString::to_string).collect(),
+            stop: result.stop,
+            prefix_key: result.prefix.map(|prefix| prefix.key),
+            prefix_len: result.prefix.map(|prefix| prefix.len),
+        })
+    }
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl RenderOptions {
+    #[new]
+    #[pyo3(signature = (
+        parallel_tool_calls = None,
+        max_tool_calls = None,
+        mixed_content_tool_calls = None,
+        grammar_syntax = None,
+        reasoning_effort = None,
+        thinking = None,
+        max_thinking_tokens = None,
+        max_thinking_chars = None,
+        response_format_json = None,
+        add_generation_prompt = None,
+        continue_final_message = None,
+        prompt_prefix = None,
+        reasoning_only = None,
+        validate_grammar = None,
+        kwargs_json = None,
+        documents_json = None,
+        knowledge_cutoff = None,
+        harmony_channels = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        parallel_tool_calls: Option<bool>,
+        max_tool_calls: Option<usize>,
+        mixed_content_tool_calls: Option<bool>,
+        grammar_syntax: Option<String>,
+        reasoning_effort: Option<String>,
+        thinking: Option<String>,
+        max_thinking_tokens: Option<usize>,
+        max_thinking_chars: Option<usize>,
+        response_format_json: Option<String>,
+        add_generation_prompt: Option<bool>,
+        continue_final_message: Option<bool>,
+        prompt_prefix: Option<bool>,
+        reasoning_only: Option<bool>,
+        validate_grammar: Option<bool>,
+        kwargs_json: Option<String>,
+        documents_json: Option<String>,
+        knowledge_cutoff: Option<String>,
+        harmony_channels: Option<Vec<String>>,
+    ) -> PyResult<Self> {
+        let mut options = acquiesce::render::RenderOptions::default();
+
+        if let Some(parallel_tool_calls) = parallel_tool_calls {
+            options.parallel_tool_calls = parallel_tool_calls;
+        }
+
+        options.max_tool_calls = max_tool_calls;
+
+        if let Some(mixed_content_tool_calls) = mixed_content_tool_calls {
+            options.mixed_content_tool_calls = mixed_content_tool_calls;
+        }
+
+        if let Some(grammar_syntax) = grammar_syntax {
+            options.grammar_syntax = match grammar_syntax.as_str() {
                 "lark" => GrammarSyntax::Lark,
                 "gbnf" => GrammarSyntax::GBNF,
+                "ebnf" => GrammarSyntax::Ebnf,
+                "llguidance" => GrammarSyntax::LLGuidance,
+                "structural_tags" => GrammarSyntax::StructuralTags,
                 _ => {
                     return Err(PyValueError::new_err(format!(
                         "Invalid grammar syntax: {grammar_syntax}"
                     )));
                 }
             };
+        }
 
-            let result = inner
-                .render(
-                    messages,
-                    tools,
-                    tool_choice,
-                    parallel_tool_calls,
-                    mixed_content_tool_calls,
-                    grammar_syntax,
-                )
-                .map_err(|e| RenderError::new_err(e.to_string()))?;
-
-            Ok(RenderResult {
-                prompt: result.prompt,
-                grammar: result.grammar,
-                parser: result.parser.map(Parser),
-            })
-        })
+        if let Some(reasoning_effort) = reasoning_effort {
+            options.reasoning_effort = Some(
+                serde_json::from_value::<ReasoningEffort>(reasoning_effort.clone().into())
+                    .map_err(|_| {
+                        PyValueError::new_err(format!(
+                            "Invalid reasoning effort: {reasoning_effort}"
+                        ))
+                    })?,
+            );
+        }
+
+        if let Some(thinking) = thinking {
+            options.thinking = Some(
+                serde_json::from_value::<ThinkingMode>(thinking.clone().into()).map_err(|_| {
+                    PyValueError::new_err(format!("Invalid thinking mode: {thinking}"))
+                })?,
+            );
+        }
+
+        options.thinking_limit = match (max_thinking_tokens, max_thinking_chars) {
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err(
+                    "Only one of max_thinking_tokens and max_thinking_chars can be set",
+                ));
+            }
+            (Some(max_tokens), None) => Some(ThinkingLimit::Tokens(max_tokens)),
+            (None, Some(max_chars)) => Some(ThinkingLimit::Characters(max_chars)),
+            (None, None) => None,
+        };
+
+        if let Some(response_format_json) = response_format_json {
+            options.response_format =
+                serde_json::from_str::<ChatResponseFormat>(&response_format_json).map_err(|e| {
+                    PyValueError::new_err(format!("Invalid response_format JSON: {e}"))
+                })?;
+        }
+
+        if let Some(add_generation_prompt) = add_generation_prompt {
+            options.add_generation_prompt = add_generation_prompt;
+        }
+
+        if let Some(continue_final_message) = continue_final_message {
+            options.continue_final_message = continue_final_message;
+        }
+
+        if let Some(prompt_prefix) = prompt_prefix {
+            options.prompt_prefix = prompt_prefix;
+        }
+
+        if let Some(reasoning_only) = reasoning_only {
+            options.reasoning_only = reasoning_only;
+        }
+
+        if let Some(validate_grammar) = validate_grammar {
+            options.validate_grammar = validate_grammar;
+        }
+
+        if let Some(kwargs_json) = kwargs_json {
+            options.kwargs = serde_json::from_str(&kwargs_json)
+                .map_err(|e| PyValueError::new_err(format!("Invalid kwargs JSON: {e}")))?;
+        }
+
+        if let Some(documents_json) = documents_json {
+            options.documents = serde_json::from_str(&documents_json)
+                .map_err(|e| PyValueError::new_err(format!("Invalid documents JSON: {e}")))?;
+        }
+
+        options.knowledge_cutoff = knowledge_cutoff;
+
+        if let Some(harmony_channels) = harmony_channels {
+            options.harmony_channels = Some(
+                harmony_channels
+                    .into_iter()
+                    .map(|channel| {
+                        serde_json::from_value::<HarmonyChannel>(channel.clone().into()).map_err(
+                            |_| {
+                                PyValueError::new_err(format!("Invalid Harmony channel: {channel}"))
+                            },
+                        )
+                    })
+                    .collect::<PyResult<_>>()?,
+            );
+        }
+
+        Ok(Self(options))
     }
 }
 
 #[gen_stub_pymethods]
 #[pymethods]
 impl Parser {
-    fn parse(&self, py: Python, _text: String) -> PyResult<Vec<String>> {
-        let Parser(inner) = self;
+    fn parse(&mut self, py: Python, text: String) -> Vec<ParseResult> {
+        let Parser(inner, _, history) = self;
 
         py.detach(|| {
-            // let result = inner.parse(_text).map_err(|e| PyParseError::new_err(e.to_string()))?;
+            *history = history
+                .take()
+                .filter(|history| history.len() + text.len() <= MAX_REPLAY_LEN)
+                .map(|history| history + &text);
 
-            Ok(vec![])
+            inner.advance(text).map(ParseResult::from).collect()
         })
     }
+
+    #[staticmethod]
+    fn _restore(args: AcquiesceArgsTuple, history: String) -> PyResult<Self> {
+        let args = AcquiesceArgs::from_tuple(args);
+
+        let mut parser = args
+            .resolve()?
+            .parser()
+            .ok_or_else(|| ParseError::new_err("config does not produce a parser"))?;
+
+        parser.advance(history.clone()).for_each(drop);
+
+        Ok(Self(parser, Arc::new(args), Some(history)))
+    }
+
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(Bound<'py, PyAny>, (AcquiesceArgsTuple, String))> {
+        let Parser(_, args, history) = &*slf.borrow();
+
+        let history = history.clone().ok_or_else(|| {
+            ParseError::new_err(format!(
+                "parser has been fed more than {MAX_REPLAY_LEN} bytes, too many to pickle"
+            ))
+        })?;
+
+        Ok((
+            slf.get_type().getattr("_restore")?,
+            (args.as_ref().clone().into_tuple(), history),
+        ))
+    }
+}
+
+/// Builds a config step by step, like the Rust crate's `AcquiesceBuilder`.
+/// Lexemes are passed as JSON: `{"token": "<think>"}` for a special token,
+/// `"\n"` for text, or a list of them. Every method returns the builder, so
+/// calls chain.
+#[gen_stub_pyclass]
+#[pyclass(module = "acquiesce_py")]
+#[derive(Clone, Default)]
+pub struct AcquiesceBuilder(acquiesce::builder::AcquiesceBuilder);
+
+impl AcquiesceBuilder {
+    fn update(
+        mut slf: PyRefMut<'_, Self>,
+        f: impl FnOnce(acquiesce::builder::AcquiesceBuilder) -> acquiesce::builder::AcquiesceBuilder,
+    ) -> PyRefMut<'_, Self> {
+        let AcquiesceBuilder(builder) = &mut *slf;
+        *builder = f(std::mem::take(builder));
+
+        slf
+    }
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(name: &str, json: &str) -> PyResult<T> {
+    serde_json::from_str(json)
+        .map_err(|e| PyValueError::new_err(format!("Invalid {name} JSON: {e}")))
+}
+
+#[gen_stub_pymethods]
+#[pymethods]
+impl AcquiesceBuilder {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn thinking<'py>(
+        slf: PyRefMut<'py, Self>,
+        prefix_json: &str,
+        suffix_json: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let prefix = from_json::<OrderedLexemes>("prefix", prefix_json)?;
+        let suffix = from_json::<OrderedLexemes>("suffix", suffix_json)?;
+
+        Ok(Self::update(slf, |builder| {
+            builder.thinking(prefix, suffix)
+        }))
+    }
+
+    /// Only applies after `thinking`.
+    fn thinking_budget<'py>(
+        slf: PyRefMut<'py, Self>,
+        budget_json: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let budget = from_json::<ThinkingBudget>("budget", budget_json)?;
+
+        Ok(Self::update(slf, |builder| builder.thinking_budget(budget)))
+    }
+
+    /// Only applies after `thinking`.
+    fn thinking_mode<'py>(slf: PyRefMut<'py, Self>, mode: String) -> PyResult<PyRefMut<'py, Self>> {
+        let mode = serde_json::from_value::<ThinkingMode>(mode.clone().into())
+            .map_err(|_| PyValueError::new_err(format!("Invalid thinking mode: {mode}")))?;
+
+        Ok(Self::update(slf, |builder| builder.thinking_mode(mode)))
+    }
+
+    /// Only applies after `thinking`.
+    fn interleaved_thinking(slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        Self::update(slf, |builder| builder.interleaved_thinking())
+    }
+
+    /// Only applies after `thinking`.
+    fn thinking_toggle<'py>(
+        slf: PyRefMut<'py, Self>,
+        toggle_json: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let toggle = from_json::<ThinkingToggle>("toggle", toggle_json)?;
+
+        Ok(Self::update(slf, |builder| builder.thinking_toggle(toggle)))
+    }
+
+    fn tool_section<'py>(
+        slf: PyRefMut<'py, Self>,
+        prefix_json: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let prefix = from_json::<OrderedLexemes>("prefix", prefix_json)?;
+
+        Ok(Self::update(slf, |builder| builder.tool_section(prefix)))
+    }
+
+    /// Only applies after `tool_section`.
+    fn section_suffix<'py>(
+        slf: PyRefMut<'py, Self>,
+        suffix_json: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let suffix = from_json::<OrderedLexemes>("suffix", suffix_json)?;
+
+        Ok(Self::update(slf, |builder| builder.section_suffix(suffix)))
+    }
+
+    /// Only applies after `tool_section`.
+    fn section_per_call(slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        Self::update(slf, |builder| builder.section_per_call())
+    }
+
+    fn tool_call<'py>(
+        slf: PyRefMut<'py, Self>,
+        tool_call_json: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let tool_call = from_json::<ToolCall>("tool_call", tool_call_json)?;
+
+        Ok(Self::update(slf, |builder| builder.tool_call(tool_call)))
+    }
+
+    fn json_object<'py>(
+        slf: PyRefMut<'py, Self>,
+        name_key: &str,
+        argument_key: &str,
+    ) -> PyRefMut<'py, Self> {
+        Self::update(slf, |builder| builder.json_object(name_key, argument_key))
+    }
+
+    fn json_array<'py>(
+        slf: PyRefMut<'py, Self>,
+        name_key: &str,
+        argument_key: &str,
+    ) -> PyRefMut<'py, Self> {
+        Self::update(slf, |builder| builder.json_array(name_key, argument_key))
+    }
+
+    #[pyo3(signature = (prefix_json = None, delimiter_json = None, suffix_json = None))]
+    fn named_parameters<'py>(
+        slf: PyRefMut<'py, Self>,
+        prefix_json: Option<&str>,
+        delimiter_json: Option<&str>,
+        suffix_json: Option<&str>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let lexemes = |name, json: Option<&str>| {
+            json.map(|json| from_json::<OrderedLexemes>(name, json))
+                .transpose()
+        };
+        let prefix = lexemes("prefix", prefix_json)?;
+        let delimiter = lexemes("delimiter", delimiter_json)?;
+        let suffix = lexemes("suffix", suffix_json)?;
+
+        Ok(Self::update(slf, |builder| {
+            builder.named_parameters(prefix, delimiter, suffix)
+        }))
+    }
+
+    fn stop(slf: PyRefMut<'_, Self>, stop: String) -> PyRefMut<'_, Self> {
+        Self::update(slf, |builder| builder.stop(stop))
+    }
+
+    /// Renames messages with role `from_role` to `to` for the template.
+    fn role(slf: PyRefMut<'_, Self>, from_role: String, to: String) -> PyRefMut<'_, Self> {
+        Self::update(slf, |builder| builder.role(from_role, to))
+    }
+
+    /// Passes messages with role `role` to the template as is.
+    fn custom_role(slf: PyRefMut<'_, Self>, role: String) -> PyRefMut<'_, Self> {
+        Self::update(slf, |builder| builder.custom_role(role))
+    }
+
+    /// The config as an `acquiesce.json` document, to pass as `source`.
+    fn build(&self) -> String {
+        let AcquiesceBuilder(builder) = self;
+        builder.clone().build().to_string()
+    }
+}
+
+/// Names of the built-in configs that can be passed as `source`.
+#[gen_stub_pyfunction]
+#[pyfunction]
+fn list_presets() -> Vec<String> {
+    acquiesce::configs::list_presets()
+        .map(str::to_string)
+        .collect()
+}
+
+/// A built-in config rendered as an `acquiesce.json` document.
+#[gen_stub_pyfunction]
+#[pyfunction]
+fn get_preset(name: &str) -> PyResult<String> {
+    acquiesce::configs::get_preset(name)
+        .map(|preset| preset.to_string())
+        .ok_or_else(|| PyValueError::new_err(format!("Unknown preset: {name}")))
 }
 
 #[pymodule]
 fn acquiesce_py(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(list_presets, m)?)?;
+    m.add_function(wrap_pyfunction!(get_preset, m)?)?;
     m.add_class::<Acquiesce>()?;
+    m.add_class::<AcquiesceBuilder>()?;
     m.add_class::<Parser>()?;
+    m.add_class::<ParseResult>()?;
+    m.add_class::<RenderOptions>()?;
     m.add_class::<RenderResult>()?;
     m.add("InitError", py.get_type::<InitError>())?;
     m.add("RenderError", py.get_type::<RenderError>())?;
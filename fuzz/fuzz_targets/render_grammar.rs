@@ -0,0 +1,36 @@
+//! Arbitrary tools and tool choices through every config. Renders may reject
+//! the tools, but any grammar they produce must compile.
+
+#![no_main]
+
+use acquiesce::render::{RenderResult, schema::ChatMessages, validate_lark};
+use acquiesce_fuzz::{Options, Tool, ToolChoice, config, tools};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    config: u8,
+    tools: Vec<Tool>,
+    tool_choice: ToolChoice,
+    options: Options,
+}
+
+fuzz_target!(|input: Input| {
+    let Ok(RenderResult {
+        grammar: Some(grammar),
+        ..
+    }) = config(input.config).render(
+        ChatMessages::Content("Hello!".to_string()),
+        tools(input.tools),
+        input.tool_choice.into(),
+        &input.options.into(),
+    )
+    else {
+        return;
+    };
+
+    if let Err(e) = validate_lark(&grammar) {
+        panic!("{e}\n{grammar}");
+    }
+});
@@ -0,0 +1,25 @@
+//! Arbitrary conversations through every config's template. Renders may fail,
+//! but must not panic.
+
+#![no_main]
+
+use acquiesce::render::schema::ChatToolChoice;
+use acquiesce_fuzz::{Message, Options, config, messages};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    config: u8,
+    messages: Vec<Message>,
+    options: Options,
+}
+
+fuzz_target!(|input: Input| {
+    let _ = config(input.config).render(
+        messages(input.messages),
+        Vec::new(),
+        ChatToolChoice::None,
+        &input.options.into(),
+    );
+});
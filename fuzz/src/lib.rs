@@ -0,0 +1,373 @@
+//! Structured inputs for the fuzz targets. Run one with
+//! `cargo +nightly fuzz run render_grammar` from this directory.
+//!
+//! Every string is left arbitrary: tool names, descriptions, schemas and custom
+//! grammars all end up spliced into prompts and grammars.
+
+use std::sync::LazyLock;
+
+use acquiesce::{
+    Acquiesce, AcquiesceRepr,
+    configs::PRESETS,
+    render::{
+        ReasoningEffort, RenderOptions,
+        schema::{
+            ChatAssistantMessage, ChatFunction, ChatImageUrl, ChatJsonSchema, ChatMessageContent,
+            ChatMessageVariant, ChatMessages, ChatResponseFormat, ChatSystemDeveloperMessage,
+            ChatTool, ChatToolCall, ChatToolChoice, ChatToolMessage, ChatUserChunk,
+            ChatUserMessage, CustomTool, CustomToolFormat, CustomToolGrammar, CustomToolSyntax,
+            FunctionName, FunctionTool, ToolCallType,
+        },
+    },
+};
+use arbitrary::Arbitrary;
+use serde_json::{Map, Value, json};
+
+const TEMPLATE: &str = "\
+{%- if tools %}{{ tools | tojson }}\n{% endif %}\
+{%- for message in messages %}\
+<|{{ message.role }}|>{{ message.content }}\
+{%- if message.tool_calls %}{{ message.tool_calls | tojson }}{% endif %}\n\
+{%- endfor %}\
+{%- if add_generation_prompt %}<|assistant|>{% endif %}";
+
+const COMPONENTS: &str = r#"{
+    "version": "v1",
+    "config": {
+        "type": "components",
+        "chat_template": null,
+        "thinking": { "prefix": "<think>", "suffix": "</think>", "budget": { "low": 256 } },
+        "tool_calls": {
+            "type": "tool_calls_section",
+            "prefix": "<tool_calls>",
+            "tool_call": { "type": "json_object", "name_key": "name", "argument_key": "arguments" },
+            "suffix": "</tool_calls>"
+        }
+    }
+}"#;
+
+const HARMONY: &str = r#"{
+    "version": "v1",
+    "config": { "type": "harmony", "builtin_tools": ["python", "browser"] }
+}"#;
+
+/// The presets plus a generic components config and Harmony, all resolved
+/// against a template that echoes every message and tool.
+pub static CONFIGS: LazyLock<Vec<Acquiesce>> = LazyLock::new(|| {
+    PRESETS
+        .iter()
+        .map(|(_, preset)| preset())
+        .chain([COMPONENTS, HARMONY].map(|config| config.parse::<AcquiesceRepr>().unwrap()))
+        .map(|repr| {
+            repr.resolve_from_options(
+                TEMPLATE.to_string(),
+                Some("<s>".to_string()),
+                Some("</s>".to_string()),
+                true,
+            )
+            .unwrap()
+        })
+        .collect()
+});
+
+pub fn config(index: u8) -> &'static Acquiesce {
+    &CONFIGS[index as usize % CONFIGS.len()]
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Message {
+    System(String),
+    Developer(String),
+    User(Vec<UserChunk>),
+    Assistant {
+        content: String,
+        tool_calls: Vec<(Option<String>, Option<String>)>,
+    },
+    Tool {
+        content: String,
+        tool_call_id: String,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum UserChunk {
+    Text(String),
+    ImageUrl(String),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Schema {
+    String {
+        pattern: Option<String>,
+    },
+    Enum(Vec<String>),
+    Const(String),
+    Integer {
+        minimum: Option<i64>,
+        maximum: Option<i64>,
+    },
+    Number,
+    Boolean,
+    Null,
+    Array {
+        items: Box<Schema>,
+        min_items: Option<u8>,
+    },
+    Object {
+        properties: Vec<(String, Schema, bool)>,
+        additional_properties: bool,
+    },
+    AnyOf(Vec<Schema>),
+    /// Parsed as JSON if it is any, otherwise used as a (nonsensical) string schema.
+    Raw(String),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Tool {
+    Function {
+        name: String,
+        description: Option<String>,
+        parameters: Schema,
+    },
+    Text {
+        name: String,
+        description: Option<String>,
+    },
+    Regex {
+        name: String,
+        definition: String,
+    },
+    Lark {
+        name: String,
+        definition: String,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum ToolChoice {
+    Auto,
+    None,
+    Required,
+    Function(String),
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema(Schema),
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct Options {
+    pub parallel_tool_calls: bool,
+    pub mixed_content_tool_calls: bool,
+    pub reasoning_effort: Option<u8>,
+    pub response_format: ResponseFormat,
+    pub add_generation_prompt: bool,
+    pub prompt_prefix: bool,
+    pub reasoning_only: bool,
+    pub kwargs: Vec<(String, String)>,
+}
+
+pub fn messages(messages: Vec<Message>) -> ChatMessages {
+    let text = ChatMessageContent::SingleText;
+
+    ChatMessages::Conversation(
+        messages
+            .into_iter()
+            .map(|message| match message {
+                Message::System(content) => {
+                    ChatMessageVariant::System(ChatSystemDeveloperMessage {
+                        content: text(content),
+                        name: None,
+                    })
+                }
+                Message::Developer(content) => {
+                    ChatMessageVariant::Developer(ChatSystemDeveloperMessage {
+                        content: text(content),
+                        name: None,
+                    })
+                }
+                Message::User(chunks) => ChatMessageVariant::User(ChatUserMessage {
+                    content: ChatMessageContent::ManyChunks(
+                        chunks
+                            .into_iter()
+                            .map(|chunk| match chunk {
+                                UserChunk::Text(text) => ChatUserChunk::Text { text },
+                                UserChunk::ImageUrl(url) => ChatUserChunk::ImageUrl {
+                                    image_url: ChatImageUrl { url },
+                                },
+                            })
+                            .collect(),
+                    ),
+                    name: None,
+                }),
+                Message::Assistant {
+                    content,
+                    tool_calls,
+                } => ChatMessageVariant::Assistant(ChatAssistantMessage {
+                    content: ChatMessageContent::SingleText(content),
+                    refusal: None,
+                    name: None,
+                    tool_calls: (!tool_calls.is_empty()).then(|| {
+                        tool_calls
+                            .into_iter()
+                            .enumerate()
+                            .map(|(index, (name, arguments))| ChatToolCall {
+                                index: Some(index),
+                                id: Some(format!("call_{index}")),
+                                r#type: Some(ToolCallType::Function),
+                                function: ChatFunction { name, arguments },
+                            })
+                            .collect()
+                    }),
+                }),
+                Message::Tool {
+                    content,
+                    tool_call_id,
+                } => ChatMessageVariant::Tool(ChatToolMessage {
+                    content: text(content),
+                    tool_call_id,
+                }),
+            })
+            .collect(),
+    )
+}
+
+impl Schema {
+    pub fn to_value(&self) -> Value {
+        match self {
+            Schema::String { pattern: None } => json!({ "type": "string" }),
+            Schema::String {
+                pattern: Some(pattern),
+            } => json!({ "type": "string", "pattern": pattern }),
+            Schema::Enum(values) => json!({ "enum": values }),
+            Schema::Const(value) => json!({ "const": value }),
+            Schema::Integer { minimum, maximum } => {
+                let mut schema = json!({ "type": "integer" });
+                if let Some(minimum) = minimum {
+                    schema["minimum"] = json!(minimum);
+                }
+                if let Some(maximum) = maximum {
+                    schema["maximum"] = json!(maximum);
+                }
+                schema
+            }
+            Schema::Number => json!({ "type": "number" }),
+            Schema::Boolean => json!({ "type": "boolean" }),
+            Schema::Null => json!({ "type": "null" }),
+            Schema::Array { items, min_items } => {
+                let mut schema = json!({ "type": "array", "items": items.to_value() });
+                if let Some(min_items) = min_items {
+                    schema["minItems"] = json!(min_items);
+                }
+                schema
+            }
+            Schema::Object {
+                properties,
+                additional_properties,
+            } => json!({
+                "type": "object",
+                "properties": properties
+                    .iter()
+                    .map(|(name, schema, _)| (name.clone(), schema.to_value()))
+                    .collect::<Map<_, _>>(),
+                "required": properties
+                    .iter()
+                    .filter(|(_, _, required)| *required)
+                    .map(|(name, _, _)| name)
+                    .collect::<Vec<_>>(),
+                "additionalProperties": additional_properties,
+            }),
+            Schema::AnyOf(schemas) => {
+                json!({ "anyOf": schemas.iter().map(Schema::to_value).collect::<Vec<_>>() })
+            }
+            Schema::Raw(raw) => serde_json::from_str(raw).unwrap_or_else(|_| json!(raw)),
+        }
+    }
+}
+
+pub fn tools(tools: Vec<Tool>) -> Vec<ChatTool> {
+    let custom = |name, description, format| ChatTool::Custom {
+        custom: CustomTool {
+            name,
+            description,
+            format,
+        },
+    };
+    let grammar = |syntax, definition| CustomToolFormat::Grammar {
+        grammar: CustomToolGrammar { definition, syntax },
+    };
+
+    tools
+        .into_iter()
+        .map(|tool| match tool {
+            Tool::Function {
+                name,
+                description,
+                parameters,
+            } => ChatTool::Function {
+                function: FunctionTool {
+                    name,
+                    description,
+                    parameters: parameters.to_value(),
+                    strict: None,
+                },
+            },
+            Tool::Text { name, description } => custom(name, description, CustomToolFormat::Text),
+            Tool::Regex { name, definition } => {
+                custom(name, None, grammar(CustomToolSyntax::Regex, definition))
+            }
+            Tool::Lark { name, definition } => {
+                custom(name, None, grammar(CustomToolSyntax::Lark, definition))
+            }
+        })
+        .collect()
+}
+
+impl From<ToolChoice> for ChatToolChoice {
+    fn from(tool_choice: ToolChoice) -> Self {
+        match tool_choice {
+            ToolChoice::Auto => ChatToolChoice::Auto,
+            ToolChoice::None => ChatToolChoice::None,
+            ToolChoice::Required => ChatToolChoice::Required,
+            ToolChoice::Function(name) => ChatToolChoice::Function(FunctionName { name }),
+        }
+    }
+}
+
+impl From<Options> for RenderOptions {
+    fn from(options: Options) -> Self {
+        RenderOptions {
+            parallel_tool_calls: options.parallel_tool_calls,
+            mixed_content_tool_calls: options.mixed_content_tool_calls,
+            reasoning_effort: options.reasoning_effort.map(|effort| match effort % 3 {
+                0 => ReasoningEffort::Low,
+                1 => ReasoningEffort::Medium,
+                _ => ReasoningEffort::High,
+            }),
+            response_format: match options.response_format {
+                ResponseFormat::Text => ChatResponseFormat::Text,
+                ResponseFormat::JsonObject => ChatResponseFormat::JsonObject,
+                ResponseFormat::JsonSchema(schema) => ChatResponseFormat::JsonSchema {
+                    json_schema: ChatJsonSchema {
+                        name: "response".to_string(),
+                        description: None,
+                        schema: Some(schema.to_value()),
+                        strict: Some(true),
+                    },
+                },
+            },
+            add_generation_prompt: options.add_generation_prompt,
+            prompt_prefix: options.prompt_prefix,
+            reasoning_only: options.reasoning_only,
+            kwargs: options
+                .kwargs
+                .into_iter()
+                .map(|(key, value)| (key, Value::String(value)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! A fluent alternative to writing out [`Config::Components`] literals.
+//!
+//! ```
+//! use acquiesce::{Lexeme, builder::AcquiesceBuilder};
+//!
+//! let repr = AcquiesceBuilder::new()
+//!     .thinking("<think>", "</think>")
+//!     .tool_section(Lexeme::Token("<tool_call>".into()))
+//!     .json_object("name", "arguments")
+//!     .section_suffix(Lexeme::Token("</tool_call>".into()))
+//!     .stop("<|im_end|>")
+//!     .build();
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{
+    AcquiesceRepr, Arguments, Config, Lexeme, OrderedLexemes, Thinking, ThinkingBudget,
+    ThinkingMode, ThinkingToggle, ToolCall, ToolCalls,
+};
+
+/// Strings are matched as text. Use [`Lexeme::Token`] for special tokens.
+impl From<&str> for Lexeme {
+    fn from(s: &str) -> Self {
+        Lexeme::Text(s.into())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct AcquiesceBuilder {
+    thinking: Option<Thinking>,
+    section: Option<(OrderedLexemes, Option<OrderedLexemes>, bool)>,
+    tool_call: Option<ToolCall>,
+    stop: Vec<String>,
+    roles: BTreeMap<String, String>,
+    custom_roles: Vec<String>,
+}
+
+impl AcquiesceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn thinking(
+        mut self,
+        prefix: impl Into<OrderedLexemes>,
+        suffix: impl Into<OrderedLexemes>,
+    ) -> Self {
+        self.thinking = Some(Thinking {
+            prefix: prefix.into(),
+            suffix: suffix.into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        });
+        self
+    }
+
+    /// Only applies after [`Self::thinking`].
+    pub fn thinking_budget(mut self, budget: ThinkingBudget) -> Self {
+        if let Some(thinking) = &mut self.thinking {
+            thinking.budget = Some(budget);
+        }
+        self
+    }
+
+    /// Only applies after [`Self::thinking`].
+    pub fn thinking_mode(mut self, mode: ThinkingMode) -> Self {
+        if let Some(thinking) = &mut self.thinking {
+            thinking.mode = Some(mode);
+        }
+        self
+    }
+
+    /// Only applies after [`Self::thinking`].
+    pub fn interleaved_thinking(mut self) -> Self {
+        if let Some(thinking) = &mut self.thinking {
+            thinking.interleaved = true;
+        }
+        self
+    }
+
+    /// Only applies after [`Self::thinking`].
+    pub fn thinking_toggle(mut self, toggle: ThinkingToggle) -> Self {
+        if let Some(thinking) = &mut self.thinking {
+            thinking.toggle = Some(toggle);
+        }
+        self
+    }
+
+    /// Wraps tool calls in a section opened by `prefix`, which repeats calls
+    /// when parallel tool calls are allowed.
+    pub fn tool_section(mut self, prefix: impl Into<OrderedLexemes>) -> Self {
+        self.section = Some((prefix.into(), None, false));
+        self
+    }
+
+    /// Only applies after [`Self::tool_section`].
+    pub fn section_suffix(mut self, suffix: impl Into<OrderedLexemes>) -> Self {
+        if let Some((_, section_suffix, _)) = &mut self.section {
+            *section_suffix = Some(suffix.into());
+        }
+        self
+    }
+
+    /// Gives each call a section of its own. Only applies after
+    /// [`Self::tool_section`].
+    pub fn section_per_call(mut self) -> Self {
+        if let Some((_, _, per_call)) = &mut self.section {
+            *per_call = true;
+        }
+        self
+    }
+
+    pub fn tool_call(mut self, tool_call: ToolCall) -> Self {
+        self.tool_call = Some(tool_call);
+        self
+    }
+
+    pub fn json_object(self, name_key: &str, argument_key: &str) -> Self {
+        self.tool_call(ToolCall::JsonObject {
+            name_key: name_key.into(),
+            argument_key: argument_key.into(),
+        })
+    }
+
+    pub fn json_array(self, name_key: &str, argument_key: &str) -> Self {
+        self.tool_call(ToolCall::JsonArray {
+            name_key: name_key.into(),
+            argument_key: argument_key.into(),
+        })
+    }
+
+    /// A call written as `<prefix>name<delimiter>{arguments}<suffix>`.
+    pub fn named_parameters(
+        self,
+        prefix: Option<OrderedLexemes>,
+        delimiter: Option<OrderedLexemes>,
+        suffix: Option<OrderedLexemes>,
+    ) -> Self {
+        self.tool_call(ToolCall::NamedParameters {
+            prefix,
+            id: None,
+            delimiter,
+            arguments: Arguments::JsonObject,
+            suffix,
+        })
+    }
+
+    pub fn stop(mut self, stop: impl Into<String>) -> Self {
+        self.stop.push(stop.into());
+        self
+    }
+
+    /// Renames messages with role `from` to `to` for the template.
+    pub fn role(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.roles.insert(from.into(), to.into());
+        self
+    }
+
+    /// Passes messages with role `role` to the template as is.
+    pub fn custom_role(mut self, role: impl Into<String>) -> Self {
+        self.custom_roles.push(role.into());
+        self
+    }
+
+    /// A section without a call format holds JSON objects with `name` and
+    /// `arguments` keys.
+    pub fn build(self) -> AcquiesceRepr {
+        let tool_calls = match (self.section, self.tool_call) {
+            (Some((prefix, suffix, per_call)), tool_call) => Some(ToolCalls::ToolCallsSection {
+                prefix,
+                tool_call: tool_call.unwrap_or_else(|| ToolCall::JsonObject {
+                    name_key: "name".into(),
+                    argument_key: "arguments".into(),
+                }),
+                suffix,
+                per_call,
+            }),
+            (None, Some(tool_call)) => Some(ToolCalls::ToolCall { tool_call }),
+            (None, None) => None,
+        };
+
+        Config::Components {
+            chat_template: (),
+            thinking: self.thinking,
+            tool_calls,
+            stop: self.stop,
+            roles: self.roles,
+            custom_roles: self.custom_roles,
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! A JSON Schema for `acquiesce.json`, for editors and for servers checking
+//! uploaded configs before loading them.
+
+use serde_json::Value;
+
+use crate::AcquiesceConfig;
+
+pub fn schema() -> Value {
+    serde_json::to_value(schemars::schema_for!(AcquiesceConfig)).expect("schemas serialize to JSON")
+}
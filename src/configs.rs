@@ -1 +1,58 @@
+use crate::AcquiesceRepr;
+
+pub mod command_r;
+pub mod deepseek;
+pub mod functionary;
+pub mod gemma3;
+pub mod glm4;
+pub mod granite;
+pub mod hermes;
+pub mod internlm;
+pub mod jamba;
 pub mod kimik2;
+pub mod minimax;
+pub mod minimax_m2;
+pub mod mistral;
+pub mod nemotron;
+pub mod phi4;
+pub mod qwen3;
+pub mod qwen3_coder;
+pub mod registry;
+pub mod seed_oss;
+pub mod xlam;
+
+/// A preset's name and the function that builds it.
+pub type Preset = (&'static str, fn() -> AcquiesceRepr);
+
+pub static PRESETS: &[Preset] = &[
+    ("kimi_k2", kimik2::kimi_k2),
+    ("qwen3", qwen3::qwen3),
+    ("mistral", mistral::mistral),
+    ("deepseek_r1", deepseek::deepseek_r1),
+    ("hermes", hermes::hermes),
+    ("phi4", phi4::phi4),
+    ("gemma3", gemma3::gemma3),
+    ("glm4", glm4::glm4),
+    ("command_r", command_r::command_r),
+    ("granite", granite::granite),
+    ("nemotron", nemotron::nemotron),
+    ("functionary", functionary::functionary),
+    ("xlam", xlam::xlam),
+    ("jamba", jamba::jamba),
+    ("minimax", minimax::minimax),
+    ("minimax_m2", minimax_m2::minimax_m2),
+    ("seed_oss", seed_oss::seed_oss),
+    ("internlm", internlm::internlm),
+    ("qwen3_coder", qwen3_coder::qwen3_coder),
+];
+
+pub fn list_presets() -> impl Iterator<Item = &'static str> {
+    PRESETS.iter().map(|(name, _)| *name)
+}
+
+pub fn get_preset(name: &str) -> Option<AcquiesceRepr> {
+    PRESETS
+        .iter()
+        .find(|(preset_name, _)| *preset_name == name)
+        .map(|(_, preset)| preset())
+}
@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, Thinking, ToolCall, ToolCalls};
+
+/// Cohere's Command-R7B and Command-A layout, where the model writes a tool plan
+/// as its reasoning and then a JSON array of actions with each tool's
+/// `tool_name` and `parameters`. Grounding documents are passed to the template
+/// through [`crate::render::RenderOptions::documents`].
+pub fn command_r() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Token("<|START_THINKING|>".into()).into(),
+            suffix: Lexeme::Token("<|END_THINKING|>".into()).into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Token("<|START_ACTION|>".into()).into(),
+            tool_call: ToolCall::JsonArray {
+                name_key: "tool_name".into(),
+                argument_key: "parameters".into(),
+            },
+            suffix: Some(Lexeme::Token("<|END_ACTION|>".into()).into()),
+            per_call: false,
+        }),
+        stop: vec!["<|END_OF_TURN_TOKEN|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_r_config() {
+        println!("{}", command_r());
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Arguments, Config, Lexeme, Thinking, ToolCall, ToolCalls};
+
+/// DeepSeek-R1's `<think>` reasoning, followed by tool calls that each carry
+/// their arguments in a fenced JSON block.
+pub fn deepseek_r1() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Token("<think>".into()).into(),
+            suffix: Lexeme::Token("</think>".into()).into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Token("<｜tool▁calls▁begin｜>".into()).into(),
+            tool_call: ToolCall::NamedParameters {
+                prefix: Some(
+                    [
+                        Lexeme::Token("<｜tool▁call▁begin｜>".into()),
+                        Lexeme::Text("function".into()),
+                        Lexeme::Token("<｜tool▁sep｜>".into()),
+                    ]
+                    .as_slice()
+                    .into(),
+                ),
+                id: None,
+                delimiter: Some(Lexeme::Text("\n```json\n".into()).into()),
+                arguments: Arguments::JsonObject,
+                suffix: Some(
+                    [
+                        Lexeme::Text("\n```".into()),
+                        Lexeme::Token("<｜tool▁call▁end｜>".into()),
+                    ]
+                    .as_slice()
+                    .into(),
+                ),
+            },
+            suffix: Some(Lexeme::Token("<｜tool▁calls▁end｜>".into()).into()),
+            per_call: false,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deepseek_r1_config() {
+        println!("{}", deepseek_r1());
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Arguments, Config, Lexeme, ToolCall, ToolCalls};
+
+/// MeetKai Functionary's `>>>` recipient headers, with the tool name, a newline
+/// and the JSON arguments.
+pub fn functionary() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCall {
+            tool_call: ToolCall::NamedParameters {
+                prefix: Some(Lexeme::Text(">>>".into()).into()),
+                id: None,
+                delimiter: Some(Lexeme::Text("\n".into()).into()),
+                arguments: Arguments::JsonObject,
+                suffix: None,
+            },
+        }),
+        stop: vec!["<|eot_id|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functionary_config() {
+        println!("{}", functionary());
+    }
+}
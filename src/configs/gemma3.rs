@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Arguments, Config, Lexeme, ToolCall, ToolCalls};
+
+/// Gemma 3 has no tool call tokens, so this follows the common prompting
+/// pattern of a `tool_code` block with one `name({...})` call per line. Its
+/// template only alternates user and model turns and folds a leading system
+/// message into the first user turn, so developer messages are rendered as
+/// system messages and tool results as user turns.
+pub fn gemma3() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Text("```tool_code\n".into()).into(),
+            tool_call: ToolCall::NamedParameters {
+                prefix: None,
+                id: None,
+                delimiter: Some(Lexeme::Text("(".into()).into()),
+                arguments: Arguments::JsonObject,
+                suffix: Some(Lexeme::Text(")\n".into()).into()),
+            },
+            suffix: Some(Lexeme::Text("```".into()).into()),
+            per_call: false,
+        }),
+        stop: vec!["<end_of_turn>".into()],
+        roles: BTreeMap::from([
+            ("developer".into(), "system".into()),
+            ("tool".into(), "user".into()),
+        ]),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gemma3_config() {
+        println!("{}", gemma3());
+    }
+}
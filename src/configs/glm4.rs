@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Arguments, Config, Lexeme, Thinking, ToolCall, ToolCalls};
+
+/// GLM-4's `<think>` reasoning and `<tool_call>` blocks holding the tool name,
+/// a newline and the JSON arguments. Generation ends at the next user or
+/// observation turn rather than at an end-of-turn token.
+pub fn glm4() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Token("<think>".into()).into(),
+            suffix: Lexeme::Token("</think>".into()).into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCall {
+            tool_call: ToolCall::NamedParameters {
+                prefix: Some(Lexeme::Token("<tool_call>".into()).into()),
+                id: None,
+                delimiter: Some(Lexeme::Text("\n".into()).into()),
+                arguments: Arguments::JsonObject,
+                suffix: Some(Lexeme::Token("</tool_call>".into()).into()),
+            },
+        }),
+        stop: vec!["<|user|>".into(), "<|observation|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glm4_config() {
+        println!("{}", glm4());
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, ToolCall, ToolCalls};
+
+/// IBM Granite 3's `<|tool_call|>` token followed by a JSON array of calls,
+/// each with the tool's `name` and `arguments`. Granite's template writes its
+/// own default system prompt when the request has none, so no roles need
+/// renaming.
+pub fn granite() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Token("<|tool_call|>".into()).into(),
+            tool_call: ToolCall::JsonArray {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: None,
+            per_call: false,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn granite_config() {
+        println!("{}", granite());
+    }
+}
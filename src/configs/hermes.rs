@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, ToolCall, ToolCalls};
+
+/// The NousResearch Hermes convention of `<tool_call>` sections holding a JSON
+/// object with the tool's `name` and `arguments`. Fine-tunes don't reliably
+/// add `<tool_call>` as a token, so the markers are matched as text.
+pub fn hermes() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Text("<tool_call>\n".into()).into(),
+            tool_call: ToolCall::JsonObject {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: Some(Lexeme::Text("\n</tool_call>".into()).into()),
+            per_call: true,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hermes_config() {
+        println!("{}", hermes());
+    }
+}
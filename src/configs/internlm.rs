@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, ToolCall, ToolCalls};
+
+/// InternLM2's plugin actions: a JSON object with the tool's `name` and
+/// `parameters` between `<|action_start|><|plugin|>` and `<|action_end|>`. The
+/// `<|interpreter|>` action runs code rather than calling a tool, so it isn't
+/// part of the grammar.
+pub fn internlm() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: [
+                Lexeme::Token("<|action_start|>".into()),
+                Lexeme::Token("<|plugin|>".into()),
+                Lexeme::Text("\n".into()),
+            ]
+            .as_slice()
+            .into(),
+            tool_call: ToolCall::JsonObject {
+                name_key: "name".into(),
+                argument_key: "parameters".into(),
+            },
+            suffix: Some(Lexeme::Token("<|action_end|>".into()).into()),
+            per_call: false,
+        }),
+        stop: vec!["<|im_end|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internlm_config() {
+        println!("{}", internlm());
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, ToolCall, ToolCalls};
+
+/// AI21 Jamba's `<tool_calls>` sections holding a JSON array of calls, each
+/// with the tool's `name` and `arguments`.
+pub fn jamba() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Token("<tool_calls>".into()).into(),
+            tool_call: ToolCall::JsonArray {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: Some(Lexeme::Token("</tool_calls>".into()).into()),
+            per_call: false,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jamba_config() {
+        println!("{}", jamba());
+    }
+}
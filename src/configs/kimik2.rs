@@ -1,32 +1,49 @@
+use std::collections::BTreeMap;
+
 use crate::{AcquiesceRepr, Arguments, Config, Lexeme, Thinking, ToolCall, ToolCalls};
 
 pub fn kimi_k2() -> AcquiesceRepr {
     Config::Components {
         chat_template: (),
         thinking: Some(Thinking {
-            prefix: Lexeme::Token("<thinking>".to_string()).into(),
-            suffix: Lexeme::Token("</thinking>".to_string()).into(),
+            prefix: Lexeme::Token("<thinking>".into()).into(),
+            suffix: Lexeme::Token("</thinking>".into()).into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
         }),
         tool_calls: Some(ToolCalls::ToolCallsSection {
-            prefix: Lexeme::Token("<|tool_calls_section_begin|>".to_string()).into(),
+            prefix: Lexeme::Token("<|tool_calls_section_begin|>".into()).into(),
             tool_call: ToolCall::NamedParameters {
-                prefix: Some(Lexeme::Token("<|tool_call_begin|>functions.".to_string()).into()),
-                delimiter: Some(
+                prefix: Some(
+                    [
+                        Lexeme::Token("<|tool_call_begin|>".into()),
+                        Lexeme::Text("functions.".into()),
+                    ]
+                    .as_slice()
+                    .into(),
+                ),
+                id: Some(
                     [
-                        Lexeme::Text(":".to_string()),
+                        Lexeme::Text(":".into()),
                         Lexeme::Regex {
-                            pattern: "[0-9]+".to_string(),
+                            pattern: "[0-9]+".into(),
                         },
-                        Lexeme::Token("<|tool_call_argument_begin|>".to_string()),
                     ]
                     .as_slice()
                     .into(),
                 ),
+                delimiter: Some(Lexeme::Token("<|tool_call_argument_begin|>".into()).into()),
                 arguments: Arguments::JsonObject,
-                suffix: Some(Lexeme::Token("<|tool_call_end|>".to_string()).into()),
+                suffix: Some(Lexeme::Token("<|tool_call_end|>".into()).into()),
             },
-            suffix: Some(Lexeme::Token("<|tool_calls_section_end|>".to_string()).into()),
+            suffix: Some(Lexeme::Token("<|tool_calls_section_end|>".into()).into()),
+            per_call: false,
         }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
     }
 }
 
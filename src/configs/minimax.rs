@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, Thinking, ToolCall, ToolCalls};
+
+/// MiniMax-M1's `<think>` reasoning and `<tool_calls>` sections with a JSON
+/// object per call, each with the tool's `name` and `arguments`. M2 switched
+/// to `<minimax:tool_call>` sections, see [`minimax_m2`](super::minimax_m2).
+pub fn minimax() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Text("<think>".into()).into(),
+            suffix: Lexeme::Text("</think>".into()).into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Text("<tool_calls>\n".into()).into(),
+            tool_call: ToolCall::JsonObject {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: Some(Lexeme::Text("\n</tool_calls>".into()).into()),
+            per_call: false,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimax_config() {
+        println!("{}", minimax());
+    }
+}
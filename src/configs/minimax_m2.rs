@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Arguments, Config, Lexeme, Thinking, ToolCall, ToolCalls};
+
+/// MiniMax-M2's `<think>` reasoning and `<minimax:tool_call>` sections, each
+/// holding an `<invoke name="...">` block per call with a
+/// `<parameter name="...">` tag per argument.
+pub fn minimax_m2() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Text("<think>".into()).into(),
+            // followed by a blank line before the answer or tool calls
+            suffix: [
+                Lexeme::Text("</think>".into()),
+                Lexeme::Whitespace {
+                    whitespace: "\n".into(),
+                },
+            ]
+            .as_slice()
+            .into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Text("<minimax:tool_call>\n".into()).into(),
+            tool_call: ToolCall::NamedParameters {
+                prefix: Some(Lexeme::Text("<invoke name=\"".into()).into()),
+                id: None,
+                delimiter: Some(Lexeme::Text("\">\n".into()).into()),
+                arguments: Arguments::KeyValueTags {
+                    prefix: Lexeme::Text("<parameter name=\"".into()).into(),
+                    delimiter: Lexeme::Text("\">".into()).into(),
+                    suffix: Lexeme::Text("</parameter>\n".into()).into(),
+                },
+                suffix: Some(Lexeme::Text("</invoke>\n".into()).into()),
+            },
+            suffix: Some(Lexeme::Text("</minimax:tool_call>".into()).into()),
+            per_call: false,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimax_m2_config() {
+        println!("{}", minimax_m2());
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, ToolCall, ToolCalls};
+
+/// Mistral's `[TOOL_CALLS]` token followed by a JSON array of calls, each with
+/// the tool's `name` and `arguments`.
+pub fn mistral() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Token("[TOOL_CALLS]".into()).into(),
+            tool_call: ToolCall::JsonArray {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: None,
+            per_call: false,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mistral_config() {
+        println!("{}", mistral());
+    }
+}
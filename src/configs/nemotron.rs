@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, Thinking, ThinkingToggle, ToolCall, ToolCalls};
+
+/// Llama-Nemotron's `<TOOLCALL>` sections holding a JSON array of calls, each
+/// with the tool's `name` and `arguments`. The model only reasons when the
+/// system prompt says `detailed thinking on`.
+pub fn nemotron() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Text("<think>".into()).into(),
+            suffix: Lexeme::Text("</think>".into()).into(),
+            budget: None,
+            toggle: Some(ThinkingToggle {
+                on: "detailed thinking on".into(),
+                off: "detailed thinking off".into(),
+                default: false,
+            }),
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Text("<TOOLCALL>".into()).into(),
+            tool_call: ToolCall::JsonArray {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: Some(Lexeme::Text("</TOOLCALL>".into()).into()),
+            per_call: false,
+        }),
+        stop: vec!["<|eot_id|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nemotron_config() {
+        println!("{}", nemotron());
+    }
+}
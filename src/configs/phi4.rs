@@ -0,0 +1,34 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, ToolCall, ToolCalls};
+
+/// Phi-4's `<|tool_call|>` sections holding a JSON array of calls, each with the
+/// tool's `name` and `arguments`.
+pub fn phi4() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Token("<|tool_call|>".into()).into(),
+            tool_call: ToolCall::JsonArray {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: Some(Lexeme::Token("<|/tool_call|>".into()).into()),
+            per_call: false,
+        }),
+        stop: vec!["<|end|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phi4_config() {
+        println!("{}", phi4());
+    }
+}
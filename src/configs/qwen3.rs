@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, Lexeme, Thinking, ToolCall, ToolCalls};
+
+/// Qwen3's Hermes-style `<tool_call>` sections, each holding a JSON object with
+/// the tool's `name` and `arguments`.
+pub fn qwen3() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Token("<think>".into()).into(),
+            // followed by a blank line before the answer or tool calls
+            suffix: [
+                Lexeme::Token("</think>".into()),
+                Lexeme::Whitespace {
+                    whitespace: "\n".into(),
+                },
+            ]
+            .as_slice()
+            .into(),
+            budget: None,
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: [
+                Lexeme::Token("<tool_call>".into()),
+                Lexeme::Text("\n".into()),
+            ]
+            .as_slice()
+            .into(),
+            tool_call: ToolCall::JsonObject {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+            suffix: Some(
+                [
+                    Lexeme::Text("\n".into()),
+                    Lexeme::Token("</tool_call>".into()),
+                ]
+                .as_slice()
+                .into(),
+            ),
+            per_call: true,
+        }),
+        stop: vec!["<|im_end|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qwen3_config() {
+        println!("{}", qwen3());
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Arguments, Config, Lexeme, ToolCall, ToolCalls};
+
+/// Qwen3-Coder's `<tool_call>` sections, which unlike Qwen3's hold a
+/// `<function=name>` block with a `<parameter=name>` tag per argument.
+pub fn qwen3_coder() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: [
+                Lexeme::Token("<tool_call>".into()),
+                Lexeme::Text("\n".into()),
+            ]
+            .as_slice()
+            .into(),
+            tool_call: ToolCall::NamedParameters {
+                prefix: Some(Lexeme::Text("<function=".into()).into()),
+                id: None,
+                delimiter: Some(Lexeme::Text(">\n".into()).into()),
+                arguments: Arguments::KeyValueTags {
+                    prefix: Lexeme::Text("<parameter=".into()).into(),
+                    delimiter: Lexeme::Text(">\n".into()).into(),
+                    suffix: Lexeme::Text("\n</parameter>\n".into()).into(),
+                },
+                suffix: Some(Lexeme::Text("</function>".into()).into()),
+            },
+            suffix: Some(
+                [
+                    Lexeme::Text("\n".into()),
+                    Lexeme::Token("</tool_call>".into()),
+                ]
+                .as_slice()
+                .into(),
+            ),
+            per_call: true,
+        }),
+        stop: vec!["<|im_end|>".into()],
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qwen3_coder_config() {
+        println!("{}", qwen3_coder());
+    }
+}
@@ -0,0 +1,274 @@
+//! Which preset a model without an `acquiesce.json` gets. Entries are tried in
+//! order, by model name first and then by the `architectures` in the model's
+//! `config.json`, so fine-tunes with unrecognizable names still resolve.
+//! [`from_template`] and [`from_tokens`] are the last resort, for models
+//! neither identifies.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    AcquiesceRepr, Config, Lexeme, OrderedLexemes, Thinking,
+    configs::{
+        command_r::command_r, deepseek::deepseek_r1, functionary::functionary, gemma3::gemma3,
+        glm4::glm4, granite::granite, hermes::hermes, internlm::internlm, jamba::jamba,
+        kimik2::kimi_k2, minimax::minimax, minimax_m2::minimax_m2, mistral::mistral,
+        nemotron::nemotron, phi4::phi4, qwen3::qwen3, qwen3_coder::qwen3_coder, seed_oss::seed_oss,
+        xlam::xlam,
+    },
+};
+
+pub struct RegistryEntry {
+    pub preset: fn() -> AcquiesceRepr,
+    /// Matches a lowercased model name containing every substring of any one
+    /// of these groups.
+    pub names: &'static [&'static [&'static str]],
+    pub architectures: &'static [&'static str],
+    /// Strings that all appear in a chat template using this preset's tool
+    /// calls, or all among its tokenizer's added tokens. Empty for formats a
+    /// template doesn't give away.
+    pub template_markers: &'static [&'static str],
+}
+
+impl RegistryEntry {
+    fn matches_name(&self, model: &str) -> bool {
+        self.names
+            .iter()
+            .any(|group| group.iter().all(|m| model.contains(m)))
+    }
+}
+
+pub static REGISTRY: &[RegistryEntry] = &[
+    RegistryEntry {
+        preset: kimi_k2,
+        names: &[&["kimi", "k2"]],
+        // shared with DeepSeek V3, which has its own tool call tokens
+        architectures: &[],
+        template_markers: &["<|tool_calls_section_begin|>"],
+    },
+    // fine-tunes name their base model too, so match them first
+    RegistryEntry {
+        preset: hermes,
+        names: &[&["hermes"]],
+        architectures: &[],
+        template_markers: &[],
+    },
+    RegistryEntry {
+        preset: functionary,
+        names: &[&["functionary"]],
+        architectures: &[],
+        template_markers: &[">>>"],
+    },
+    RegistryEntry {
+        preset: xlam,
+        names: &[&["xlam"]],
+        architectures: &[],
+        template_markers: &[],
+    },
+    RegistryEntry {
+        preset: qwen3_coder,
+        names: &[&["qwen3", "coder"]],
+        // shared with Qwen3's MoE models
+        architectures: &[],
+        template_markers: &["<function=", "<parameter=", "<|im_start|>"],
+    },
+    RegistryEntry {
+        preset: qwen3,
+        names: &[&["qwen"]],
+        architectures: &[
+            "Qwen2ForCausalLM",
+            "Qwen3ForCausalLM",
+            "Qwen3MoeForCausalLM",
+        ],
+        template_markers: &["<tool_call>", "<|im_start|>"],
+    },
+    RegistryEntry {
+        preset: mistral,
+        names: &[&["mistral"], &["mixtral"], &["ministral"]],
+        architectures: &["MistralForCausalLM", "MixtralForCausalLM"],
+        template_markers: &["[TOOL_CALLS]"],
+    },
+    RegistryEntry {
+        preset: deepseek_r1,
+        names: &[&["deepseek", "r1"]],
+        architectures: &[],
+        template_markers: &["<｜tool▁calls▁begin｜>"],
+    },
+    RegistryEntry {
+        preset: phi4,
+        names: &[&["phi-4"], &["phi4"]],
+        architectures: &["Phi3ForCausalLM"],
+        template_markers: &["<|tool_call|>", "<|end|>"],
+    },
+    RegistryEntry {
+        preset: gemma3,
+        names: &[&["gemma-3"], &["gemma3"]],
+        architectures: &["Gemma3ForCausalLM", "Gemma3ForConditionalGeneration"],
+        template_markers: &["```tool_code"],
+    },
+    RegistryEntry {
+        preset: glm4,
+        names: &[&["glm"]],
+        architectures: &["Glm4MoeForCausalLM"],
+        template_markers: &["<tool_call>", "[gMASK]"],
+    },
+    RegistryEntry {
+        preset: command_r,
+        names: &[&["command-r"], &["command-a"]],
+        architectures: &["Cohere2ForCausalLM"],
+        template_markers: &["<|START_ACTION|>"],
+    },
+    RegistryEntry {
+        preset: granite,
+        names: &[&["granite"]],
+        architectures: &["GraniteForCausalLM", "GraniteMoeForCausalLM"],
+        template_markers: &["<|tool_call|>", "<|start_of_role|>"],
+    },
+    RegistryEntry {
+        preset: nemotron,
+        names: &[&["nemotron"]],
+        architectures: &["DeciLMForCausalLM"],
+        template_markers: &["<TOOLCALL>"],
+    },
+    RegistryEntry {
+        preset: jamba,
+        names: &[&["jamba"]],
+        architectures: &["JambaForCausalLM"],
+        template_markers: &["<tool_calls>", "<|bom|>"],
+    },
+    RegistryEntry {
+        preset: minimax_m2,
+        names: &[&["minimax", "m2"]],
+        architectures: &["MiniMaxM2ForCausalLM"],
+        template_markers: &["<minimax:tool_call>"],
+    },
+    RegistryEntry {
+        preset: minimax,
+        names: &[&["minimax"]],
+        architectures: &["MiniMaxM1ForCausalLM"],
+        template_markers: &["<tool_calls>", "]~b]"],
+    },
+    RegistryEntry {
+        preset: seed_oss,
+        names: &[&["seed-oss"]],
+        architectures: &["SeedOssForCausalLM"],
+        template_markers: &["<seed:tool_call>"],
+    },
+    RegistryEntry {
+        preset: internlm,
+        names: &[&["internlm"]],
+        architectures: &["InternLM2ForCausalLM"],
+        template_markers: &["<|plugin|>"],
+    },
+];
+
+pub fn find_by_name(model_name: &str) -> Option<&'static RegistryEntry> {
+    let model = model_name.trim().to_lowercase();
+
+    REGISTRY.iter().find(|entry| entry.matches_name(&model))
+}
+
+pub fn find_by_architecture(architectures: &[String]) -> Option<&'static RegistryEntry> {
+    architectures.iter().find_map(|architecture| {
+        REGISTRY
+            .iter()
+            .find(|entry| entry.architectures.contains(&architecture.as_str()))
+    })
+}
+
+pub fn find_by_template(template: &str) -> Option<&'static RegistryEntry> {
+    find_by_markers(|marker| template.contains(marker))
+}
+
+/// The entry whose markers are all among `tokens`, the added tokens of the
+/// model's tokenizer.
+pub fn find_by_tokens(tokens: &[String]) -> Option<&'static RegistryEntry> {
+    find_by_markers(|marker| tokens.iter().any(|token| token == marker))
+}
+
+fn find_by_markers(contains: impl Fn(&str) -> bool) -> Option<&'static RegistryEntry> {
+    REGISTRY.iter().find(|entry| {
+        !entry.template_markers.is_empty()
+            && entry.template_markers.iter().all(|marker| contains(marker))
+    })
+}
+
+/// Thinking markers recognized in models whose tool calls aren't.
+static THINKING_MARKERS: &[(&str, &str)] = &[
+    ("<think>", "</think>"),
+    ("<|START_THINKING|>", "<|END_THINKING|>"),
+];
+
+/// A config built from the markers in a chat template: the tool calls, stop
+/// sequences and roles of the preset whose markers it contains, and thinking
+/// if the template mentions its markers. `None` when it has neither.
+pub fn from_template(template: &str) -> Option<AcquiesceRepr> {
+    from_markers(|marker| template.contains(marker), Lexeme::Text)
+}
+
+/// Like [`from_template`], but for a tokenizer's added tokens. Thinking
+/// markers found this way are matched as tokens.
+pub fn from_tokens(tokens: &[String]) -> Option<AcquiesceRepr> {
+    from_markers(
+        |marker| tokens.iter().any(|token| token == marker),
+        Lexeme::Token,
+    )
+}
+
+fn from_markers(
+    contains: impl Fn(&str) -> bool,
+    lexeme: fn(Arc<str>) -> Lexeme,
+) -> Option<AcquiesceRepr> {
+    let appears = |lexemes: &OrderedLexemes| {
+        lexemes.0.iter().all(|l| match l {
+            Lexeme::Text(s) | Lexeme::Token(s) => contains(s),
+            Lexeme::Regex { .. }
+            | Lexeme::TokenId(_)
+            | Lexeme::Choice { .. }
+            | Lexeme::Optional { .. }
+            | Lexeme::Whitespace { .. }
+            | Lexeme::JsonSchema(_) => true,
+        })
+    };
+
+    let (thinking, tool_calls, stop, roles, custom_roles) =
+        match find_by_markers(&contains).map(|entry| (entry.preset)()) {
+            Some(Config::Components {
+                thinking,
+                tool_calls,
+                stop,
+                roles,
+                custom_roles,
+                ..
+            }) => (
+                thinking.filter(|thinking| appears(&thinking.prefix)),
+                tool_calls,
+                stop,
+                roles,
+                custom_roles,
+            ),
+            _ => (None, None, Vec::new(), BTreeMap::new(), Vec::new()),
+        };
+
+    let thinking = thinking.or_else(|| {
+        THINKING_MARKERS
+            .iter()
+            .find(|(prefix, suffix)| contains(prefix) && contains(suffix))
+            .map(|(prefix, suffix)| Thinking {
+                prefix: lexeme((*prefix).into()).into(),
+                suffix: lexeme((*suffix).into()).into(),
+                budget: None,
+                toggle: None,
+                mode: None,
+                interleaved: false,
+            })
+    });
+
+    (thinking.is_some() || tool_calls.is_some()).then_some(Config::Components {
+        chat_template: (),
+        thinking,
+        tool_calls,
+        stop,
+        roles,
+        custom_roles,
+    })
+}
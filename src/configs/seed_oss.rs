@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    AcquiesceRepr, Arguments, Config, Lexeme, Thinking, ThinkingBudget, ToolCall, ToolCalls,
+};
+
+/// ByteDance Seed-OSS's `<seed:think>` reasoning and `<seed:tool_call>` blocks
+/// with a `<function=name>` header per call and a `<parameter=name>` tag per
+/// argument.
+///
+/// The budgets follow the 512 token steps Seed-OSS is trained on. The model
+/// only knows its budget when the template gets `thinking_budget` in the
+/// render kwargs.
+pub fn seed_oss() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: Some(Thinking {
+            prefix: Lexeme::Token("<seed:think>".into()).into(),
+            suffix: Lexeme::Token("</seed:think>".into()).into(),
+            budget: Some(ThinkingBudget {
+                low: Some(512),
+                medium: Some(2048),
+                high: None,
+            }),
+            toggle: None,
+            mode: None,
+            interleaved: false,
+        }),
+        tool_calls: Some(ToolCalls::ToolCallsSection {
+            prefix: Lexeme::Token("<seed:tool_call>".into()).into(),
+            tool_call: ToolCall::NamedParameters {
+                prefix: Some(Lexeme::Text("\n<function=".into()).into()),
+                id: None,
+                delimiter: Some(Lexeme::Text(">\n".into()).into()),
+                arguments: Arguments::KeyValueTags {
+                    prefix: Lexeme::Text("<parameter=".into()).into(),
+                    delimiter: Lexeme::Text(">".into()).into(),
+                    suffix: Lexeme::Text("</parameter>\n".into()).into(),
+                },
+                suffix: Some(Lexeme::Text("</function>".into()).into()),
+            },
+            suffix: Some(
+                [
+                    Lexeme::Text("\n".into()),
+                    Lexeme::Token("</seed:tool_call>".into()),
+                ]
+                .as_slice()
+                .into(),
+            ),
+            per_call: false,
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_oss_config() {
+        println!("{}", seed_oss());
+    }
+}
@@ -0,0 +1,31 @@
+use std::collections::BTreeMap;
+
+use crate::{AcquiesceRepr, Config, ToolCall, ToolCalls};
+
+/// Salesforce xLAM's bare JSON array of calls, each with the tool's `name` and
+/// `arguments`, and nothing around it.
+pub fn xlam() -> AcquiesceRepr {
+    Config::Components {
+        chat_template: (),
+        thinking: None,
+        tool_calls: Some(ToolCalls::ToolCall {
+            tool_call: ToolCall::JsonArray {
+                name_key: "name".into(),
+                argument_key: "arguments".into(),
+            },
+        }),
+        stop: Vec::new(),
+        roles: BTreeMap::new(),
+        custom_roles: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xlam_config() {
+        println!("{}", xlam());
+    }
+}
@@ -0,0 +1,42 @@
+//! Tool call formats implemented outside this crate. A format registered under
+//! a name can be used by any config with
+//! `{"type": "external", "name": "<name>"}` as its `tool_call`.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use crate::{OrderedLexemes, parse::Parser, render::template::TemplateTool};
+
+pub trait ToolCallFormat: Send + Sync {
+    /// One call to `tool`, as lexemes the grammar matches in order. A tool call
+    /// matches the lexemes of any one of the request's tools.
+    fn tool_call(&self, tool: &TemplateTool) -> OrderedLexemes;
+
+    /// A fresh streaming parser for completions that use this format.
+    fn parser(&self) -> Parser;
+}
+
+static FORMATS: LazyLock<RwLock<HashMap<String, Arc<dyn ToolCallFormat>>>> =
+    LazyLock::new(Default::default);
+
+/// Makes `format` available to configs under `name`, returning the format it
+/// replaced, if any.
+pub fn register_tool_call_format(
+    name: impl Into<String>,
+    format: impl ToolCallFormat + 'static,
+) -> Option<Arc<dyn ToolCallFormat>> {
+    FORMATS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name.into(), Arc::new(format))
+}
+
+pub fn tool_call_format(name: &str) -> Option<Arc<dyn ToolCallFormat>> {
+    FORMATS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name)
+        .cloned()
+}
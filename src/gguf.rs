@@ -0,0 +1,221 @@
+//! Reads what a config needs from the metadata of a GGUF file: the chat
+//! template, the BOS and EOS tokens, and what identifies the model. Tensor
+//! data is never read.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use crate::{Acquiesce, AcquiesceRepr, InitError};
+
+const MAGIC: &[u8; 4] = b"GGUF";
+
+// https://github.com/ggml-org/ggml/blob/master/docs/gguf.md
+const TYPE_UINT8: u32 = 0;
+const TYPE_INT8: u32 = 1;
+const TYPE_UINT16: u32 = 2;
+const TYPE_INT16: u32 = 3;
+const TYPE_UINT32: u32 = 4;
+const TYPE_INT32: u32 = 5;
+const TYPE_FLOAT32: u32 = 6;
+const TYPE_BOOL: u32 = 7;
+const TYPE_STRING: u32 = 8;
+const TYPE_ARRAY: u32 = 9;
+const TYPE_UINT64: u32 = 10;
+const TYPE_INT64: u32 = 11;
+const TYPE_FLOAT64: u32 = 12;
+
+/// `tokenizer.ggml.token_type` values of tokens outside the normal vocab.
+const TOKEN_TYPE_CONTROL: i64 = 3;
+const TOKEN_TYPE_USER_DEFINED: i64 = 4;
+
+#[derive(Debug, Clone, Default)]
+pub struct GgufMetadata {
+    /// `general.architecture`, llama.cpp's name for it, like `qwen3`.
+    pub architecture: Option<String>,
+    pub name: Option<String>,
+    pub chat_template: Option<String>,
+    pub bos_token: Option<String>,
+    pub eos_token: Option<String>,
+    /// Control and user-defined tokens by ID, the GGUF counterpart of a
+    /// tokenizer's added tokens.
+    pub added_tokens: BTreeMap<u32, String>,
+}
+
+enum Value {
+    Int(i64),
+    String(String),
+    Array(Vec<Value>),
+    Other,
+}
+
+struct GgufReader<R> {
+    reader: R,
+}
+
+impl<R: Read> GgufReader<R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], InitError> {
+        let mut buf = [0; N];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn u32(&mut self) -> Result<u32, InitError> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, InitError> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn string(&mut self) -> Result<String, InitError> {
+        let len = self.u64()?;
+        let mut buf = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut buf)?;
+
+        if buf.len() as u64 != len {
+            return Err(InitError::InvalidGguf("truncated string"));
+        }
+
+        String::from_utf8(buf).map_err(|_| InitError::InvalidGguf("string is not UTF-8"))
+    }
+
+    fn value(&mut self, value_type: u32) -> Result<Value, InitError> {
+        Ok(match value_type {
+            TYPE_UINT8 => Value::Int(u8::from_le_bytes(self.bytes()?).into()),
+            TYPE_INT8 => Value::Int(i8::from_le_bytes(self.bytes()?).into()),
+            TYPE_UINT16 => Value::Int(u16::from_le_bytes(self.bytes()?).into()),
+            TYPE_INT16 => Value::Int(i16::from_le_bytes(self.bytes()?).into()),
+            TYPE_UINT32 => Value::Int(self.u32()?.into()),
+            TYPE_INT32 => Value::Int(i32::from_le_bytes(self.bytes()?).into()),
+            TYPE_UINT64 => Value::Int(self.u64()? as i64),
+            TYPE_INT64 => Value::Int(i64::from_le_bytes(self.bytes()?)),
+            TYPE_BOOL => Value::Int(self.bytes::<1>()?[0].into()),
+            TYPE_FLOAT32 => {
+                self.bytes::<4>()?;
+                Value::Other
+            }
+            TYPE_FLOAT64 => {
+                self.bytes::<8>()?;
+                Value::Other
+            }
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_ARRAY => {
+                let item_type = self.u32()?;
+                let len = self.u64()?;
+
+                Value::Array(
+                    (0..len)
+                        .map(|_| self.value(item_type))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            _ => return Err(InitError::InvalidGguf("unknown metadata value type")),
+        })
+    }
+}
+
+impl GgufMetadata {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, InitError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader(reader: impl Read) -> Result<Self, InitError> {
+        let mut reader = GgufReader { reader };
+
+        if &reader.bytes::<4>()? != MAGIC {
+            return Err(InitError::InvalidGguf("not a GGUF file"));
+        }
+        if reader.u32()? < 2 {
+            return Err(InitError::InvalidGguf("GGUF v1 is not supported"));
+        }
+
+        let _tensor_count = reader.u64()?;
+        let kv_count = reader.u64()?;
+
+        let mut metadata = GgufMetadata::default();
+        let mut tokens = Vec::new();
+        let mut token_types = Vec::new();
+        let mut bos_token_id = None;
+        let mut eos_token_id = None;
+
+        for _ in 0..kv_count {
+            let key = reader.string()?;
+            let value_type = reader.u32()?;
+
+            match (key.as_str(), reader.value(value_type)?) {
+                ("general.architecture", Value::String(s)) => metadata.architecture = Some(s),
+                ("general.name", Value::String(s)) => metadata.name = Some(s),
+                ("tokenizer.chat_template", Value::String(s)) => metadata.chat_template = Some(s),
+                ("tokenizer.ggml.bos_token_id", Value::Int(id)) => bos_token_id = Some(id),
+                ("tokenizer.ggml.eos_token_id", Value::Int(id)) => eos_token_id = Some(id),
+                ("tokenizer.ggml.tokens", Value::Array(values)) => tokens = values,
+                ("tokenizer.ggml.token_type", Value::Array(values)) => token_types = values,
+                _ => {}
+            }
+        }
+
+        let token = |id: Option<i64>| match tokens.get(usize::try_from(id?).ok()?) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
+        };
+
+        metadata.bos_token = token(bos_token_id);
+        metadata.eos_token = token(eos_token_id);
+        metadata.added_tokens = (0..)
+            .zip(tokens.iter().zip(&token_types))
+            .filter_map(|(id, (token, token_type))| match (token, token_type) {
+                (Value::String(s), Value::Int(TOKEN_TYPE_CONTROL | TOKEN_TYPE_USER_DEFINED)) => {
+                    Some((id, s.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Ok(metadata)
+    }
+}
+
+impl AcquiesceRepr {
+    /// Infers a preset from the GGUF's model name or architecture, falling
+    /// back to its chat template and then its added tokens.
+    pub fn infer_from_gguf(metadata: &GgufMetadata) -> Result<Self, InitError> {
+        [&metadata.name, &metadata.architecture]
+            .into_iter()
+            .flatten()
+            .find_map(|name| Self::infer_default(name).ok())
+            .ok_or(InitError::InferFailed)
+            .or_else(|_| Self::infer_from_template(metadata.chat_template.as_deref().unwrap_or("")))
+            .or_else(|_| {
+                Self::infer_from_tokens(
+                    &metadata.added_tokens.values().cloned().collect::<Vec<_>>(),
+                )
+            })
+    }
+
+    pub fn resolve_from_gguf(self, metadata: &GgufMetadata) -> Result<Acquiesce, InitError> {
+        self.with_token_ids(&metadata.added_tokens)
+            .resolve_from_options(
+                metadata
+                    .chat_template
+                    .clone()
+                    .ok_or(InitError::MissingTemplate)?,
+                metadata.bos_token.clone(),
+                metadata.eos_token.clone(),
+                false,
+            )
+    }
+}
+
+impl Acquiesce {
+    /// Loads a config for the model in a `.gguf` file, which embeds no
+    /// `acquiesce.json`, so the config is always inferred.
+    pub fn from_gguf(path: impl AsRef<Path>) -> Result<Self, InitError> {
+        let metadata = GgufMetadata::from_file(path)?;
+
+        AcquiesceRepr::infer_from_gguf(&metadata)?.resolve_from_gguf(&metadata)
+    }
+}
@@ -0,0 +1,59 @@
+//! Callbacks a host server can install on an [`Acquiesce`] to record metrics
+//! or sample logs. Hooks that aren't set cost a branch and nothing else.
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{Acquiesce, Config, parse::ParseResult, render::RenderWarning};
+
+pub type Hook<T> = Arc<dyn Fn(&T) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct Hooks {
+    /// Called after every successful render.
+    pub on_render: Option<Hook<RenderMeta>>,
+    /// Called for every result of parsers created after the hook is set.
+    pub on_parse_event: Option<Hook<ParseResult>>,
+    /// Called for each warning a render returns.
+    pub on_warning: Option<Hook<RenderWarning>>,
+}
+
+/// A summary of one render.
+#[derive(Debug, Clone)]
+pub struct RenderMeta {
+    pub tools: usize,
+    /// `None` for [`Acquiesce::render_to`], which never holds the prompt.
+    pub prompt_len: Option<usize>,
+    /// `None` when there is no grammar or it hasn't been generated yet.
+    pub grammar_len: Option<usize>,
+    pub warnings: usize,
+    pub elapsed: Duration,
+}
+
+impl Hooks {
+    pub(crate) fn rendered(&self, meta: impl FnOnce() -> RenderMeta, warnings: &[RenderWarning]) {
+        if let Some(on_warning) = &self.on_warning {
+            warnings.iter().for_each(|warning| on_warning(warning));
+        }
+
+        if let Some(on_render) = &self.on_render {
+            on_render(&meta());
+        }
+    }
+}
+
+impl Acquiesce {
+    pub fn hooks(&self) -> &Hooks {
+        match self {
+            Config::Components { chat_template, .. } => &chat_template.hooks,
+            Config::Harmony(harmony) => &harmony.hooks,
+        }
+    }
+
+    /// Replaces the hooks on this instance.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        match self {
+            Config::Components { chat_template, .. } => chat_template.hooks = hooks,
+            Config::Harmony(harmony) => harmony.hooks = hooks,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Loading straight from the Hugging Face Hub. Files are downloaded into the
+//! usual hf-hub cache and then read back through [`Acquiesce::from_repo`].
+//! With the `tokio` feature, the `_async` variants do the I/O without blocking
+//! the runtime's worker threads.
+
+use hf_hub::{
+    Cache, CacheRepo, Repo, RepoType,
+    api::sync::{ApiBuilder, ApiError, ApiRepo},
+};
+
+use crate::{
+    ACQUIESCE_CONFIG, Acquiesce, AcquiesceRepr, InitError,
+    render::template::{CHAT_TEMPLATE, MODEL_CONFIG, TOKENIZER_CONFIG},
+};
+
+impl Acquiesce {
+    /// Downloads the files a config is loaded from for `repo_id` at
+    /// `revision`, a branch, tag or commit, and loads it. Repos without an
+    /// `acquiesce.json` get a config inferred with
+    /// [`AcquiesceRepr::infer_from_repo`].
+    pub fn from_hub(repo_id: &str, revision: &str) -> Result<Self, InitError> {
+        let repo = Repo::with_revision(repo_id.to_string(), RepoType::Model, revision.to_string());
+        let cache = Cache::from_env();
+        let api = ApiBuilder::from_env()
+            .with_progress(false)
+            .build()?
+            .repo(repo.clone());
+
+        let has_config = download(&api)?;
+
+        Self::from_downloaded(repo_id, &cache.repo(repo), has_config)
+    }
+
+    fn from_downloaded(
+        repo_id: &str,
+        repo: &CacheRepo,
+        has_config: bool,
+    ) -> Result<Self, InitError> {
+        if has_config {
+            Self::from_repo(repo)
+        } else {
+            AcquiesceRepr::infer_from_repo(repo_id, repo)?.resolve_from_repo(repo)
+        }
+    }
+}
+
+/// Returns whether the repo has an `acquiesce.json`. The chat template file
+/// is optional too, since most repos keep it in `tokenizer_config.json`.
+fn download(api: &ApiRepo) -> Result<bool, InitError> {
+    api.get(TOKENIZER_CONFIG)?;
+    api.get(MODEL_CONFIG)?;
+    optional(api.get(CHAT_TEMPLATE))?;
+
+    optional(api.get(ACQUIESCE_CONFIG))
+}
+
+/// Whether an optional file was downloaded. Only the hub answering that it
+/// doesn't have the file means it's missing; failing to ask, or being refused
+/// by a gated repo, is still an error.
+fn optional<T>(download: Result<T, ApiError>) -> Result<bool, InitError> {
+    match download {
+        Ok(_) => Ok(true),
+        Err(ApiError::RequestError(e)) if matches!(*e, ureq::Error::Status(404, _)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(feature = "tokio")]
+async fn download_async(api: &hf_hub::api::tokio::ApiRepo) -> Result<bool, InitError> {
+    api.get(TOKENIZER_CONFIG).await?;
+    api.get(MODEL_CONFIG).await?;
+    optional_async(api.get(CHAT_TEMPLATE).await)?;
+
+    optional_async(api.get(ACQUIESCE_CONFIG).await)
+}
+
+/// [`optional`] for hf-hub's async client.
+#[cfg(feature = "tokio")]
+fn optional_async<T>(download: Result<T, hf_hub::api::tokio::ApiError>) -> Result<bool, InitError> {
+    use hf_hub::api::tokio::ApiError;
+
+    match download {
+        Ok(_) => Ok(true),
+        Err(ApiError::RequestError(e))
+            if e.status().is_some_and(|status| status.as_u16() == 404) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Runs `f` on tokio's blocking pool, resuming any panic on the caller.
+#[cfg(feature = "tokio")]
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Acquiesce {
+    /// [`Acquiesce::from_repo`] on tokio's blocking pool.
+    pub async fn from_repo_async(repo: CacheRepo) -> Result<Self, InitError> {
+        blocking(move || Self::from_repo(&repo)).await
+    }
+
+    /// [`Acquiesce::from_hub`], downloading with hf-hub's async client.
+    pub async fn from_hub_async(repo_id: &str, revision: &str) -> Result<Self, InitError> {
+        let repo = Repo::with_revision(repo_id.to_string(), RepoType::Model, revision.to_string());
+        let cache = Cache::from_env();
+        let api = hf_hub::api::tokio::ApiBuilder::from_env()
+            .with_progress(false)
+            .build()?
+            .repo(repo.clone());
+
+        let has_config = download_async(&api).await?;
+
+        let repo_id = repo_id.to_string();
+        blocking(move || Self::from_downloaded(&repo_id, &cache.repo(repo), has_config)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(code: u16) -> Result<(), ApiError> {
+        let response = ureq::Response::new(code, "", "").unwrap();
+
+        Err(ApiError::RequestError(Box::new(ureq::Error::Status(
+            code, response,
+        ))))
+    }
+
+    #[test]
+    fn only_not_found_files_are_missing() {
+        assert!(optional(Ok(())).unwrap());
+        assert!(!optional(status(404)).unwrap());
+
+        // a gated repo, a hub outage and no connection at all
+        assert!(matches!(optional(status(401)), Err(InitError::Hub(_))));
+        assert!(matches!(optional(status(500)), Err(InitError::Hub(_))));
+
+        let offline = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "offline");
+        let offline = Err(ApiError::RequestError(Box::new(offline.into())));
+        assert!(matches!(optional::<()>(offline), Err(InitError::Hub(_))));
+    }
+}
@@ -0,0 +1,121 @@
+//! The contract between a render and an inference engine: a prompt, an
+//! optional grammar in the syntax the engine accepts, and stop strings. The
+//! adapters here build request bodies for engines that take exactly that.
+
+use serde::Serialize;
+
+use crate::{
+    Acquiesce,
+    render::{
+        GrammarSyntax, RenderError, RenderOptions, RenderResult, RenderWarning,
+        schema::{ChatTool, ChatToolChoice},
+        template::TemplateChatMessage,
+    },
+};
+
+/// A rendered request, as an engine consumes it.
+pub trait PromptSource {
+    fn prompt(&self) -> &str;
+    fn grammar(&self) -> Option<&str>;
+    fn stop(&self) -> &[String];
+}
+
+impl PromptSource for RenderResult {
+    fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    fn grammar(&self) -> Option<&str> {
+        self.grammar.as_deref()
+    }
+
+    fn stop(&self) -> &[String] {
+        &self.stop
+    }
+}
+
+/// An engine request that a render is applied to.
+pub trait ConstraintSink {
+    /// The grammar syntax the engine accepts, which overrides
+    /// [`RenderOptions::grammar_syntax`] in [`Acquiesce::render_into`].
+    const GRAMMAR_SYNTAX: GrammarSyntax;
+
+    fn apply(&mut self, source: &impl PromptSource);
+}
+
+impl Acquiesce {
+    /// Renders with the grammar syntax `sink` accepts and applies the result
+    /// to it, returning the render's warnings.
+    pub fn render_into<'a, S: ConstraintSink>(
+        &self,
+        sink: &mut S,
+        messages: impl Into<Vec<TemplateChatMessage<'a>>>,
+        tools: Vec<ChatTool>,
+        tool_choice: ChatToolChoice,
+        options: &RenderOptions,
+    ) -> Result<Vec<RenderWarning>, RenderError> {
+        let result = self.render(
+            messages,
+            tools,
+            tool_choice,
+            &RenderOptions {
+                grammar_syntax: S::GRAMMAR_SYNTAX,
+                ..options.clone()
+            },
+        )?;
+
+        sink.apply(&result);
+
+        Ok(result.warnings)
+    }
+}
+
+/// The body of a llama.cpp server `/completion` request.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LlamaCppRequest {
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl ConstraintSink for LlamaCppRequest {
+    const GRAMMAR_SYNTAX: GrammarSyntax = GrammarSyntax::GBNF;
+
+    fn apply(&mut self, source: &impl PromptSource) {
+        self.prompt = source.prompt().to_string();
+        self.grammar = source.grammar().map(str::to_string);
+        self.stop = source.stop().to_vec();
+    }
+}
+
+/// The grammar of a mistral.rs completion request. Its llguidance backend
+/// takes Lark grammars directly.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum MistralRsGrammar {
+    Lark(String),
+}
+
+/// The body of a mistral.rs server `/v1/completions` request.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MistralRsRequest {
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<MistralRsGrammar>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl ConstraintSink for MistralRsRequest {
+    const GRAMMAR_SYNTAX: GrammarSyntax = GrammarSyntax::Lark;
+
+    fn apply(&mut self, source: &impl PromptSource) {
+        self.prompt = source.prompt().to_string();
+        self.grammar = source
+            .grammar()
+            .map(|grammar| MistralRsGrammar::Lark(grammar.to_string()));
+        self.stop = source.stop().to_vec();
+    }
+}
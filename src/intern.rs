@@ -0,0 +1,150 @@
+//! Deduplicates lexeme strings in a config so repeated markers share one allocation.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{Config, Lexeme, OrderedLexemes, Thinking, ToolCall, ToolCalls};
+
+#[derive(Default)]
+pub(crate) struct Interner {
+    strings: HashSet<Arc<str>>,
+    /// The text [`Lexeme::TokenId`]s are replaced with.
+    token_ids: BTreeMap<u32, Arc<str>>,
+}
+
+impl Interner {
+    fn intern(&mut self, s: &mut Arc<str>) {
+        match self.strings.get(&**s) {
+            Some(interned) => *s = interned.clone(),
+            None => {
+                self.strings.insert(s.clone());
+            }
+        }
+    }
+}
+
+pub(crate) trait Intern {
+    fn intern(&mut self, interner: &mut Interner);
+}
+
+impl<T: Intern> Intern for Option<T> {
+    fn intern(&mut self, interner: &mut Interner) {
+        if let Some(inner) = self {
+            inner.intern(interner);
+        }
+    }
+}
+
+impl Intern for Lexeme {
+    fn intern(&mut self, interner: &mut Interner) {
+        match self {
+            Lexeme::Text(s)
+            | Lexeme::Token(s)
+            | Lexeme::Regex { pattern: s }
+            | Lexeme::Whitespace { whitespace: s } => interner.intern(s),
+            Lexeme::TokenId(id) => {
+                if let Some(token) = interner.token_ids.get(id).cloned() {
+                    *self = Lexeme::Token(token);
+                }
+            }
+            Lexeme::Choice { choices } => {
+                for choice in choices {
+                    choice.intern(interner);
+                }
+            }
+            Lexeme::Optional { optional } => optional.intern(interner),
+            Lexeme::JsonSchema(_) => {}
+        }
+    }
+}
+
+impl Intern for OrderedLexemes {
+    fn intern(&mut self, interner: &mut Interner) {
+        let OrderedLexemes(lexemes) = self;
+
+        for lexeme in lexemes {
+            lexeme.intern(interner);
+        }
+    }
+}
+
+impl Intern for Thinking {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.prefix.intern(interner);
+        self.suffix.intern(interner);
+    }
+}
+
+impl Intern for ToolCall {
+    fn intern(&mut self, interner: &mut Interner) {
+        match self {
+            ToolCall::JsonObject { .. }
+            | ToolCall::JsonArray { .. }
+            | ToolCall::External { .. } => {}
+            ToolCall::NamedParameters {
+                prefix,
+                id,
+                delimiter,
+                suffix,
+                ..
+            } => {
+                prefix.intern(interner);
+                id.intern(interner);
+                delimiter.intern(interner);
+                suffix.intern(interner);
+            }
+        }
+    }
+}
+
+impl Intern for ToolCalls {
+    fn intern(&mut self, interner: &mut Interner) {
+        match self {
+            ToolCalls::ToolCall { tool_call } => tool_call.intern(interner),
+            ToolCalls::ToolCallsSection {
+                prefix,
+                tool_call,
+                suffix,
+                ..
+            } => {
+                prefix.intern(interner);
+                tool_call.intern(interner);
+                suffix.intern(interner);
+            }
+        }
+    }
+}
+
+impl<T> Config<T> {
+    pub(crate) fn interned(self) -> Self {
+        self.interned_with(Interner::default())
+    }
+
+    /// This config with its [`Lexeme::TokenId`]s replaced by the tokens in
+    /// `token_ids`. IDs it doesn't have are left as they are.
+    pub fn with_token_ids(self, token_ids: &BTreeMap<u32, String>) -> Self {
+        self.interned_with(Interner {
+            token_ids: token_ids
+                .iter()
+                .map(|(id, token)| (*id, Arc::from(token.as_str())))
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    fn interned_with(mut self, mut interner: Interner) -> Self {
+        if let Config::Components {
+            thinking,
+            tool_calls,
+            ..
+        } = &mut self
+        {
+            thinking.intern(&mut interner);
+            tool_calls.intern(&mut interner);
+        }
+
+        self
+    }
+}
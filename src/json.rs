@@ -1,227 +1,11 @@
-use std::fmt::{self, Display};
+use crate::parse::ConsumeResult;
+use std::fmt::{self, Write};
 
-use itertools::{Either, Itertools};
-use serde::Serialize;
-use serde_json::Value;
+use serde_json::{Number, Value};
 
-use crate::parse::{ConsumeResult, Consumer};
+pub use crate::render::json::JsonFormatter;
 
-pub struct JsonFormatter<'a> {
-    pub indent_width: Option<usize>,
-    pub key_separator: &'a str,
-    pub item_separator: &'a str,
-    pub sort_keys: bool,
-    pub ensure_ascii: bool,
-    pub escape_solidus: bool,
-}
-
-impl<'a> Default for JsonFormatter<'a> {
-    fn default() -> Self {
-        Self {
-            indent_width: None,
-            key_separator: ": ",
-            item_separator: ", ",
-            sort_keys: false,
-            ensure_ascii: false,
-            escape_solidus: false,
-        }
-    }
-}
-
-impl<'a> JsonFormatter<'a> {
-    pub fn pretty(indent_width: usize) -> Self {
-        Self {
-            indent_width: Some(indent_width),
-            item_separator: ",",
-            ..Default::default()
-        }
-    }
-
-    pub fn compact() -> Self {
-        Self {
-            key_separator: ":",
-            item_separator: ",",
-            ..Default::default()
-        }
-    }
-
-    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<String, serde_json::Error> {
-        let json_value = serde_json::to_value(value)?;
-
-        Ok(JsonFormatterState {
-            value: &json_value,
-            format: self,
-            depth: 0,
-        }
-        .to_string())
-    }
-}
-
-struct JsonFormatterState<'a> {
-    value: &'a Value,
-    format: &'a JsonFormatter<'a>,
-    depth: usize,
-}
-
-struct JsonStringFormatter<'a> {
-    value: &'a str,
-    format: &'a JsonFormatter<'a>,
-}
-
-impl<'a> Display for JsonStringFormatter<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "\"")?;
-
-        for c in self.value.chars() {
-            match c {
-                '"' => write!(f, "\\\"")?,
-                '\\' => write!(f, "\\\\")?,
-                '/' if self.format.escape_solidus => write!(f, "\\/")?,
-                '\u{0008}' => write!(f, "\\b")?,
-                '\u{000C}' => write!(f, "\\f")?,
-                '\n' => write!(f, "\\n")?,
-                '\r' => write!(f, "\\r")?,
-                '\t' => write!(f, "\\t")?,
-                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
-                c if !c.is_ascii() && self.format.ensure_ascii => {
-                    let mut buf = [0u16; 2];
-                    for codepoint in c.encode_utf16(&mut buf) {
-                        write!(f, "\\u{codepoint:04x}")?;
-                    }
-                }
-                _ => write!(f, "{c}")?,
-            }
-        }
-
-        write!(f, "\"")?;
-
-        Ok(())
-    }
-}
-
-impl<'a> Display for JsonFormatterState<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.value {
-            Value::String(s) => JsonStringFormatter {
-                value: s,
-                format: self.format,
-            }
-            .fmt(f),
-            Value::Object(entries) => {
-                if entries.is_empty() {
-                    write!(f, "{{}}")?;
-
-                    return Ok(());
-                }
-
-                let Whitespace {
-                    newline,
-                    inner,
-                    outer,
-                } = self.whitespace();
-
-                write!(
-                    f,
-                    "{{{}{newline}{outer}}}",
-                    if self.format.sort_keys {
-                        Either::Left(entries.iter().sorted_by_key(|(key, _)| key.as_str()))
-                    } else {
-                        Either::Right(entries.iter())
-                    }
-                    .format_with(
-                        self.format.item_separator,
-                        |(key, value), f| {
-                            let key_formatter = JsonStringFormatter {
-                                value: key,
-                                format: self.format,
-                            };
-                            let value_formatter = JsonFormatterState {
-                                value,
-                                format: self.format,
-                                depth: self.depth + 1,
-                            };
-
-                            f(&format_args!(
-                                "{newline}{inner}{key_formatter}{}{value_formatter}",
-                                self.format.key_separator
-                            ))
-                        }
-                    )
-                )
-            }
-            Value::Array(elements) => {
-                if elements.is_empty() {
-                    write!(f, "[]")?;
-
-                    return Ok(());
-                }
-
-                let Whitespace {
-                    newline,
-                    inner,
-                    outer,
-                } = self.whitespace();
-
-                write!(
-                    f,
-                    "[{}{newline}{outer}]",
-                    elements
-                        .iter()
-                        .format_with(self.format.item_separator, |value, f| {
-                            let element_formatter = JsonFormatterState {
-                                value,
-                                format: self.format,
-                                depth: self.depth + 1,
-                            };
-
-                            f(&format_args!("{newline}{inner}{element_formatter}"))
-                        }),
-                )
-            }
-            _ => self.value.fmt(f),
-        }
-    }
-}
-
-struct Whitespace<T: Display> {
-    newline: &'static str,
-    inner: T,
-    outer: T,
-}
-
-impl<'a> JsonFormatterState<'a> {
-    fn whitespace(&self) -> Whitespace<impl Display> {
-        struct Padding(usize);
-
-        impl Display for Padding {
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                write!(f, "{:width$}", "", width = self.0)
-            }
-        }
-
-        if let Some(indent_width) = self.format.indent_width {
-            Whitespace {
-                newline: "\n",
-                inner: Padding(indent_width * (self.depth + 1)),
-                outer: Padding(indent_width * self.depth),
-            }
-        } else {
-            Whitespace {
-                newline: "",
-                inner: Padding(0),
-                outer: Padding(0),
-            }
-        }
-    }
-}
-
-pub fn partial_json_consumer() -> Consumer {
-    let mut state = PartialJson::default();
-
-    Consumer(Box::new(move |c| state.consume_char(c)))
-}
-
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub enum PartialJson {
     #[default]
     Start,
@@ -244,6 +28,7 @@ pub enum PartialJson {
     },
 }
 
+#[derive(Clone)]
 pub enum ObjectState {
     Opened,
     Key(JsonString),
@@ -253,6 +38,7 @@ pub enum ObjectState {
     Closed,
 }
 
+#[derive(Clone)]
 pub enum ArrayState {
     Opened,
     Element(Box<PartialJson>),
@@ -260,11 +46,13 @@ pub enum ArrayState {
     Closed,
 }
 
+#[derive(Clone)]
 pub struct JsonString {
     buffer: String,
     state: StringState,
 }
 
+#[derive(Clone)]
 pub enum StringState {
     Start,
     Opened,
@@ -273,6 +61,7 @@ pub enum StringState {
     Closed,
 }
 
+#[derive(Clone)]
 pub enum NumberState {
     OpenedPositive,
     OpenedZero,
@@ -544,6 +333,304 @@ impl PartialJson {
     }
 }
 
+impl PartialJson {
+    /// Feeds a whole chunk, copying runs of plain string characters in bulk
+    /// instead of pushing them one at a time. Stops at the first character
+    /// that is not consumed and returns its byte offset alongside the result.
+    pub fn consume_str(&mut self, chunk: &str) -> Option<(usize, ConsumeResult)> {
+        let mut rest = chunk;
+
+        while let Some(c) = rest.chars().next() {
+            if let Some(json_string) = self.open_string() {
+                let run = rest
+                    .find(|c: char| matches!(c, '"' | '\\') || c.is_control())
+                    .unwrap_or(rest.len());
+
+                if run > 0 {
+                    json_string.buffer.push_str(&rest[..run]);
+                    rest = &rest[run..];
+                    continue;
+                }
+            }
+
+            match self.consume_char(c) {
+                ConsumeResult::Consumed | ConsumeResult::Omitted => rest = &rest[c.len_utf8()..],
+                consume_result => return Some((chunk.len() - rest.len(), consume_result)),
+            }
+        }
+
+        None
+    }
+
+    /// The innermost string currently accepting raw characters, if any.
+    fn open_string(&mut self) -> Option<&mut JsonString> {
+        match self {
+            PartialJson::String(json_string)
+            | PartialJson::Object {
+                state: ObjectState::Key(json_string),
+                ..
+            } => matches!(json_string.state, StringState::Opened).then_some(json_string),
+            PartialJson::Object {
+                state: ObjectState::Value(_, value),
+                ..
+            } => value.open_string(),
+            PartialJson::Array {
+                state: ArrayState::Element(element),
+                ..
+            } => element.open_string(),
+            _ => None,
+        }
+    }
+}
+
+impl PartialJson {
+    /// Compact JSON for the state so far, with open strings, arrays and objects
+    /// closed so every snapshot parses. Entries whose value hasn't started are
+    /// left out, as are dangling number signs, dots and exponents. `None` until
+    /// the value itself has started.
+    pub fn to_completed_string(&self) -> Option<String> {
+        let mut out = String::new();
+        self.write_completed(&mut out, &JsonFormatter::compact())
+            .then_some(out)
+    }
+
+    fn write_completed(&self, out: &mut String, format: &JsonFormatter) -> bool {
+        match self {
+            PartialJson::Start => return false,
+            PartialJson::Object { entries, state } => {
+                let in_flight = match state {
+                    ObjectState::Value(key, value) => Some((key, &**value)),
+                    _ => None,
+                };
+
+                out.push('{');
+
+                let mut first = true;
+                for (key, value) in entries
+                    .iter()
+                    .map(|(key, value)| (key, value))
+                    .chain(in_flight)
+                {
+                    let rollback = out.len();
+
+                    if !first {
+                        out.push(',');
+                    }
+                    let _ = write!(out, "{}:", format.display_str(key));
+
+                    if value.write_completed(out, format) {
+                        first = false;
+                    } else {
+                        out.truncate(rollback);
+                    }
+                }
+
+                out.push('}');
+            }
+            PartialJson::Array { elements, state } => {
+                let in_flight = match state {
+                    ArrayState::Element(element) => Some(&**element),
+                    _ => None,
+                };
+
+                out.push('[');
+
+                let mut first = true;
+                for element in elements.iter().chain(in_flight) {
+                    let rollback = out.len();
+
+                    if !first {
+                        out.push(',');
+                    }
+
+                    if element.write_completed(out, format) {
+                        first = false;
+                    } else {
+                        out.truncate(rollback);
+                    }
+                }
+
+                out.push(']');
+            }
+            PartialJson::String(JsonString { buffer, .. }) => {
+                let _ = write!(out, "{}", format.display_str(buffer));
+            }
+            PartialJson::Number { buffer, .. } => match completed_number(buffer) {
+                Some(number) => out.push_str(number),
+                None => return false,
+            },
+            PartialJson::Literal { literal, .. } => out.push_str(literal),
+        }
+
+        true
+    }
+}
+
+/// Writes the same closed snapshot as [`PartialJson::to_completed_string`],
+/// or nothing before the value has started.
+impl fmt::Display for PartialJson {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_completed_string() {
+            Some(snapshot) => f.write_str(&snapshot),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PartialJson {
+    /// The state so far as a `Value`, closed the same way as
+    /// [`PartialJson::to_completed_string`]. Numbers are parsed from their raw
+    /// text, so with the `arbitrary_precision` feature big integers such as
+    /// 64-bit IDs come through exactly rather than being rounded to `f64`.
+    pub fn to_value(&self) -> Option<Value> {
+        match self {
+            PartialJson::Start => None,
+            PartialJson::Object { entries, state } => {
+                let in_flight = match state {
+                    ObjectState::Value(key, value) => Some((key, &**value)),
+                    _ => None,
+                };
+
+                Some(Value::Object(
+                    entries
+                        .iter()
+                        .map(|(key, value)| (key, value))
+                        .chain(in_flight)
+                        .filter_map(|(key, value)| Some((key.clone(), value.to_value()?)))
+                        .collect(),
+                ))
+            }
+            PartialJson::Array { elements, state } => {
+                let in_flight = match state {
+                    ArrayState::Element(element) => Some(&**element),
+                    _ => None,
+                };
+
+                Some(Value::Array(
+                    elements
+                        .iter()
+                        .chain(in_flight)
+                        .filter_map(PartialJson::to_value)
+                        .collect(),
+                ))
+            }
+            PartialJson::String(JsonString { buffer, .. }) => Some(Value::String(buffer.clone())),
+            PartialJson::Number { buffer, .. } => completed_number(buffer)?
+                .parse::<Number>()
+                .ok()
+                .map(Value::Number),
+            PartialJson::Literal { literal, .. } => Some(match *literal {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::Null,
+            }),
+        }
+    }
+}
+
+/// A number buffer without any dangling sign, dot or exponent marker.
+fn completed_number(buffer: &str) -> Option<&str> {
+    let number = buffer.trim_end_matches(['.', 'e', 'E', '+', '-']);
+    (!number.is_empty()).then_some(number)
+}
+
+/// A borrowed view of a value inside an in-flight [`PartialJson`], which may
+/// still be growing.
+#[derive(Clone, Copy)]
+pub struct PartialView<'a>(&'a PartialJson);
+
+impl PartialJson {
+    /// Looks up an RFC 6901 JSON pointer such as `/arguments/query` in the
+    /// state so far, including the entry or element currently being streamed.
+    pub fn get(&self, pointer: &str) -> Option<PartialView<'_>> {
+        PartialView(self).get(pointer)
+    }
+}
+
+impl<'a> PartialView<'a> {
+    pub fn get(self, pointer: &str) -> Option<PartialView<'a>> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+
+        pointer
+            .strip_prefix('/')?
+            .split('/')
+            .try_fold(self, |PartialView(value), token| {
+                let token = token.replace("~1", "/").replace("~0", "~");
+
+                match value {
+                    PartialJson::Object { entries, state } => {
+                        let in_flight = match state {
+                            ObjectState::Value(key, value) if *key == token => Some(&**value),
+                            _ => None,
+                        };
+
+                        in_flight
+                            .or_else(|| {
+                                entries
+                                    .iter()
+                                    .rev()
+                                    .find(|(key, _)| *key == token)
+                                    .map(|(_, value)| value)
+                            })
+                            .map(PartialView)
+                    }
+                    PartialJson::Array { elements, state } => {
+                        if token.len() > 1 && token.starts_with('0') {
+                            return None;
+                        }
+
+                        let index = token.parse::<usize>().ok()?;
+
+                        match (elements.get(index), state) {
+                            (Some(element), _) => Some(PartialView(element)),
+                            (None, ArrayState::Element(element)) if index == elements.len() => {
+                                Some(PartialView(element))
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            })
+    }
+
+    /// The text received so far for a string (unescaped), number or literal.
+    pub fn as_str(self) -> Option<&'a str> {
+        match self.0 {
+            PartialJson::String(JsonString { buffer, .. })
+            | PartialJson::Number { buffer, .. }
+            | PartialJson::Literal { buffer, .. } => Some(buffer),
+            _ => None,
+        }
+    }
+
+    /// Compact JSON for the value so far, closed the same way as
+    /// [`PartialJson::to_completed_string`].
+    pub fn to_completed_string(self) -> Option<String> {
+        self.0.to_completed_string()
+    }
+
+    /// Whether the value would be valid JSON if the stream ended here.
+    pub fn is_complete(self) -> bool {
+        match self.0 {
+            PartialJson::Start => false,
+            PartialJson::Object { state, .. } => matches!(state, ObjectState::Closed),
+            PartialJson::Array { state, .. } => matches!(state, ArrayState::Closed),
+            PartialJson::String(JsonString { state, .. }) => matches!(state, StringState::Closed),
+            PartialJson::Number { state, .. } => matches!(
+                state,
+                NumberState::OpenedPositive
+                    | NumberState::OpenedZero
+                    | NumberState::Decimal
+                    | NumberState::Exponent
+            ),
+            PartialJson::Literal { buffer, literal } => buffer.len() == literal.len(),
+        }
+    }
+}
+
 impl JsonString {
     pub fn consume_char(&mut self, c: char) -> ConsumeResult {
         match &mut self.state {
@@ -598,6 +685,7 @@ impl JsonString {
                         {
                             if let Some(unicode_char) = char::from_u32(code_point) {
                                 self.buffer.push(unicode_char);
+                                self.state = StringState::Opened;
                                 return ConsumeResult::Consumed;
                             } else {
                                 return ConsumeResult::Rejected(c, "a valid unicode code point");
@@ -1,38 +1,107 @@
-use std::{collections::HashSet, fmt::Display};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
 use hf_hub::CacheRepo;
+use moka::sync::Cache;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{configs::kimik2::kimi_k2, render::template::ChatTemplate};
+use crate::{
+    configs::registry,
+    hooks::Hooks,
+    render::{
+        ReasoningEffort,
+        json::JsonFormatter,
+        template::{ChatTemplate, ModelConfig, TokenizerConfig},
+    },
+};
+
+/// Evaluates `$body` inside a debug span named `$name`, logging how long it
+/// took, when the `tracing` feature is enabled. Otherwise this is just `$body`.
+macro_rules! traced {
+    ($name:literal $(, $field:ident = $value:expr)* ; $body:expr) => {{
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($name $(, $field = $value)*).entered();
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+
+        let result = $body;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(elapsed_us = start.elapsed().as_micros() as u64, "finished");
+
+        result
+    }};
+}
 
+pub mod builder;
+#[cfg(feature = "json-schema")]
+pub mod config_schema;
 pub mod configs;
+pub mod format;
+pub mod gguf;
+pub mod hooks;
+pub mod hub;
+pub mod integrations;
+mod intern;
 pub mod json;
+pub mod merge;
+pub mod migrate;
 pub mod parse;
 pub mod render;
 pub mod schema;
+#[cfg(feature = "test-utils")]
+pub mod testing;
+pub mod validate;
 
 pub static ACQUIESCE_CONFIG: &str = "acquiesce.json";
 
 #[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum Version {
     V1,
 }
 
 #[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct AcquiesceConfig {
     version: Version,
+    /// A preset this config is merged over, making `config` a partial one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extends: Option<String>,
     config: AcquiesceRepr,
 }
 
+/// Serializes like [`AcquiesceConfig`] without taking ownership of the config.
+#[derive(Serialize)]
+pub struct AcquiesceConfigRef<'a> {
+    version: Version,
+    config: &'a AcquiesceRepr,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum Arguments {
     JsonObject,
+    /// A tagged block per argument, like Qwen3-Coder's
+    /// `<parameter=city>\nParis\n</parameter>`. Blocks follow the order of the
+    /// schema's `properties`. String values are written raw and can't contain
+    /// `<`; anything else is JSON.
+    KeyValueTags {
+        prefix: OrderedLexemes,
+        delimiter: OrderedLexemes,
+        suffix: OrderedLexemes,
+    },
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum ToolCall {
@@ -46,13 +115,21 @@ pub enum ToolCall {
     },
     NamedParameters {
         prefix: Option<OrderedLexemes>,
+        /// The call's ID, written right after the name, like Kimi K2's `:0` in
+        /// `functions.get_weather:0`. The parser reports it separately from the
+        /// arguments.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<OrderedLexemes>,
         delimiter: Option<OrderedLexemes>,
         arguments: Arguments,
         suffix: Option<OrderedLexemes>,
     },
+    /// A format registered with [`format::register_tool_call_format`].
+    External { name: String },
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum ToolCalls {
@@ -63,70 +140,301 @@ pub enum ToolCalls {
         prefix: OrderedLexemes,
         tool_call: ToolCall,
         suffix: Option<OrderedLexemes>,
+        /// Whether each call gets a section of its own, like Qwen3's
+        /// `<tool_call>` blocks, rather than one section holding them all.
+        /// Newlines can separate the sections of parallel calls.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        per_call: bool,
     },
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 pub struct Thinking {
     prefix: OrderedLexemes,
     suffix: OrderedLexemes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    budget: Option<ThinkingBudget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    toggle: Option<ThinkingToggle>,
+    /// Whether the model thinks when neither the request nor the toggle says.
+    /// Defaults to [`ThinkingMode::Forced`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<ThinkingMode>,
+    /// Whether the model can think again after each round of tool calls,
+    /// rather than only once at the start.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    interleaved: bool,
 }
 
+/// Whether the grammar makes the model think before answering. The template
+/// sees it as `enable_thinking`, like Qwen3's and GLM-4.5's do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ThinkingMode {
+    /// The output starts directly at content or tool calls.
+    Disabled,
+    /// The model can think or answer directly.
+    Optional,
+    /// The output starts with reasoning.
+    #[default]
+    Forced,
+}
+
+/// A phrase in the system prompt that turns thinking on or off, for models
+/// like Nemotron that only think when asked to. The last system or developer
+/// message that contains either phrase decides. `off` is checked first.
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+pub struct ThinkingToggle {
+    pub on: String,
+    pub off: String,
+    /// Whether the model thinks when no message contains either phrase.
+    pub default: bool,
+}
+
+/// The most tokens the grammar allows between the thinking markers at each
+/// reasoning effort. Efforts without a budget are left unbounded.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct ThinkingBudget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub medium: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub high: Option<usize>,
+}
+
+impl ThinkingBudget {
+    pub fn max_tokens(&self, reasoning_effort: ReasoningEffort) -> Option<usize> {
+        match reasoning_effort {
+            ReasoningEffort::Low => self.low,
+            ReasoningEffort::Medium => self.medium,
+            ReasoningEffort::High => self.high,
+        }
+    }
+}
+
+/// A hard cap on the thinking section of one request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ThinkingLimit {
+    /// Counted by the engine, which only Lark grammars can ask for. GBNF and
+    /// EBNF grammars leave the thinking unbounded and the render warns.
+    Tokens(usize),
+    /// Spelled out in the grammar's rules, so every syntax enforces it. GBNF
+    /// and EBNF engines unroll the repetition into a rule per character, so
+    /// there it can be at most [`ThinkingLimit::MAX_GBNF_CHARACTERS`].
+    Characters(usize),
+}
+
+impl ThinkingLimit {
+    /// The largest character limit a GBNF or EBNF grammar is rendered with.
+    /// llama.cpp builds a rule for each repeated character, and past a few
+    /// thousand that costs more than the limit saves.
+    pub const MAX_GBNF_CHARACTERS: usize = 4096;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum HarmonyBuiltinTool {
+    Browser,
+    Python,
+}
+
+/// Where a Harmony message goes: reasoning, tool calls and their preambles, or
+/// the answer.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum HarmonyChannel {
+    Analysis,
+    Commentary,
+    Final,
+}
+
+/// What goes into the Harmony system message besides the conversation itself.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct HarmonyOptions {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub builtin_tools: Vec<HarmonyBuiltinTool>,
+    /// Replaces the default "You are ChatGPT..." identity line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub knowledge_cutoff: Option<String>,
+    /// The channels the model may write to, all of them by default. Tools
+    /// can't be called without `commentary`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<Vec<HarmonyChannel>>,
+    #[serde(skip)]
+    pub(crate) hooks: Hooks,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
+// there's one config per model, so its size doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum Config<T> {
     Components {
         chat_template: T,
         thinking: Option<Thinking>,
         tool_calls: Option<ToolCalls>,
+        /// Stop strings to return with every render, on top of the ones
+        /// derived from the template and the rest of the config.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        stop: Vec<String>,
+        /// Message roles the template can't render, renamed to ones it can,
+        /// like `{"tool": "user"}` for templates without a tool role.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        roles: BTreeMap<String, String>,
+        /// Roles beyond the OpenAI ones that the template renders as is, like
+        /// `ipython` or `observation`. Messages with any other role are
+        /// rejected.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        custom_roles: Vec<String>,
     },
-    Harmony,
+    Harmony(HarmonyOptions),
 }
 
 pub type AcquiesceRepr = Config<()>;
 
 pub type Acquiesce = Config<ChatTemplate>;
 
+// servers share one resolved config per model across request handlers
+const _: () = {
+    const fn assert_shareable<T: Clone + Send + Sync>() {}
+    assert_shareable::<Acquiesce>();
+};
+
 impl Display for AcquiesceRepr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let config = AcquiesceConfig {
+        let config = AcquiesceConfigRef {
             version: Version::V1,
-            config: self.clone(),
+            config: self,
         };
 
-        let json_string = serde_json::to_string_pretty(&config).map_err(|_| std::fmt::Error)?;
-
-        write!(f, "{json_string}")
+        write!(
+            f,
+            "{}",
+            JsonFormatter::pretty(2).display_serialized(&config)
+        )
     }
 }
 
 impl Acquiesce {
+    /// The config this was resolved from, which [`AcquiesceRepr::resolve_from_options`]
+    /// turns back into this given the same template and tokens.
+    pub fn to_repr(&self) -> AcquiesceRepr {
+        match self {
+            Config::Components {
+                thinking,
+                tool_calls,
+                stop,
+                roles,
+                custom_roles,
+                ..
+            } => Config::Components {
+                chat_template: (),
+                thinking: thinking.clone(),
+                tool_calls: tool_calls.clone(),
+                stop: stop.clone(),
+                roles: roles.clone(),
+                custom_roles: custom_roles.clone(),
+            },
+            Config::Harmony(options) => Config::Harmony(HarmonyOptions {
+                hooks: Hooks::default(),
+                ..options.clone()
+            }),
+        }
+    }
+
     pub fn from_repo(repo: &CacheRepo) -> Result<Self, InitError> {
         let config_string = std::fs::read_to_string(
             repo.get(ACQUIESCE_CONFIG)
                 .ok_or(InitError::ConfigNotFound(ACQUIESCE_CONFIG))?,
         )?;
 
-        let repr = serde_json::from_str::<AcquiesceConfig>(&config_string)?.config;
+        config_string
+            .parse::<AcquiesceRepr>()?
+            .resolve_from_repo(repo)
+    }
 
-        repr.resolve_from_repo(repo)
+    /// The config resolved for `model_id` at `revision`, shared across the
+    /// process. `loader` only runs when no config is cached for the model or
+    /// the cached one was loaded from a different revision.
+    pub fn get_or_load(
+        model_id: &str,
+        revision: &str,
+        loader: impl FnOnce() -> Result<Self, InitError>,
+    ) -> Result<Self, InitError> {
+        static LOADED: OnceLock<Cache<String, (Arc<str>, Acquiesce)>> = OnceLock::new();
+
+        let loaded = LOADED.get_or_init(|| Cache::new(64));
+        let model_id = model_id.to_string();
+
+        match loaded.get(&model_id) {
+            Some((loaded_revision, acquiesce)) if &*loaded_revision == revision => Ok(acquiesce),
+            _ => {
+                let acquiesce = loader()?;
+                loaded.insert(model_id, (revision.into(), acquiesce.clone()));
+
+                Ok(acquiesce)
+            }
+        }
+    }
+}
+
+impl FromStr for AcquiesceRepr {
+    type Err = InitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(migrate::migrate(s)?.config)
     }
 }
 
 impl AcquiesceRepr {
+    /// Like [`str::parse`], for configs written in YAML.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(s: &str) -> Result<Self, InitError> {
+        Ok(migrate::migrate_document(serde_yaml::from_str(s)?)?.config)
+    }
+
+    /// Like [`str::parse`], for configs written in TOML.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(s: &str) -> Result<Self, InitError> {
+        Ok(migrate::migrate_document(toml::from_str(s)?)?.config)
+    }
+
     pub fn resolve_from_repo(self, repo: &CacheRepo) -> Result<Acquiesce, InitError> {
-        Ok(match self {
+        let token_ids = TokenizerConfig::from_repo(repo)?.added_token_ids();
+
+        Ok(match self.with_token_ids(&token_ids) {
             Config::Components {
                 tool_calls,
                 thinking,
+                stop,
+                roles,
+                custom_roles,
                 ..
             } => Acquiesce::Components {
                 chat_template: ChatTemplate::from_repo(repo)?,
                 thinking,
                 tool_calls,
+                stop,
+                roles,
+                custom_roles,
             },
-            Config::Harmony => Config::Harmony,
+            Config::Harmony(options) => Config::Harmony(options),
         })
     }
 
@@ -136,12 +444,14 @@ impl AcquiesceRepr {
         bos_token: Option<String>,
         eos_token: Option<String>,
         multimodal: bool,
-        add_generation_prompt: bool,
     ) -> Result<Acquiesce, InitError> {
-        Ok(match self {
+        Ok(match self.interned() {
             Config::Components {
                 thinking,
                 tool_calls,
+                stop,
+                roles,
+                custom_roles,
                 ..
             } => Acquiesce::Components {
                 chat_template: ChatTemplate::from_options(
@@ -149,22 +459,49 @@ impl AcquiesceRepr {
                     bos_token,
                     eos_token,
                     multimodal,
-                    add_generation_prompt,
                 )?,
                 thinking,
                 tool_calls,
+                stop,
+                roles,
+                custom_roles,
             },
-            Config::Harmony => Config::Harmony,
+            Config::Harmony(options) => Config::Harmony(options),
         })
     }
 
     pub fn infer_default(model_name: &str) -> Result<Self, InitError> {
-        let model = model_name.trim().to_lowercase();
+        Self::infer(model_name, &[])
+    }
 
-        match model {
-            _ if ["kimi", "k2"].iter().all(|m| model.contains(m)) => Ok(kimi_k2()),
-            _ => Err(InitError::InferFailed),
-        }
+    /// Infers a preset from the model's name, falling back to the
+    /// `architectures` listed in its `config.json`.
+    pub fn infer(model_name: &str, architectures: &[String]) -> Result<Self, InitError> {
+        registry::find_by_name(model_name)
+            .or_else(|| registry::find_by_architecture(architectures))
+            .map(|entry| (entry.preset)())
+            .ok_or(InitError::InferFailed)
+    }
+
+    /// [`AcquiesceRepr::infer`] with the architectures read from `repo`,
+    /// falling back to the markers in its chat template and then to its
+    /// tokenizer's added tokens.
+    pub fn infer_from_repo(model_name: &str, repo: &CacheRepo) -> Result<Self, InitError> {
+        Self::infer(model_name, &ModelConfig::from_repo(repo)?.architectures)
+            .or_else(|_| Self::infer_from_template(ChatTemplate::from_repo(repo)?.source()))
+            .or_else(|_| Self::infer_from_tokens(&TokenizerConfig::from_repo(repo)?.added_tokens()))
+    }
+
+    /// Synthesizes a config from the tool call and thinking markers in a chat
+    /// template, for models nothing else identifies.
+    pub fn infer_from_template(chat_template: &str) -> Result<Self, InitError> {
+        registry::from_template(chat_template).ok_or(InitError::InferFailed)
+    }
+
+    /// Synthesizes a config from the tool call and thinking tokens among a
+    /// tokenizer's added tokens.
+    pub fn infer_from_tokens(added_tokens: &[String]) -> Result<Self, InitError> {
+        registry::from_tokens(added_tokens).ok_or(InitError::InferFailed)
     }
 }
 
@@ -186,6 +523,7 @@ pub fn default_argument_keys() -> DistinctLiterals {
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(from = "DistinctLiteralsRepr", into = "DistinctLiteralsRepr")]
 pub struct DistinctLiterals(HashSet<String>);
 
@@ -202,6 +540,7 @@ impl From<&[&str]> for DistinctLiterals {
 }
 
 #[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(untagged)]
 enum DistinctLiteralsRepr {
     String(String),
@@ -228,16 +567,49 @@ impl From<DistinctLiterals> for DistinctLiteralsRepr {
     }
 }
 
+/// Lexeme strings are shared rather than owned, so grammar emitters and parsers
+/// hold references into the resolved config instead of copies.
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(untagged)]
 pub enum Lexeme {
-    Text(String),
-    Token(String),
-    Regex { pattern: String },
+    Text(Arc<str>),
+    /// A special token, written as `{"token": "..."}` so it doesn't read back
+    /// as text.
+    Token(
+        #[serde(
+            serialize_with = "serialize_token",
+            deserialize_with = "deserialize_token"
+        )]
+        #[cfg_attr(feature = "json-schema", schemars(with = "TokenRepr"))]
+        Arc<str>,
+    ),
+    Regex {
+        pattern: Arc<str>,
+    },
+    /// A special token by its ID, for tokens without a stable string form.
+    /// Becomes a [`Lexeme::Token`] when the config is resolved with its
+    /// tokenizer, see [`AcquiesceRepr::with_token_ids`].
+    TokenId(u32),
+    /// Any one of the alternatives, like a special token or the plain text
+    /// a model falls back to when it doesn't sample it.
+    Choice {
+        choices: Vec<OrderedLexemes>,
+    },
+    /// The lexemes or nothing.
+    Optional {
+        optional: OrderedLexemes,
+    },
+    /// Any run of the given characters, including none, for the newlines and
+    /// spaces models put between markers inconsistently.
+    Whitespace {
+        whitespace: Arc<str>,
+    },
     JsonSchema(serde_json::Value),
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(from = "OrderedLexemesRepr", into = "OrderedLexemesRepr")]
 pub struct OrderedLexemes(Vec<Lexeme>);
 
@@ -254,10 +626,36 @@ impl<T: Into<Lexeme> + Clone> From<&[T]> for OrderedLexemes {
 }
 
 #[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+struct TokenRepr {
+    token: Arc<str>,
+}
+
+fn serialize_token<S: serde::Serializer>(
+    token: &Arc<str>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    TokenRepr {
+        token: token.clone(),
+    }
+    .serialize(serializer)
+}
+
+fn deserialize_token<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Arc<str>, D::Error> {
+    TokenRepr::deserialize(deserializer).map(|TokenRepr { token }| token)
+}
+
+/// Arrays are tried first, since a lone [`Lexeme::JsonSchema`] would
+/// otherwise swallow every array.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
 #[serde(untagged)]
 enum OrderedLexemesRepr {
-    String(Lexeme),
     Array(Vec<Lexeme>),
+    String(Lexeme),
 }
 
 impl From<OrderedLexemesRepr> for OrderedLexemes {
@@ -297,6 +695,30 @@ pub enum InitError {
     #[error("chat template not found")]
     MissingTemplate,
 
+    #[error("failed to download from the hub: {0}")]
+    Hub(#[from] hf_hub::api::sync::ApiError),
+
+    #[cfg(feature = "tokio")]
+    #[error("failed to download from the hub: {0}")]
+    AsyncHub(#[from] hf_hub::api::tokio::ApiError),
+
+    #[error("unsupported config version: {}", .0.as_deref().unwrap_or("missing"))]
+    UnsupportedVersion(Option<String>),
+
+    #[cfg(feature = "yaml")]
+    #[error("invalid YAML config: {0}")]
+    InvalidYaml(#[from] serde_yaml::Error),
+
+    #[cfg(feature = "toml")]
+    #[error("invalid TOML config: {0}")]
+    InvalidToml(#[from] toml::de::Error),
+
+    #[error("unknown preset to extend: {0}")]
+    UnknownPreset(String),
+
+    #[error("invalid GGUF file: {0}")]
+    InvalidGguf(&'static str),
+
     #[error("chat template compilation error: {0}")]
     TemplateCompilation(#[from] minijinja::Error),
 }
@@ -0,0 +1,238 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    process::ExitCode,
+};
+
+use acquiesce::{
+    Acquiesce, AcquiesceRepr,
+    parse::ParseResult,
+    render::{
+        GrammarSyntax, RenderOptions, RenderResult,
+        schema::{ChatMessages, ChatTool, ChatToolChoice},
+        validate_grammar,
+    },
+};
+use hf_hub::{Cache, api::sync::Api};
+use serde::Deserialize;
+
+static USAGE: &str = "usage: acquiesce test --model <repo_id> [--corpus <messages.jsonl>] [--config <acquiesce.json>]";
+
+static DEFAULT_CORPUS: &str = "tests/messages.jsonl";
+
+#[derive(Deserialize)]
+struct TestCase {
+    messages: ChatMessages,
+    tools: Vec<ChatTool>,
+    #[serde(default)]
+    tool_choice: ChatToolChoice,
+    #[serde(default)]
+    completion: Option<String>,
+}
+
+struct TestArgs {
+    model: String,
+    corpus: String,
+    config: Option<String>,
+}
+
+impl TestArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut model = None;
+        let mut corpus = None;
+        let mut config = None;
+
+        while let Some(flag) = args.next() {
+            let slot = match flag.as_str() {
+                "--model" => &mut model,
+                "--corpus" => &mut corpus,
+                "--config" => &mut config,
+                _ => return Err(format!("unknown argument: {flag}")),
+            };
+
+            *slot = Some(args.next().ok_or(format!("missing value for {flag}"))?);
+        }
+
+        Ok(Self {
+            model: model.ok_or("missing --model")?,
+            corpus: corpus.unwrap_or_else(|| DEFAULT_CORPUS.to_string()),
+            config,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+
+    let result = match args.next().as_deref() {
+        Some("test") => TestArgs::parse(args).and_then(|args| run_test(&args)),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(failures) => {
+            eprintln!("{failures} failure(s)");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn load_acquiesce(args: &TestArgs) -> Result<Acquiesce, String> {
+    let repo = Api::new()
+        .map_err(|e| e.to_string())?
+        .model(args.model.clone());
+
+    for file in [
+        "chat_template.jinja",
+        "tokenizer_config.json",
+        "config.json",
+    ] {
+        if let Err(e) = repo.get(file) {
+            eprintln!("warning: failed to fetch {file}: {e}");
+        }
+    }
+
+    let cache = Cache::default().model(args.model.clone());
+
+    let repr = match &args.config {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())?
+            .parse::<AcquiesceRepr>(),
+        None => AcquiesceRepr::infer_from_repo(&args.model, &cache),
+    }
+    .map_err(|e| e.to_string())?;
+
+    for diagnostic in repr.validate() {
+        eprintln!("warning: {diagnostic}");
+    }
+
+    repr.resolve_from_repo(&cache).map_err(|e| e.to_string())
+}
+
+fn run_test(args: &TestArgs) -> Result<usize, String> {
+    let acquiesce = load_acquiesce(args)?;
+
+    let reader = BufReader::new(File::open(&args.corpus).map_err(|e| e.to_string())?);
+    let mut failures = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        println!("=== case {index}");
+
+        let test_case = match serde_json::from_str::<TestCase>(&line) {
+            Ok(test_case) => test_case,
+            Err(e) => {
+                println!("--- invalid case: {e}");
+                failures += 1;
+                continue;
+            }
+        };
+
+        failures += run_case(&acquiesce, test_case);
+    }
+
+    Ok(failures)
+}
+
+fn run_case(acquiesce: &Acquiesce, test_case: TestCase) -> usize {
+    let TestCase {
+        messages,
+        tools,
+        tool_choice,
+        completion,
+    } = test_case;
+
+    let mut failures = 0;
+
+    for (label, grammar_syntax) in [("lark", GrammarSyntax::Lark), ("gbnf", GrammarSyntax::GBNF)] {
+        let print_prompt = matches!(grammar_syntax, GrammarSyntax::Lark);
+
+        match acquiesce.render(
+            &messages,
+            tools.clone(),
+            tool_choice.clone(),
+            &RenderOptions {
+                parallel_tool_calls: true,
+                mixed_content_tool_calls: true,
+                grammar_syntax,
+                ..Default::default()
+            },
+        ) {
+            Ok(RenderResult {
+                prompt,
+                grammar,
+                warnings,
+                stop,
+                ..
+            }) => {
+                if print_prompt {
+                    println!("--- prompt\n{prompt}");
+
+                    for warning in &warnings {
+                        println!("--- warning: {warning}");
+                    }
+
+                    println!("--- stop: {stop:?}");
+                }
+
+                match grammar {
+                    Some(grammar) => match validate_grammar(&grammar, grammar_syntax) {
+                        Ok(()) => println!("--- {label} grammar: ok\n{grammar}"),
+                        Err(e) => {
+                            println!("--- {label} grammar: failed\n{grammar}\n{e}");
+                            failures += 1;
+                        }
+                    },
+                    None => println!("--- {label} grammar: none"),
+                }
+            }
+            Err(e) => {
+                println!("--- {label} render: failed\n{e}");
+                failures += 1;
+            }
+        }
+    }
+
+    if let Some(completion) = completion {
+        match acquiesce.parser() {
+            Some(parser) => {
+                println!("--- completion");
+
+                for result in parser.parse_iter(completion.chars().map(String::from)) {
+                    match result {
+                        ParseResult::Content(content) => println!("content: {content:?}"),
+                        ParseResult::Reasoning(delta) => {
+                            println!("reasoning[{}]: {:?}", delta.index, delta.delta)
+                        }
+                        ParseResult::ToolCall(delta) => {
+                            println!("tool_call[{}]: {:?}", delta.index, delta.delta)
+                        }
+                        ParseResult::ToolCallId(delta) => {
+                            println!("tool_call_id[{}]: {:?}", delta.index, delta.delta)
+                        }
+                        ParseResult::ToolCallName(delta) => {
+                            println!("tool_call_name[{}]: {:?}", delta.index, delta.delta)
+                        }
+                        ParseResult::Rejected(text, expected) => {
+                            println!("rejected: {text:?}, expected {expected}");
+                            failures += 1;
+                        }
+                        ParseResult::Complete => println!("complete"),
+                    }
+                }
+            }
+            None => println!("--- completion: no parser for config"),
+        }
+    }
+
+    failures
+}
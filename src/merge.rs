@@ -0,0 +1,41 @@
+//! Layering partial configs over a base, like a preset, so a fine-tune only
+//! has to spell out what it changes. Overrides are JSON merge patches
+//! (RFC 7396) of the config: objects merge key by key, anything else
+//! replaces, and `null` removes, which disables optional parts like
+//! `tool_calls`.
+
+use serde_json::Value;
+
+use crate::{AcquiesceRepr, InitError};
+
+pub(crate) fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+impl AcquiesceRepr {
+    /// This config with `overrides` merged over it.
+    pub fn merge(&self, overrides: &Value) -> Result<Self, InitError> {
+        let mut config = serde_json::to_value(self)?;
+        merge_patch(&mut config, overrides);
+
+        Ok(serde_json::from_value(config)?)
+    }
+}
@@ -0,0 +1,117 @@
+//! Reading `acquiesce.json` files written for other versions of the format.
+//! Older versions are upgraded one step at a time by [`MIGRATIONS`] before
+//! being deserialized; newer ones are read as the current version, ignoring
+//! the fields it doesn't know. A config with `"extends": "<preset>"` is a
+//! partial one, merged over that preset with [`AcquiesceRepr::merge`].
+
+use serde_json::{Map, Value};
+
+use crate::{AcquiesceRepr, InitError, configs::get_preset, merge::merge_patch};
+
+/// The version configs are written with.
+pub const CURRENT_VERSION: u32 = 1;
+
+/// Upgrades a config, the object under `"config"`, from version `i + 1` to
+/// `i + 2`. Empty until the format first changes.
+static MIGRATIONS: &[fn(&mut Value)] = &[];
+
+/// A config read from any supported version, with what had to change.
+pub struct Migrated {
+    pub config: AcquiesceRepr,
+    /// The version the config was written with.
+    pub version: u32,
+    /// JSON pointers to fields the config didn't set, which were defaulted.
+    pub defaulted: Vec<String>,
+    /// JSON pointers to fields this version doesn't know, which were ignored.
+    pub ignored: Vec<String>,
+}
+
+fn parse_version(version: &Value) -> Option<u32> {
+    version.as_str()?.strip_prefix('v')?.parse().ok()
+}
+
+pub fn migrate(s: &str) -> Result<Migrated, InitError> {
+    migrate_document(serde_json::from_str(s)?)
+}
+
+/// [`migrate`] for a config already parsed, from JSON or any other format.
+pub fn migrate_document(mut document: Map<String, Value>) -> Result<Migrated, InitError> {
+    let version = document
+        .get("version")
+        .ok_or(InitError::UnsupportedVersion(None))?;
+    let version = parse_version(version)
+        .filter(|version| *version >= 1)
+        .ok_or_else(|| InitError::UnsupportedVersion(Some(version.to_string())))?;
+
+    let mut raw = document
+        .remove("config")
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    for migration in MIGRATIONS.iter().skip(version as usize - 1) {
+        migration(&mut raw);
+    }
+
+    if let Some(extends) = document.get("extends") {
+        let base = extends
+            .as_str()
+            .and_then(get_preset)
+            .ok_or_else(|| InitError::UnknownPreset(extends.to_string()))?;
+
+        let mut merged = serde_json::to_value(&base)?;
+        merge_patch(&mut merged, &raw);
+        raw = merged;
+    }
+
+    let config = serde_json::from_value::<AcquiesceRepr>(raw.clone())?;
+
+    let mut defaulted = Vec::new();
+    let mut ignored = Vec::new();
+    diff_keys(
+        &raw,
+        &serde_json::to_value(&config)?,
+        "/config",
+        &mut defaulted,
+        &mut ignored,
+    );
+
+    Ok(Migrated {
+        config,
+        version,
+        defaulted,
+        ignored,
+    })
+}
+
+/// Records the object keys only in `read` as ignored and the ones only in
+/// `written` as defaulted, recursing into what both share.
+fn diff_keys(
+    read: &Value,
+    written: &Value,
+    path: &str,
+    defaulted: &mut Vec<String>,
+    ignored: &mut Vec<String>,
+) {
+    let pointer = |key: &str| format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+
+    match (read, written) {
+        (Value::Object(read), Value::Object(written)) => {
+            for (key, value) in read {
+                match written.get(key) {
+                    Some(written) => diff_keys(value, written, &pointer(key), defaulted, ignored),
+                    None => ignored.push(pointer(key)),
+                }
+            }
+            defaulted.extend(
+                written
+                    .keys()
+                    .filter(|key| !read.contains_key(*key))
+                    .map(|key| pointer(key)),
+            );
+        }
+        (Value::Array(read), Value::Array(written)) if read.len() == written.len() => {
+            for (i, (read, written)) in read.iter().zip(written).enumerate() {
+                diff_keys(read, written, &pointer(&i.to_string()), defaulted, ignored);
+            }
+        }
+        _ => {}
+    }
+}
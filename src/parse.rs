@@ -1,13 +1,22 @@
-use crate::{Acquiesce, Config, ToolCall, ToolCalls};
+use std::cmp::Reverse;
 
-pub(crate) mod literal;
+use crate::{Acquiesce, Config, hooks::Hook};
+
+mod harmony;
+mod tool_calls;
 
 pub struct ToolCallDelta {
     pub index: usize,
     pub delta: String,
 }
 
-pub(crate) enum ConsumeResult {
+/// Part of a thinking block. Each block in a completion has the next `index`.
+pub struct ReasoningDelta {
+    pub index: usize,
+    pub delta: String,
+}
+
+pub enum ConsumeResult {
     Consumed,
     Omitted,
     Unconsumed(char),
@@ -16,13 +25,17 @@ pub(crate) enum ConsumeResult {
 
 pub enum ParseResult {
     Content(String),
+    Reasoning(ReasoningDelta),
     ToolCall(ToolCallDelta),
+    /// Part of a call's ID, for formats that write one. Streamed like the call.
+    ToolCallId(ToolCallDelta),
+    /// The name of a call, for formats that write it outside the call itself.
+    /// Emitted whole before the call's arguments.
+    ToolCallName(ToolCallDelta),
     Rejected(String, &'static str),
     Complete,
 }
 
-pub(crate) struct Consumer(pub Box<dyn FnMut(char) -> ConsumeResult>);
-
 pub(crate) trait DynStatefulParser: Send + Sync {
     fn parse(&mut self, token: String) -> Vec<ParseResult>;
     fn box_clone(&self) -> Box<dyn DynStatefulParser>;
@@ -53,9 +66,150 @@ pub(crate) type StatefulParser = Box<dyn DynStatefulParser>;
 pub struct Parser(pub(crate) StatefulParser);
 
 impl Parser {
+    /// Wraps a function from each streamed token to the results it completes,
+    /// for [`ToolCallFormat`](crate::format::ToolCallFormat) implementations.
+    pub fn new(
+        parse: impl FnMut(String) -> Vec<ParseResult> + Send + Sync + Clone + 'static,
+    ) -> Self {
+        Parser(Box::new(parse))
+    }
+
+    /// Splits the reasoning between any of `prefixes` and any of `suffixes` out
+    /// of the stream before this parser sees it. Only the first thinking block
+    /// is split out unless `interleaved` is set.
+    fn with_reasoning(
+        self,
+        prefixes: Vec<String>,
+        suffixes: Vec<String>,
+        interleaved: bool,
+    ) -> Self {
+        let Parser(mut parser) = self;
+
+        let mut pending = String::new();
+        let mut index = None;
+        let mut blocks = 0;
+
+        Parser(Box::new(move |token: String| {
+            let mut results = Vec::new();
+            let mut emit = |delta: String, index: Option<usize>| match index {
+                _ if delta.is_empty() => {}
+                Some(index) => {
+                    results.push(ParseResult::Reasoning(ReasoningDelta { index, delta }))
+                }
+                None => results.extend(parser.parse(delta)),
+            };
+
+            pending.push_str(&token);
+
+            loop {
+                let markers = match index {
+                    Some(_) => &suffixes,
+                    None if interleaved || blocks == 0 => &prefixes,
+                    None => {
+                        emit(std::mem::take(&mut pending), None);
+                        break;
+                    }
+                };
+
+                // the earliest marker, and the longest of those starting there
+                let found = markers
+                    .iter()
+                    .filter_map(|marker| Some((pending.find(marker.as_str())?, marker.len())))
+                    .min_by_key(|(start, len)| (*start, Reverse(*len)));
+
+                let Some((start, len)) = found else {
+                    let held = held_back(&pending, markers);
+                    emit(pending.drain(..pending.len() - held).collect(), index);
+                    break;
+                };
+
+                let text = pending[..start].to_string();
+                pending.drain(..start + len);
+                emit(text, index);
+
+                index = match index {
+                    Some(_) => None,
+                    None => {
+                        blocks += 1;
+                        Some(blocks - 1)
+                    }
+                };
+            }
+
+            results
+        }))
+    }
+
+    /// Completes the parse once a call past the first `max` starts, rejecting
+    /// anything after it.
+    pub fn with_max_tool_calls(self, max: usize) -> Self {
+        let Parser(mut parser) = self;
+        let mut complete = false;
+
+        Parser(Box::new(move |token: String| {
+            if complete {
+                return vec![ParseResult::Rejected(token, "end of completion")];
+            }
+
+            let mut results = Vec::new();
+            for result in parser.parse(token) {
+                if let ParseResult::ToolCall(ToolCallDelta { index, .. })
+                | ParseResult::ToolCallId(ToolCallDelta { index, .. })
+                | ParseResult::ToolCallName(ToolCallDelta { index, .. }) = &result
+                    && *index >= max
+                {
+                    complete = true;
+                    results.push(ParseResult::Complete);
+                    break;
+                }
+
+                results.push(result);
+            }
+
+            results
+        }))
+    }
+
+    /// Rejects content other than whitespace, for requests whose grammar only
+    /// allows tool calls: a required tool choice without
+    /// [`RenderOptions::mixed_content_tool_calls`](crate::render::RenderOptions::mixed_content_tool_calls).
+    pub fn without_content(self) -> Self {
+        let Parser(mut parser) = self;
+
+        Parser(Box::new(move |token| {
+            parser
+                .parse(token)
+                .into_iter()
+                .map(|result| match result {
+                    ParseResult::Content(content) if !content.trim().is_empty() => {
+                        ParseResult::Rejected(content, "tool call")
+                    }
+                    result => result,
+                })
+                .collect()
+        }))
+    }
+
+    /// Passes every result to `hook` on its way out.
+    fn observed(self, hook: Hook<ParseResult>) -> Self {
+        let Parser(mut parser) = self;
+
+        Parser(Box::new(move |token| {
+            let results = parser.parse(token);
+            results.iter().for_each(|result| hook(result));
+
+            results
+        }))
+    }
+
     pub fn advance(&mut self, token: String) -> impl Iterator<Item = ParseResult> {
         let Parser(parser) = self;
-        parser.parse(token).into_iter()
+
+        let results = parser.parse(token);
+        #[cfg(feature = "tracing")]
+        trace_results(&results);
+
+        results.into_iter()
     }
 
     // pub fn parse_stream(
@@ -70,91 +224,90 @@ impl Parser {
         iter: impl Iterator<Item = String>,
     ) -> impl Iterator<Item = ParseResult> {
         let Parser(mut parser) = self;
-        iter.flat_map(move |token| parser.parse(token))
+        iter.flat_map(move |token| {
+            let results = parser.parse(token);
+            #[cfg(feature = "tracing")]
+            trace_results(&results);
+
+            results
+        })
     }
 }
 
-impl Acquiesce {
-    pub fn parser(&self) -> Option<Parser> {
-        match self {
-            Config::Components { tool_calls, .. } => match tool_calls.as_ref()? {
-                ToolCalls::ToolCall { tool_call } => Some(Parser(tool_call.parser())),
-                ToolCalls::ToolCallsSection {
-                    prefix,
-                    tool_call,
-                    suffix,
-                } => Some(Parser(tool_call.parser())),
-            },
-            Config::Harmony => None,
+/// How many bytes at the end of `pending` to hold back because they could be
+/// the start of one of `markers`.
+pub(crate) fn held_back(pending: &str, markers: &[impl AsRef<str>]) -> usize {
+    markers
+        .iter()
+        .map(AsRef::as_ref)
+        .filter_map(|marker| {
+            (1..marker.len().min(pending.len() + 1))
+                .rev()
+                .filter(|len| pending.is_char_boundary(pending.len() - len))
+                .find(|len| marker.starts_with(&pending[pending.len() - len..]))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(feature = "tracing")]
+fn trace_results(results: &[ParseResult]) {
+    for result in results {
+        match result {
+            ParseResult::Content(content) => tracing::trace!(len = content.len(), "content"),
+            ParseResult::Reasoning(ReasoningDelta { index, delta }) => {
+                tracing::trace!(index, len = delta.len(), "reasoning delta")
+            }
+            ParseResult::ToolCall(ToolCallDelta { index, delta }) => {
+                tracing::trace!(index, len = delta.len(), "tool call delta")
+            }
+            ParseResult::ToolCallId(ToolCallDelta { index, delta }) => {
+                tracing::trace!(index, id = delta, "tool call id delta")
+            }
+            ParseResult::ToolCallName(ToolCallDelta { index, delta }) => {
+                tracing::trace!(index, name = delta, "tool call name")
+            }
+            ParseResult::Rejected(text, expected) => {
+                tracing::debug!(text, expected, "parser rejected input")
+            }
+            ParseResult::Complete => tracing::debug!("parser complete"),
         }
     }
 }
 
-impl ToolCall {
-    fn parser(&self) -> StatefulParser {
-        match self {
-            ToolCall::JsonObject {
-                name_key,
-                argument_key,
-            } => todo!(),
-            ToolCall::JsonArray {
-                name_key,
-                argument_key,
-            } => todo!(),
-            ToolCall::NamedParameters {
-                prefix,
-                delimiter,
-                arguments,
-                suffix,
+impl Acquiesce {
+    pub fn parser(&self) -> Option<Parser> {
+        let parser = match self {
+            Config::Components {
+                thinking,
+                tool_calls,
+                ..
             } => {
-                enum NamedParametersState {
-                    Prefix(String),
-                    Name(String),
-                    Delimiter(String),
-                    Arguments(StatefulParser),
-                    Suffix(String),
-                }
+                let parser = match tool_calls {
+                    Some(tool_calls) => tool_calls.parser()?,
+                    None if thinking.is_some() => {
+                        Parser::new(|token| vec![ParseResult::Content(token)])
+                    }
+                    None => return None,
+                };
 
-                todo!()
-
-                // let arguments_consumer = || match arguments {
-                //     Arguments::JsonObject => partial_json_consumer(),
-                // };
-
-                // let mut state = NamedParametersState::Prefix(String::new());
-
-                // Parser(Box::new(move |c| match state {
-                //     NamedParametersState::Prefix(prefix) => match prefix.consume_char(c) {
-                //         ConsumeResult::Captured(c) => {
-                //             state = NamedParametersState::Name(c.to_string());
-                //         }
-                //         ConsumeResult::Unconsumed(c) => {
-                //             state = NamedParametersState::Prefix(c.to_string());
-                //         }
-                //         ConsumeResult::Omitted => {
-                //             state = NamedParametersState::Prefix(c.to_string());
-                //         }
-                //     },
-                //     NamedParametersState::Name(name) => match name.consume_char(c) {
-                //         ConsumeResult::Captured(c) => {
-                //             state = NamedParametersState::Delimiter(c.to_string());
-                //         }
-                //     },
-                //     NamedParametersState::Delimiter(delimiter) => match delimiter.consume_char(c) {
-                //         ConsumeResult::Captured(c) => {
-                //             state = NamedParametersState::Arguments(arguments_parser());
-                //         }
-                //     },
-                //     NamedParametersState::Arguments(arguments) => {
-                //         match arguments.consume_char(c) {}
-                //     }
-                //     NamedParametersState::Suffix(suffix) => match suffix.consume_char(c) {
-                //         ConsumeResult::Captured(c) => {
-                //             state = NamedParametersState::Suffix(c.to_string());
-                //         }
-                //     },
-                // }))
+                match thinking.as_ref().and_then(|thinking| {
+                    let prefixes = thinking.prefix.literals()?;
+                    let suffixes = thinking.suffix.literals()?;
+                    Some((prefixes, suffixes, thinking.interleaved))
+                }) {
+                    Some((prefixes, suffixes, interleaved)) => {
+                        parser.with_reasoning(prefixes, suffixes, interleaved)
+                    }
+                    None => parser,
+                }
             }
-        }
+            Config::Harmony(options) => options.parser(),
+        };
+
+        Some(match &self.hooks().on_parse_event {
+            Some(on_parse_event) => parser.observed(on_parse_event.clone()),
+            None => parser,
+        })
     }
 }
@@ -0,0 +1,138 @@
+//! Splits gpt-oss output into its Harmony messages: the `analysis` channel is
+//! reasoning, messages addressed to a recipient are tool calls, and everything
+//! else, the `final` answer and preambles in `commentary`, is content.
+
+use crate::{
+    HarmonyOptions,
+    parse::{ParseResult, Parser, ReasoningDelta, ToolCallDelta, held_back},
+};
+
+static MESSAGE: &str = "<|message|>";
+
+/// Ends a message. Only `<|end|>` can be followed by another one.
+static MESSAGE_ENDS: &[&str] = &["<|end|>", "<|call|>", "<|return|>"];
+
+#[derive(Clone, Copy)]
+enum Message {
+    Reasoning(usize),
+    Content,
+    ToolCall(usize),
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Header,
+    Body(Message),
+    Done,
+}
+
+/// The value after `key` in a message header, like the channel after
+/// `<|channel|>` or the recipient after `to=`.
+fn header_field<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let (_, rest) = header.split_once(key)?;
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == '<')
+        .unwrap_or(rest.len());
+
+    Some(&rest[..end])
+}
+
+impl HarmonyOptions {
+    /// Parses a completion that starts after the `<|start|>assistant` the
+    /// generation prompt ends with.
+    pub(crate) fn parser(&self) -> Parser {
+        let mut pending = String::new();
+        let mut state = State::Header;
+        let mut reasoning_blocks = 0;
+        let mut tool_calls = 0;
+
+        Parser::new(move |token: String| {
+            let mut results = Vec::new();
+            pending.push_str(&token);
+
+            loop {
+                match state {
+                    State::Header => {
+                        let Some(start) = pending.find(MESSAGE) else {
+                            break;
+                        };
+
+                        let header = pending[..start].to_string();
+                        pending.drain(..start + MESSAGE.len());
+
+                        let message = match header_field(&header, "to=") {
+                            Some(recipient) => {
+                                let name =
+                                    recipient.strip_prefix("functions.").unwrap_or(recipient);
+                                results.push(ParseResult::ToolCallName(ToolCallDelta {
+                                    index: tool_calls,
+                                    delta: name.to_string(),
+                                }));
+
+                                tool_calls += 1;
+                                Message::ToolCall(tool_calls - 1)
+                            }
+                            None if header_field(&header, "<|channel|>") == Some("analysis") => {
+                                reasoning_blocks += 1;
+                                Message::Reasoning(reasoning_blocks - 1)
+                            }
+                            None => Message::Content,
+                        };
+
+                        state = State::Body(message);
+                    }
+                    State::Body(message) => {
+                        let found = MESSAGE_ENDS
+                            .iter()
+                            .filter_map(|end| Some((pending.find(end)?, *end)))
+                            .min_by_key(|(start, _)| *start);
+
+                        let (delta, end) = match found {
+                            Some((start, end)) => {
+                                let delta = pending[..start].to_string();
+                                pending.drain(..start + end.len());
+                                (delta, Some(end))
+                            }
+                            None => {
+                                let held = held_back(&pending, MESSAGE_ENDS);
+                                (pending.drain(..pending.len() - held).collect(), None)
+                            }
+                        };
+
+                        if !delta.is_empty() {
+                            results.push(match message {
+                                Message::Reasoning(index) => {
+                                    ParseResult::Reasoning(ReasoningDelta { index, delta })
+                                }
+                                Message::Content => ParseResult::Content(delta),
+                                Message::ToolCall(index) => {
+                                    ParseResult::ToolCall(ToolCallDelta { index, delta })
+                                }
+                            });
+                        }
+
+                        match end {
+                            None => break,
+                            Some("<|end|>") => state = State::Header,
+                            Some(_) => {
+                                results.push(ParseResult::Complete);
+                                state = State::Done;
+                            }
+                        }
+                    }
+                    State::Done => {
+                        if !pending.is_empty() {
+                            results.push(ParseResult::Rejected(
+                                std::mem::take(&mut pending),
+                                "end of completion",
+                            ));
+                        }
+                        break;
+                    }
+                }
+            }
+
+            results
+        })
+    }
+}
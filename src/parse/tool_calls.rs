@@ -0,0 +1,769 @@
+//! Splits the tool calls a [`ToolCalls`] config describes out of a completion,
+//! passing the content around them through. Named parameters stream their
+//! arguments as they arrive; a JSON call is reported once its object closes,
+//! since its name can come after its arguments.
+
+use std::sync::Arc;
+
+use regex_automata::{
+    Anchored, Input, MatchKind,
+    dfa::{Automaton, dense::DFA},
+};
+use serde_json::Value;
+
+use crate::{
+    Arguments, Lexeme, OrderedLexemes, ToolCall, ToolCalls,
+    format::tool_call_format,
+    json::PartialJson,
+    parse::{ConsumeResult, ParseResult, Parser, ToolCallDelta},
+};
+
+/// A lexeme compiled for matching streamed text.
+#[derive(Clone)]
+enum Pattern {
+    Literal(Arc<str>),
+    Regex(Arc<DFA<Vec<u32>>>),
+    Whitespace(Arc<str>),
+    Choice(Vec<Vec<Pattern>>),
+    /// Token IDs and schemas, which text is never matched against.
+    Never,
+}
+
+fn compile(OrderedLexemes(lexemes): &OrderedLexemes) -> Vec<Pattern> {
+    lexemes
+        .iter()
+        .map(|lexeme| match lexeme {
+            Lexeme::Text(text) | Lexeme::Token(text) => Pattern::Literal(text.clone()),
+            Lexeme::Regex { pattern } => DFA::builder()
+                .configure(DFA::config().match_kind(MatchKind::All))
+                .build(pattern)
+                .map_or(Pattern::Never, |dfa| Pattern::Regex(Arc::new(dfa))),
+            Lexeme::Whitespace { whitespace } => Pattern::Whitespace(whitespace.clone()),
+            Lexeme::Choice { choices } => Pattern::Choice(choices.iter().map(compile).collect()),
+            Lexeme::Optional { optional } => Pattern::Choice(vec![compile(optional), Vec::new()]),
+            Lexeme::TokenId(_) | Lexeme::JsonSchema(_) => Pattern::Never,
+        })
+        .collect()
+}
+
+/// Where a match of `patterns` at the start of `text` can end, and whether
+/// more text could still match them further.
+fn match_ends(patterns: &[Pattern], text: &str) -> (Vec<usize>, bool) {
+    let Some((first, rest)) = patterns.split_first() else {
+        return (vec![0], false);
+    };
+
+    let (first_ends, mut more) = first.match_ends(text);
+
+    let mut ends = Vec::new();
+    for first_end in first_ends {
+        let (rest_ends, rest_more) = match_ends(rest, &text[first_end..]);
+        ends.extend(rest_ends.into_iter().map(|rest_end| first_end + rest_end));
+        more |= rest_more;
+    }
+
+    (ends, more)
+}
+
+impl Pattern {
+    fn match_ends(&self, text: &str) -> (Vec<usize>, bool) {
+        match self {
+            Pattern::Literal(literal) if text.starts_with(&**literal) => {
+                (vec![literal.len()], false)
+            }
+            Pattern::Literal(literal) => (Vec::new(), literal.starts_with(text)),
+            Pattern::Regex(dfa) => regex_ends(dfa, text),
+            Pattern::Whitespace(whitespace) => {
+                let run = text.find(|c| !whitespace.contains(c)).unwrap_or(text.len());
+                let ends = text[..run]
+                    .char_indices()
+                    .map(|(end, _)| end)
+                    .chain([run])
+                    .collect();
+
+                (ends, run == text.len())
+            }
+            Pattern::Choice(choices) => {
+                choices
+                    .iter()
+                    .fold((Vec::new(), false), |(mut ends, more), choice| {
+                        let (choice_ends, choice_more) = match_ends(choice, text);
+                        ends.extend(choice_ends);
+                        (ends, more || choice_more)
+                    })
+            }
+            Pattern::Never => (Vec::new(), false),
+        }
+    }
+}
+
+/// Where an anchored match of `dfa` at the start of `text` can end, and
+/// whether more text could still match it further.
+fn regex_ends(dfa: &DFA<Vec<u32>>, text: &str) -> (Vec<usize>, bool) {
+    let input = Input::new(text).anchored(Anchored::Yes);
+    let Ok(mut state) = dfa.start_state_forward(&input) else {
+        return (Vec::new(), false);
+    };
+
+    let mut ends = Vec::new();
+    for (position, byte) in text.bytes().enumerate() {
+        state = dfa.next_state(state, byte);
+
+        // a match is only seen one byte after it ends
+        if dfa.is_match_state(state) && text.is_char_boundary(position) {
+            ends.push(position);
+        }
+        if dfa.is_dead_state(state) || dfa.is_quit_state(state) {
+            return (ends, false);
+        }
+    }
+
+    if dfa.is_match_state(dfa.next_eoi_state(state)) {
+        ends.push(text.len());
+    }
+
+    (ends, true)
+}
+
+enum Match {
+    /// The longest match, once more text can't change it.
+    Full(usize),
+    /// The text so far could still start a match.
+    Partial,
+    None,
+}
+
+fn match_start(patterns: &[Pattern], text: &str) -> Match {
+    let (ends, more) = match_ends(patterns, text);
+
+    match ends.into_iter().max() {
+        _ if more => Match::Partial,
+        Some(end) => Match::Full(end),
+        None => Match::None,
+    }
+}
+
+/// The first place in `text` a non-empty match of `patterns` starts, or could
+/// start once more text arrives.
+fn find(patterns: &[Pattern], text: &str) -> Option<(usize, Match)> {
+    text.char_indices()
+        .find_map(|(start, _)| match match_start(patterns, &text[start..]) {
+            Match::Full(0) | Match::None => None,
+            found => Some((start, found)),
+        })
+}
+
+enum Scan {
+    /// The run's length and the length of the match of its end after it.
+    Until(usize, usize),
+    Pending,
+    Invalid,
+}
+
+/// Scans a run of `allowed` characters at the start of `text` up to where
+/// `end` matches.
+fn scan_until(text: &str, end: &[Pattern], allowed: impl Fn(char) -> bool) -> Scan {
+    for (start, c) in text.char_indices() {
+        match match_start(end, &text[start..]) {
+            Match::Full(len) => return Scan::Until(start, len),
+            Match::Partial => return Scan::Pending,
+            Match::None if !allowed(c) => return Scan::Invalid,
+            Match::None => {}
+        }
+    }
+
+    Scan::Pending
+}
+
+/// Whether `c` can be part of a tool name: letters, digits, `_`, `-` and `.`.
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+fn json_whitespace(text: &str) -> usize {
+    text.len() - text.trim_start_matches([' ', '\t', '\n', '\r']).len()
+}
+
+/// A [`ToolCall`] with its lexemes compiled.
+#[derive(Clone)]
+enum Format {
+    Json {
+        name_key: String,
+        argument_key: String,
+        array: bool,
+    },
+    Named {
+        prefix: Option<Vec<Pattern>>,
+        /// What the name runs up to: the ID, the delimiter or the arguments.
+        name_end: Vec<Pattern>,
+        id: Option<Vec<Pattern>>,
+        delimiter: Vec<Pattern>,
+        /// The tags around each parameter, for [`Arguments::KeyValueTags`].
+        tags: Option<Tags>,
+        suffix: Vec<Pattern>,
+    },
+}
+
+#[derive(Clone)]
+struct Tags {
+    prefix: Vec<Pattern>,
+    delimiter: Vec<Pattern>,
+    suffix: Vec<Pattern>,
+}
+
+impl Format {
+    /// `None` for [`ToolCall::External`], which brings its own parser.
+    fn new(tool_call: &ToolCall) -> Option<Self> {
+        Some(match tool_call {
+            ToolCall::JsonObject {
+                name_key,
+                argument_key,
+            } => Format::Json {
+                name_key: name_key.clone(),
+                argument_key: argument_key.clone(),
+                array: false,
+            },
+            ToolCall::JsonArray {
+                name_key,
+                argument_key,
+            } => Format::Json {
+                name_key: name_key.clone(),
+                argument_key: argument_key.clone(),
+                array: true,
+            },
+            ToolCall::NamedParameters {
+                prefix,
+                id,
+                delimiter,
+                arguments,
+                suffix,
+            } => {
+                let id = id.as_ref().map(compile);
+                let delimiter = delimiter.as_ref().map(compile).unwrap_or_default();
+                let suffix = suffix.as_ref().map(compile).unwrap_or_default();
+                let tags = match arguments {
+                    Arguments::JsonObject => None,
+                    Arguments::KeyValueTags {
+                        prefix,
+                        delimiter,
+                        suffix,
+                    } => Some(Tags {
+                        prefix: compile(prefix),
+                        delimiter: compile(delimiter),
+                        suffix: compile(suffix),
+                    }),
+                };
+
+                let name_end = match (&id, &tags) {
+                    (Some(id), _) => id.clone(),
+                    (None, _) if !delimiter.is_empty() => delimiter.clone(),
+                    (None, None) => vec![Pattern::Literal("{".into())],
+                    (None, Some(tags)) => {
+                        vec![Pattern::Choice(vec![tags.prefix.clone(), suffix.clone()])]
+                    }
+                };
+
+                Format::Named {
+                    prefix: prefix.as_ref().map(compile),
+                    name_end,
+                    id,
+                    delimiter,
+                    tags,
+                    suffix,
+                }
+            }
+            ToolCall::External { .. } => return None,
+        })
+    }
+}
+
+#[derive(Clone)]
+enum State {
+    /// Content, until a section or a call opens. `opening` is whether a call
+    /// without a marker can open here, at the start of the completion or
+    /// right after another call.
+    Content {
+        opening: bool,
+    },
+    /// Between the calls of a section, right after one if `after_call`.
+    Section {
+        after_call: bool,
+    },
+    /// A JSON call object.
+    Json(PartialJson),
+    /// Inside a JSON array of calls, right after one if `after_call`.
+    Elements {
+        after_call: bool,
+    },
+    Name,
+    Id,
+    Delimiter,
+    /// JSON arguments, streamed as they arrive.
+    Arguments(PartialJson),
+    /// Between tagged parameters, right after one if `after_parameter`.
+    Parameters {
+        after_parameter: bool,
+    },
+    ParameterName {
+        after_parameter: bool,
+    },
+    /// A tagged parameter's value, with the JSON its key becomes.
+    ParameterValue(String),
+    Suffix,
+}
+
+enum Step {
+    Continue,
+    Wait,
+    Next(State),
+    CallDone,
+    CallsDone,
+    Reject(&'static str),
+}
+
+#[derive(Clone)]
+struct Section {
+    prefix: Vec<Pattern>,
+    suffix: Option<Vec<Pattern>>,
+    per_call: bool,
+}
+
+#[derive(Clone)]
+struct ToolCallsParser {
+    section: Option<Section>,
+    format: Format,
+    state: State,
+    pending: String,
+    /// The calls so far, which is also the index of the one in progress.
+    tool_calls: usize,
+}
+
+impl ToolCallsParser {
+    fn parse(&mut self, token: String) -> Vec<ParseResult> {
+        let mut results = Vec::new();
+        self.pending.push_str(&token);
+
+        while self.step(&mut results) {}
+
+        results
+    }
+
+    fn after_calls(&self) -> State {
+        match self.section {
+            Some(_) => State::Section { after_call: true },
+            None => State::Content { opening: true },
+        }
+    }
+
+    /// Moves through the pending text, returning whether there's more to do
+    /// before the next token.
+    fn step(&mut self, results: &mut Vec<ParseResult>) -> bool {
+        let pending = &mut self.pending;
+        let index = self.tool_calls;
+        let mut emit_content = |content: String| {
+            if !content.is_empty() {
+                results.push(ParseResult::Content(content));
+            }
+        };
+
+        let step = match (&mut self.state, &self.format) {
+            (State::Content { opening }, format) => {
+                let start = match (&self.section, format) {
+                    (Some(section), _) => Some(&section.prefix),
+                    (None, Format::Named { prefix, .. }) => prefix.as_ref(),
+                    (None, Format::Json { .. }) => None,
+                };
+
+                match (start, format) {
+                    (Some(start), _) => match find(start, pending) {
+                        Some((at, found)) => {
+                            emit_content(pending.drain(..at).collect());
+
+                            match found {
+                                Match::Full(len) => {
+                                    pending.drain(..len);
+                                    Step::Next(match self.section {
+                                        Some(_) => State::Section { after_call: false },
+                                        None => State::Name,
+                                    })
+                                }
+                                _ => Step::Wait,
+                            }
+                        }
+                        None => {
+                            emit_content(std::mem::take(pending));
+                            Step::Wait
+                        }
+                    },
+                    // JSON calls without a section have no marker, so they
+                    // can only open where content hasn't started
+                    (None, Format::Json { array, .. }) if *opening => {
+                        emit_content(pending.drain(..json_whitespace(pending)).collect());
+
+                        match pending.chars().next() {
+                            None => Step::Wait,
+                            Some('{') if !array => Step::Next(State::Json(PartialJson::default())),
+                            Some('[') if *array => {
+                                pending.drain(..1);
+                                Step::Next(State::Elements { after_call: false })
+                            }
+                            Some(_) => Step::Next(State::Content { opening: false }),
+                        }
+                    }
+                    (None, _) => {
+                        emit_content(std::mem::take(pending));
+                        Step::Wait
+                    }
+                }
+            }
+            (State::Section { after_call }, format) => {
+                let (suffix, per_call) = match &self.section {
+                    Some(section) => (section.suffix.as_deref(), section.per_call),
+                    None => (None, false),
+                };
+                let end = suffix.map_or(Match::None, |suffix| match_start(suffix, pending));
+
+                match (end, format) {
+                    (Match::Full(len), _) if len > 0 => {
+                        pending.drain(..len);
+                        Step::Next(State::Content { opening: true })
+                    }
+                    (Match::Partial, _) => Step::Wait,
+                    // a section of a call's own ends right after it
+                    _ if per_call && *after_call => match suffix {
+                        Some(_) => Step::Reject("the end of the section"),
+                        None => Step::Next(State::Content { opening: true }),
+                    },
+                    (_, Format::Json { array, .. }) => {
+                        pending.drain(..json_whitespace(pending));
+
+                        match pending.chars().next() {
+                            None => Step::Wait,
+                            Some('{') if !array => Step::Next(State::Json(PartialJson::default())),
+                            Some('[') if *array => {
+                                pending.drain(..1);
+                                Step::Next(State::Elements { after_call: false })
+                            }
+                            Some(_) => Step::Reject("a tool call or the end of the section"),
+                        }
+                    }
+                    (_, Format::Named { prefix, .. }) => {
+                        match match_start(prefix.as_deref().unwrap_or_default(), pending) {
+                            Match::Full(len) => {
+                                pending.drain(..len);
+                                Step::Next(State::Name)
+                            }
+                            Match::Partial => Step::Wait,
+                            Match::None => Step::Reject("a tool call or the end of the section"),
+                        }
+                    }
+                }
+            }
+            (
+                State::Json(json),
+                Format::Json {
+                    name_key,
+                    argument_key,
+                    ..
+                },
+            ) => {
+                let result = json.consume_str(pending);
+                let complete = json.get("").is_some_and(|json| json.is_complete());
+
+                match (result, complete) {
+                    (Some((_, ConsumeResult::Rejected(_, expected))), _) => Step::Reject(expected),
+                    (None, false) => {
+                        pending.clear();
+                        Step::Wait
+                    }
+                    (Some(_), false) => Step::Reject("a JSON object"),
+                    (result, true) => {
+                        pending.drain(..result.map_or(pending.len(), |(offset, _)| offset));
+
+                        match json_call(json, name_key, argument_key) {
+                            Some((name, arguments)) => {
+                                results.push(ParseResult::ToolCallName(ToolCallDelta {
+                                    index,
+                                    delta: name,
+                                }));
+                                results.push(ParseResult::ToolCall(ToolCallDelta {
+                                    index,
+                                    delta: arguments,
+                                }));
+
+                                Step::CallDone
+                            }
+                            None => Step::Reject("a tool call with a name and arguments"),
+                        }
+                    }
+                }
+            }
+            (State::Elements { after_call }, _) => {
+                pending.drain(..json_whitespace(pending));
+
+                match (pending.chars().next(), *after_call) {
+                    (None, _) => Step::Wait,
+                    (Some(']'), _) => {
+                        pending.drain(..1);
+                        Step::CallsDone
+                    }
+                    (Some(','), true) => {
+                        pending.drain(..1);
+                        *after_call = false;
+                        Step::Continue
+                    }
+                    (Some('{'), false) => Step::Next(State::Json(PartialJson::default())),
+                    (Some(_), true) => Step::Reject("a comma or the end of the tool calls"),
+                    (Some(_), false) => Step::Reject("a tool call object"),
+                }
+            }
+            (State::Name, Format::Named { name_end, id, .. }) => {
+                match scan_until(pending, name_end, is_name_char) {
+                    Scan::Until(0, _) | Scan::Invalid => Step::Reject("a tool name"),
+                    Scan::Until(len, _) => {
+                        results.push(ParseResult::ToolCallName(ToolCallDelta {
+                            index,
+                            delta: pending.drain(..len).collect(),
+                        }));
+
+                        Step::Next(match id {
+                            Some(_) => State::Id,
+                            None => State::Delimiter,
+                        })
+                    }
+                    Scan::Pending => Step::Wait,
+                }
+            }
+            (State::Id, Format::Named { id: Some(id), .. }) => match match_start(id, pending) {
+                Match::Full(len) => {
+                    results.push(ParseResult::ToolCallId(ToolCallDelta {
+                        index,
+                        delta: pending.drain(..len).collect(),
+                    }));
+
+                    Step::Next(State::Delimiter)
+                }
+                Match::Partial => Step::Wait,
+                Match::None => Step::Reject("a tool call ID"),
+            },
+            (
+                State::Delimiter,
+                Format::Named {
+                    delimiter, tags, ..
+                },
+            ) => match match_start(delimiter, pending) {
+                Match::Full(len) => {
+                    pending.drain(..len);
+
+                    match tags {
+                        None => Step::Next(State::Arguments(PartialJson::default())),
+                        Some(_) => {
+                            results.push(ParseResult::ToolCall(ToolCallDelta {
+                                index,
+                                delta: "{".to_string(),
+                            }));
+
+                            Step::Next(State::Parameters {
+                                after_parameter: false,
+                            })
+                        }
+                    }
+                }
+                Match::Partial => Step::Wait,
+                Match::None => Step::Reject("a tool call delimiter"),
+            },
+            (State::Arguments(json), _) => {
+                if matches!(json, PartialJson::Start) {
+                    pending.drain(..json_whitespace(pending));
+                }
+
+                match pending.chars().next() {
+                    None => Step::Wait,
+                    Some(c) if c != '{' && matches!(json, PartialJson::Start) => {
+                        Step::Reject("a JSON object")
+                    }
+                    Some(_) => {
+                        let result = json.consume_str(pending);
+                        let complete = json.get("").is_some_and(|json| json.is_complete());
+
+                        match result {
+                            Some((_, ConsumeResult::Rejected(_, expected))) => {
+                                Step::Reject(expected)
+                            }
+                            result => {
+                                let consumed =
+                                    result.as_ref().map_or(pending.len(), |(offset, _)| *offset);
+                                results.push(ParseResult::ToolCall(ToolCallDelta {
+                                    index,
+                                    delta: pending.drain(..consumed).collect(),
+                                }));
+
+                                match (result, complete) {
+                                    (_, true) => Step::Next(State::Suffix),
+                                    (None, false) => Step::Wait,
+                                    (Some(_), false) => Step::Reject("a JSON object"),
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            (
+                State::Parameters { after_parameter },
+                Format::Named {
+                    tags: Some(tags),
+                    suffix,
+                    ..
+                },
+            ) => match match_start(&tags.prefix, pending) {
+                Match::Full(len) => {
+                    pending.drain(..len);
+                    Step::Next(State::ParameterName {
+                        after_parameter: *after_parameter,
+                    })
+                }
+                Match::Partial => Step::Wait,
+                Match::None => match match_start(suffix, pending) {
+                    Match::Full(len) => {
+                        pending.drain(..len);
+                        results.push(ParseResult::ToolCall(ToolCallDelta {
+                            index,
+                            delta: "}".to_string(),
+                        }));
+
+                        Step::CallDone
+                    }
+                    Match::Partial => Step::Wait,
+                    Match::None => Step::Reject("a parameter or the end of the tool call"),
+                },
+            },
+            (
+                State::ParameterName { after_parameter },
+                Format::Named {
+                    tags: Some(tags), ..
+                },
+            ) => match scan_until(pending, &tags.delimiter, |c| !c.is_whitespace()) {
+                Scan::Until(0, _) | Scan::Invalid => Step::Reject("a parameter name"),
+                Scan::Until(len, delimiter_len) => {
+                    let name = pending.drain(..len).collect::<String>();
+                    pending.drain(..delimiter_len);
+
+                    let separator = if *after_parameter { "," } else { "" };
+                    Step::Next(State::ParameterValue(format!(
+                        "{separator}{}:",
+                        Value::String(name)
+                    )))
+                }
+                Scan::Pending => Step::Wait,
+            },
+            (
+                State::ParameterValue(key),
+                Format::Named {
+                    tags: Some(tags), ..
+                },
+            ) => match scan_until(pending, &tags.suffix, |_| true) {
+                Scan::Until(len, suffix_len) => {
+                    let value = pending.drain(..len).collect::<String>();
+                    pending.drain(..suffix_len);
+
+                    results.push(ParseResult::ToolCall(ToolCallDelta {
+                        index,
+                        delta: format!("{key}{}", parameter_value(&value)),
+                    }));
+
+                    Step::Next(State::Parameters {
+                        after_parameter: true,
+                    })
+                }
+                Scan::Pending | Scan::Invalid => Step::Wait,
+            },
+            (State::Suffix, Format::Named { suffix, .. }) => match match_start(suffix, pending) {
+                Match::Full(len) => {
+                    pending.drain(..len);
+                    Step::CallDone
+                }
+                Match::Partial => Step::Wait,
+                Match::None => Step::Reject("the end of the tool call"),
+            },
+            _ => unreachable!("the state belongs to another format"),
+        };
+
+        match step {
+            Step::Continue => {}
+            Step::Wait => return false,
+            Step::Next(state) => self.state = state,
+            Step::CallDone => {
+                self.tool_calls += 1;
+                self.state = match self.format {
+                    Format::Json { array: true, .. } => State::Elements { after_call: true },
+                    _ => self.after_calls(),
+                };
+            }
+            Step::CallsDone => self.state = self.after_calls(),
+            Step::Reject(expected) => {
+                results.push(ParseResult::Rejected(
+                    std::mem::take(&mut self.pending),
+                    expected,
+                ));
+                self.state = State::Content { opening: false };
+            }
+        }
+
+        true
+    }
+}
+
+/// The name and arguments of a closed JSON call object.
+fn json_call(json: &PartialJson, name_key: &str, argument_key: &str) -> Option<(String, String)> {
+    let pointer = |key: &str| format!("/{}", key.replace('~', "~0").replace('/', "~1"));
+
+    let name = json.get(&pointer(name_key))?.as_str()?.to_string();
+    let arguments = json.get(&pointer(argument_key))?.to_completed_string()?;
+
+    Some((name, arguments))
+}
+
+/// A tagged parameter's value as JSON: as written if it already is JSON,
+/// otherwise the string it's written as.
+fn parameter_value(value: &str) -> String {
+    match serde_json::from_str::<Value>(value) {
+        Ok(_) => value.trim().to_string(),
+        Err(_) => Value::String(value.to_string()).to_string(),
+    }
+}
+
+impl ToolCalls {
+    pub(crate) fn parser(&self) -> Option<Parser> {
+        let (section, tool_call) = match self {
+            ToolCalls::ToolCall { tool_call } => (None, tool_call),
+            ToolCalls::ToolCallsSection {
+                prefix,
+                tool_call,
+                suffix,
+                per_call,
+            } => (
+                Some(Section {
+                    prefix: compile(prefix),
+                    suffix: suffix.as_ref().map(compile),
+                    per_call: *per_call,
+                }),
+                tool_call,
+            ),
+        };
+
+        if let ToolCall::External { name } = tool_call {
+            return tool_call_format(name).map(|format| format.parser());
+        }
+        let format = Format::new(tool_call)?;
+
+        let mut parser = ToolCallsParser {
+            section,
+            format,
+            state: State::Content { opening: true },
+            pending: String::new(),
+            tool_calls: 0,
+        };
+
+        Some(Parser::new(move |token| parser.parse(token)))
+    }
+}
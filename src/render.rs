@@ -1,231 +1,1583 @@
 use core::fmt;
-use std::{collections::HashMap, fmt::Display, sync::OnceLock};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
 
-use llguidance::{ParserFactory, api::TopLevelGrammar, toktrie::ApproximateTokEnv};
+use indexmap::IndexMap;
+use itertools::Itertools;
+use llguidance::{
+    ParserFactory,
+    api::{GrammarInit, ParserLimits, TopLevelGrammar},
+    earley::ValidationResult,
+    toktrie::ApproximateTokEnv,
+};
+use moka::sync::Cache;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
-    Acquiesce, Arguments, Config, Error, Lexeme, OrderedLexemes, Thinking, ToolCall, ToolCalls,
+    Acquiesce, Arguments, Config, DEFAULT_ROLES, Error, HarmonyChannel, HarmonyOptions, Lexeme,
+    OrderedLexemes, Thinking, ThinkingLimit, ThinkingMode, ThinkingToggle, ToolCall, ToolCalls,
+    format::tool_call_format,
+    hooks::RenderMeta,
     render::{
-        gbnf::{gbnf_regex, gbnf_string_literal},
-        lark::{lark_json_schema, lark_regex, lark_string_literal, lark_token_literal},
+        gbnf::{gbnf_regex, gbnf_string_literal, gbnf_token_id, gbnf_token_literal},
+        harmony::HARMONY_STOP_SEQUENCES,
+        lark::{
+            lark_json_schema, lark_regex, lark_string_literal, lark_text, lark_text_until,
+            lark_token_id, lark_token_literal,
+        },
         schema::{
-            ChatTool, ChatToolChoice, CustomTool, CustomToolFormat, CustomToolGrammar,
-            CustomToolSyntax, FunctionName, FunctionTool,
+            AllowedTools, AllowedToolsMode, ChatCompletionRequest, ChatJsonSchema,
+            ChatResponseFormat, ChatTool, ChatToolChoice, CustomTool, CustomToolFormat,
+            CustomToolGrammar, CustomToolSyntax, FunctionName, FunctionTool,
         },
-        template::{TemplateChatMessage, TemplateTool},
+        template::{TemplateChatMessage, TemplateTool, final_message_text, with_scratch},
     },
-    schema::{Schema, SchemaCompiler, ArraySchema, ObjectSchema, NumberSchema, StringSchema},
 };
 
 pub(crate) mod gbnf;
+pub(crate) mod harmony;
 pub(crate) mod lark;
+pub(crate) mod structural_tags;
 
+pub mod json;
 pub mod schema;
 pub mod template;
 
+pub use gbnf::validate_gbnf;
+
+#[derive(Clone, Copy, Default)]
 pub enum GrammarSyntax {
+    #[default]
     Lark,
     GBNF,
+    /// The GBNF dialect XGrammar reads, as used by SGLang and vLLM.
+    Ebnf,
+    /// The Lark grammar wrapped in a serialized llguidance `TopLevelGrammar`,
+    /// for callers that hand it to llguidance directly.
+    LLGuidance,
+    /// A vLLM `structural_tag` response format, which only constrains the
+    /// arguments of tool calls.
+    StructuralTags,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "json-schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl Display for ReasoningEffort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReasoningEffort::Low => write!(f, "low"),
+            ReasoningEffort::Medium => write!(f, "medium"),
+            ReasoningEffort::High => write!(f, "high"),
+        }
+    }
+}
+
+/// Per-request settings for [`Acquiesce::render`]. Fields a request leaves
+/// unset can be filled in with `..Default::default()`.
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub parallel_tool_calls: bool,
+    /// Caps how many calls a tool call section can hold when parallel calls
+    /// are allowed, and must be at least one; a tool choice of none allows no
+    /// calls. Parsers stop at the same count with
+    /// [`Parser::with_max_tool_calls`](crate::parse::Parser::with_max_tool_calls),
+    /// which completes once a call past the cap starts, since calls don't
+    /// signal their end.
+    pub max_tool_calls: Option<usize>,
+    /// Allow content before tool calls, and between them when parallel calls
+    /// aren't capped. Otherwise a completion is either content or tool calls,
+    /// and only tool calls when the tool choice requires one.
+    pub mixed_content_tool_calls: bool,
+    pub grammar_syntax: GrammarSyntax,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Overrides the config's [`ThinkingMode`] and any thinking toggle.
+    pub thinking: Option<ThinkingMode>,
+    /// Overrides the config's [`ThinkingBudget`](crate::ThinkingBudget) for
+    /// the reasoning effort.
+    pub thinking_limit: Option<ThinkingLimit>,
+    /// JSON response formats constrain content to a schema in the grammar.
+    pub response_format: ChatResponseFormat,
+    pub add_generation_prompt: bool,
+    /// Leave a trailing assistant message open for the model to carry on, like
+    /// a prefill, instead of starting a new one. The grammar then starts
+    /// mid-message: thinking is over and content can come before tool calls.
+    /// Harmony configs always start a new message.
+    pub continue_final_message: bool,
+    /// Also compute [`RenderResult::prefix`], at the cost of rendering the
+    /// conversation twice.
+    pub prompt_prefix: bool,
+    /// Generate only the model's reasoning, stopping at the thinking suffix.
+    pub reasoning_only: bool,
+    /// Compile the grammar before returning it, failing the render with
+    /// [`RenderError::GrammarCompilation`] if it wouldn't load in an engine.
+    pub validate_grammar: bool,
+    /// Extra chat template variables, like `chat_template_kwargs` in
+    /// OpenAI-compatible servers.
+    pub kwargs: serde_json::Map<String, serde_json::Value>,
+    /// Grounding documents, passed as `documents` to templates with a
+    /// retrieval-augmented prompt layout like Command-R's.
+    pub documents: Vec<serde_json::Value>,
+    /// Overrides a Harmony config's knowledge cutoff, like `2024-06`.
+    pub knowledge_cutoff: Option<String>,
+    /// Overrides a Harmony config's channels.
+    pub harmony_channels: Option<Vec<HarmonyChannel>>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            parallel_tool_calls: true,
+            max_tool_calls: None,
+            mixed_content_tool_calls: false,
+            grammar_syntax: GrammarSyntax::default(),
+            reasoning_effort: None,
+            thinking: None,
+            thinking_limit: None,
+            response_format: ChatResponseFormat::default(),
+            add_generation_prompt: true,
+            continue_final_message: false,
+            prompt_prefix: false,
+            reasoning_only: false,
+            validate_grammar: false,
+            kwargs: serde_json::Map::new(),
+            documents: Vec::new(),
+            knowledge_cutoff: None,
+            harmony_channels: None,
+        }
+    }
 }
 
-pub struct RenderResult {
-    pub prompt: String,
-    pub grammar: Option<String>,
+/// `P` and `G` are `()` and [`LazyGrammar`] for [`Acquiesce::render_to`] and
+/// [`Acquiesce::render_lazy`] respectively.
+pub struct RenderResult<P = String, G = Option<String>> {
+    pub prompt: P,
+    pub grammar: G,
+    pub warnings: Vec<RenderWarning>,
+    /// Strings the engine should stop generation at for this request.
+    pub stop: Vec<String>,
+    /// Set when [`RenderOptions::prompt_prefix`] is.
+    pub prefix: Option<PromptPrefix>,
+    /// What tool calls open with, for engines that only apply the grammar
+    /// once one of these is generated. Empty when a call can start without a
+    /// marker, since a lazy grammar would miss it.
+    pub triggers: Vec<GrammarTrigger>,
     // pub parser: Option<Parser>,
 }
 
+/// A marker that activates a lazy grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrammarTrigger {
+    Text(String),
+    /// A special token, which engines match as a single token.
+    Token(String),
+    TokenId(u32),
+}
+
+/// The start of the prompt that stays the same as the conversation goes on:
+/// the system prompt, tools and every turn before the last user message. For
+/// servers that cache work on shared prompt prefixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptPrefix {
+    /// FNV-1a hash of the prefix, so it is stable across processes and builds.
+    pub key: u64,
+    /// Length of the prefix in bytes.
+    pub len: usize,
+}
+
+/// Something that didn't stop the render but changed or dropped part of the
+/// request, for servers to log or pass on to clients.
+///
+/// Tool names are never rewritten (invalid ones fail with
+/// [`RenderError::ToolValidation`]) and messages are never truncated, so
+/// neither has a warning; fitting a conversation to the context is up to the
+/// caller.
+#[derive(Debug, Clone)]
+pub enum RenderWarning {
+    /// The tool's schema uses a keyword the grammar can't enforce, so
+    /// generated arguments may not satisfy it.
+    UnenforcedSchemaKeyword { tool: String, keyword: String },
+    /// Tools were passed but the chat template never reads `tools`, so the
+    /// model isn't told about them.
+    ToolsIgnoredByTemplate,
+    /// The thinking has a token limit but GBNF and EBNF grammars can't count
+    /// tokens, so the grammar leaves it unbounded.
+    UnenforcedThinkingLimit { max_tokens: usize },
+}
+
+impl Display for RenderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderWarning::UnenforcedSchemaKeyword { tool, keyword } => write!(
+                f,
+                "json schema keyword {keyword} for tool {tool} is not enforced by the grammar"
+            ),
+            RenderWarning::ToolsIgnoredByTemplate => {
+                write!(f, "chat template does not render tools")
+            }
+            RenderWarning::UnenforcedThinkingLimit { max_tokens } => write!(
+                f,
+                "thinking limit of {max_tokens} tokens is not enforced by the grammar"
+            ),
+        }
+    }
+}
+
+type GrammarResult = Result<Option<String>, RenderError>;
+
+/// A grammar that is only generated the first time it is asked for, for
+/// callers that often skip constrained decoding.
+pub struct LazyGrammar(LazyLock<GrammarResult, Box<dyn FnOnce() -> GrammarResult + Send>>);
+
+impl LazyGrammar {
+    pub fn get(&self) -> Result<Option<&str>, &RenderError> {
+        LazyLock::force(&self.0).as_ref().map(Option::as_deref)
+    }
+}
+
+pub type LazyRenderResult = RenderResult<String, LazyGrammar>;
+
+/// Everything grammar generation needs once the prompt has been rendered.
+/// `tool_calls` is `None` when only the response format constrains output.
+struct ToolConstraint<'a> {
+    thinking: Option<Cow<'a, Thinking>>,
+    thinking_mode: ThinkingMode,
+    tool_calls: Option<Cow<'a, ToolCalls>>,
+    tools: Vec<TemplateTool>,
+    tool_choice: ChatToolChoice,
+    response_schema: Option<serde_json::Value>,
+    /// Whether the output continues the prompt's last message.
+    continued: bool,
+}
+
 impl Acquiesce {
-    pub fn render(
+    pub fn render<'a>(
         &self,
-        messages: impl Into<Vec<TemplateChatMessage>>,
+        messages: impl Into<Vec<TemplateChatMessage<'a>>>,
         tools: Vec<ChatTool>,
         tool_choice: ChatToolChoice,
-        parallel_tool_calls: bool,
-        mixed_content_tool_calls: bool,
-        grammar_syntax: GrammarSyntax,
+        options: &RenderOptions,
+    ) -> Result<RenderResult, RenderError> {
+        let start = self.hooks().on_render.is_some().then(Instant::now);
+        let tool_count = tools.len();
+
+        let mut warnings = Vec::new();
+        let stop = self.stop_sequences(&tool_choice, options);
+        let (prompt, (constraint, prefix)) = with_scratch(|out| {
+            self.render_prompt_to(out, messages, tools, tool_choice, options, &mut warnings)
+        })?;
+
+        let triggers = constraint
+            .as_ref()
+            .map(ToolConstraint::triggers)
+            .unwrap_or_default();
+        let grammar = match constraint {
+            Some(constraint) => constraint.render(options)?,
+            None => None,
+        };
+
+        self.hooks().rendered(
+            || RenderMeta {
+                tools: tool_count,
+                prompt_len: Some(prompt.len()),
+                grammar_len: grammar.as_ref().map(String::len),
+                warnings: warnings.len(),
+                elapsed: start.map(|start| start.elapsed()).unwrap_or_default(),
+            },
+            &warnings,
+        );
+
+        Ok(RenderResult {
+            prompt,
+            grammar,
+            warnings,
+            stop,
+            prefix,
+            triggers,
+            // parser: self.parser(),
+        })
+    }
+
+    /// Renders a chat completions request body as sent by a client.
+    pub fn render_request(
+        &self,
+        request: &ChatCompletionRequest,
     ) -> Result<RenderResult, RenderError> {
+        self.render(
+            &request.messages,
+            request.tools.clone().unwrap_or_default(),
+            request.tool_choice.clone().unwrap_or_default(),
+            &request.render_options(),
+        )
+    }
+
+    /// Like [`Acquiesce::render`], but defers grammar generation until
+    /// [`LazyGrammar::get`] is first called. Tools are still validated eagerly.
+    pub fn render_lazy<'a>(
+        &self,
+        messages: impl Into<Vec<TemplateChatMessage<'a>>>,
+        tools: Vec<ChatTool>,
+        tool_choice: ChatToolChoice,
+        options: &RenderOptions,
+    ) -> Result<LazyRenderResult, RenderError> {
+        let start = self.hooks().on_render.is_some().then(Instant::now);
+        let tool_count = tools.len();
+
+        let mut warnings = Vec::new();
+        let stop = self.stop_sequences(&tool_choice, options);
+        let (prompt, (constraint, prefix)) = with_scratch(|out| {
+            self.render_prompt_to(out, messages, tools, tool_choice, options, &mut warnings)
+        })?;
+        let triggers = constraint
+            .as_ref()
+            .map(ToolConstraint::triggers)
+            .unwrap_or_default();
+        let constraint = constraint.map(ToolConstraint::into_owned);
+        let options = options.clone();
+
+        self.hooks().rendered(
+            || RenderMeta {
+                tools: tool_count,
+                prompt_len: Some(prompt.len()),
+                grammar_len: None,
+                warnings: warnings.len(),
+                elapsed: start.map(|start| start.elapsed()).unwrap_or_default(),
+            },
+            &warnings,
+        );
+
+        Ok(LazyRenderResult {
+            prompt,
+            grammar: LazyGrammar(LazyLock::new(Box::new(move || match constraint {
+                Some(constraint) => constraint.render(&options),
+                None => Ok(None),
+            }))),
+            warnings,
+            stop,
+            prefix,
+            triggers,
+        })
+    }
+
+    /// Like [`Acquiesce::render`], but writes the prompt into `out` instead of
+    /// returning it.
+    pub fn render_to<'a>(
+        &self,
+        out: &mut impl fmt::Write,
+        messages: impl Into<Vec<TemplateChatMessage<'a>>>,
+        tools: Vec<ChatTool>,
+        tool_choice: ChatToolChoice,
+        options: &RenderOptions,
+    ) -> Result<RenderResult<()>, RenderError> {
+        let start = self.hooks().on_render.is_some().then(Instant::now);
+        let tool_count = tools.len();
+
+        let mut warnings = Vec::new();
+        let stop = self.stop_sequences(&tool_choice, options);
+
+        let (constraint, prefix) =
+            self.render_prompt_to(out, messages, tools, tool_choice, options, &mut warnings)?;
+
+        let triggers = constraint
+            .as_ref()
+            .map(ToolConstraint::triggers)
+            .unwrap_or_default();
+        let grammar = match constraint {
+            Some(constraint) => constraint.render(options)?,
+            None => None,
+        };
+
+        self.hooks().rendered(
+            || RenderMeta {
+                tools: tool_count,
+                prompt_len: None,
+                grammar_len: grammar.as_ref().map(String::len),
+                warnings: warnings.len(),
+                elapsed: start.map(|start| start.elapsed()).unwrap_or_default(),
+            },
+            &warnings,
+        );
+
+        Ok(RenderResult {
+            prompt: (),
+            grammar,
+            warnings,
+            stop,
+            prefix,
+            triggers,
+        })
+    }
+
+    /// The EOS token, the tool calls section prefix when tools can't be called,
+    /// the thinking suffix for reasoning-only requests, then any configured
+    /// extras.
+    fn stop_sequences(&self, tool_choice: &ChatToolChoice, options: &RenderOptions) -> Vec<String> {
+        match self {
+            Config::Components {
+                chat_template,
+                thinking,
+                tool_calls,
+                stop,
+                ..
+            } => {
+                let mut stop_sequences = Vec::new();
+
+                stop_sequences.extend(chat_template.eos_token().map(str::to_string));
+
+                // a model that tries to call a tool anyway stops before the section opens
+                if let (ChatToolChoice::None, Some(ToolCalls::ToolCallsSection { prefix, .. })) =
+                    (tool_choice, tool_calls)
+                {
+                    stop_sequences.extend(prefix.literals().into_iter().flatten());
+                }
+
+                if let (true, Some(Thinking { suffix, .. })) = (options.reasoning_only, thinking) {
+                    stop_sequences.extend(suffix.literals().into_iter().flatten());
+                }
+
+                stop_sequences.extend(stop.iter().cloned());
+
+                stop_sequences.into_iter().unique().collect()
+            }
+            Config::Harmony(_) => HARMONY_STOP_SEQUENCES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    fn render_prompt_to<'a>(
+        &self,
+        out: &mut impl fmt::Write,
+        messages: impl Into<Vec<TemplateChatMessage<'a>>>,
+        tools: Vec<ChatTool>,
+        tool_choice: ChatToolChoice,
+        options: &RenderOptions,
+        warnings: &mut Vec<RenderWarning>,
+    ) -> Result<(Option<ToolConstraint<'_>>, Option<PromptPrefix>), RenderError> {
+        if options.max_tool_calls == Some(0) {
+            return Err(RenderError::ZeroMaxToolCalls);
+        }
+
         match self {
             Config::Components {
                 chat_template,
                 thinking,
                 tool_calls,
+                ..
             } => {
+                let messages = messages.into();
+                let continued = options.continue_final_message
+                    && messages
+                        .last()
+                        .is_some_and(|message| message.role == "assistant")
+                    && final_message_text(&messages).is_some();
+                let thinking_mode = match thinking {
+                    Some(thinking) => Some(thinking.mode(&messages, options)),
+                    None => options.thinking,
+                };
+                let thinking = thinking
+                    .as_ref()
+                    .filter(|_| thinking_mode != Some(ThinkingMode::Disabled));
+
+                // the template sees the mode the grammar uses, and only
+                // continues a message the grammar continues too
+                let options = &match (thinking_mode, continued) {
+                    (thinking, continue_final_message)
+                        if thinking == options.thinking
+                            && continue_final_message == options.continue_final_message =>
+                    {
+                        Cow::Borrowed(options)
+                    }
+                    (thinking, continue_final_message) => Cow::Owned(RenderOptions {
+                        thinking,
+                        continue_final_message,
+                        ..options.clone()
+                    }),
+                };
+                let thinking_mode = thinking_mode.unwrap_or_default();
+
+                if !tools.is_empty() && !chat_template.uses_tools() {
+                    warnings.push(RenderWarning::ToolsIgnoredByTemplate);
+                }
+
+                let response_schema = options.response_format.json_schema()?;
+
                 let (Some(tool_calls), false, false) = (
                     tool_calls,
                     tools.is_empty(),
                     matches!(tool_choice, ChatToolChoice::None),
                 ) else {
-                    let prompt = chat_template.render(messages.into(), &[])?;
-
-                    return Ok(RenderResult {
-                        prompt,
-                        grammar: None,
-                        // parser: None,
+                    let prefix = self.render_conversation_to(out, messages, &[], options)?;
+
+                    let constraint = response_schema.map(|response_schema| ToolConstraint {
+                        thinking: thinking.map(Cow::Borrowed),
+                        thinking_mode,
+                        tool_calls: None,
+                        tools: Vec::new(),
+                        tool_choice,
+                        response_schema: Some(response_schema),
+                        continued,
                     });
+
+                    if let Some(constraint) = &constraint {
+                        constraint.check_thinking_limit(options, warnings)?;
+                    }
+
+                    return Ok((constraint, prefix));
                 };
 
-                let validated_tools =
-                    tools
-                        .into_iter()
-                        .try_fold(Vec::new(), |mut tool_acc, tool| {
-                            match &tool {
-                                ChatTool::Function {
-                                    function:
-                                        FunctionTool {
-                                            name, parameters, ..
-                                        },
-                                } => {
-                                    jsonschema::meta::validate(parameters).map_err(|e| {
-                                        RenderError::JsonSchema(name.clone(), e.to_string())
-                                    })?;
-                                }
-                                ChatTool::Custom {
-                                    custom: CustomTool { name, format, .. },
-                                } => match format {
-                                    CustomToolFormat::Text => {}
-                                    CustomToolFormat::Grammar {
-                                        grammar: CustomToolGrammar { definition, syntax },
-                                    } => match syntax {
-                                        CustomToolSyntax::Lark => {
-                                            static PARSER_FACTORY: OnceLock<ParserFactory> =
-                                                OnceLock::new();
-
-                                            let parser_factory = PARSER_FACTORY.get_or_init(|| {
-                                                let tok_env = ApproximateTokEnv::single_byte_env();
-                                                ParserFactory::new_simple(&tok_env).unwrap()
-                                            });
-
-                                            let grammar =
-                                                TopLevelGrammar::from_lark(definition.clone());
-                                            parser_factory.create_parser(grammar).map_err(|e| {
-                                                RenderError::Lark(name.clone(), e.to_string())
-                                            })?;
-                                        }
-                                        CustomToolSyntax::Regex => {
-                                            regex::Regex::new(definition).map_err(|e| {
-                                                RenderError::Regex(name.clone(), e.to_string())
-                                            })?;
-                                        }
-                                    },
-                                },
-                            }
+                let validated_tools = validate_tools(tools)?;
+
+                warnings.extend(
+                    validated_tools
+                        .iter()
+                        .flat_map(TemplateTool::schema_warnings),
+                );
+
+                let prefix =
+                    self.render_conversation_to(out, messages, &validated_tools, options)?;
+
+                let constraint = ToolConstraint {
+                    thinking: thinking.map(Cow::Borrowed),
+                    thinking_mode,
+                    tool_calls: Some(Cow::Borrowed(tool_calls)),
+                    tools: validated_tools,
+                    tool_choice,
+                    response_schema,
+                    continued,
+                };
+
+                constraint.check_thinking_limit(options, warnings)?;
+
+                Ok((Some(constraint), prefix))
+            }
+            Config::Harmony(harmony) => {
+                let channels = harmony.channels(options);
+
+                // tools are called on the commentary channel
+                let validated_tools = match channels.contains(&HarmonyChannel::Commentary) {
+                    true => validate_tools(tools)?,
+                    false => Vec::new(),
+                };
+
+                let prefix =
+                    self.render_conversation_to(out, messages.into(), &validated_tools, options)?;
+
+                let constraint =
+                    HarmonyOptions::constraint(&channels, validated_tools, tool_choice, options);
 
-                            tool_acc.push(tool.into());
+                Ok((constraint, prefix))
+            }
+        }
+    }
 
-                            Ok::<_, RenderError>(tool_acc)
-                        })?;
+    /// Renders the conversation into `out`, and if asked for, the
+    /// [`PromptPrefix`] it shares with the same conversation cut off before its
+    /// last user message.
+    fn render_conversation_to(
+        &self,
+        out: &mut impl fmt::Write,
+        messages: Vec<TemplateChatMessage>,
+        tools: &[TemplateTool],
+        options: &RenderOptions,
+    ) -> Result<Option<PromptPrefix>, RenderError> {
+        if !options.prompt_prefix {
+            self.render_messages_to(out, messages, tools, options)?;
+            return Ok(None);
+        }
 
-                let prompt = chat_template.render(messages.into(), &validated_tools)?;
+        let split = messages
+            .iter()
+            .rposition(|message| message.role == "user")
+            .unwrap_or(messages.len());
+
+        let mut expected = String::new();
+        self.render_messages_to(
+            &mut expected,
+            messages[..split].to_vec(),
+            tools,
+            &RenderOptions {
+                add_generation_prompt: false,
+                continue_final_message: false,
+                ..options.clone()
+            },
+        )?;
+
+        let mut writer = PrefixWriter {
+            out,
+            expected: &expected,
+            matched: 0,
+            diverged: false,
+        };
+        self.render_messages_to(&mut writer, messages, tools, options)?;
 
-                let mut rules = Rules::new(grammar_syntax);
+        let prefix = &expected[..writer.matched];
+
+        Ok(Some(PromptPrefix {
+            key: fnv1a(prefix.as_bytes()),
+            len: prefix.len(),
+        }))
+    }
 
-                let Some((tools_rule, allow_content)) = (match tool_calls {
-                    ToolCalls::ToolCall { tool_call } => {
-                        tool_choice.render(tool_call, &validated_tools, &mut rules)?
+    fn render_messages_to(
+        &self,
+        out: &mut impl fmt::Write,
+        messages: Vec<TemplateChatMessage>,
+        tools: &[TemplateTool],
+        options: &RenderOptions,
+    ) -> Result<(), RenderError> {
+        match self {
+            Config::Components {
+                chat_template,
+                roles,
+                custom_roles,
+                ..
+            } => {
+                let mut messages = messages;
+                for message in &mut messages {
+                    if let Some(role) = roles.get(&*message.role) {
+                        message.role = Cow::Owned(role.clone());
+                    } else if !DEFAULT_ROLES.contains(&&*message.role)
+                        && !custom_roles.iter().any(|role| *role == message.role)
+                    {
+                        return Err(RenderError::UnknownRole(message.role.to_string()));
                     }
-                    ToolCalls::ToolCallsSection {
-                        prefix,
-                        tool_call,
-                        suffix,
-                    } => tool_choice
-                        .render(tool_call, &validated_tools, &mut rules)?
-                        .map(|(mut tool_choice, allow_content)| {
-                            let mut acc = vec![prefix.render(&mut rules)?];
-
-                            if parallel_tool_calls {
-                                tool_choice =
-                                    rules.insert_repetition("tool_choice", tool_choice, 0, None);
-                            }
+                }
+
+                chat_template.render_to(out, messages, tools, options)
+            }
+            Config::Harmony(harmony) => Ok(harmony.render_to(out, &messages, tools, options)?),
+        }
+    }
+}
 
-                            acc.push(tool_choice);
+/// Passes writes through to `out` while counting how many bytes of the prompt
+/// match `expected` from the start.
+struct PrefixWriter<'w, W> {
+    out: &'w mut W,
+    expected: &'w str,
+    matched: usize,
+    diverged: bool,
+}
 
-                            if let Some(suffix) = suffix {
-                                acc.push(suffix.render(&mut rules)?);
-                            }
+impl<W: fmt::Write> fmt::Write for PrefixWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if !self.diverged {
+            let matched = self.expected[self.matched..]
+                .char_indices()
+                .zip(s.chars())
+                .take_while(|((_, expected), actual)| expected == actual)
+                .last()
+                .map_or(0, |((index, c), _)| index + c.len_utf8());
+
+            self.matched += matched;
+            self.diverged = matched < s.len();
+        }
 
-                            let tools_rule = rules.insert_sequence("tool_choices", &acc);
-                            Ok::<_, RenderError>((tools_rule, allow_content))
-                        })
-                        .transpose()?,
-                }) else {
-                    return Ok(RenderResult {
-                        prompt,
-                        grammar: None,
-                        // parser: None,
-                    });
+        self.out.write_str(s)
+    }
+}
+
+/// Adds what `lexemes` can start with to `triggers`. False when that isn't
+/// always a marker, like a regex or a sequence that can match nothing.
+fn collect_triggers(lexemes: &[Lexeme], triggers: &mut Vec<GrammarTrigger>) -> bool {
+    for lexeme in lexemes {
+        let trigger = match lexeme {
+            Lexeme::Text(text) => GrammarTrigger::Text(text.to_string()),
+            Lexeme::Token(token) => GrammarTrigger::Token(token.to_string()),
+            Lexeme::TokenId(id) => GrammarTrigger::TokenId(*id),
+            Lexeme::Choice { choices } => {
+                return choices
+                    .iter()
+                    .all(|choice| collect_triggers(&choice.0, triggers));
+            }
+            // the lexemes after these can come first too
+            Lexeme::Optional { optional } => {
+                if !collect_triggers(&optional.0, triggers) {
+                    return false;
+                }
+                continue;
+            }
+            Lexeme::Whitespace { .. } => continue,
+            Lexeme::Regex { .. } | Lexeme::JsonSchema(_) => return false,
+        };
+
+        if !triggers.contains(&trigger) {
+            triggers.push(trigger);
+        }
+        return true;
+    }
+
+    false
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl ToolConstraint<'_> {
+    fn into_owned(self) -> ToolConstraint<'static> {
+        ToolConstraint {
+            thinking: self
+                .thinking
+                .map(|thinking| Cow::Owned(thinking.into_owned())),
+            thinking_mode: self.thinking_mode,
+            tool_calls: self
+                .tool_calls
+                .map(|tool_calls| Cow::Owned(tool_calls.into_owned())),
+            tools: self.tools,
+            tool_choice: self.tool_choice,
+            response_schema: self.response_schema,
+            continued: self.continued,
+        }
+    }
+
+    /// The thinking's limit, if the grammar has a thinking section to apply
+    /// it to.
+    fn thinking_limit(&self, options: &RenderOptions) -> Option<ThinkingLimit> {
+        let thinking = self
+            .thinking
+            .as_deref()
+            .filter(|thinking| !self.continued || thinking.interleaved)?;
+
+        thinking.limit(options)
+    }
+
+    /// Warns about token limits the grammar can't enforce, and rejects
+    /// character limits too long to spell out in GBNF.
+    fn check_thinking_limit(
+        &self,
+        options: &RenderOptions,
+        warnings: &mut Vec<RenderWarning>,
+    ) -> Result<(), RenderError> {
+        if !matches!(
+            options.grammar_syntax,
+            GrammarSyntax::GBNF | GrammarSyntax::Ebnf
+        ) {
+            return Ok(());
+        }
+
+        match self.thinking_limit(options) {
+            Some(ThinkingLimit::Tokens(max_tokens)) => {
+                warnings.push(RenderWarning::UnenforcedThinkingLimit { max_tokens });
+            }
+            Some(ThinkingLimit::Characters(max_chars))
+                if max_chars > ThinkingLimit::MAX_GBNF_CHARACTERS =>
+            {
+                return Err(RenderError::ThinkingLimitTooLarge(max_chars));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn triggers(&self) -> Vec<GrammarTrigger> {
+        let mut triggers = Vec::new();
+
+        let complete = match self.tool_calls.as_deref() {
+            None => false,
+            Some(_) if matches!(self.tool_choice, ChatToolChoice::None) => false,
+            Some(ToolCalls::ToolCallsSection { prefix, .. }) => {
+                collect_triggers(&prefix.0, &mut triggers)
+            }
+            Some(ToolCalls::ToolCall { tool_call }) => self.tools.iter().all(|tool| {
+                let lexemes = match tool_call {
+                    // JSON can open with whitespace, so there's no fixed marker
+                    ToolCall::JsonObject { .. } | ToolCall::JsonArray { .. } => return false,
+                    ToolCall::NamedParameters { prefix, .. } => prefix
+                        .iter()
+                        .flat_map(|prefix| prefix.0.iter().cloned())
+                        .chain([Lexeme::Text(tool.name.as_str().into())])
+                        .collect(),
+                    ToolCall::External { name } => match tool_call_format(name) {
+                        Some(format) => format.tool_call(tool).0,
+                        None => return false,
+                    },
                 };
 
-                let text_rule = rules.insert_text_lexeme()?;
-                let mut acc = Vec::new();
+                collect_triggers(&lexemes, &mut triggers)
+            }),
+        };
+
+        match complete {
+            true => triggers,
+            false => Vec::new(),
+        }
+    }
+
+    fn render(&self, options: &RenderOptions) -> GrammarResult {
+        let grammar = traced!("render_grammar", tools = self.tools.len(); {
+            match options.grammar_syntax {
+                GrammarSyntax::StructuralTags => self.structural_tags(),
+                _ => self.render_rules(options),
+            }
+        })?;
+
+        if options.validate_grammar
+            && let Some(grammar) = &grammar
+        {
+            validate_grammar(grammar, options.grammar_syntax)?;
+        }
+
+        Ok(grammar)
+    }
+
+    fn render_rules(&self, options: &RenderOptions) -> GrammarResult {
+        let ToolConstraint {
+            thinking,
+            thinking_mode,
+            tool_calls,
+            tools,
+            tool_choice,
+            response_schema,
+            continued,
+        } = self;
+
+        let mut rules = Rules::new(options.grammar_syntax);
+
+        // the content a continued message started can run on before its calls
+        let mixed = options.mixed_content_tool_calls || *continued;
+        let triggers = self.triggers();
+        let markers = triggers
+            .iter()
+            .filter_map(|trigger| match trigger {
+                GrammarTrigger::Text(text) | GrammarTrigger::Token(text) => Some(text.clone()),
+                GrammarTrigger::TokenId(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        // llguidance can't end text where a marker starts, so the content before
+        // a call is lexed along with the text marker opening it
+        let leads = match options.grammar_syntax {
+            GrammarSyntax::Lark | GrammarSyntax::LLGuidance if mixed && !triggers.is_empty() => {
+                triggers
+                    .iter()
+                    .map(|trigger| match trigger {
+                        GrammarTrigger::Text(text) => Some(text.clone()),
+                        GrammarTrigger::Token(_) | GrammarTrigger::TokenId(_) => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+            }
+            _ => None,
+        };
+
+        let tool_choices = match tool_calls.as_deref() {
+            None => None,
+            Some(ToolCalls::ToolCall { tool_call }) => {
+                rules.leads = leads.clone().unwrap_or_default();
+                let tool_choices = tool_choice.render(tool_call, tools, &mut rules)?;
+                rules.leads.clear();
+
+                tool_choices
+            }
+            Some(ToolCalls::ToolCallsSection {
+                prefix,
+                tool_call,
+                suffix,
+                per_call,
+            }) => tool_choice
+                .render(tool_call, tools, &mut rules)?
+                .map(|(mut tool_choice, optional)| {
+                    rules.leads = leads.clone().unwrap_or_default();
+                    let prefix = prefix.render(&mut rules)?;
+                    rules.leads.clear();
+
+                    let mut acc = vec![prefix];
+
+                    if options.parallel_tool_calls && !per_call {
+                        tool_choice = rules.insert_repetition(
+                            "tool_choice",
+                            tool_choice,
+                            1,
+                            options.max_tool_calls,
+                        );
+                    }
+
+                    acc.push(tool_choice);
+
+                    if let Some(suffix) = suffix {
+                        acc.push(suffix.render(&mut rules)?);
+                    }
+
+                    let mut tools_rule = rules.insert_sequence("tool_choices", &acc);
+
+                    // parallel calls repeat the whole section instead
+                    if options.parallel_tool_calls
+                        && *per_call
+                        && options.max_tool_calls.is_none_or(|max| max > 1)
+                    {
+                        let newlines = rules.insert_lexeme(
+                            "newlines",
+                            &Lexeme::Whitespace {
+                                whitespace: "\n".into(),
+                            },
+                        )?;
+                        let section =
+                            rules.insert_sequence("section", &[newlines, tools_rule.clone()]);
+                        let sections = rules.insert_repetition(
+                            "sections",
+                            section,
+                            0,
+                            options.max_tool_calls.map(|max| max - 1),
+                        );
+                        tools_rule =
+                            rules.insert_sequence("tool_sections", &[tools_rule, sections]);
+                    }
+
+                    Ok::<_, RenderError>((tools_rule, optional))
+                })
+                .transpose()?,
+        };
+
+        let (tools_rule, optional) = match tool_choices {
+            Some((tools_rule, optional)) => (Some(tools_rule), optional),
+            None if response_schema.is_some() => (None, true),
+            None => return Ok(None),
+        };
+
+        let mut acc = Vec::new();
+        let mut interleaved_thinking = None;
+
+        if let Some(
+            thinking @ Thinking {
+                prefix,
+                suffix,
+                interleaved,
+                ..
+            },
+        ) = thinking
+            .as_deref()
+            .filter(|thinking| !*continued || thinking.interleaved)
+        {
+            let limit = thinking.limit(options);
+
+            let mut thinking_acc = vec![prefix.render(&mut rules)?];
+            thinking_acc.extend(rules.insert_thinking_text(limit, suffix)?);
+
+            let thinking_rule = rules.insert_sequence("thinking", &thinking_acc);
+            if *interleaved {
+                interleaved_thinking = Some(thinking_rule.clone());
+            }
+
+            // a continued message is already past its opening thinking
+            if !*continued {
+                acc.push(match thinking_mode {
+                    ThinkingMode::Optional => {
+                        rules.insert_repetition("optional_thinking", thinking_rule, 0, Some(1))
+                    }
+                    ThinkingMode::Disabled | ThinkingMode::Forced => thinking_rule,
+                });
+            }
+        }
+
+        let content = match (&tools_rule, response_schema) {
+            (Some(_), _) if !optional && (!mixed || leads.is_some()) => None,
+            (_, Some(schema)) => {
+                Some(rules.insert_lexeme("content", &Lexeme::JsonSchema(schema.clone()))?)
+            }
+            // calls without a marker are JSON, which text just can't open like
+            (Some(_), None) if markers.is_empty() => Some(rules.insert_lexeme(
+                "text",
+                &Lexeme::Regex {
+                    pattern: "[^{](?s:.)*".into(),
+                },
+            )?),
+            (_, None) => Some(rules.insert_text("text", &markers, None)?),
+        };
+        let optional_content = content
+            .clone()
+            .filter(|_| mixed && leads.is_none())
+            .map(|content| rules.insert_repetition("optional_content", content, 0, Some(1)));
+
+        acc.push(match (tools_rule, content) {
+            (Some(tools_rule), content) => {
+                // each round of tool calls can be followed by more thinking, and
+                // with mixed content more text, before another round
+                let mut round = Vec::from_iter(interleaved_thinking);
+                if options.parallel_tool_calls && options.max_tool_calls.is_none() {
+                    round.extend(optional_content.clone());
+                }
+
+                let mut calls = match round.is_empty() {
+                    true => tools_rule,
+                    false => {
+                        round.push(tools_rule.clone());
+                        let round = rules.insert_sequence("round", &round);
+                        let rounds = rules.insert_repetition("rounds", round, 0, None);
+                        rules.insert_sequence("calls", &[tools_rule, rounds])
+                    }
+                };
+
+                if let Some(optional_content) = optional_content {
+                    calls = rules.insert_sequence("calls", &[optional_content, calls]);
+                }
+
+                match content.filter(|_| optional) {
+                    Some(content) => {
+                        rules.insert_alternative("content_or_calls", &[content, calls])
+                    }
+                    None => calls,
+                }
+            }
+            (None, content) => content.expect("content is allowed without tool calls"),
+        });
+
+        Ok(Some(rules.resolve(&acc)))
+    }
+}
+
+impl Thinking {
+    /// The request's limit, then the budget for its reasoning effort.
+    fn limit(&self, options: &RenderOptions) -> Option<ThinkingLimit> {
+        options.thinking_limit.or_else(|| {
+            self.budget
+                .as_ref()
+                .zip(options.reasoning_effort)
+                .and_then(|(budget, reasoning_effort)| budget.max_tokens(reasoning_effort))
+                .map(ThinkingLimit::Tokens)
+        })
+    }
+
+    /// The request's mode, then its `enable_thinking` template variable, then
+    /// the toggle, then the config's mode.
+    fn mode(&self, messages: &[TemplateChatMessage], options: &RenderOptions) -> ThinkingMode {
+        let requested = options.thinking.or_else(|| {
+            let enabled = options.kwargs.get("enable_thinking")?.as_bool()?;
+            Some(match enabled {
+                true => ThinkingMode::Forced,
+                false => ThinkingMode::Disabled,
+            })
+        });
+
+        requested.unwrap_or_else(|| match self.enabled(messages) {
+            true => self.mode.unwrap_or_default(),
+            false => ThinkingMode::Disabled,
+        })
+    }
+
+    fn enabled(&self, messages: &[TemplateChatMessage]) -> bool {
+        let Some(ThinkingToggle { on, off, default }) = &self.toggle else {
+            return true;
+        };
+
+        messages
+            .iter()
+            .rev()
+            .filter(|message| matches!(&*message.role, "system" | "developer"))
+            .find_map(|message| {
+                let text = message.content.text();
+
+                match (text.contains(off.as_str()), text.contains(on.as_str())) {
+                    (true, _) => Some(false),
+                    (false, true) => Some(true),
+                    (false, false) => None,
+                }
+            })
+            .unwrap_or(*default)
+    }
+}
+
+impl ChatCompletionRequest {
+    /// The request's settings on top of the [`RenderOptions`] defaults, for
+    /// callers that need to adjust them before rendering.
+    pub fn render_options(&self) -> RenderOptions {
+        let defaults = RenderOptions::default();
+
+        RenderOptions {
+            parallel_tool_calls: self
+                .parallel_tool_calls
+                .unwrap_or(defaults.parallel_tool_calls),
+            reasoning_effort: self.reasoning_effort,
+            response_format: self.response_format.clone().unwrap_or_default(),
+            kwargs: self.chat_template_kwargs.clone().unwrap_or_default(),
+            documents: self.documents.clone().unwrap_or_default(),
+            continue_final_message: self.continue_final_message.unwrap_or_default(),
+            ..defaults
+        }
+    }
+}
+
+impl ChatResponseFormat {
+    /// The schema content must match, or `None` for plain text.
+    fn json_schema(&self) -> Result<Option<serde_json::Value>, RenderError> {
+        match self {
+            ChatResponseFormat::Text => Ok(None),
+            ChatResponseFormat::JsonObject => Ok(Some(json!({ "type": "object" }))),
+            ChatResponseFormat::JsonSchema {
+                json_schema: ChatJsonSchema { name, schema, .. },
+            } => {
+                let schema = schema
+                    .clone()
+                    .unwrap_or_else(|| json!({ "type": "object" }));
+
+                jsonschema::meta::validate(&schema)
+                    .map_err(|e| RenderError::JsonSchema(name.clone(), e.to_string()))?;
+
+                Ok(Some(schema))
+            }
+        }
+    }
+}
+
+/// Validates every tool, reporting all the problems found rather than only the
+/// first.
+fn validate_tools(tools: Vec<ChatTool>) -> Result<Vec<TemplateTool>, RenderError> {
+    traced!("validate_tools", tools = tools.len(); {
+        let mut errors = Vec::new();
+
+        if tools.iter().any(|tool| tool.name().is_empty()) {
+            errors.push(ToolValidationError::EmptyName);
+        }
+
+        errors.extend(
+            tools
+                .iter()
+                .map(ChatTool::name)
+                .filter(|name| !name.is_empty())
+                .duplicates()
+                .map(|name| ToolValidationError::DuplicateName(name.to_string())),
+        );
+
+        #[cfg(not(feature = "rayon"))]
+        let results = tools
+            .into_iter()
+            .map(ChatTool::validate)
+            .collect::<Vec<_>>();
+
+        // large tool sets are validated in parallel; collect preserves tool order
+        #[cfg(feature = "rayon")]
+        let results = tools
+            .into_par_iter()
+            .map(ChatTool::validate)
+            .collect::<Vec<_>>();
+
+        let (validated_tools, invalid): (Vec<_>, Vec<_>) = results.into_iter().partition_result();
+        errors.extend(invalid);
+
+        match errors.is_empty() {
+            true => Ok(validated_tools),
+            false => Err(RenderError::ToolValidation(errors)),
+        }
+    })
+}
+
+impl ChatTool {
+    fn name(&self) -> &str {
+        match self {
+            ChatTool::Function { function } => &function.name,
+            ChatTool::Custom { custom } => &custom.name,
+        }
+    }
+
+    fn validate(mut self) -> Result<TemplateTool, ToolValidationError> {
+        match &mut self {
+            ChatTool::Function {
+                function:
+                    FunctionTool {
+                        name,
+                        parameters,
+                        strict,
+                        ..
+                    },
+            } => {
+                jsonschema::meta::validate(parameters)
+                    .map_err(|e| ToolValidationError::JsonSchema(name.clone(), e.to_string()))?;
+
+                // the parameters end up nested in the call, where `#/$defs/...` would
+                // point somewhere else
+                *parameters = crate::schema::inline_refs(parameters, name)
+                    .map_err(|e| ToolValidationError::JsonSchema(name.clone(), e.to_string()))?;
+
+                if *strict == Some(true) {
+                    crate::schema::strict(parameters);
+                }
+            }
+            ChatTool::Custom {
+                custom: CustomTool { name, format, .. },
+            } => match format {
+                CustomToolFormat::Text => {}
+                CustomToolFormat::Grammar {
+                    grammar: CustomToolGrammar { definition, syntax },
+                } => match syntax {
+                    CustomToolSyntax::Lark => {
+                        validate_custom_lark(definition)
+                            .map_err(|e| ToolValidationError::Lark(name.clone(), e))?;
+                    }
+                    CustomToolSyntax::Regex => {
+                        regex::Regex::new(definition)
+                            .map_err(|e| ToolValidationError::Regex(name.clone(), e.to_string()))?;
+                    }
+                },
+            },
+        }
+
+        Ok(self.into())
+    }
+}
+
+/// Whether engines can match `token` as a special token by name, which
+/// llguidance and llama.cpp write in angle brackets without whitespace. Other
+/// tokens, like Mistral's `[TOOL_CALLS]`, are matched by their text.
+pub(crate) fn is_token_name(token: &str) -> bool {
+    token
+        .strip_prefix('<')
+        .and_then(|token| token.strip_suffix('>'))
+        .is_some_and(|name| {
+            !name.is_empty() && !name.contains(|c: char| c == '<' || c == '>' || c.is_whitespace())
+        })
+}
+
+/// Compiles a grammar through llguidance. There's no tokenizer to look special
+/// tokens up in, so any token name is taken to exist.
+fn compile_llguidance(grammar: TopLevelGrammar) -> Result<(), String> {
+    match GrammarInit::Serialized(grammar).validate(None, ParserLimits::default()) {
+        ValidationResult::Error(e) => Err(e),
+        ValidationResult::Valid | ValidationResult::Warnings(_) => Ok(()),
+    }
+}
+
+/// The grammar object llguidance compiles a Lark grammar as.
+fn lark_top_level_grammar(grammar: String) -> TopLevelGrammar {
+    TopLevelGrammar::from_lark(grammar)
+}
+
+/// Custom tool definitions are resent verbatim on every request, so validation
+/// outcomes are cached by grammar text instead of recompiling each time, and
+/// parsers come from one shared factory. The grammars constrain the tool's
+/// input, which has no special tokens, so a byte-level tokenizer is enough.
+fn validate_custom_lark(definition: &str) -> Result<(), String> {
+    static PARSERS: LazyLock<ParserFactory> = LazyLock::new(|| {
+        let mut factory = ParserFactory::new_simple(&ApproximateTokEnv::single_byte_env())
+            .expect("a byte-level tokenizer has a parser factory");
+        factory.quiet();
+
+        factory
+    });
+    // keyed by the text's hash so a hit doesn't copy it, with the text kept to
+    // rule out collisions
+    type Validated = (Arc<str>, Result<(), String>);
+    static VALIDATED: LazyLock<Cache<u64, Validated>> = LazyLock::new(|| Cache::new(1024));
+
+    let mut hasher = DefaultHasher::new();
+    definition.hash(&mut hasher);
+    let key = hasher.finish();
+
+    if let Some((cached, result)) = VALIDATED.get(&key)
+        && *cached == *definition
+    {
+        return result;
+    }
 
-                if let Some(Thinking { prefix, suffix }) = thinking {
-                    acc.push(prefix.render(&mut rules)?);
-                    acc.push(text_rule.clone());
-                    acc.push(suffix.render(&mut rules)?);
-                }
+    let result = PARSERS
+        .create_parser(lark_top_level_grammar(definition.to_string()))
+        .map(|_| ())
+        .map_err(|e| e.to_string());
 
-                if allow_content || mixed_content_tool_calls {
-                    acc.push(text_rule.clone());
-                }
+    VALIDATED.insert(key, (definition.into(), result.clone()));
 
-                acc.push(tools_rule);
+    result
+}
 
-                let root = rules.insert_sequence("root", &acc);
-                let grammar = rules.resolve(root);
+/// Compiles a Lark grammar through llguidance, as a constrained decoding engine would.
+pub fn validate_lark(grammar: &str) -> Result<(), RenderError> {
+    compile_llguidance(lark_top_level_grammar(grammar.to_string()))
+        .map_err(RenderError::GrammarCompilation)
+}
 
-                Ok(RenderResult {
-                    prompt,
-                    grammar: Some(grammar),
-                    // parser: self.parser(),
-                })
-            }
-            Config::Harmony => Ok(RenderResult {
-                prompt: String::new(),
-                grammar: None,
-                // parser: None,
-            }),
+/// Compiles a rendered grammar the way an engine reading `syntax` would.
+/// Structural tags aren't a grammar and pass as is.
+pub fn validate_grammar(grammar: &str, syntax: GrammarSyntax) -> Result<(), RenderError> {
+    match syntax {
+        GrammarSyntax::Lark => validate_lark(grammar),
+        GrammarSyntax::GBNF | GrammarSyntax::Ebnf => validate_gbnf(grammar),
+        GrammarSyntax::LLGuidance => {
+            let grammar = serde_json::from_str::<TopLevelGrammar>(grammar)
+                .map_err(|e| RenderError::GrammarCompilation(e.to_string()))?;
+
+            compile_llguidance(grammar).map_err(RenderError::GrammarCompilation)
         }
+        GrammarSyntax::StructuralTags => Ok(()),
     }
 }
 
 impl OrderedLexemes {
     fn render(&self, rules: &mut Rules) -> Result<RuleKey, RenderError> {
+        let sequence_keys = self.render_each(rules)?;
+
+        Ok(rules.insert_sequence("sequence", &sequence_keys))
+    }
+
+    fn render_each(&self, rules: &mut Rules) -> Result<Vec<RuleKey>, RenderError> {
         let OrderedLexemes(literals) = self;
 
-        let sequence_keys = literals
+        literals
             .iter()
             .map(|lexeme| rules.insert_lexeme("sequence", lexeme))
-            .collect::<Result<Vec<_>, RenderError>>()?;
+            .collect()
+    }
 
-        Ok(rules.insert_sequence("sequence", &sequence_keys))
+    /// Every string the lexemes can spell out, if none of them are patterns,
+    /// schemas or token IDs. Trailing whitespace is left off, so a marker
+    /// ending in it is found as soon as its other lexemes are.
+    pub(crate) fn literals(&self) -> Option<Vec<String>> {
+        let OrderedLexemes(lexemes) = self;
+        let end = lexemes
+            .iter()
+            .rposition(|lexeme| !matches!(lexeme, Lexeme::Whitespace { .. }))
+            .map_or(0, |last| last + 1);
+
+        lexemes[..end]
+            .iter()
+            .try_fold(vec![String::new()], |prefixes, lexeme| {
+                let suffixes = match lexeme {
+                    Lexeme::Text(s) | Lexeme::Token(s) => vec![s.to_string()],
+                    Lexeme::Choice { choices } => choices
+                        .iter()
+                        .map(OrderedLexemes::literals)
+                        .collect::<Option<Vec<_>>>()?
+                        .concat(),
+                    Lexeme::Optional { optional } => {
+                        let mut literals = optional.literals()?;
+                        literals.push(String::new());
+                        literals
+                    }
+                    Lexeme::Regex { .. }
+                    | Lexeme::TokenId(_)
+                    | Lexeme::Whitespace { .. }
+                    | Lexeme::JsonSchema(_) => {
+                        return None;
+                    }
+                };
+
+                Some(
+                    prefixes
+                        .iter()
+                        .flat_map(|prefix| {
+                            suffixes
+                                .iter()
+                                .map(move |suffix| format!("{prefix}{suffix}"))
+                        })
+                        .collect(),
+                )
+            })
+    }
+}
+
+/// `c` escaped for a character class, which Lark and GBNF write the same way.
+fn escape_class_char(c: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\\' | ']' | '[' | '^' | '-' | '/' => format!("\\{c}"),
+        c => c.to_string(),
     }
 }
 
+/// Keywords that validation honours but grammar generation can't express.
+static UNENFORCED_SCHEMA_KEYWORDS: &[&str] = &[
+    "not",
+    "if",
+    "then",
+    "else",
+    "dependentRequired",
+    "dependentSchemas",
+    "unevaluatedItems",
+    "unevaluatedProperties",
+    "uniqueItems",
+    "contains",
+];
+
 impl TemplateTool {
-    fn naive_json_schema(&self, name_key: &str, argument_key: &str) -> serde_json::Value {
-        json!({
+    fn schema_warnings(&self) -> Vec<RenderWarning> {
+        fn collect(schema: &serde_json::Value, found: &mut Vec<&'static str>) {
+            match schema {
+                serde_json::Value::Object(map) => {
+                    for (key, value) in map {
+                        if let Some(keyword) = UNENFORCED_SCHEMA_KEYWORDS
+                            .iter()
+                            .find(|keyword| **keyword == key)
+                        {
+                            found.push(keyword);
+                        }
+
+                        match key.as_str() {
+                            // maps of names to subschemas, whose keys are not keywords
+                            "properties" | "patternProperties" | "$defs" | "definitions"
+                            | "dependentSchemas" => {
+                                if let serde_json::Value::Object(subschemas) = value {
+                                    subschemas.values().for_each(|s| collect(s, found));
+                                }
+                            }
+                            // literal data rather than subschemas
+                            "const" | "enum" | "default" | "examples" => {}
+                            _ => collect(value, found),
+                        }
+                    }
+                }
+                serde_json::Value::Array(items) => items.iter().for_each(|s| collect(s, found)),
+                _ => {}
+            }
+        }
+
+        let mut found = Vec::new();
+        collect(&self.parameters, &mut found);
+
+        found
+            .into_iter()
+            .unique()
+            .map(|keyword| RenderWarning::UnenforcedSchemaKeyword {
+                tool: self.name.clone(),
+                keyword: keyword.to_string(),
+            })
+            .collect()
+    }
+
+    /// A block per parameter for [`Arguments::KeyValueTags`], optional
+    /// unless the schema requires it.
+    fn key_value_tags(
+        &self,
+        prefix: &OrderedLexemes,
+        delimiter: &OrderedLexemes,
+        suffix: &OrderedLexemes,
+        rules: &mut Rules,
+    ) -> Result<Vec<RuleKey>, RenderError> {
+        let required: HashSet<_> = self
+            .parameters
+            .get("required")
+            .and_then(serde_json::Value::as_array)
+            .map(|required| {
+                required
+                    .iter()
+                    .filter_map(serde_json::Value::as_str)
+                    .collect()
+            })
+            .unwrap_or_default();
+        // each value is a schema of its own, so each takes the recursive
+        // definitions along
+        let defs = self
+            .parameters
+            .get("$defs")
+            .and_then(serde_json::Value::as_object);
+
+        self.parameters
+            .get("properties")
+            .and_then(serde_json::Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(name, schema)| {
+                let mut tag = vec![
+                    prefix.render(rules)?,
+                    rules.insert_lexeme("parameter_name", &Lexeme::Text(name.as_str().into()))?,
+                    delimiter.render(rules)?,
+                ];
+
+                match schema.get("type").and_then(serde_json::Value::as_str) {
+                    // strings are written raw, so they run up to the suffix
+                    Some("string") => {
+                        let (value, suffix) =
+                            rules.insert_text_until("parameter_value", suffix, None)?;
+                        tag.extend([value].into_iter().chain(suffix));
+                    }
+                    _ => {
+                        let mut schema = schema.clone();
+                        if let Some(defs) = defs {
+                            crate::schema::extend_defs(&mut schema, defs.clone());
+                        }
+
+                        tag.extend([
+                            rules.insert_lexeme("parameter_value", &Lexeme::JsonSchema(schema))?,
+                            suffix.render(rules)?,
+                        ]);
+                    }
+                }
+
+                let tag = rules.insert_sequence("parameter", &tag);
+
+                Ok(match required.contains(name.as_str()) {
+                    true => tag,
+                    false => rules.insert_repetition("optional_parameter", tag, 0, Some(1)),
+                })
+            })
+            .collect()
+    }
+
+    /// A call to this tool as a JSON object: exactly its name under
+    /// `name_key` and its parameters under `argument_key`.
+    fn json_call_schema(&self, name_key: &str, argument_key: &str) -> serde_json::Value {
+        let mut parameters = self.parameters.clone();
+        let defs = crate::schema::take_defs(&mut parameters);
+
+        let mut schema = json!({
             "type": "object",
             "properties": {
-                "name": {
-                    "type": "string",
-                    "enum": [
-                        name_key,
-                    ],
-                },
-                argument_key: {
-                    "type": "object",
-                    "properties": self.parameters,
-                },
-                "required": [
-                    "name",
-                    argument_key,
-                ]
-            }
-        })
+                name_key: { "const": self.name },
+                argument_key: parameters,
+            },
+            "required": [name_key, argument_key],
+            "additionalProperties": false,
+        });
+        crate::schema::extend_defs(&mut schema, defs);
+
+        schema
     }
 }
 
+/// Each tool's [`TemplateTool::json_call_schema`], and the recursive
+/// definitions taken out of them for the root of the schema they end up in.
+fn json_call_schemas(
+    tools: &[TemplateTool],
+    name_key: &str,
+    argument_key: &str,
+) -> (
+    Vec<serde_json::Value>,
+    serde_json::Map<String, serde_json::Value>,
+) {
+    let call_schema = |tool: &TemplateTool| {
+        let mut schema = tool.json_call_schema(name_key, argument_key);
+        let defs = crate::schema::take_defs(&mut schema);
+
+        (schema, defs)
+    };
+
+    #[cfg(not(feature = "rayon"))]
+    let schemas = tools.iter().map(call_schema).collect::<Vec<_>>();
+
+    // each tool's parameters are copied into its call in parallel
+    #[cfg(feature = "rayon")]
+    let schemas = tools.par_iter().map(call_schema).collect::<Vec<_>>();
+
+    let mut all_defs = serde_json::Map::new();
+    let schemas = schemas
+        .into_iter()
+        .map(|(schema, defs)| {
+            all_defs.extend(defs);
+            schema
+        })
+        .collect();
+
+    (schemas, all_defs)
+}
+
 impl ChatToolChoice {
     fn render(
         &self,
@@ -234,12 +1586,7 @@ impl ChatToolChoice {
         rules: &mut Rules,
     ) -> Result<Option<(RuleKey, bool)>, RenderError> {
         Ok(match self {
-            ChatToolChoice::Auto => {
-                let tool_choice = tool_call.render(validated_tools, rules)?;
-                let tool_choice = rules.insert_repetition("tool_choice", tool_choice, 0, Some(1));
-
-                Some((tool_choice, true))
-            }
+            ChatToolChoice::Auto => Some((tool_call.render(validated_tools, rules)?, true)),
             ChatToolChoice::None => None,
             ChatToolChoice::Required => Some((tool_call.render(validated_tools, rules)?, false)),
             ChatToolChoice::Function(FunctionName { name }) => {
@@ -251,6 +1598,21 @@ impl ChatToolChoice {
 
                 Some((tool_choice, false))
             }
+            ChatToolChoice::AllowedTools(AllowedTools { mode, tools }) => {
+                let allowed_tools = tools
+                    .iter()
+                    .map(|allowed| {
+                        validated_tools
+                            .iter()
+                            .find(|tool| tool.name == allowed.name())
+                            .cloned()
+                            .ok_or(RenderError::ChatToolChoice)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let tool_choice = tool_call.render(&allowed_tools, rules)?;
+
+                Some((tool_choice, matches!(mode, AllowedToolsMode::Auto)))
+            }
         })
     }
 }
@@ -262,14 +1624,12 @@ impl ToolCall {
                 name_key,
                 argument_key,
             } => {
-                let schema_choices = tools
-                    .iter()
-                    .map(|tool| tool.naive_json_schema(name_key, argument_key))
-                    .collect::<Vec<_>>();
+                let (schema_choices, defs) = json_call_schemas(tools, name_key, argument_key);
 
-                let object_schema = json!({
+                let mut object_schema = json!({
                     "anyOf": schema_choices,
                 });
+                crate::schema::extend_defs(&mut object_schema, defs);
 
                 Ok(rules.insert_lexeme("tool_choice", &Lexeme::JsonSchema(object_schema))?)
             }
@@ -277,27 +1637,27 @@ impl ToolCall {
                 name_key,
                 argument_key,
             } => {
-                let schema_choices = tools
-                    .iter()
-                    .map(|tool| tool.naive_json_schema(name_key, argument_key))
-                    .collect::<Vec<_>>();
+                let (schema_choices, defs) = json_call_schemas(tools, name_key, argument_key);
 
-                let array_schema = json!({
+                let mut array_schema = json!({
                     "type": "array",
                     "items": {
                         "anyOf": schema_choices,
                     },
+                    "minItems": 1,
                 });
+                crate::schema::extend_defs(&mut array_schema, defs);
 
                 Ok(rules.insert_lexeme("tool_choice", &Lexeme::JsonSchema(array_schema))?)
             }
             ToolCall::NamedParameters {
                 prefix,
+                id,
                 delimiter,
                 arguments,
                 suffix,
             } => {
-                let alternative_keys = tools
+                let sequences = tools
                     .iter()
                     .map(|tool| {
                         let mut acc = Vec::new();
@@ -306,30 +1666,64 @@ impl ToolCall {
                             acc.push(prefix.render(rules)?);
                         }
 
-                        acc.push(rules.insert_lexeme("name", &Lexeme::Text(tool.name.clone()))?);
+                        acc.push(
+                            rules
+                                .insert_lexeme("name", &Lexeme::Text(tool.name.as_str().into()))?,
+                        );
+
+                        if let Some(id) = id {
+                            acc.push(id.render(rules)?);
+                        }
 
                         if let Some(delimiter) = delimiter {
                             acc.push(delimiter.render(rules)?);
                         }
 
-                        match arguments {
-                            Arguments::JsonObject => {
+                        match (&tool.grammar, arguments) {
+                            // custom tools take their raw input, whatever the arguments look like
+                            (Some(grammar), _) => acc.push(rules.insert_custom_grammar(grammar)?),
+                            (None, Arguments::JsonObject) => {
                                 acc.push(rules.insert_lexeme(
                                     "parameters",
                                     &Lexeme::JsonSchema(tool.parameters.clone()),
                                 )?);
                             }
+                            (
+                                None,
+                                Arguments::KeyValueTags {
+                                    prefix,
+                                    delimiter,
+                                    suffix,
+                                },
+                            ) => {
+                                acc.extend(tool.key_value_tags(prefix, delimiter, suffix, rules)?);
+                            }
                         }
 
                         if let Some(suffix) = suffix {
                             acc.push(suffix.render(rules)?);
                         }
 
-                        Ok(rules.insert_sequence("tool_choice_item", &acc))
+                        Ok(acc)
                     })
                     .collect::<Result<Vec<_>, RenderError>>()?;
 
-                Ok(rules.insert_alternative("tool_choices", &alternative_keys))
+                Ok(rules.insert_factored_alternative(
+                    "tool_choices",
+                    "tool_choice_item",
+                    &sequences,
+                ))
+            }
+            ToolCall::External { name } => {
+                let format = tool_call_format(name)
+                    .ok_or_else(|| RenderError::UnknownToolCallFormat(name.clone()))?;
+
+                let sequences = tools
+                    .iter()
+                    .map(|tool| format.tool_call(tool).render_each(rules))
+                    .collect::<Result<Vec<_>, RenderError>>()?;
+
+                Ok(rules.insert_factored_alternative("tool_choices", "sequence", &sequences))
             }
         }
     }
@@ -344,37 +1738,122 @@ impl Display for RuleKey {
     }
 }
 
+/// Whether Lark reads a rule by this name as a terminal.
+fn is_terminal(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+fn body_hash(terminal: bool, value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    terminal.hash(&mut hasher);
+    value.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// Grammar rules in insertion order, so emitted grammars are deterministic.
 struct Rules {
-    rules: HashMap<RuleKey, String>,
+    rules: IndexMap<RuleKey, String>,
+    /// Indices into `rules` keyed by a hash of each rule's body, so a duplicate
+    /// is found without comparing against every rule. Duplicates are shared
+    /// whatever they're named, so rules built from them are shared too.
+    bodies: HashMap<u64, Vec<usize>>,
+    /// The next free counter for each rule name.
+    counters: HashMap<String, usize>,
+    /// Lark rule attributes such as `max_tokens=`, written after the rule name.
+    attributes: HashMap<RuleKey, String>,
+    /// Text lexemes that take in any text before them, for content mixed in
+    /// before the tool calls they open.
+    leads: Vec<String>,
+    /// The shared JSON value rules of GBNF and EBNF grammars, by name.
+    primitives: HashMap<&'static str, RuleKey>,
+    /// The rules of the recursive definitions in the JSON schema being
+    /// converted, by `$ref`.
+    definitions: HashMap<String, RuleKey>,
+    /// Where [`Self::insert_rule`] writes each body before looking for a
+    /// duplicate.
+    body: String,
     syntax: GrammarSyntax,
 }
 
 impl Rules {
     fn new(syntax: GrammarSyntax) -> Self {
         Self {
-            rules: HashMap::new(),
+            rules: IndexMap::new(),
+            bodies: HashMap::new(),
+            counters: HashMap::new(),
+            attributes: HashMap::new(),
+            leads: Vec::new(),
+            primitives: HashMap::new(),
+            definitions: HashMap::new(),
+            body: String::new(),
             syntax,
         }
     }
 
     fn insert_sequence(&mut self, key: &str, sequence_keys: &[RuleKey]) -> RuleKey {
-        let rule = sequence_keys
-            .iter()
-            .map(|rule_key| rule_key.to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-
-        self.insert_rule(key, rule)
+        self.insert_rule(key, sequence_keys.iter().format(" "))
     }
 
     fn insert_alternative(&mut self, key: &str, alternative_keys: &[RuleKey]) -> RuleKey {
-        let rule = alternative_keys
+        self.insert_rule(key, alternative_keys.iter().format(" | "))
+    }
+
+    /// An alternative between sequences, with the rules they all start or end
+    /// with hoisted out of it, so many tools sharing a prefix and suffix only
+    /// spell them out once.
+    fn insert_factored_alternative(
+        &mut self,
+        key: &str,
+        item_key: &str,
+        sequences: &[Vec<RuleKey>],
+    ) -> RuleKey {
+        let Some(first) = sequences.first().filter(|_| sequences.len() > 1) else {
+            let alternative_keys = sequences
+                .iter()
+                .map(|sequence| self.insert_sequence(item_key, sequence))
+                .collect::<Vec<_>>();
+
+            return self.insert_alternative(key, &alternative_keys);
+        };
+
+        // every sequence keeps at least one rule of its own
+        let shared = sequences
             .iter()
-            .map(|rule_key| rule_key.to_string())
-            .collect::<Vec<_>>()
-            .join(" | ");
+            .map(Vec::len)
+            .min()
+            .unwrap_or(0)
+            .saturating_sub(1);
+        let lead = (0..shared)
+            .take_while(|&i| sequences.iter().all(|sequence| sequence[i] == first[i]))
+            .count();
+        let tail = (0..shared - lead)
+            .take_while(|&i| {
+                sequences
+                    .iter()
+                    .all(|sequence| sequence[sequence.len() - 1 - i] == first[first.len() - 1 - i])
+            })
+            .count();
+
+        let alternative_keys = sequences
+            .iter()
+            .map(|sequence| self.insert_sequence(item_key, &sequence[lead..sequence.len() - tail]))
+            .collect::<Vec<_>>();
+        let alternative = self.insert_alternative(key, &alternative_keys);
+
+        match lead + tail {
+            0 => alternative,
+            _ => {
+                let sequence = first[..lead]
+                    .iter()
+                    .cloned()
+                    .chain([alternative])
+                    .chain(first[first.len() - tail..].iter().cloned())
+                    .collect::<Vec<_>>();
 
-        self.insert_rule(key, rule)
+                self.insert_sequence(key, &sequence)
+            }
+        }
     }
 
     fn insert_repetition(
@@ -384,342 +1863,364 @@ impl Rules {
         start: usize,
         end: Option<usize>,
     ) -> RuleKey {
-        let rule = match (start, end) {
-            (0, None) => format!("{}*", repetition_key),
-            (1, None) => format!("{}+", repetition_key),
-            (0, Some(1)) => format!("{}?", repetition_key),
+        match (start, end) {
+            (0, None) => self.insert_rule(key, format_args!("{repetition_key}*")),
+            (1, None) => self.insert_rule(key, format_args!("{repetition_key}+")),
+            (0, Some(1)) => self.insert_rule(key, format_args!("{repetition_key}?")),
             (exact, Some(maybe_exact)) if exact == maybe_exact => {
-                format!("{}{{{}}}", repetition_key, exact)
+                self.insert_rule(key, format_args!("{repetition_key}{{{exact}}}"))
             }
-            (at_least, None) => format!("{}{{{},}}", repetition_key, at_least),
-            (at_least, Some(at_most)) => format!("{}{{{},{}}}", repetition_key, at_least, at_most),
-        };
-
-        self.insert_rule(key, rule)
+            (at_least, None) => {
+                self.insert_rule(key, format_args!("{repetition_key}{{{at_least},}}"))
+            }
+            (at_least, Some(at_most)) => self.insert_rule(
+                key,
+                format_args!("{repetition_key}{{{at_least},{at_most}}}"),
+            ),
+        }
     }
 
-    fn insert_lexeme(&mut self, key: &str, lexeme: &Lexeme) -> Result<RuleKey, RenderError> {
+    /// A rule matching `limited_key` that llguidance cuts off after
+    /// `max_tokens` tokens. GBNF and EBNF have no notion of tokens, so there
+    /// `limited_key` is returned unbounded.
+    fn insert_token_limit(
+        &mut self,
+        key: &str,
+        limited_key: RuleKey,
+        max_tokens: usize,
+    ) -> RuleKey {
         match self.syntax {
-            GrammarSyntax::Lark => {
-                let rule = match lexeme {
-                    Lexeme::Text(text) => lark_string_literal(text),
-                    Lexeme::Token(token) => lark_token_literal(token),
-                    Lexeme::Regex { pattern } => lark_regex(pattern),
-                    Lexeme::JsonSchema(json_schema) => lark_json_schema(json_schema),
-                };
+            GrammarSyntax::Lark | GrammarSyntax::LLGuidance => {
+                // never shared, since the limit only applies here
+                let rule_key = self.insert_new_rule(key, limited_key.to_string());
+                self.attributes
+                    .insert(rule_key.clone(), format!("max_tokens={max_tokens}"));
 
-                Ok(self.insert_rule(&key.to_uppercase(), rule))
-            }
-            GrammarSyntax::GBNF => {
-                match lexeme {
-                    Lexeme::Text(text) => Ok(self.insert_rule(key, gbnf_string_literal(text))),
-                    Lexeme::Token(token) => Ok(self.insert_rule(key, gbnf_string_literal(token))),
-                    Lexeme::Regex { pattern } => Ok(self.insert_rule(key, gbnf_regex(pattern))),
-                    Lexeme::JsonSchema(json_schema) => {
-                        let schema = SchemaCompiler::compile(json_schema)
-                            .map_err(|e| RenderError::JsonSchemaConversion(e.to_string()))?;
-                        self.insert_schema(key, &schema)
-                    }
-                }
+                rule_key
             }
+            GrammarSyntax::GBNF | GrammarSyntax::Ebnf => limited_key,
+            GrammarSyntax::StructuralTags => unreachable!("structural tags have no rules"),
         }
     }
 
-    /// Render a Schema AST to grammar rules
-    fn insert_schema(&mut self, name: &str, schema: &Schema) -> Result<RuleKey, RenderError> {
-        match schema {
-            Schema::Any => self.insert_primitive("value"),
-            Schema::Unsatisfiable(reason) => {
-                Err(RenderError::JsonSchemaConversion(format!("Unsatisfiable: {}", reason)))
-            }
-            Schema::Null => self.insert_primitive("null"),
-            Schema::Boolean(None) => self.insert_primitive("boolean"),
-            Schema::Boolean(Some(b)) => {
-                let lit = if *b { "true" } else { "false" };
-                Ok(self.insert_rule(name, format!(r#""{}" space"#, lit)))
-            }
-            Schema::Number(num) => self.insert_number_schema(name, num),
-            Schema::String(str_schema) => self.insert_string_schema(name, str_schema),
-            Schema::Array(arr) => self.insert_array_schema(name, arr),
-            Schema::Object(obj) => self.insert_object_schema(name, obj),
-            Schema::AnyOf(alts) | Schema::OneOf(alts) => {
-                let alt_keys: Vec<RuleKey> = alts.iter().enumerate()
-                    .map(|(i, s)| self.insert_schema(&format!("{}-{}", name, i), s))
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(self.insert_alternative(name, &alt_keys))
-            }
-            Schema::Const(val) => {
-                let json_str = serde_json::to_string(val)?;
-                let lit = gbnf_string_literal(&json_str);
-                Ok(self.insert_rule(name, format!("{} space", lit)))
-            }
-            Schema::Enum(vals) => {
-                let alts: Vec<String> = vals.iter()
-                    .map(|v| gbnf_string_literal(&serde_json::to_string(v).unwrap_or_default()))
-                    .collect();
-                Ok(self.insert_rule(name, format!("({}) space", alts.join(" | "))))
-            }
-            Schema::Ref(ref_name) => {
-                Ok(RuleKey(ref_name.split('/').last().unwrap_or(ref_name).to_string(), 0))
-            }
-        }
-    }
+    /// The thinking text followed by the `suffix` ending it.
+    fn insert_thinking_text(
+        &mut self,
+        limit: Option<ThinkingLimit>,
+        suffix: &OrderedLexemes,
+    ) -> Result<Vec<RuleKey>, RenderError> {
+        let max_chars = match limit {
+            Some(ThinkingLimit::Characters(max_chars)) => Some(max_chars),
+            Some(ThinkingLimit::Tokens(_)) | None => None,
+        };
 
-    fn insert_number_schema(&mut self, _name: &str, num: &NumberSchema) -> Result<RuleKey, RenderError> {
-        if num.integer {
-            self.insert_primitive("integer")
-        } else {
-            self.insert_primitive("number")
-        }
-    }
+        let (text_rule, suffix) = self.insert_text_until("thinking_text", suffix, max_chars)?;
 
-    fn insert_string_schema(&mut self, name: &str, str_schema: &StringSchema) -> Result<RuleKey, RenderError> {
-        // Handle format
-        if let Some(ref fmt) = str_schema.format {
-            let fmt_name = format!("{}-string", fmt);
-            if let Some((content, deps)) = lookup_format_rule(&fmt_name) {
-                return self.insert_primitive_with_deps(&fmt_name, content, deps);
+        let text_rule = match limit {
+            Some(ThinkingLimit::Tokens(max_tokens)) => {
+                self.insert_token_limit("thinking", text_rule, max_tokens)
             }
-        }
+            Some(ThinkingLimit::Characters(_)) | None => text_rule,
+        };
 
-        // Handle pattern
-        if let Some(ref pattern) = str_schema.pattern {
-            let pat = pattern.trim_start_matches('^').trim_end_matches('$');
-            return Ok(self.insert_rule(name, format!(r#""\"" ({}) "\"" space"#, pat)));
-        }
+        Ok([text_rule].into_iter().chain(suffix).collect())
+    }
 
-        // Handle length constraints
-        if str_schema.min_length > 0 || str_schema.max_length.is_some() {
-            let char_key = self.insert_primitive("char")?;
-            let rep = self.build_repetition_str(&char_key.to_string(), str_schema.min_length, str_schema.max_length);
-            return Ok(self.insert_rule(name, format!(r#""\"" {} "\"" space"#, rep)));
-        }
+    /// Possibly empty free text ended by `suffix`, and the rule for what of
+    /// the suffix is left. In Lark a suffix opening with text has that text,
+    /// and whitespace right after it, taken into the text's terminal, since a
+    /// terminal for the text alone would run over it.
+    fn insert_text_until(
+        &mut self,
+        key: &str,
+        suffix: &OrderedLexemes,
+        max_chars: Option<usize>,
+    ) -> Result<(RuleKey, Option<RuleKey>), RenderError> {
+        Ok(match (self.syntax, suffix.0.as_slice()) {
+            (
+                GrammarSyntax::Lark | GrammarSyntax::LLGuidance,
+                [Lexeme::Text(marker), rest @ ..],
+            ) => {
+                let (whitespace, rest) = match rest {
+                    [Lexeme::Whitespace { whitespace }, rest @ ..] => (
+                        whitespace
+                            .chars()
+                            .map(escape_class_char)
+                            .collect::<String>(),
+                        rest,
+                    ),
+                    rest => (String::new(), rest),
+                };
+                let text = self.insert_rule(
+                    &key.to_uppercase(),
+                    lark_text_until(marker, &whitespace, max_chars),
+                );
+                let rest = match rest {
+                    [] => None,
+                    rest => Some(OrderedLexemes::from(rest).render(self)?),
+                };
 
-        self.insert_primitive("string")
-    }
+                (text, rest)
+            }
+            _ => {
+                let markers = suffix.literals().unwrap_or_default();
+                let text = self.insert_text(key, &markers, max_chars)?;
 
-    fn insert_array_schema(&mut self, name: &str, arr: &ArraySchema) -> Result<RuleKey, RenderError> {
-        if !arr.prefix_items.is_empty() {
-            // Tuple
-            let item_keys: Vec<RuleKey> = arr.prefix_items.iter().enumerate()
-                .map(|(i, s)| self.insert_schema(&format!("{}-tuple-{}", name, i), s))
-                .collect::<Result<Vec<_>, _>>()?;
-            
-            let comma = self.insert_rule("comma", r#""," space"#.to_string());
-            let mut seq = Vec::new();
-            for (i, key) in item_keys.into_iter().enumerate() {
-                if i > 0 { seq.push(comma.clone()); }
-                seq.push(key);
+                (
+                    self.insert_repetition(key, text, 0, Some(1)),
+                    Some(suffix.render(self)?),
+                )
             }
-            let inner = self.insert_sequence(&format!("{}-items", name), &seq);
-            Ok(self.insert_rule(name, format!(r#""[" space {} "]" space"#, inner)))
-        } else if let Some(ref items) = arr.items {
-            // Homogeneous array
-            let item_key = self.insert_schema(&format!("{}-item", name), items)?;
-            let rep = self.build_repetition_sep(&item_key.to_string(), arr.min_items, arr.max_items, r#""," space"#);
-            Ok(self.insert_rule(name, format!(r#""[" space {} "]" space"#, rep)))
-        } else {
-            self.insert_primitive("array")
-        }
+        })
     }
 
-    fn insert_object_schema(&mut self, name: &str, obj: &ObjectSchema) -> Result<RuleKey, RenderError> {
-        if obj.properties.is_empty() && obj.additional_properties.is_none() {
-            return self.insert_primitive("object");
-        }
+    fn insert_lexeme(&mut self, key: &str, lexeme: &Lexeme) -> Result<RuleKey, RenderError> {
+        // these can hold rules as well as terminals, so they're always a rule
+        match lexeme {
+            Lexeme::Choice { choices } => {
+                let alternative_keys = choices
+                    .iter()
+                    .map(|choice| choice.render(self))
+                    .collect::<Result<Vec<_>, RenderError>>()?;
+
+                return Ok(self.insert_alternative("choice", &alternative_keys));
+            }
+            Lexeme::Optional { optional } => {
+                let optional = optional.render(self)?;
 
-        let mut kv_rules: Vec<(String, RuleKey)> = Vec::new();
+                return Ok(self.insert_repetition("optional", optional, 0, Some(1)));
+            }
+            Lexeme::Whitespace { whitespace } => {
+                // an optional run of at least one, since terminals can't match nothing
+                let class = whitespace
+                    .chars()
+                    .map(escape_class_char)
+                    .collect::<String>();
+                let run = match self.syntax {
+                    GrammarSyntax::Lark | GrammarSyntax::LLGuidance => {
+                        self.insert_rule("WHITESPACE", format_args!("/[{class}]+/"))
+                    }
+                    GrammarSyntax::GBNF | GrammarSyntax::Ebnf => {
+                        self.insert_rule("whitespace", format_args!("[{class}]+"))
+                    }
+                    GrammarSyntax::StructuralTags => unreachable!("structural tags have no rules"),
+                };
 
-        // Generate rules for each property
-        for (prop_name, prop_schema) in &obj.properties {
-            let prop_key = self.insert_schema(&format!("{}-{}", name, prop_name), prop_schema)?;
-            let key_lit = gbnf_string_literal(&format!("\"{}\"", prop_name));
-            let kv_rule = format!(r#"{} space ":" space {}"#, key_lit, prop_key);
-            let kv_key = self.insert_rule(&format!("{}-{}-kv", name, prop_name), kv_rule);
-            kv_rules.push((prop_name.clone(), kv_key));
+                return Ok(self.insert_repetition("whitespace", run, 0, Some(1)));
+            }
+            _ => {}
         }
 
-        let required: Vec<_> = kv_rules.iter()
-            .filter(|(k, _)| obj.required.contains(k))
-            .map(|(_, key)| key.clone())
-            .collect();
-        let optional: Vec<_> = kv_rules.iter()
-            .filter(|(k, _)| !obj.required.contains(k))
-            .map(|(_, key)| key.clone())
-            .collect();
+        match self.syntax {
+            GrammarSyntax::Lark | GrammarSyntax::LLGuidance => {
+                let rule = match lexeme {
+                    Lexeme::Text(text) if self.leads.iter().any(|lead| **lead == **text) => {
+                        lark_text_until(text, "", None)
+                    }
+                    Lexeme::Text(text) => lark_string_literal(text),
+                    Lexeme::Token(token) => lark_token_literal(token),
+                    Lexeme::Regex { pattern } => lark_regex(pattern),
+                    Lexeme::TokenId(id) => lark_token_id(*id),
+                    Lexeme::Choice { .. } | Lexeme::Optional { .. } | Lexeme::Whitespace { .. } => {
+                        unreachable!()
+                    }
+                    Lexeme::JsonSchema(json_schema) => lark_json_schema(json_schema),
+                };
 
-        let mut parts = Vec::new();
-        
-        // Required properties
-        if !required.is_empty() {
-            let comma = self.insert_rule("comma", r#""," space"#.to_string());
-            let mut seq = Vec::new();
-            for (i, key) in required.iter().enumerate() {
-                if i > 0 { seq.push(comma.clone()); }
-                seq.push(key.clone());
+                // llguidance only allows %json and special tokens in rules, not terminals
+                Ok(match lexeme {
+                    Lexeme::JsonSchema(_) | Lexeme::TokenId(_) => self.insert_rule(key, rule),
+                    Lexeme::Token(token) if is_token_name(token) => self.insert_rule(key, rule),
+                    _ => self.insert_rule(&key.to_uppercase(), rule),
+                })
+            }
+            GrammarSyntax::GBNF | GrammarSyntax::Ebnf => {
+                match lexeme {
+                    Lexeme::Text(text) => Ok(self.insert_rule(key, gbnf_string_literal(text))),
+                    // XGrammar only matches text
+                    Lexeme::Token(token) => Ok(self.insert_rule(
+                        key,
+                        match self.syntax {
+                            GrammarSyntax::GBNF => gbnf_token_literal(token),
+                            _ => gbnf_string_literal(token),
+                        },
+                    )),
+                    Lexeme::Regex { pattern } => Ok(self.insert_rule(key, gbnf_regex(pattern)?)),
+                    Lexeme::TokenId(id) => match self.syntax {
+                        GrammarSyntax::GBNF => Ok(self.insert_rule(key, gbnf_token_id(*id))),
+                        _ => Err(RenderError::UnresolvedTokenId(*id)),
+                    },
+                    Lexeme::Choice { .. } | Lexeme::Optional { .. } | Lexeme::Whitespace { .. } => {
+                        unreachable!()
+                    }
+                    Lexeme::JsonSchema(json_schema) => self.insert_json_schema(key, json_schema),
+                }
             }
-            parts.push(self.insert_sequence(&format!("{}-required", name), &seq));
+            GrammarSyntax::StructuralTags => unreachable!("structural tags have no rules"),
         }
+    }
 
-        // Optional properties (simplified - just make them all optional with ?)
-        for opt_key in &optional {
-            let comma_opt = self.insert_rule(
-                &format!("{}-opt", opt_key),
-                format!(r#"("," space {})?"#, opt_key)
-            );
-            parts.push(comma_opt);
-        }
+    /// Writes `value` into a reused buffer, so only a rule that isn't a
+    /// duplicate allocates a body of its own.
+    fn insert_rule(&mut self, key: &str, value: impl Display) -> RuleKey {
+        let mut body = std::mem::take(&mut self.body);
+        body.clear();
+        fmt::Write::write_fmt(&mut body, format_args!("{value}"))
+            .expect("writing to a String is infallible");
 
-        let inner = if parts.is_empty() {
-            self.insert_rule(&format!("{}-empty", name), String::new())
-        } else {
-            self.insert_sequence(&format!("{}-body", name), &parts)
-        };
+        let rule_key = self.insert_body(key, &body);
+        self.body = body;
 
-        Ok(self.insert_rule(name, format!(r#""{{"  space {} "}}" space"#, inner)))
+        rule_key
     }
 
-    fn insert_primitive(&mut self, name: &str) -> Result<RuleKey, RenderError> {
-        if let Some((content, deps)) = lookup_primitive_rule(name) {
-            self.insert_primitive_with_deps(name, content, deps)
-        } else {
-            Ok(RuleKey(name.to_string(), 0))
-        }
-    }
+    fn insert_body(&mut self, key: &str, value: &str) -> RuleKey {
+        let terminal = is_terminal(key);
+        let hash = body_hash(terminal, value);
 
-    fn insert_primitive_with_deps(&mut self, name: &str, content: &str, deps: &[&str]) -> Result<RuleKey, RenderError> {
-        // Add dependencies first
-        for dep in deps {
-            if !self.rules.iter().any(|(k, _)| &k.0 == dep) {
-                self.insert_primitive(dep)?;
+        for &index in self.bodies.get(&hash).into_iter().flatten() {
+            let (rule_key, rule) = self.rules.get_index(index).unwrap();
+
+            // a Lark terminal and rule can't stand in for each other
+            if is_terminal(&rule_key.0) == terminal
+                && rule == value
+                && !self.attributes.contains_key(rule_key)
+            {
+                return rule_key.clone();
             }
         }
-        
-        // Add the primitive itself
-        if !self.rules.iter().any(|(k, _)| &k.0 == name) {
-            self.rules.insert(RuleKey(name.to_string(), 0), content.to_string());
-        }
-        
-        Ok(RuleKey(name.to_string(), 0))
-    }
 
-    fn build_repetition_str(&self, item: &str, min: usize, max: Option<usize>) -> String {
-        match (min, max) {
-            (0, Some(0)) => String::new(),
-            (0, Some(1)) => format!("{}?", item),
-            (1, None) => format!("{}+", item),
-            (0, None) => format!("{}*", item),
-            (min, None) => format!("{}{{{},}}", item, min),
-            (min, Some(max)) if min == max => format!("{}{{{}}}", item, min),
-            (min, Some(max)) => format!("{}{{{},{}}}", item, min, max),
-        }
+        self.insert_new_rule(key, value.to_owned())
     }
 
-    fn build_repetition_sep(&self, item: &str, min: usize, max: Option<usize>, sep: &str) -> String {
-        if max == Some(0) { return String::new(); }
-        if min == 0 && max == Some(1) { return format!("{}?", item); }
-        
-        let inner = format!("({} {})", sep, item);
-        let inner_min = min.saturating_sub(1);
-        let inner_max = max.map(|m| m.saturating_sub(1));
-        let inner_rep = self.build_repetition_str(&inner, inner_min, inner_max);
-        let result = format!("{} {}", item, inner_rep);
-        
-        if min == 0 { format!("({})?", result) } else { result }
+    fn insert_new_rule(&mut self, key: &str, value: String) -> RuleKey {
+        let rule_key = self.next_key(key);
+
+        self.bodies
+            .entry(body_hash(is_terminal(key), &value))
+            .or_default()
+            .push(self.rules.len());
+        self.rules.insert(rule_key.clone(), value);
+
+        rule_key
     }
 
-    fn insert_rule(&mut self, key: &str, value: String) -> RuleKey {
-        let mut count = 0;
-        let mut rule_key = RuleKey(key.to_string(), count);
+    /// A rule for [`Self::define_rule`] to fill in later, so rules inside it
+    /// can refer back to it. It's never shared with another rule.
+    fn reserve_rule(&mut self, key: &str) -> RuleKey {
+        let rule_key = self.next_key(key);
+        self.rules.insert(rule_key.clone(), String::new());
 
-        while let Some(rule) = self.rules.get(&rule_key) {
-            if rule == &value {
-                return rule_key;
-            }
+        rule_key
+    }
 
-            count += 1;
-            rule_key.1 = count;
-        }
+    fn define_rule(&mut self, key: &RuleKey, value: String) {
+        self.rules[key] = value;
+    }
 
-        self.rules.insert(rule_key.clone(), value);
+    fn next_key(&mut self, key: &str) -> RuleKey {
+        let counter = self.counters.entry(key.to_string()).or_default();
+        let rule_key = RuleKey(key.to_string(), *counter);
+        *counter += 1;
 
         rule_key
     }
 
-    fn insert_text_lexeme(&mut self) -> Result<RuleKey, RenderError> {
-        match self.syntax {
-            GrammarSyntax::Lark => {
-                self.insert_lexeme("text", &Lexeme::Text(lark::TEXT.to_string()))
-            }
-            GrammarSyntax::GBNF => {
-                self.insert_lexeme("text", &Lexeme::Text(gbnf::TEXT.to_string()))
+    /// A custom tool's input, held to its own grammar. GBNF can't embed a Lark
+    /// grammar, so that input is left as free text there.
+    fn insert_custom_grammar(
+        &mut self,
+        CustomToolGrammar { definition, syntax }: &CustomToolGrammar,
+    ) -> Result<RuleKey, RenderError> {
+        match (syntax, self.syntax) {
+            (CustomToolSyntax::Regex, _) => self.insert_lexeme(
+                "input",
+                &Lexeme::Regex {
+                    pattern: definition.as_str().into(),
+                },
+            ),
+            (CustomToolSyntax::Lark, GrammarSyntax::Lark | GrammarSyntax::LLGuidance) => {
+                Ok(self.insert_rule("input", format_args!("%lark {{\n{definition}\n}}")))
             }
+            (CustomToolSyntax::Lark, _) => self.insert_text("text", &[], None),
         }
     }
 
-    fn resolve(&mut self, root_key: RuleKey) -> String {
-        let root_rule = self.rules.remove(&root_key).unwrap_or_default();
-
-        match self.syntax {
-            GrammarSyntax::Lark => {
-                format!(
-                    "start: {root_rule}\n{}",
-                    self.rules
-                        .iter()
-                        .map(|(key, value)| format!("{key}: {value}"))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                )
+    /// Free text that never contains one of `markers`, of at most `max_chars`
+    /// characters. GBNF and EBNF only exclude markers from unlimited text.
+    fn insert_text(
+        &mut self,
+        key: &str,
+        markers: &[String],
+        max_chars: Option<usize>,
+    ) -> Result<RuleKey, RenderError> {
+        match (self.syntax, max_chars) {
+            (GrammarSyntax::Lark | GrammarSyntax::LLGuidance, _) => {
+                Ok(self.insert_rule(&key.to_uppercase(), lark_text(markers, max_chars)))
             }
-            GrammarSyntax::GBNF => {
-                format!(
-                    "root ::= {root_rule}\n{}",
-                    self.rules
-                        .iter()
-                        .map(|(key, value)| format!("{key} ::= {value}"))
-                        .collect::<Vec<_>>()
-                        .join("\n")
+            (GrammarSyntax::GBNF | GrammarSyntax::Ebnf, None) if !markers.is_empty() => {
+                Ok(self.insert_text_excluding(key, markers))
+            }
+            (GrammarSyntax::GBNF | GrammarSyntax::Ebnf, _) => {
+                let pattern = match max_chars {
+                    Some(max_chars) => format!("(?s:.){{0,{max_chars}}}"),
+                    None => "(?s:.)+".to_string(),
+                };
+
+                self.insert_lexeme(
+                    key,
+                    &Lexeme::Regex {
+                        pattern: pattern.into(),
+                    },
                 )
             }
+            (GrammarSyntax::StructuralTags, _) => unreachable!("structural tags have no rules"),
         }
     }
-}
 
-// Primitive GBNF rules
-const PRIMITIVE_RULES: &[(&str, &str, &[&str])] = &[
-    ("space", r#"| " " | "\n"{1,2} [ \t]{0,20}"#, &[]),
-    ("boolean", r#"("true" | "false") space"#, &[]),
-    ("decimal-part", "[0-9]{1,16}", &[]),
-    ("integral-part", "[0] | [1-9] [0-9]{0,15}", &[]),
-    ("number", r#"("-"? integral-part) ("." decimal-part)? ([eE] [-+]? integral-part)? space"#, &["integral-part", "decimal-part"]),
-    ("integer", r#"("-"? integral-part) space"#, &["integral-part"]),
-    ("value", "object | array | string | number | boolean | null", &["object", "array", "string", "number", "boolean", "null"]),
-    ("object", r#""{" space ( string ":" space value ("," space string ":" space value)* )? "}" space"#, &["string", "value"]),
-    ("array", r#""[" space ( value ("," space value)* )? "]" space"#, &["value"]),
-    ("char", r#"[^"\\\x7F\x00-\x1F] | [\\] (["\\bfnrt] | "u" [0-9a-fA-F]{4})"#, &[]),
-    ("string", r#""\"" char* "\"" space"#, &["char"]),
-    ("null", r#""null" space"#, &[]),
-];
+    fn resolve(&self, root_keys: &[RuleKey]) -> String {
+        let (root_name, separator) = match self.syntax {
+            GrammarSyntax::Lark | GrammarSyntax::LLGuidance => ("start", ": "),
+            GrammarSyntax::GBNF | GrammarSyntax::Ebnf => ("root", " ::= "),
+            GrammarSyntax::StructuralTags => unreachable!("structural tags have no rules"),
+        };
 
-const FORMAT_RULES: &[(&str, &str, &[&str])] = &[
-    ("date", r#"[0-9]{4} "-" ( "0" [1-9] | "1" [0-2] ) "-" ( "0" [1-9] | [1-2] [0-9] | "3" [0-1] )"#, &[]),
-    ("time", r#"([01] [0-9] | "2" [0-3]) ":" [0-5] [0-9] ":" [0-5] [0-9] ( "." [0-9]{3} )? ( "Z" | ( "+" | "-" ) ( [01] [0-9] | "2" [0-3] ) ":" [0-5] [0-9] )"#, &[]),
-    ("date-time", r#"date "T" time"#, &["date", "time"]),
-    ("date-string", r#""\"" date "\"" space"#, &["date"]),
-    ("time-string", r#""\"" time "\"" space"#, &["time"]),
-    ("date-time-string", r#""\"" date-time "\"" space"#, &["date-time"]),
-];
+        // the grammar is written into the thread's scratch buffer, which has
+        // grown to fit earlier grammars, and copied out at its exact size
+        let root_rule = root_keys.iter().format(" ");
+        let (grammar, ()) =
+            with_scratch(|buffer| Ok(self.write_rules(buffer, root_name, separator, root_rule)?))
+                .expect("writing to a String is infallible");
 
-fn lookup_primitive_rule(name: &str) -> Option<(&'static str, &'static [&'static str])> {
-    PRIMITIVE_RULES.iter()
-        .find(|(n, _, _)| *n == name)
-        .map(|(_, content, deps)| (*content, *deps))
-}
+        match self.syntax {
+            GrammarSyntax::LLGuidance => serde_json::to_string(&lark_top_level_grammar(grammar))
+                .expect("grammars serialize to JSON"),
+            _ => grammar,
+        }
+    }
+
+    fn write_rules(
+        &self,
+        out: &mut impl fmt::Write,
+        root_name: &str,
+        separator: &str,
+        root_rule: impl Display,
+    ) -> fmt::Result {
+        writeln!(out, "{root_name}{separator}{root_rule}")?;
+
+        for (i, (key, value)) in self.rules.iter().enumerate() {
+            if i > 0 {
+                out.write_char('\n')?;
+            }
+
+            match self.attributes.get(key) {
+                Some(attributes) => write!(out, "{key}[{attributes}]{separator}{value}")?,
+                None => write!(out, "{key}{separator}{value}")?,
+            }
+        }
 
-fn lookup_format_rule(name: &str) -> Option<(&'static str, &'static [&'static str])> {
-    FORMAT_RULES.iter()
-        .find(|(n, _, _)| *n == name)
-        .map(|(_, content, deps)| (*content, *deps))
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -730,18 +2231,67 @@ pub enum RenderError {
     #[error("json schema conversion error: {0}")]
     JsonSchemaConversion(String),
 
-    #[error("regex for tool {0} is invalid: {1}")]
-    Regex(String, String),
+    #[error("invalid tools: {}", .0.iter().join("; "))]
+    ToolValidation(Vec<ToolValidationError>),
 
     #[error("tool choice not found in provided tools")]
     ChatToolChoice,
 
-    #[error("lark grammar for tool {0} is invalid: {1}")]
-    Lark(String, String),
+    #[error("tool call format {0} is not registered")]
+    UnknownToolCallFormat(String),
+
+    #[error("{0} can't be written as a structural tag")]
+    StructuralTag(String),
+
+    #[error("message role {0} is not declared in the config")]
+    UnknownRole(String),
+
+    #[error("final message to continue is missing from the rendered prompt")]
+    FinalMessageNotRendered,
+
+    #[error("token id {0} has no text in EBNF, resolve the config with its tokenizer")]
+    UnresolvedTokenId(u32),
+
+    #[error("regex {0:?} can't be converted to GBNF: {1}")]
+    GbnfRegex(String, String),
+
+    #[error("generated grammar failed to compile: {0}")]
+    GrammarCompilation(String),
+
+    #[error(
+        "thinking limit of {0} characters is over the {max} GBNF and EBNF grammars allow",
+        max = ThinkingLimit::MAX_GBNF_CHARACTERS
+    )]
+    ThinkingLimitTooLarge(usize),
+
+    #[error("max_tool_calls must be at least 1, use a tool choice of none to disallow calls")]
+    ZeroMaxToolCalls,
 
     #[error("chat template render error: {0}")]
     Template(#[from] minijinja::Error),
 
     #[error("json serialization error: {0}")]
     Json(#[from] serde_json::Error),
+
+    #[error("failed to write prompt: {0}")]
+    Write(#[from] fmt::Error),
+}
+
+/// One problem with one of the tools in a request.
+#[derive(Debug, Error)]
+pub enum ToolValidationError {
+    #[error("json schema for tool {0} is invalid: {1}")]
+    JsonSchema(String, String),
+
+    #[error("regex for tool {0} is invalid: {1}")]
+    Regex(String, String),
+
+    #[error("lark grammar for tool {0} is invalid: {1}")]
+    Lark(String, String),
+
+    #[error("tool name is empty")]
+    EmptyName,
+
+    #[error("tool name {0} is used by more than one tool")]
+    DuplicateName(String),
 }
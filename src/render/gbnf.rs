@@ -1,17 +1,737 @@
-//! GBNF-specific formatting utilities
+//! GBNF-specific formatting utilities, and JSON schemas converted to GBNF
+//! rules since GBNF has no `%json` like Lark. XGrammar's EBNF is close enough
+//! to GBNF to share all of it.
 
-pub static TEXT: &str = r#"/[^{](.|\n)*/"#;
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use regex_syntax::hir::{Class, Hir, HirKind, Literal};
+use serde_json::Value;
+
+use crate::{
+    render::{RenderError, RuleKey, Rules, is_token_name, json::JsonFormatter},
+    schema::{ArraySchema, NumberSchema, ObjectSchema, Schema, SchemaCompiler, StringSchema},
+};
 
 pub fn gbnf_string_literal(literal: &str) -> String {
-    let escaped = literal
-        .replace('\\', "\\\\")
-        .replace('"', "\\\"")
-        .replace('\n', "\\n")
-        .replace('\r', "\\r")
-        .replace('\t', "\\t");
-    format!(r#""{escaped}""#)
+    let mut escaped = String::with_capacity(literal.len() + 2);
+    escaped.push('"');
+
+    for c in literal.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+/// The special token itself, or its text when llama.cpp can't name it.
+pub fn gbnf_token_literal(token: &str) -> String {
+    match is_token_name(token) {
+        true => token.to_string(),
+        false => gbnf_string_literal(token),
+    }
+}
+
+/// llama.cpp's syntax for matching a token by ID.
+pub fn gbnf_token_id(id: u32) -> String {
+    format!("<[{id}]>")
+}
+
+/// An equivalent GBNF expression for a regex. Anchors are dropped, since a
+/// rule always matches its whole input.
+pub fn gbnf_regex(regex: &str) -> Result<String, RenderError> {
+    let hir = regex_syntax::parse(regex)
+        .map_err(|e| RenderError::GbnfRegex(regex.to_string(), e.to_string()))?;
+
+    Ok(gbnf_hir(&hir))
+}
+
+fn gbnf_hir(hir: &Hir) -> String {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => r#""""#.to_string(),
+        HirKind::Literal(Literal(bytes)) => gbnf_string_literal(&String::from_utf8_lossy(bytes)),
+        HirKind::Class(Class::Unicode(class)) => gbnf_class(
+            class
+                .ranges()
+                .iter()
+                .map(|range| (range.start(), range.end())),
+        ),
+        HirKind::Class(Class::Bytes(class)) => gbnf_class(
+            class
+                .ranges()
+                .iter()
+                .map(|range| (range.start() as char, range.end() as char)),
+        ),
+        HirKind::Repetition(repetition) => repeat(
+            &format!("({})", gbnf_hir(&repetition.sub)),
+            repetition.min as usize,
+            repetition.max.map(|max| max as usize),
+        ),
+        HirKind::Capture(capture) => format!("({})", gbnf_hir(&capture.sub)),
+        HirKind::Concat(hirs) => format!("({})", hirs.iter().map(gbnf_hir).join(" ")),
+        HirKind::Alternation(hirs) => format!("({})", hirs.iter().map(gbnf_hir).join(" | ")),
+    }
+}
+
+fn gbnf_class(ranges: impl Iterator<Item = (char, char)>) -> String {
+    let ranges = ranges.collect::<Vec<_>>();
+
+    // `.` matches any character in GBNF, newlines included
+    if ranges == [('\0', char::MAX)] {
+        return ".".to_string();
+    }
+
+    let ranges = ranges
+        .into_iter()
+        .map(|(start, end)| match start == end {
+            true => gbnf_class_char(start),
+            false => format!("{}-{}", gbnf_class_char(start), gbnf_class_char(end)),
+        })
+        .join("");
+
+    format!("[{ranges}]")
+}
+
+fn gbnf_class_char(c: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\\' | ']' | '[' => format!("\\{c}"),
+        // llama.cpp has no escapes for these, so they're written by code point
+        '^' | '-' => format!("\\x{:02X}", c as u32),
+        c if c.is_control() => format!("\\x{:02X}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+/// Checks a GBNF grammar the way llama.cpp reads one: every rule parses,
+/// every referenced rule is defined and there is a `root`. The error names the
+/// offending rule.
+pub fn validate_gbnf(grammar: &str) -> Result<(), RenderError> {
+    let mut rules: Vec<(&str, String)> = Vec::new();
+
+    for line in grammar.lines() {
+        match line.split_once("::=") {
+            Some((name, body)) if is_rule_name(name.trim()) => {
+                rules.push((name.trim(), body.to_string()));
+            }
+            _ => match rules.last_mut() {
+                Some((_, body)) => {
+                    body.push('\n');
+                    body.push_str(line);
+                }
+                None if line.trim().is_empty() || line.trim_start().starts_with('#') => {}
+                None => return Err(invalid_gbnf("", "text before the first rule")),
+            },
+        }
+    }
+
+    let mut defined = HashSet::new();
+    for (name, _) in &rules {
+        if !defined.insert(*name) {
+            return Err(invalid_gbnf(name, "defined more than once"));
+        }
+    }
+
+    if !defined.contains("root") {
+        return Err(invalid_gbnf("root", "not defined"));
+    }
+
+    for (name, body) in &rules {
+        let references = gbnf_references(body).map_err(|e| invalid_gbnf(name, &e))?;
+
+        if let Some(reference) = references.iter().find(|r| !defined.contains(*r)) {
+            return Err(invalid_gbnf(
+                name,
+                &format!("refers to undefined rule {reference}"),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid_gbnf(rule: &str, problem: &str) -> RenderError {
+    RenderError::GrammarCompilation(format!("rule {rule}: {problem}"))
+}
+
+fn is_rule_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The rules a rule body refers to, or what keeps it from parsing.
+fn gbnf_references(body: &str) -> Result<Vec<&str>, String> {
+    let mut references = Vec::new();
+    let mut depth = 0usize;
+    // whether there's an item for a repetition to apply to
+    let mut item = false;
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => while chars.next_if(|(_, c)| *c != '\n').is_some() {},
+            '"' | '[' => {
+                let end = if c == '"' { '"' } else { ']' };
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => gbnf_escape(&mut chars)?,
+                        Some((_, c)) if c == end => break,
+                        Some(_) => {}
+                        None => return Err(format!("unterminated {c}")),
+                    }
+                }
+                item = true;
+            }
+            '.' => item = true,
+            '<' => {
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '>')) if !token.is_empty() => break,
+                        Some((_, c)) if !c.is_whitespace() && c != '<' && c != '>' => token.push(c),
+                        _ => return Err("unterminated token".into()),
+                    }
+                }
+                item = true;
+            }
+            '(' => {
+                depth += 1;
+                item = false;
+            }
+            ')' => {
+                depth = depth.checked_sub(1).ok_or("unmatched )")?;
+                item = true;
+            }
+            '|' => item = false,
+            '*' | '+' | '?' | '{' if !item => return Err(format!("{c} follows nothing")),
+            '*' | '+' | '?' => {}
+            '{' => {
+                let mut bounds = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => bounds.push(c),
+                        None => return Err("unterminated {".into()),
+                    }
+                }
+
+                let (min, max) = bounds.split_once(',').unwrap_or((&bounds, &bounds));
+                let min = min.trim().parse::<usize>();
+                let max = match max.trim() {
+                    "" => Ok(None),
+                    max => max.parse::<usize>().map(Some),
+                };
+                match (min, max) {
+                    (Ok(min), Ok(max)) if max.is_none_or(|max| min <= max) => {}
+                    _ => return Err(format!("invalid repetition {{{bounds}}}")),
+                }
+            }
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+                {
+                    end = i + c.len_utf8();
+                }
+
+                references.push(&body[start..end]);
+                item = true;
+            }
+            c => return Err(format!("unexpected {c:?}")),
+        }
+    }
+
+    match depth {
+        0 => Ok(references),
+        _ => Err("unclosed (".into()),
+    }
+}
+
+/// Consumes the rest of an escape inside a literal or character class.
+fn gbnf_escape(chars: &mut impl Iterator<Item = (usize, char)>) -> Result<(), String> {
+    let digits = match chars.next() {
+        Some((_, 'x')) => 2,
+        Some((_, 'u')) => 4,
+        Some((_, 'U')) => 8,
+        Some((_, 't' | 'r' | 'n' | '\\' | '"' | '[' | ']')) => return Ok(()),
+        Some((_, c)) => return Err(format!("unknown escape \\{c}")),
+        None => return Err("unterminated escape".into()),
+    };
+
+    for _ in 0..digits {
+        match chars.next() {
+            Some((_, c)) if c.is_ascii_hexdigit() => {}
+            _ => return Err(format!("escape needs {digits} hex digits")),
+        }
+    }
+
+    Ok(())
+}
+
+/// `item` repeated between `min` and `max` times. `item` must be a single
+/// rule or a group.
+fn repeat(item: &str, min: usize, max: Option<usize>) -> String {
+    match (min, max) {
+        (0, None) => format!("{item}*"),
+        (1, None) => format!("{item}+"),
+        (0, Some(1)) => format!("{item}?"),
+        (min, None) => format!("{item}{{{min},}}"),
+        (min, Some(max)) if min == max => format!("{item}{{{min}}}"),
+        (min, Some(max)) => format!("{item}{{{min},{max}}}"),
+    }
+}
+
+/// A rule name for a property, which can be any string.
+fn rule_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '-',
+        })
+        .collect()
 }
 
-pub fn gbnf_regex(regex: &str) -> String {
-    format!("/{regex}/")
+impl Rules {
+    pub(super) fn insert_json_schema(
+        &mut self,
+        name: &str,
+        json_schema: &Value,
+    ) -> Result<RuleKey, RenderError> {
+        let schema = SchemaCompiler::compile(json_schema)
+            .map_err(|e| RenderError::JsonSchemaConversion(e.to_string()))?;
+
+        // references are only unique within one schema
+        self.definitions.clear();
+        self.insert_schema(name, &schema)
+    }
+
+    fn insert_schema(&mut self, name: &str, schema: &Schema) -> Result<RuleKey, RenderError> {
+        Ok(match schema {
+            Schema::Any => self.insert_primitive("value"),
+            Schema::Unsatisfiable(reason) => {
+                return Err(RenderError::JsonSchemaConversion(format!(
+                    "{name} can't match anything: {reason}"
+                )));
+            }
+            Schema::Null => self.insert_primitive("null"),
+            Schema::Boolean(None) => self.insert_primitive("boolean"),
+            Schema::Boolean(Some(value)) => self.insert_json_values(name, &[Value::Bool(*value)]),
+            Schema::Number(NumberSchema { integer: true }) => self.insert_primitive("integer"),
+            Schema::Number(NumberSchema { integer: false }) => self.insert_primitive("number"),
+            Schema::String(string) => self.insert_string_schema(name, string)?,
+            Schema::Array(array) => self.insert_array_schema(name, array)?,
+            Schema::Object(object) => self.insert_object_schema(name, object)?,
+            Schema::AnyOf(schemas) | Schema::OneOf(schemas) => {
+                let alternative_keys = schemas
+                    .iter()
+                    .enumerate()
+                    .map(|(i, schema)| self.insert_schema(&format!("{name}-{i}"), schema))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.insert_alternative(name, &alternative_keys)
+            }
+            Schema::Const(value) => self.insert_json_values(name, std::slice::from_ref(value)),
+            Schema::Enum(values) => self.insert_json_values(name, values),
+            Schema::Ref(reference) => self.definitions[reference].clone(),
+            Schema::Recursive(reference, schema) => {
+                let key = self.reserve_rule(name);
+                self.definitions.insert(reference.clone(), key.clone());
+
+                let definition = self.insert_schema(name, schema)?;
+                self.define_rule(&key, definition.to_string());
+
+                key
+            }
+        })
+    }
+
+    fn insert_json_values(&mut self, name: &str, values: &[Value]) -> RuleKey {
+        let space = self.insert_primitive("space");
+        let values = values
+            .iter()
+            .map(|value| gbnf_string_literal(&JsonFormatter::compact().display(value).to_string()))
+            .join(" | ");
+
+        self.insert_rule(name, format_args!("({values}) {space}"))
+    }
+
+    fn insert_string_schema(
+        &mut self,
+        name: &str,
+        string: &StringSchema,
+    ) -> Result<RuleKey, RenderError> {
+        let format = match string.format.as_deref() {
+            Some("date") => Some("date"),
+            Some("time") => Some("time"),
+            Some("date-time") => Some("date-time"),
+            _ => None,
+        };
+
+        let content = match (format, &string.pattern) {
+            (Some(format), _) => self.insert_primitive(format).to_string(),
+            // patterns are treated as anchored, like most engines do
+            (None, Some(pattern)) => gbnf_regex(pattern)?,
+            (None, None) if string.min_length > 0 || string.max_length.is_some() => repeat(
+                &self.insert_primitive("char").to_string(),
+                string.min_length,
+                string.max_length,
+            ),
+            (None, None) => return Ok(self.insert_primitive("string")),
+        };
+
+        let space = self.insert_primitive("space");
+        Ok(self.insert_rule(name, format_args!(r#""\"" {content} "\"" {space}"#)))
+    }
+
+    fn insert_array_schema(
+        &mut self,
+        name: &str,
+        array: &ArraySchema,
+    ) -> Result<RuleKey, RenderError> {
+        let ArraySchema {
+            prefix_items,
+            items,
+            min_items,
+            max_items,
+        } = array;
+
+        let space = self.insert_primitive("space");
+        let item = match items {
+            Some(items) => self.insert_schema(&format!("{name}-item"), items)?,
+            None if prefix_items.is_empty() => self.insert_primitive("value"),
+            None => {
+                let tuple = self.insert_tuple(name, prefix_items)?;
+                return Ok(
+                    self.insert_rule(name, format_args!(r#""[" {space} {tuple} "]" {space}"#))
+                );
+            }
+        };
+
+        let elements = match prefix_items.is_empty() {
+            // the tuple's items are all required, any more are optional
+            false => format!(
+                r#"{} ("," {space} {item})*"#,
+                self.insert_tuple(name, prefix_items)?
+            ),
+            true if *max_items == Some(0) => String::new(),
+            true => {
+                let rest = repeat(
+                    &format!(r#"("," {space} {item})"#),
+                    min_items.saturating_sub(1),
+                    max_items.map(|max| max - 1),
+                );
+
+                match min_items {
+                    0 => format!("({item} {rest})?"),
+                    _ => format!("{item} {rest}"),
+                }
+            }
+        };
+
+        Ok(self.insert_rule(name, format_args!(r#""[" {space} {elements} "]" {space}"#)))
+    }
+
+    fn insert_tuple(
+        &mut self,
+        name: &str,
+        prefix_items: &[Schema],
+    ) -> Result<RuleKey, RenderError> {
+        let space = self.insert_primitive("space");
+        let items = prefix_items
+            .iter()
+            .enumerate()
+            .map(|(i, schema)| self.insert_schema(&format!("{name}-{i}"), schema))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let separator = format!(r#" "," {space} "#);
+        Ok(self.insert_rule(&format!("{name}-tuple"), items.iter().format(&separator)))
+    }
+
+    /// Properties in the order the schema lists them, required ones first.
+    fn insert_object_schema(
+        &mut self,
+        name: &str,
+        object: &ObjectSchema,
+    ) -> Result<RuleKey, RenderError> {
+        let ObjectSchema {
+            properties,
+            required,
+            additional_properties,
+        } = object;
+
+        let space = self.insert_primitive("space");
+
+        let mut required_keys = Vec::new();
+        let mut optional_keys = Vec::new();
+        for (property, schema) in properties {
+            let property_name = format!("{name}-{}", rule_name(property));
+            let value = self.insert_schema(&property_name, schema)?;
+            let key = gbnf_string_literal(
+                &JsonFormatter::compact()
+                    .display(&Value::String(property.clone()))
+                    .to_string(),
+            );
+
+            let member = self.insert_rule(
+                &format!("{property_name}-kv"),
+                format_args!(r#"{key} {space} ":" {space} {value}"#),
+            );
+
+            match required.contains(property) {
+                true => required_keys.push(member),
+                false => optional_keys.push(member),
+            }
+        }
+
+        let additional = additional_properties
+            .as_deref()
+            .map(|schema| {
+                let value = self.insert_schema(&format!("{name}-additional"), schema)?;
+                let string = self.insert_primitive("string");
+
+                Ok::<_, RenderError>(self.insert_rule(
+                    &format!("{name}-additional-kv"),
+                    format_args!(r#"{string} ":" {space} {value}"#),
+                ))
+            })
+            .transpose()?;
+
+        let comma = |member: &RuleKey| format!(r#"("," {space} {member})"#);
+        let additional_rest = additional
+            .iter()
+            .map(|member| format!("{}*", comma(member)))
+            .collect::<Vec<_>>();
+
+        let members = match required_keys.is_empty() {
+            false => required_keys
+                .iter()
+                .map(ToString::to_string)
+                .chain(
+                    optional_keys
+                        .iter()
+                        .map(|member| format!("{}?", comma(member))),
+                )
+                .chain(additional_rest)
+                .join(" "),
+            // whichever member comes first has no comma before it
+            true => {
+                let alternatives = optional_keys
+                    .iter()
+                    .enumerate()
+                    .map(|(i, first)| {
+                        std::iter::once(first.to_string())
+                            .chain(
+                                optional_keys[i + 1..]
+                                    .iter()
+                                    .map(|m| format!("{}?", comma(m))),
+                            )
+                            .chain(additional_rest.iter().cloned())
+                            .join(" ")
+                    })
+                    .chain(
+                        additional
+                            .iter()
+                            .map(|member| format!("{member} {}*", comma(member))),
+                    )
+                    .join(" | ");
+
+                match alternatives.is_empty() {
+                    true => String::new(),
+                    false => format!("({alternatives})?"),
+                }
+            }
+        };
+
+        Ok(self.insert_rule(name, format_args!(r#""{{" {space} {members} "}}" {space}"#)))
+    }
+
+    /// Free text that never contains one of `markers`, so a marker always opens
+    /// whatever follows the text. GBNF has no negation, so each rule is a state
+    /// tracking the longest start of a marker the text ends with.
+    pub(super) fn insert_text_excluding(&mut self, name: &str, markers: &[String]) -> RuleKey {
+        let markers = markers
+            .iter()
+            .filter(|marker| !marker.is_empty())
+            .map(|marker| marker.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let contains_marker = |text: &[char]| {
+            markers
+                .iter()
+                .any(|marker| text.windows(marker.len()).any(|window| window == marker))
+        };
+
+        let mut states = vec![Vec::new()];
+        for marker in &markers {
+            for end in 1..marker.len() {
+                let start = &marker[..end];
+                if !states.iter().any(|state| state == start) && !contains_marker(start) {
+                    states.push(start.to_vec());
+                }
+            }
+        }
+
+        // the state after each character that can continue a marker, or none
+        // once the text would end with a whole one
+        let next = |state: &[char], c: char| {
+            let text = state.iter().copied().chain([c]).collect::<Vec<_>>();
+            if markers.iter().any(|marker| text.ends_with(marker)) {
+                return None;
+            }
+
+            (0..=text.len())
+                .find_map(|start| states.iter().position(|state| *state == text[start..]))
+        };
+        let marker_chars = markers
+            .iter()
+            .flatten()
+            .copied()
+            .sorted()
+            .dedup()
+            .collect::<Vec<_>>();
+
+        // reserved before the bodies are built, since the states refer to each other
+        let reserve = |rules: &mut Self| {
+            let counter = rules.counters.entry(name.to_string()).or_default();
+            let key = RuleKey(name.to_string(), *counter);
+            *counter += 1;
+            key
+        };
+        let text = reserve(self);
+        let keys = states.iter().map(|_| reserve(self)).collect::<Vec<_>>();
+
+        let transitions = states
+            .iter()
+            .map(|state| {
+                let mut targets = vec![Vec::new(); states.len()];
+                let mut excluded = Vec::new();
+
+                for &c in &marker_chars {
+                    match next(state, c) {
+                        Some(0) => {}
+                        Some(target) => {
+                            targets[target].push(c);
+                            excluded.push(c);
+                        }
+                        None => excluded.push(c),
+                    }
+                }
+
+                let other = match excluded.is_empty() {
+                    true => ".".to_string(),
+                    false => format!("[^{}]", excluded.into_iter().map(gbnf_class_char).join("")),
+                };
+
+                [format!("{other} {}", keys[0])]
+                    .into_iter()
+                    .chain(
+                        targets
+                            .iter()
+                            .zip(&keys)
+                            .filter(|(chars, _)| !chars.is_empty())
+                            .map(|(chars, key)| {
+                                format!(
+                                    "[{}] {key}",
+                                    chars.iter().copied().map(gbnf_class_char).join("")
+                                )
+                            }),
+                    )
+                    .join(" | ")
+            })
+            .collect::<Vec<_>>();
+
+        self.rules.insert(text.clone(), transitions[0].clone());
+        for (key, transitions) in keys.into_iter().zip(transitions) {
+            self.rules.insert(key, format!("({transitions})?"));
+        }
+
+        text
+    }
+
+    /// The shared rules for JSON values, inserted once along with the ones
+    /// they refer to.
+    fn insert_primitive(&mut self, name: &'static str) -> RuleKey {
+        if let Some(key) = self.primitives.get(name) {
+            return key.clone();
+        }
+
+        // reserved before the body is built, since values and the containers
+        // holding them refer to each other
+        let counter = self.counters.entry(name.to_string()).or_default();
+        let key = RuleKey(name.to_string(), *counter);
+        *counter += 1;
+        self.primitives.insert(name, key.clone());
+
+        let body = match name {
+            // XGrammar needs the empty alternative spelled out
+            "space" => r#""" | " " | "\n"{1,2} [ \t]{0,20}"#.to_string(),
+            "null" => format!(r#""null" {}"#, self.insert_primitive("space")),
+            "boolean" => format!(r#"("true" | "false") {}"#, self.insert_primitive("space")),
+            "decimal-part" => "[0-9]{1,16}".to_string(),
+            "integral-part" => "[0] | [1-9] [0-9]{0,15}".to_string(),
+            "integer" => format!(
+                r#""-"? {} {}"#,
+                self.insert_primitive("integral-part"),
+                self.insert_primitive("space")
+            ),
+            "number" => {
+                let integral = self.insert_primitive("integral-part");
+                let decimal = self.insert_primitive("decimal-part");
+                let space = self.insert_primitive("space");
+
+                format!(r#""-"? {integral} ("." {decimal})? ([eE] [-+]? {integral})? {space}"#)
+            }
+            "char" => r#"[^"\\\x7F\x00-\x1F] | [\\] (["\\bfnrt] | "u" [0-9a-fA-F]{4})"#.to_string(),
+            "string" => format!(
+                r#""\"" {}* "\"" {}"#,
+                self.insert_primitive("char"),
+                self.insert_primitive("space")
+            ),
+            "value" => ["object", "array", "string", "number", "boolean", "null"]
+                .into_iter()
+                .map(|primitive| self.insert_primitive(primitive))
+                .join(" | "),
+            "object" => {
+                let string = self.insert_primitive("string");
+                let value = self.insert_primitive("value");
+                let space = self.insert_primitive("space");
+
+                format!(
+                    r#""{{" {space} ({string} ":" {space} {value} ("," {space} {string} ":" {space} {value})*)? "}}" {space}"#
+                )
+            }
+            "array" => {
+                let value = self.insert_primitive("value");
+                let space = self.insert_primitive("space");
+
+                format!(r#""[" {space} ({value} ("," {space} {value})*)? "]" {space}"#)
+            }
+            "date" => r#"[0-9]{4} "-" ("0" [1-9] | "1" [0-2]) "-" ("0" [1-9] | [1-2] [0-9] | "3" [0-1])"#
+                .to_string(),
+            "time" => r#"([01] [0-9] | "2" [0-3]) ":" [0-5] [0-9] ":" [0-5] [0-9] ("." [0-9]{3})? ("Z" | ("+" | "-") ([01] [0-9] | "2" [0-3]) ":" [0-5] [0-9])"#
+                .to_string(),
+            "date-time" => format!(
+                r#"{} "T" {}"#,
+                self.insert_primitive("date"),
+                self.insert_primitive("time")
+            ),
+            _ => unreachable!("unknown primitive {name}"),
+        };
+
+        self.rules.insert(key.clone(), body);
+        key
+    }
 }
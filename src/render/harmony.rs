@@ -0,0 +1,458 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use chrono::Utc;
+use itertools::Itertools;
+use serde_json::Value;
+
+use crate::{
+    Arguments, HarmonyBuiltinTool, HarmonyChannel, HarmonyOptions, Lexeme, OrderedLexemes,
+    Thinking, ThinkingMode, ToolCall, ToolCalls,
+    render::{
+        ReasoningEffort, RenderOptions, ToolConstraint,
+        schema::ChatToolChoice,
+        template::{ChatTemplateChunk, ChatTemplateContent, TemplateChatMessage, TemplateTool},
+    },
+};
+
+static DEFAULT_MODEL_IDENTITY: &str = "You are ChatGPT, a large language model trained by OpenAI.";
+static DEFAULT_KNOWLEDGE_CUTOFF: &str = "2024-06";
+
+static ALL_CHANNELS: &[HarmonyChannel] = &[
+    HarmonyChannel::Analysis,
+    HarmonyChannel::Commentary,
+    HarmonyChannel::Final,
+];
+
+/// Ends a final answer and a tool call respectively.
+pub(crate) static HARMONY_STOP_SEQUENCES: &[&str] = &["<|return|>", "<|call|>"];
+
+static BROWSER_TOOL: &str = r#"## browser
+
+// Tool for browsing.
+// The `cursor` appears in brackets before each browsing display: `[{cursor}]`.
+// Cite information from the tool using the following format:
+// `【{cursor}†L{line_start}(-L{line_end})?】`, for example: `【6†L9-L11】` or `【8†L3】`.
+// Do not quote more than 10 words directly from the tool output.
+// sources=web (default: web)
+namespace browser {
+
+// Searches for information related to `query` and displays `topn` results.
+type search = (_: {
+query: string,
+topn?: number, // default: 10
+source?: string,
+}) => any;
+
+// Opens the link `id` from the page indicated by `cursor` starting at line number `loc`, showing `num_lines` lines.
+// Valid link ids are displayed with the formatting: `【{id}†.*】`.
+// If `cursor` is not provided, the most recent page is implied.
+// If `id` is a string, it is treated as a fully qualified URL associated with `source`.
+// If `loc` is not provided, the viewport will be positioned at the beginning of the document or centered on the most relevant passage, if available.
+// Use this function without `id` to scroll to a new location of an opened page.
+type open = (_: {
+id?: number | string, // default: -1
+cursor?: number, // default: -1
+loc?: number, // default: -1
+num_lines?: number, // default: -1
+view_source?: boolean, // default: false
+source?: string,
+}) => any;
+
+// Finds exact matches of `pattern` in the current page, or the page given by `cursor`.
+type find = (_: {
+pattern: string,
+cursor?: number, // default: -1
+}) => any;
+
+} // namespace browser"#;
+
+static PYTHON_TOOL: &str = "## python
+
+Use this tool to execute Python code in your chain of thought. The code will not be shown to the user. This tool should be used for internal reasoning, but not for code that is intended to be visible to the user (e.g. when creating plots, tables, or files).
+
+When you send a message containing Python code to python, it will be executed in a stateful Jupyter notebook environment. python will respond with the output of the execution or time out after 120.0 seconds. The drive at '/mnt/data' can be used to save and persist user files. Internet access for this session is UNKNOWN. Depends on the cluster.";
+
+impl Display for HarmonyChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HarmonyChannel::Analysis => write!(f, "analysis"),
+            HarmonyChannel::Commentary => write!(f, "commentary"),
+            HarmonyChannel::Final => write!(f, "final"),
+        }
+    }
+}
+
+/// A `<|channel|>` header, naming the channel as text since it's not a token.
+fn channel_header(channel: HarmonyChannel, rest: &str) -> OrderedLexemes {
+    OrderedLexemes(vec![
+        Lexeme::Token("<|channel|>".into()),
+        Lexeme::Text(format!("{channel}{rest}").into()),
+    ])
+}
+
+impl HarmonyOptions {
+    /// The request's channels, then the config's, in the order Harmony lists
+    /// them.
+    pub(crate) fn channels(&self, options: &RenderOptions) -> Vec<HarmonyChannel> {
+        let channels = options
+            .harmony_channels
+            .as_deref()
+            .or(self.channels.as_deref())
+            .unwrap_or(ALL_CHANNELS);
+
+        ALL_CHANNELS
+            .iter()
+            .copied()
+            .filter(|channel| channels.contains(channel))
+            .collect()
+    }
+
+    /// The reasoning on the analysis channel, then tool calls on the
+    /// commentary channel. Content is left unconstrained, but only allowed
+    /// when the final channel is.
+    pub(super) fn constraint(
+        channels: &[HarmonyChannel],
+        tools: Vec<TemplateTool>,
+        tool_choice: ChatToolChoice,
+        options: &RenderOptions,
+    ) -> Option<ToolConstraint<'static>> {
+        if tools.is_empty() || matches!(tool_choice, ChatToolChoice::None) {
+            return None;
+        }
+
+        let thinking_mode = options.thinking.unwrap_or_default();
+        let thinking = (channels.contains(&HarmonyChannel::Analysis)
+            && thinking_mode != ThinkingMode::Disabled)
+            .then(|| Thinking {
+                prefix: {
+                    let mut prefix = channel_header(HarmonyChannel::Analysis, "");
+                    prefix.0.push(Lexeme::Token("<|message|>".into()));
+                    prefix
+                },
+                suffix: OrderedLexemes(vec![
+                    Lexeme::Token("<|end|>".into()),
+                    Lexeme::Token("<|start|>".into()),
+                    Lexeme::Text("assistant".into()),
+                ]),
+                budget: None,
+                toggle: None,
+                mode: None,
+                interleaved: false,
+            });
+
+        let tool_call = ToolCall::NamedParameters {
+            prefix: Some(channel_header(HarmonyChannel::Commentary, " to=functions.")),
+            id: None,
+            delimiter: Some(OrderedLexemes(vec![
+                Lexeme::Text(" ".into()),
+                Lexeme::Token("<|constrain|>".into()),
+                Lexeme::Text("json".into()),
+                Lexeme::Token("<|message|>".into()),
+            ])),
+            arguments: Arguments::JsonObject,
+            suffix: Some(Lexeme::Token("<|call|>".into()).into()),
+        };
+
+        Some(ToolConstraint {
+            thinking: thinking.map(Cow::Owned),
+            thinking_mode,
+            tool_calls: Some(Cow::Owned(ToolCalls::ToolCall { tool_call })),
+            tools,
+            tool_choice: match channels.contains(&HarmonyChannel::Final) {
+                true => tool_choice,
+                false => tool_choice.required(),
+            },
+            response_schema: None,
+            continued: false,
+        })
+    }
+
+    /// Renders the conversation in the Harmony format used by gpt-oss, ending
+    /// with the header of the assistant message to generate if
+    /// `add_generation_prompt` is set.
+    pub(crate) fn render_to(
+        &self,
+        out: &mut impl fmt::Write,
+        messages: &[TemplateChatMessage],
+        tools: &[TemplateTool],
+        options: &RenderOptions,
+    ) -> fmt::Result {
+        self.write_system_message(out, options, !tools.is_empty())?;
+
+        let instructions = messages
+            .iter()
+            .filter(|message| matches!(&*message.role, "system" | "developer"))
+            .map(|message| content_text(&message.content))
+            .join("\n\n");
+
+        if !instructions.is_empty() || !tools.is_empty() {
+            write_developer_message(out, &instructions, tools)?;
+        }
+
+        let mut tool_names = HashMap::new();
+
+        for message in messages {
+            let content = content_text(&message.content);
+
+            match &*message.role {
+                "user" => write!(out, "<|start|>user<|message|>{content}<|end|>")?,
+                "assistant" => {
+                    let tool_calls = message.tool_calls.as_deref().unwrap_or_default();
+
+                    if !content.is_empty() {
+                        let channel = if tool_calls.is_empty() {
+                            "final"
+                        } else {
+                            "commentary"
+                        };
+
+                        write!(
+                            out,
+                            "<|start|>assistant<|channel|>{channel}<|message|>{content}<|end|>"
+                        )?;
+                    }
+
+                    for tool_call in tool_calls {
+                        let name = tool_call.function.name.as_deref().unwrap_or_default();
+
+                        if let Some(id) = &tool_call.id {
+                            tool_names.insert(id.as_str(), name);
+                        }
+
+                        write!(
+                            out,
+                            "<|start|>assistant to=functions.{name}<|channel|>commentary json<|message|>{}<|call|>",
+                            tool_call.function.arguments.as_deref().unwrap_or("{}")
+                        )?;
+                    }
+                }
+                "tool" => {
+                    let tool_call_id = message.tool_call_id.as_deref().unwrap_or_default();
+                    let name = tool_names
+                        .get(tool_call_id)
+                        .copied()
+                        .unwrap_or(tool_call_id);
+
+                    write!(
+                        out,
+                        "<|start|>functions.{name} to=assistant<|channel|>commentary<|message|>{content}<|end|>"
+                    )?;
+                }
+                _ => {}
+            }
+        }
+
+        if options.add_generation_prompt {
+            write!(out, "<|start|>assistant")?;
+        }
+
+        Ok(())
+    }
+
+    fn write_system_message(
+        &self,
+        out: &mut impl fmt::Write,
+        options: &RenderOptions,
+        has_functions: bool,
+    ) -> fmt::Result {
+        let HarmonyOptions {
+            builtin_tools,
+            model_identity,
+            knowledge_cutoff,
+            ..
+        } = self;
+        let reasoning_effort = options.reasoning_effort.unwrap_or(ReasoningEffort::Medium);
+        let knowledge_cutoff = options
+            .knowledge_cutoff
+            .as_ref()
+            .or(knowledge_cutoff.as_ref());
+        let channels = self.channels(options);
+
+        write!(
+            out,
+            "<|start|>system<|message|>{}\nKnowledge cutoff: {}\nCurrent date: {}\n\nReasoning: {reasoning_effort}\n\n",
+            model_identity.as_deref().unwrap_or(DEFAULT_MODEL_IDENTITY),
+            knowledge_cutoff.map_or(DEFAULT_KNOWLEDGE_CUTOFF, String::as_str),
+            Utc::now().format("%Y-%m-%d"),
+        )?;
+
+        let builtin_tools = [
+            (HarmonyBuiltinTool::Browser, BROWSER_TOOL),
+            (HarmonyBuiltinTool::Python, PYTHON_TOOL),
+        ]
+        .into_iter()
+        .filter(|(tool, _)| builtin_tools.contains(tool))
+        .map(|(_, section)| section)
+        .collect::<Vec<_>>();
+
+        if !builtin_tools.is_empty() {
+            write!(out, "# Tools\n\n{}\n\n", builtin_tools.join("\n\n"))?;
+        }
+
+        write!(
+            out,
+            "# Valid channels: {}. Channel must be included for every message.",
+            channels.iter().format(", ")
+        )?;
+
+        if has_functions && channels.contains(&HarmonyChannel::Commentary) {
+            write!(
+                out,
+                "\nCalls to these tools must go to the commentary channel: 'functions'."
+            )?;
+        }
+
+        write!(out, "<|end|>")
+    }
+}
+
+fn write_developer_message(
+    out: &mut impl fmt::Write,
+    instructions: &str,
+    tools: &[TemplateTool],
+) -> fmt::Result {
+    write!(out, "<|start|>developer<|message|>")?;
+
+    if !instructions.is_empty() {
+        write!(out, "# Instructions\n\n{instructions}")?;
+
+        if !tools.is_empty() {
+            write!(out, "\n\n")?;
+        }
+    }
+
+    if !tools.is_empty() {
+        write!(out, "# Tools\n\n## functions\n\nnamespace functions {{\n\n")?;
+
+        for tool in tools {
+            write_function(out, tool)?;
+            writeln!(out)?;
+        }
+
+        write!(out, "}} // namespace functions")?;
+    }
+
+    write!(out, "<|end|>")
+}
+
+/// Declares a tool as a TypeScript function type, the way Harmony describes
+/// functions to the model.
+fn write_function(out: &mut impl fmt::Write, tool: &TemplateTool) -> fmt::Result {
+    if let Some(description) = &tool.description {
+        for line in description.lines() {
+            writeln!(out, "// {line}")?;
+        }
+    }
+
+    let Some(properties) = tool
+        .parameters
+        .get("properties")
+        .and_then(Value::as_object)
+        .filter(|properties| !properties.is_empty())
+    else {
+        return writeln!(out, "type {} = () => any;", tool.name);
+    };
+
+    let required = tool
+        .parameters
+        .get("required")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    writeln!(out, "type {} = (_: {{", tool.name)?;
+
+    for (name, schema) in properties {
+        if let Some(description) = schema.get("description").and_then(Value::as_str) {
+            for line in description.lines() {
+                writeln!(out, "// {line}")?;
+            }
+        }
+
+        let optional = if required.iter().any(|key| key == name) {
+            ""
+        } else {
+            "?"
+        };
+
+        write!(out, "{name}{optional}: {},", TypeScriptType(schema))?;
+
+        match schema.get("default") {
+            Some(default) => writeln!(out, " // default: {default}")?,
+            None => writeln!(out)?,
+        }
+    }
+
+    writeln!(out, "}}) => any;")
+}
+
+/// A best-effort TypeScript type for a JSON schema.
+struct TypeScriptType<'a>(&'a Value);
+
+impl Display for TypeScriptType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let TypeScriptType(schema) = self;
+
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            return write!(f, "{}", values.iter().format(" | "));
+        }
+
+        if let Some(variants) = ["anyOf", "oneOf"]
+            .iter()
+            .find_map(|key| schema.get(key).and_then(Value::as_array))
+        {
+            return write!(f, "{}", variants.iter().map(TypeScriptType).format(" | "));
+        }
+
+        match schema.get("type") {
+            Some(Value::String(ty)) if ty == "array" => match schema.get("items") {
+                Some(items) => write!(f, "{}[]", TypeScriptType(items)),
+                None => write!(f, "any[]"),
+            },
+            Some(Value::String(ty)) => write!(f, "{}", primitive_type(ty)),
+            Some(Value::Array(types)) => write!(
+                f,
+                "{}",
+                types
+                    .iter()
+                    .map(|ty| ty.as_str().map_or("any", primitive_type))
+                    .format(" | ")
+            ),
+            _ => write!(f, "any"),
+        }
+    }
+}
+
+fn primitive_type(ty: &str) -> &'static str {
+    match ty {
+        "string" => "string",
+        "integer" | "number" => "number",
+        "boolean" => "boolean",
+        "null" => "null",
+        "array" => "any[]",
+        "object" => "object",
+        _ => "any",
+    }
+}
+
+fn content_text<'a>(content: &'a ChatTemplateContent) -> Cow<'a, str> {
+    match content {
+        ChatTemplateContent::Collapsed(text) => Cow::Borrowed(text),
+        ChatTemplateContent::Chunks(chunks) => match chunks.as_slice() {
+            [ChatTemplateChunk::Text { text }] => Cow::Borrowed(text),
+            _ => Cow::Owned(
+                chunks
+                    .iter()
+                    .filter_map(|chunk| match chunk {
+                        ChatTemplateChunk::Text { text } => Some(&**text),
+                        ChatTemplateChunk::Image { .. } => None,
+                    })
+                    .collect(),
+            ),
+        },
+    }
+}
@@ -0,0 +1,642 @@
+use std::{
+    fmt::{self, Display},
+    io::{self, Write},
+};
+
+use itertools::{Either, Itertools};
+use serde::Serialize;
+use serde_json::{
+    Value,
+    ser::{CharEscape, CompactFormatter, Formatter},
+};
+
+pub struct JsonFormatter<'a> {
+    pub indent_width: Option<usize>,
+    pub key_separator: &'a str,
+    pub item_separator: &'a str,
+    pub sort_keys: bool,
+    pub ensure_ascii: bool,
+    pub escape_solidus: bool,
+    /// Escape `<`, `>` and `&` so the output can be embedded in HTML.
+    pub escape_html: bool,
+    /// Escape U+2028 and U+2029, which are invalid in JavaScript string literals
+    /// before ES2019.
+    pub escape_line_separators: bool,
+    /// Match CPython's `json.dumps` byte for byte: float `repr` formatting and
+    /// Python's choice of which characters to `\u`-escape.
+    pub python_compat: bool,
+    /// RFC 8785 (JCS) canonical output: keys sorted by UTF-16 code units,
+    /// minimal string escapes and ECMAScript number formatting. Stable across
+    /// implementations, so it is suitable for hashing.
+    pub canonical: bool,
+}
+
+impl<'a> Default for JsonFormatter<'a> {
+    fn default() -> Self {
+        Self {
+            indent_width: None,
+            key_separator: ": ",
+            item_separator: ", ",
+            sort_keys: false,
+            ensure_ascii: false,
+            escape_solidus: false,
+            escape_html: false,
+            escape_line_separators: false,
+            python_compat: false,
+            canonical: false,
+        }
+    }
+}
+
+impl<'a> JsonFormatter<'a> {
+    pub fn pretty(indent_width: usize) -> Self {
+        Self {
+            indent_width: Some(indent_width),
+            item_separator: ",",
+            ..Default::default()
+        }
+    }
+
+    /// The output of `json.dumps(value)` with default arguments.
+    pub fn python() -> Self {
+        Self {
+            ensure_ascii: true,
+            python_compat: true,
+            ..Default::default()
+        }
+    }
+
+    /// Compact output that is safe to embed in HTML `<script>` tags and
+    /// JavaScript string literals.
+    pub fn html_safe() -> Self {
+        Self {
+            escape_html: true,
+            escape_line_separators: true,
+            ..Self::compact()
+        }
+    }
+
+    pub fn canonical() -> Self {
+        Self {
+            sort_keys: true,
+            canonical: true,
+            ..Self::compact()
+        }
+    }
+
+    pub fn compact() -> Self {
+        Self {
+            key_separator: ":",
+            item_separator: ",",
+            ..Default::default()
+        }
+    }
+
+    pub fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, serde_json::Error> {
+        let mut out = Vec::new();
+        self.serialize_into(&mut out, value)?;
+
+        String::from_utf8(out).map_err(|e| serde_json::Error::io(io::Error::other(e)))
+    }
+
+    /// Streams `value` to `writer` as it's serialized. Sorting keys needs each
+    /// object whole, so with `sort_keys` it goes through a `Value` first.
+    pub fn serialize_into<W: Write, T: Serialize + ?Sized>(
+        &self,
+        mut writer: W,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        if self.sort_keys {
+            let value = serde_json::to_value(value)?;
+
+            return write!(writer, "{}", self.display(&value)).map_err(serde_json::Error::io);
+        }
+
+        let formatter = JsonStreamFormatter {
+            format: self,
+            depth: 0,
+            has_value: false,
+        };
+
+        value.serialize(&mut serde_json::Serializer::with_formatter(
+            writer, formatter,
+        ))
+    }
+
+    /// Formats `value` as it's serialized, like [`Self::serialize_into`].
+    pub fn display_serialized<'v, T: Serialize + ?Sized>(
+        &'v self,
+        value: &'v T,
+    ) -> impl Display + 'v {
+        JsonSerializedFormatter {
+            value,
+            format: self,
+        }
+    }
+
+    /// Formats an already built `Value` without a round trip through `Serialize`.
+    pub fn display<'v>(&'v self, value: &'v Value) -> impl Display + 'v {
+        JsonFormatterState {
+            value,
+            format: self,
+            depth: 0,
+        }
+    }
+
+    pub fn display_str<'v>(&'v self, value: &'v str) -> impl Display + 'v {
+        JsonStringFormatter {
+            value,
+            format: self,
+        }
+    }
+}
+
+struct JsonFormatterState<'a> {
+    value: &'a Value,
+    format: &'a JsonFormatter<'a>,
+    depth: usize,
+}
+
+struct JsonStringFormatter<'a> {
+    value: &'a str,
+    format: &'a JsonFormatter<'a>,
+}
+
+/// A string's contents with the escapes `format` asks for, without the quotes.
+struct JsonEscapedFormatter<'a> {
+    value: &'a str,
+    format: &'a JsonFormatter<'a>,
+}
+
+impl<'a> Display for JsonStringFormatter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let escaped = JsonEscapedFormatter {
+            value: self.value,
+            format: self.format,
+        };
+
+        write!(f, "\"{escaped}\"")
+    }
+}
+
+impl<'a> Display for JsonEscapedFormatter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.value.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '/' if self.format.escape_solidus => write!(f, "\\/")?,
+                '<' | '>' | '&' if self.format.escape_html => write!(f, "\\u{:04x}", c as u32)?,
+                '\u{2028}' | '\u{2029}' if self.format.escape_line_separators => {
+                    write!(f, "\\u{:04x}", c as u32)?
+                }
+                '\u{0008}' => write!(f, "\\b")?,
+                '\u{000C}' => write!(f, "\\f")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                // python only escapes C0 controls, plus everything outside
+                // printable ascii when ensure_ascii is set
+                c if self.format.minimal_escapes() && c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+                c if !self.format.minimal_escapes() && c.is_control() => {
+                    write!(f, "\\u{:04x}", c as u32)?
+                }
+                c if !matches!(c, ' '..='~') && self.format.ensure_ascii => {
+                    let mut buf = [0u16; 2];
+                    for codepoint in c.encode_utf16(&mut buf) {
+                        write!(f, "\\u{codepoint:04x}")?;
+                    }
+                }
+                _ => write!(f, "{c}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Display for JsonFormatterState<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Value::String(s) => JsonStringFormatter {
+                value: s,
+                format: self.format,
+            }
+            .fmt(f),
+            Value::Object(entries) => {
+                if entries.is_empty() {
+                    write!(f, "{{}}")?;
+
+                    return Ok(());
+                }
+
+                let Whitespace {
+                    newline,
+                    inner,
+                    outer,
+                } = self.whitespace();
+
+                write!(
+                    f,
+                    "{{{}{newline}{outer}}}",
+                    if self.format.sort_keys {
+                        Either::Left(entries.iter().sorted_by(|(a, _), (b, _)| {
+                            if self.format.canonical {
+                                a.encode_utf16().cmp(b.encode_utf16())
+                            } else {
+                                a.cmp(b)
+                            }
+                        }))
+                    } else {
+                        Either::Right(entries.iter())
+                    }
+                    .format_with(
+                        self.format.item_separator,
+                        |(key, value), f| {
+                            let key_formatter = JsonStringFormatter {
+                                value: key,
+                                format: self.format,
+                            };
+                            let value_formatter = JsonFormatterState {
+                                value,
+                                format: self.format,
+                                depth: self.depth + 1,
+                            };
+
+                            f(&format_args!(
+                                "{newline}{inner}{key_formatter}{}{value_formatter}",
+                                self.format.key_separator
+                            ))
+                        }
+                    )
+                )
+            }
+            Value::Array(elements) => {
+                if elements.is_empty() {
+                    write!(f, "[]")?;
+
+                    return Ok(());
+                }
+
+                let Whitespace {
+                    newline,
+                    inner,
+                    outer,
+                } = self.whitespace();
+
+                write!(
+                    f,
+                    "[{}{newline}{outer}]",
+                    elements
+                        .iter()
+                        .format_with(self.format.item_separator, |value, f| {
+                            let element_formatter = JsonFormatterState {
+                                value,
+                                format: self.format,
+                                depth: self.depth + 1,
+                            };
+
+                            f(&format_args!("{newline}{inner}{element_formatter}"))
+                        }),
+                )
+            }
+            Value::Number(n) if self.format.canonical => match n.as_f64() {
+                Some(x) => EcmaScriptNumber(x).fmt(f),
+                None => n.fmt(f),
+            },
+            Value::Number(n) if self.format.python_compat => match n.as_f64() {
+                Some(x) if n.is_f64() => PythonFloat(x).fmt(f),
+                _ => n.fmt(f),
+            },
+            _ => self.value.fmt(f),
+        }
+    }
+}
+
+struct JsonSerializedFormatter<'a, T: ?Sized> {
+    value: &'a T,
+    format: &'a JsonFormatter<'a>,
+}
+
+impl<T: Serialize + ?Sized> Display for JsonSerializedFormatter<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.format
+            .serialize_into(FmtWriter(f), self.value)
+            .map_err(|_| fmt::Error)
+    }
+}
+
+/// Passes serde_json's output, which is always written a whole `str` at a
+/// time, on to a `fmt::Formatter`.
+struct FmtWriter<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl Write for FmtWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = std::str::from_utf8(buf).map_err(io::Error::other)?;
+        self.0.write_str(s).map_err(io::Error::other)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// [`JsonFormatter`]'s output as serde_json writes it, so values stream
+/// straight to the writer instead of being built into a `Value`. Keys are
+/// written in serialization order.
+struct JsonStreamFormatter<'a> {
+    format: &'a JsonFormatter<'a>,
+    depth: usize,
+    has_value: bool,
+}
+
+impl JsonStreamFormatter<'_> {
+    fn newline<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        match self.format.indent_width {
+            Some(indent_width) => {
+                write!(writer, "\n{:width$}", "", width = indent_width * self.depth)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// JCS numbers are all doubles, so integers are written as the double they
+/// round to.
+macro_rules! write_integers {
+    ($($method:ident: $ty:ty),*) => {$(
+        fn $method<W: Write + ?Sized>(&mut self, writer: &mut W, value: $ty) -> io::Result<()> {
+            match self.format.canonical {
+                true => write!(writer, "{}", EcmaScriptNumber(value as f64)),
+                false => CompactFormatter.$method(writer, value),
+            }
+        }
+    )*};
+}
+
+impl Formatter for JsonStreamFormatter<'_> {
+    write_integers!(
+        write_i8: i8, write_i16: i16, write_i32: i32, write_i64: i64, write_i128: i128,
+        write_u8: u8, write_u16: u16, write_u32: u32, write_u64: u64, write_u128: u128
+    );
+
+    fn write_f32<W: Write + ?Sized>(&mut self, writer: &mut W, value: f32) -> io::Result<()> {
+        match self.format.canonical || self.format.python_compat {
+            true => self.write_f64(writer, value.into()),
+            false => CompactFormatter.write_f32(writer, value),
+        }
+    }
+
+    fn write_f64<W: Write + ?Sized>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        if self.format.canonical {
+            write!(writer, "{}", EcmaScriptNumber(value))
+        } else if self.format.python_compat {
+            write!(writer, "{}", PythonFloat(value))
+        } else {
+            CompactFormatter.write_f64(writer, value)
+        }
+    }
+
+    /// Numbers kept as written, with `arbitrary_precision`.
+    fn write_number_str<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        value: &str,
+    ) -> io::Result<()> {
+        let is_float = value.contains(['.', 'e', 'E']);
+
+        match value.parse::<f64>() {
+            Ok(x) if self.format.canonical => write!(writer, "{}", EcmaScriptNumber(x)),
+            Ok(x) if self.format.python_compat && is_float => write!(writer, "{}", PythonFloat(x)),
+            _ => writer.write_all(value.as_bytes()),
+        }
+    }
+
+    fn write_string_fragment<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> io::Result<()> {
+        let escaped = JsonEscapedFormatter {
+            value: fragment,
+            format: self.format,
+        };
+
+        write!(writer, "{escaped}")
+    }
+
+    fn write_char_escape<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        char_escape: CharEscape,
+    ) -> io::Result<()> {
+        let c = match char_escape {
+            CharEscape::Quote => '"',
+            CharEscape::ReverseSolidus => '\\',
+            CharEscape::Solidus => '/',
+            CharEscape::Backspace => '\u{0008}',
+            CharEscape::FormFeed => '\u{000C}',
+            CharEscape::LineFeed => '\n',
+            CharEscape::CarriageReturn => '\r',
+            CharEscape::Tab => '\t',
+            CharEscape::AsciiControl(byte) => byte.into(),
+        };
+
+        self.write_string_fragment(writer, c.encode_utf8(&mut [0; 4]))
+    }
+
+    fn begin_array<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth += 1;
+        self.has_value = false;
+
+        writer.write_all(b"[")
+    }
+
+    fn end_array<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth -= 1;
+        if self.has_value {
+            self.newline(writer)?;
+        }
+
+        writer.write_all(b"]")
+    }
+
+    fn begin_array_value<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        if !first {
+            writer.write_all(self.format.item_separator.as_bytes())?;
+        }
+
+        self.newline(writer)
+    }
+
+    fn end_array_value<W: Write + ?Sized>(&mut self, _writer: &mut W) -> io::Result<()> {
+        self.has_value = true;
+
+        Ok(())
+    }
+
+    fn begin_object<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth += 1;
+        self.has_value = false;
+
+        writer.write_all(b"{")
+    }
+
+    fn end_object<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.depth -= 1;
+        if self.has_value {
+            self.newline(writer)?;
+        }
+
+        writer.write_all(b"}")
+    }
+
+    fn begin_object_key<W: Write + ?Sized>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.begin_array_value(writer, first)
+    }
+
+    fn begin_object_value<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.format.key_separator.as_bytes())
+    }
+
+    fn end_object_value<W: Write + ?Sized>(&mut self, _writer: &mut W) -> io::Result<()> {
+        self.has_value = true;
+
+        Ok(())
+    }
+}
+/// Formats a float like CPython's `float.__repr__`: the shortest round-trip
+/// digits, in exponent notation outside of `1e-4..1e16`.
+struct PythonFloat(f64);
+
+impl Display for PythonFloat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let PythonFloat(x) = *self;
+        let (digits, exponent) = shortest_digits(x)?;
+
+        if x.is_sign_negative() {
+            write!(f, "-")?;
+        }
+
+        if (-4..16).contains(&exponent) {
+            let point = exponent + 1;
+
+            if point <= 0 {
+                write!(f, "0.{:0>width$}{digits}", "", width = -point as usize)
+            } else if digits.len() > point as usize {
+                let (int, frac) = digits.split_at(point as usize);
+                write!(f, "{int}.{frac}")
+            } else {
+                write!(f, "{digits:0<width$}.0", width = point as usize)
+            }
+        } else {
+            let (first, rest) = digits.split_at(1);
+            let sign = if exponent < 0 { '-' } else { '+' };
+
+            write!(f, "{first}")?;
+            if !rest.is_empty() {
+                write!(f, ".{rest}")?;
+            }
+            write!(f, "e{sign}{:02}", exponent.abs())
+        }
+    }
+}
+
+/// Formats a number like ECMAScript's `Number.prototype.toString`, as RFC 8785
+/// requires: integers without a fraction, exponent notation outside of
+/// `1e-7..1e21`, and no sign on zero.
+struct EcmaScriptNumber(f64);
+
+impl Display for EcmaScriptNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let EcmaScriptNumber(x) = *self;
+
+        if x == 0.0 {
+            return write!(f, "0");
+        }
+
+        let (digits, exponent) = shortest_digits(x)?;
+        let point = exponent + 1;
+
+        if x.is_sign_negative() {
+            write!(f, "-")?;
+        }
+
+        if (digits.len() as i32..=21).contains(&point) {
+            write!(f, "{digits:0<width$}", width = point as usize)
+        } else if (1..=21).contains(&point) {
+            let (int, frac) = digits.split_at(point as usize);
+            write!(f, "{int}.{frac}")
+        } else if (-5..=0).contains(&point) {
+            write!(f, "0.{:0>width$}{digits}", "", width = -point as usize)
+        } else {
+            let (first, rest) = digits.split_at(1);
+            let sign = if exponent < 0 { '-' } else { '+' };
+
+            write!(f, "{first}")?;
+            if !rest.is_empty() {
+                write!(f, ".{rest}")?;
+            }
+            write!(f, "e{sign}{}", exponent.abs())
+        }
+    }
+}
+
+/// The shortest round-trip decimal digits of `x` and the exponent of the
+/// first digit, e.g. `("15", -3)` for `0.0015`.
+fn shortest_digits(x: f64) -> Result<(String, i32), fmt::Error> {
+    let scientific = format!("{:e}", x.abs());
+    let (mantissa, exponent) = scientific.split_once('e').ok_or(fmt::Error)?;
+    let exponent = exponent.parse::<i32>().map_err(|_| fmt::Error)?;
+
+    Ok((mantissa.replace('.', ""), exponent))
+}
+
+struct Whitespace<T: Display> {
+    newline: &'static str,
+    inner: T,
+    outer: T,
+}
+
+impl JsonFormatter<'_> {
+    /// Python and JCS only escape C0 controls; otherwise all controls are.
+    fn minimal_escapes(&self) -> bool {
+        self.python_compat || self.canonical
+    }
+}
+
+impl<'a> JsonFormatterState<'a> {
+    fn whitespace(&self) -> Whitespace<impl Display> {
+        struct Padding(usize);
+
+        impl Display for Padding {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{:width$}", "", width = self.0)
+            }
+        }
+
+        if let Some(indent_width) = self.format.indent_width {
+            Whitespace {
+                newline: "\n",
+                inner: Padding(indent_width * (self.depth + 1)),
+                outer: Padding(indent_width * self.depth),
+            }
+        } else {
+            Whitespace {
+                newline: "",
+                inner: Padding(0),
+                outer: Padding(0),
+            }
+        }
+    }
+}
@@ -1,18 +1,116 @@
-pub static TEXT: &str = r#"/[^{](.|\n)*/"#;
-pub static NUMBER: &str = "/[0-9]/";
+use itertools::Itertools;
 
+use crate::render::{is_token_name, json::JsonFormatter};
+
+/// Lark strings are read as JSON strings, except that DEL has to be escaped too.
 pub fn lark_string_literal(literal: &str) -> String {
-    format!(r#""{literal}""#)
+    let mut escaped = String::with_capacity(literal.len() + 2);
+    escaped.push('"');
+
+    for c in literal.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
 }
 
+/// The special token itself, or its text when llguidance can't name it.
 pub fn lark_token_literal(token: &str) -> String {
-    token.to_string()
+    match is_token_name(token) {
+        true => token.to_string(),
+        false => lark_string_literal(token),
+    }
 }
 
+/// llguidance's syntax for matching a token by ID.
+pub fn lark_token_id(id: u32) -> String {
+    format!("<[{id}]>")
+}
+
+/// Slashes would end the regex early and raw line breaks would end the rule,
+/// so both are escaped. Escaped characters are kept as they are.
 pub fn lark_regex(regex: &str) -> String {
-    format!("/{regex}/")
+    let mut escaped = String::with_capacity(regex.len() + 2);
+    escaped.push('/');
+
+    let mut chars = regex.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                escaped.push(c);
+                escaped.extend(chars.next());
+            }
+            '/' => escaped.push_str("\\/"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('/');
+    escaped
+}
+
+/// Free text that never contains one of `markers`. Terminals are greedy and
+/// never backtrack, so text that could contain a marker would run on over
+/// whatever the marker opens.
+pub fn lark_text(markers: &[String], max_chars: Option<usize>) -> String {
+    let text = match max_chars {
+        Some(max_chars) => lark_regex(&format!("(?s:.){{0,{max_chars}}}")),
+        None => lark_regex("(?s:.)+"),
+    };
+
+    match markers.is_empty() {
+        true => text,
+        false => {
+            let markers = markers
+                .iter()
+                .map(|marker| regex_syntax::escape(marker))
+                .join("|");
+
+            format!(
+                "{text} & ~{}",
+                lark_regex(&format!("(?s:.)*(?:{markers})(?s:.)*"))
+            )
+        }
+    }
+}
+
+/// Free text up to and including the first `marker`, as a single terminal. This
+/// is how text followed by a marker gets lexed, since a terminal for the text
+/// alone can't tell where the marker starts. A run of the characters in the
+/// `whitespace` class after the marker is taken in too, so it isn't lexed as
+/// the start of whatever text follows.
+pub fn lark_text_until(marker: &str, whitespace: &str, max_chars: Option<usize>) -> String {
+    let marker = regex_syntax::escape(marker);
+    let text = match max_chars {
+        Some(max_chars) => format!("(?s:.){{0,{max_chars}}}"),
+        None => "(?s:.)*".to_string(),
+    };
+    let (trailing, past) = match whitespace.is_empty() {
+        true => (String::new(), "(?s:.)+".to_string()),
+        false => (
+            format!("[{whitespace}]*"),
+            format!("[{whitespace}]*[^{whitespace}](?s:.)*"),
+        ),
+    };
+
+    format!(
+        "{} & ~{}",
+        lark_regex(&format!("{text}{marker}{trailing}")),
+        lark_regex(&format!("(?s:.)*{marker}{past}"))
+    )
 }
 
 pub fn lark_json_schema(json_schema: &serde_json::Value) -> String {
-    format!("%json {json_schema}")
+    format!("%json {}", JsonFormatter::compact().display(json_schema))
 }
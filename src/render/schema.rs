@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::render::ReasoningEffort;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ChatImageUrl {
     pub url: String,
@@ -76,6 +78,15 @@ pub struct ChatToolMessage {
     pub tool_call_id: String,
 }
 
+/// A message with a role the config declares in `custom_roles`, like
+/// `ipython` or `observation`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChatCustomMessage {
+    pub role: String,
+    pub content: ChatMessageContent<String>,
+    pub name: Option<String>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(tag = "role", rename_all = "snake_case")]
 pub enum ChatMessageVariant {
@@ -84,6 +95,8 @@ pub enum ChatMessageVariant {
     User(ChatUserMessage),
     Assistant(ChatAssistantMessage),
     Tool(ChatToolMessage),
+    #[serde(untagged)]
+    Custom(ChatCustomMessage),
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -98,6 +111,9 @@ pub struct FunctionTool {
     pub name: String,
     pub description: Option<String>,
     pub parameters: serde_json::Value,
+    /// Closes every object in `parameters` to exactly its properties, all of
+    /// them required.
+    pub strict: Option<bool>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -136,16 +152,70 @@ pub enum ChatTool {
     Custom { custom: CustomTool },
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChatJsonSchema {
+    pub name: String,
+    pub description: Option<String>,
+    pub schema: Option<serde_json::Value>,
+    pub strict: Option<bool>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum ChatResponseFormat {
+    #[default]
+    Text,
+    JsonObject,
+    JsonSchema {
+        json_schema: ChatJsonSchema,
+    },
+}
+
 #[derive(Clone, Deserialize)]
 pub struct FunctionName {
     pub name: String,
 }
 
+/// A tool named in [`AllowedTools`].
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum AllowedTool {
+    Function { function: FunctionName },
+    Custom { custom: FunctionName },
+}
+
+impl AllowedTool {
+    pub fn name(&self) -> &str {
+        match self {
+            AllowedTool::Function { function } => &function.name,
+            AllowedTool::Custom { custom } => &custom.name,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowedToolsMode {
+    Auto,
+    Required,
+}
+
+/// Restricts calls to some of the request's tools, while the prompt still
+/// shows all of them.
+#[derive(Clone, Deserialize)]
+pub struct AllowedTools {
+    pub mode: AllowedToolsMode,
+    pub tools: Vec<AllowedTool>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 enum TypedChoice {
     Function { function: FunctionName },
+    AllowedTools { allowed_tools: AllowedTools },
 }
 
 #[derive(Deserialize)]
@@ -167,6 +237,9 @@ impl From<ToolChoiceRepr> for ChatToolChoice {
             ToolChoiceRepr::TypedChoice(TypedChoice::Function { function }) => {
                 ChatToolChoice::Function(function)
             }
+            ToolChoiceRepr::TypedChoice(TypedChoice::AllowedTools { allowed_tools }) => {
+                ChatToolChoice::AllowedTools(allowed_tools)
+            }
         }
     }
 }
@@ -179,4 +252,46 @@ pub enum ChatToolChoice {
     None,
     Required,
     Function(FunctionName),
+    AllowedTools(AllowedTools),
+}
+
+impl ChatToolChoice {
+    /// The same tools, but a call is no longer optional.
+    pub fn required(self) -> Self {
+        match self {
+            ChatToolChoice::Auto => ChatToolChoice::Required,
+            ChatToolChoice::AllowedTools(AllowedTools { tools, .. }) => {
+                ChatToolChoice::AllowedTools(AllowedTools {
+                    mode: AllowedToolsMode::Required,
+                    tools,
+                })
+            }
+            tool_choice => tool_choice,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ChatStreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// The render-relevant fields of an OpenAI chat completions request body.
+/// Other fields, like sampling parameters, are ignored.
+#[derive(Clone, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: Option<String>,
+    pub messages: ChatMessages,
+    pub tools: Option<Vec<ChatTool>>,
+    pub tool_choice: Option<ChatToolChoice>,
+    pub parallel_tool_calls: Option<bool>,
+    pub response_format: Option<ChatResponseFormat>,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    #[serde(default)]
+    pub stream: bool,
+    pub stream_options: Option<ChatStreamOptions>,
+    pub chat_template_kwargs: Option<serde_json::Map<String, serde_json::Value>>,
+    pub documents: Option<Vec<serde_json::Value>>,
+    pub continue_final_message: Option<bool>,
 }
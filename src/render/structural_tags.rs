@@ -0,0 +1,261 @@
+//! vLLM's structural tags: rather than a grammar for the whole completion, a
+//! JSON schema for each tool's arguments, enforced between a literal `begin`
+//! and `end` once the model writes one of the `triggers`. Text outside of tool
+//! calls, thinking included, is left unconstrained.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    Arguments, Lexeme, OrderedLexemes, ToolCall, ToolCalls,
+    format::tool_call_format,
+    render::{
+        GrammarResult, RenderError, ToolConstraint, schema::ChatToolChoice, template::TemplateTool,
+    },
+};
+
+#[derive(Serialize)]
+struct StructuralTags {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    structures: Vec<Structure>,
+    triggers: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Structure {
+    begin: String,
+    schema: Value,
+    end: String,
+}
+
+/// Appends the text `lexemes` match. Tags are fixed strings, so optional
+/// lexemes are left out and choices take their first alternative.
+fn write_literal(lexemes: &[Lexeme], out: &mut String) -> Result<(), RenderError> {
+    for lexeme in lexemes {
+        match lexeme {
+            Lexeme::Text(s) | Lexeme::Token(s) => out.push_str(s),
+            Lexeme::Whitespace { .. } | Lexeme::Optional { .. } => {}
+            Lexeme::Choice { choices } => {
+                if let Some(OrderedLexemes(choice)) = choices.first() {
+                    write_literal(choice, out)?;
+                }
+            }
+            Lexeme::TokenId(id) => return Err(RenderError::UnresolvedTokenId(*id)),
+            Lexeme::Regex { pattern } => {
+                return Err(RenderError::StructuralTag(format!("regex {pattern:?}")));
+            }
+            Lexeme::JsonSchema(_) => {
+                return Err(RenderError::StructuralTag(
+                    "a JSON schema outside of the arguments".into(),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn json_string(s: &str) -> String {
+    serde_json::to_string(s).expect("strings serialize to JSON")
+}
+
+impl ToolCall {
+    /// One call to `tool` as lexemes, with its arguments as the only
+    /// [`Lexeme::JsonSchema`].
+    fn tagged_lexemes(&self, tool: &TemplateTool) -> Result<Vec<Lexeme>, RenderError> {
+        let arguments = Lexeme::JsonSchema(tool.parameters.clone());
+
+        Ok(match self {
+            ToolCall::JsonObject {
+                name_key,
+                argument_key,
+            }
+            | ToolCall::JsonArray {
+                name_key,
+                argument_key,
+            } => {
+                let (open, close) = match self {
+                    ToolCall::JsonArray { .. } => ("[{", "}]"),
+                    _ => ("{", "}"),
+                };
+                let head = format!(
+                    "{open}{}: {}, {}: ",
+                    json_string(name_key),
+                    json_string(&tool.name),
+                    json_string(argument_key),
+                );
+
+                vec![
+                    Lexeme::Text(head.into()),
+                    arguments,
+                    Lexeme::Text(close.into()),
+                ]
+            }
+            ToolCall::NamedParameters {
+                arguments: Arguments::KeyValueTags { .. },
+                ..
+            } => {
+                return Err(RenderError::StructuralTag("key-value tag arguments".into()));
+            }
+            ToolCall::NamedParameters {
+                prefix,
+                id,
+                delimiter,
+                arguments: Arguments::JsonObject,
+                suffix,
+            } => {
+                let mut lexemes = Vec::new();
+                lexemes.extend(prefix.iter().flat_map(|prefix| prefix.0.clone()));
+                lexemes.push(Lexeme::Text(tool.name.as_str().into()));
+                lexemes.extend(id.iter().flat_map(|id| id.0.clone()));
+                lexemes.extend(delimiter.iter().flat_map(|delimiter| delimiter.0.clone()));
+                lexemes.push(arguments);
+                lexemes.extend(suffix.iter().flat_map(|suffix| suffix.0.clone()));
+                lexemes
+            }
+            ToolCall::External { name } => {
+                let format = tool_call_format(name)
+                    .ok_or_else(|| RenderError::UnknownToolCallFormat(name.clone()))?;
+
+                format.tool_call(tool).0
+            }
+        })
+    }
+}
+
+/// Splits a call's lexemes around its arguments, wrapped in the section's
+/// `prefix` and `suffix`.
+fn structure(
+    lexemes: &[Lexeme],
+    prefix: Option<&OrderedLexemes>,
+    suffix: Option<&OrderedLexemes>,
+) -> Result<Structure, RenderError> {
+    let schemas = lexemes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, lexeme)| match lexeme {
+            Lexeme::JsonSchema(schema) => Some((i, schema)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let [(at, schema)] = schemas[..] else {
+        return Err(RenderError::StructuralTag(
+            "a call without exactly one JSON schema".into(),
+        ));
+    };
+
+    let mut begin = String::new();
+    if let Some(prefix) = prefix {
+        write_literal(&prefix.0, &mut begin)?;
+    }
+    write_literal(&lexemes[..at], &mut begin)?;
+
+    let mut end = String::new();
+    write_literal(&lexemes[at + 1..], &mut end)?;
+    if let Some(suffix) = suffix {
+        write_literal(&suffix.0, &mut end)?;
+    }
+
+    Ok(Structure {
+        begin,
+        schema: schema.clone(),
+        end,
+    })
+}
+
+/// The longest prefix every structure begins with.
+fn common_prefix(structures: &[Structure]) -> &str {
+    let Some((first, rest)) = structures.split_first() else {
+        return "";
+    };
+
+    let len = rest.iter().fold(first.begin.len(), |len, structure| {
+        first.begin[..len]
+            .char_indices()
+            .zip(structure.begin.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(structure.begin.len()), |((i, _), _)| i)
+    });
+
+    &first.begin[..len]
+}
+
+impl ToolConstraint<'_> {
+    /// The tool calls as a serialized `structural_tag` response format. Tool
+    /// choice only narrows the tools, since calls can't be required.
+    pub(super) fn structural_tags(&self) -> GrammarResult {
+        let (section_prefix, tool_call, section_suffix) = match self.tool_calls.as_deref() {
+            Some(ToolCalls::ToolCall { tool_call }) => (None, tool_call, None),
+            // every call is wrapped in the section's markers, whether or not
+            // the section is `per_call`
+            Some(ToolCalls::ToolCallsSection {
+                prefix,
+                tool_call,
+                suffix,
+                ..
+            }) => (Some(prefix), tool_call, suffix.as_ref()),
+            None if self.response_schema.is_some() => {
+                return Err(RenderError::StructuralTag("a response format".into()));
+            }
+            None => return Ok(None),
+        };
+
+        let tools = match &self.tool_choice {
+            ChatToolChoice::None => return Ok(None),
+            ChatToolChoice::Auto | ChatToolChoice::Required => self.tools.iter().collect(),
+            ChatToolChoice::Function(function) => self
+                .tools
+                .iter()
+                .filter(|tool| tool.name == function.name)
+                .collect::<Vec<_>>(),
+            ChatToolChoice::AllowedTools(allowed_tools) => self
+                .tools
+                .iter()
+                .filter(|tool| {
+                    allowed_tools
+                        .tools
+                        .iter()
+                        .any(|allowed| allowed.name() == tool.name)
+                })
+                .collect(),
+        };
+
+        let structures = tools
+            .into_iter()
+            .map(|tool| {
+                structure(
+                    &tool_call.tagged_lexemes(tool)?,
+                    section_prefix,
+                    section_suffix,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // the section's prefix opens every call, otherwise whatever they share does
+        let mut trigger = String::new();
+        if let Some(prefix) = section_prefix {
+            write_literal(&prefix.0, &mut trigger)?;
+        }
+        if trigger.is_empty() {
+            trigger = common_prefix(&structures).to_string();
+        }
+
+        if trigger.is_empty() {
+            return Err(RenderError::StructuralTag(
+                "calls without a common prefix".into(),
+            ));
+        }
+
+        let tags = StructuralTags {
+            ty: "structural_tag",
+            structures,
+            triggers: vec![trigger],
+        };
+
+        Ok(Some(
+            serde_json::to_string(&tags).expect("structural tags serialize to JSON"),
+        ))
+    }
+}
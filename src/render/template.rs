@@ -1,18 +1,19 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, cell::RefCell, collections::BTreeMap, fmt, io, sync::Arc};
 
 use chrono::Utc;
 use hf_hub::CacheRepo;
 use itertools::Itertools;
 use minijinja::{Environment, ErrorKind, Template, value::Kwargs};
 use minijinja_contrib::pycompat;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::json;
 
 use crate::{
-    InitError,
-    json::JsonFormatter,
+    InitError, ThinkingMode,
+    hooks::Hooks,
     render::{
-        RenderError,
+        ReasoningEffort, RenderError, RenderOptions, fnv1a,
+        json::JsonFormatter,
         schema::{
             ChatAssistantChunk, ChatImageUrl, ChatMessageContent, ChatMessageVariant, ChatMessages,
             ChatTool, ChatToolCall, ChatUserChunk, CustomTool, CustomToolFormat, CustomToolGrammar,
@@ -21,42 +22,126 @@ use crate::{
     },
 };
 
-static CHAT_TEMPLATE: &str = "chat_template.jinja";
-static TOKENIZER_CONFIG: &str = "tokenizer_config.json";
-static MODEL_CONFIG: &str = "config.json";
+pub(crate) static CHAT_TEMPLATE: &str = "chat_template.jinja";
+pub(crate) static TOKENIZER_CONFIG: &str = "tokenizer_config.json";
+pub(crate) static MODEL_CONFIG: &str = "config.json";
 
+/// Scratch buffers larger than this are dropped rather than kept for the next render.
+const MAX_SCRATCH_CAPACITY: usize = 4 << 20;
+
+thread_local! {
+    static SCRATCH: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Runs `f` against a per-thread scratch buffer and copies the result out, so
+/// large prompts don't regrow a fresh `String` on every render.
+pub(crate) fn with_scratch<R>(
+    f: impl FnOnce(&mut String) -> Result<R, RenderError>,
+) -> Result<(String, R), RenderError> {
+    let mut buffer = SCRATCH.take();
+    buffer.clear();
+
+    let result = f(&mut buffer).map(|r| (buffer.as_str().to_owned(), r));
+
+    if buffer.capacity() <= MAX_SCRATCH_CAPACITY {
+        SCRATCH.set(buffer);
+    }
+
+    result
+}
+
+/// Adapts a `fmt::Write` so minijinja can stream into it. minijinja only ever
+/// writes whole `str` fragments, so each chunk is valid UTF-8 on its own.
+struct FmtWriter<'w, W>(&'w mut W);
+
+impl<W: fmt::Write> io::Write for FmtWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let FmtWriter(out) = self;
+
+        let s =
+            std::str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        out.write_str(s).map_err(io::Error::other)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Cloning is cheap: the compiled template is reference counted and the
+/// special tokens are shared.
+#[derive(Clone)]
 pub struct ChatTemplate {
     template: Template<'static, 'static>,
-    bos_token: Option<String>,
-    eos_token: Option<String>,
+    bos_token: Option<Arc<str>>,
+    eos_token: Option<Arc<str>>,
     multimodal: bool,
-    add_generation_prompt: bool,
+    uses_tools: bool,
+    pub(crate) hooks: Hooks,
+}
+
+/// What a resolved [`ChatTemplate`] serializes as. The source is only
+/// identified by its hash, so this is small enough to log with every request.
+#[derive(Serialize)]
+struct ChatTemplateSummary<'a> {
+    source_hash: String,
+    bos_token: Option<&'a str>,
+    eos_token: Option<&'a str>,
+    multimodal: bool,
+}
+
+impl Serialize for ChatTemplate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ChatTemplateSummary {
+            source_hash: format!("{:016x}", self.source_hash()),
+            bos_token: self.bos_token.as_deref(),
+            eos_token: self.eos_token.as_deref(),
+            multimodal: self.multimodal,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// The request data a template reads, so a server can warn when a request
+/// supplies something the template would ignore.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TemplateIntrospection {
+    pub tools: bool,
+    pub documents: bool,
+    pub add_generation_prompt: bool,
+    pub enable_thinking: bool,
+    pub date_string: bool,
+    /// A message attribute rather than a variable, so this is only a check
+    /// that the template source mentions it.
+    pub reasoning_content: bool,
 }
 
 #[derive(Serialize)]
 pub struct ChatTemplateInputs<'a> {
-    messages: &'a [TemplateChatMessage],
+    #[serde(flatten)]
+    kwargs: &'a serde_json::Map<String, serde_json::Value>,
+    messages: &'a [TemplateChatMessage<'a>],
     tools: &'a [TemplateTool],
     bos_token: Option<&'a str>,
     eos_token: Option<&'a str>,
     add_generation_prompt: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<ReasoningEffort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enable_thinking: Option<bool>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    documents: &'a [serde_json::Value],
 }
 
 impl ChatTemplate {
     pub fn from_repo(repo: &CacheRepo) -> Result<Self, InitError> {
         let template_filename = repo.get(CHAT_TEMPLATE);
 
-        let tokenizer_config_string = std::fs::read_to_string(
-            repo.get(TOKENIZER_CONFIG)
-                .ok_or(InitError::ConfigNotFound(TOKENIZER_CONFIG))?,
-        )?;
-        let tokenizer_config = serde_json::from_str::<TokenizerConfig>(&tokenizer_config_string)?;
+        let tokenizer_config = TokenizerConfig::from_repo(repo)?;
 
-        let model_config_string = std::fs::read_to_string(
-            repo.get(MODEL_CONFIG)
-                .ok_or(InitError::ConfigNotFound(MODEL_CONFIG))?,
-        )?;
-        let model_config = serde_json::from_str::<ModelConfig>(&model_config_string)?;
+        let model_config = ModelConfig::from_repo(repo)?;
 
         let multimodal = model_config.image_token_id.is_some();
 
@@ -80,7 +165,6 @@ impl ChatTemplate {
             tokenizer_config.bos_token,
             tokenizer_config.eos_token,
             multimodal,
-            true,
         )
     }
 
@@ -89,7 +173,6 @@ impl ChatTemplate {
         bos_token: Option<String>,
         eos_token: Option<String>,
         multimodal: bool,
-        add_generation_prompt: bool,
     ) -> Result<Self, InitError> {
         let mut environment = Environment::new();
         environment.set_unknown_method_callback(pycompat::unknown_method_callback);
@@ -128,6 +211,10 @@ impl ChatTemplate {
                 sort_keys: sort_keys.unwrap_or(false),
                 ensure_ascii: ensure_ascii.unwrap_or(true),
                 escape_solidus: false,
+                escape_html: false,
+                escape_line_separators: false,
+                python_compat: true,
+                canonical: false,
             };
 
             formatter
@@ -150,78 +237,152 @@ impl ChatTemplate {
         let template = Box::leak(Box::new(environment))
             .template_from_str(Box::leak(chat_template.into_boxed_str()))?;
 
-        // let variables = template.undeclared_variables(true);
-        // let use_default_tool_template = !variables.contains("tools");
+        let uses_tools = template.undeclared_variables(false).contains("tools");
 
         Ok(Self {
             template,
-            bos_token,
-            eos_token,
+            bos_token: bos_token.map(Arc::from),
+            eos_token: eos_token.map(Arc::from),
             multimodal,
-            add_generation_prompt,
+            uses_tools,
+            hooks: Hooks::default(),
         })
     }
 
+    pub fn eos_token(&self) -> Option<&str> {
+        self.eos_token.as_deref()
+    }
+
+    pub fn bos_token(&self) -> Option<&str> {
+        self.bos_token.as_deref()
+    }
+
+    pub fn source(&self) -> &str {
+        self.template.source()
+    }
+
+    /// An FNV-1a hash of [`Self::source`], stable across processes.
+    pub fn source_hash(&self) -> u64 {
+        fnv1a(self.source().as_bytes())
+    }
+
+    pub fn multimodal(&self) -> bool {
+        self.multimodal
+    }
+
+    /// Whether the template reads the `tools` variable at all.
+    pub fn uses_tools(&self) -> bool {
+        self.uses_tools
+    }
+
+    pub fn introspect(&self) -> TemplateIntrospection {
+        let variables = self.template.undeclared_variables(false);
+
+        TemplateIntrospection {
+            tools: variables.contains("tools"),
+            documents: variables.contains("documents"),
+            add_generation_prompt: variables.contains("add_generation_prompt"),
+            enable_thinking: variables.contains("enable_thinking"),
+            date_string: variables.contains("date_string"),
+            reasoning_content: self.template.source().contains("reasoning_content"),
+        }
+    }
+
     pub fn render(
         &self,
-        mut messages: Vec<TemplateChatMessage>,
+        messages: Vec<TemplateChatMessage>,
         tools: &[TemplateTool],
+        options: &RenderOptions,
     ) -> Result<String, RenderError> {
+        with_scratch(|out| self.render_to(out, messages, tools, options)).map(|(prompt, ())| prompt)
+    }
+
+    /// Renders the prompt straight into `out`, e.g. a server's request buffer.
+    /// The reasoning effort and kwargs in `options` are passed to the template
+    /// as variables, for templates that read them.
+    pub fn render_to(
+        &self,
+        out: &mut impl fmt::Write,
+        mut messages: Vec<TemplateChatMessage>,
+        tools: &[TemplateTool],
+        options: &RenderOptions,
+    ) -> Result<(), RenderError> {
         for message in messages.iter_mut() {
             if self.multimodal {
                 if let ChatTemplateContent::Collapsed(text) = &mut message.content {
-                    message.content =
-                        ChatTemplateContent::Chunks(vec![std::mem::take(text).into()]);
+                    message.content = ChatTemplateContent::Chunks(vec![ChatTemplateChunk::Text {
+                        text: std::mem::take(text),
+                    }]);
                 }
             } else if let ChatTemplateContent::Chunks(chunks) = &mut message.content {
-                message.content = ChatTemplateContent::Collapsed(chunks.iter().fold(
-                    String::new(),
-                    |mut acc, chunk| {
+                let collapsed = match chunks.as_mut_slice() {
+                    [ChatTemplateChunk::Text { text }] => std::mem::take(text),
+                    _ => Cow::Owned(chunks.iter().fold(String::new(), |mut acc, chunk| {
                         if let ChatTemplateChunk::Text { text } = chunk {
-                            acc += text;
+                            acc.push_str(text);
                         }
 
                         acc
-                    },
-                ));
+                    })),
+                };
+
+                message.content = ChatTemplateContent::Collapsed(collapsed);
             }
         }
 
-        // let final_message = messages.last().and_then(|msg| {
-        //     msg.content.last().and_then(|chunk| {
-        //         if let ChatTemplateChunk::Text { text } = chunk {
-        //             Some((msg.role.clone(), text.clone()))
-        //         } else {
-        //             None
-        //         }
-        //     })
-        // });
+        // the prompt is cut off right after the final message's text, which
+        // leaves its turn open for the model to carry on
+        let final_message = options
+            .continue_final_message
+            .then(|| final_message_text(&messages))
+            .flatten();
 
         let inputs = ChatTemplateInputs {
+            kwargs: &options.kwargs,
             messages: &messages,
             tools,
             bos_token: self.bos_token.as_deref(),
             eos_token: self.eos_token.as_deref(),
-            add_generation_prompt: true,
+            add_generation_prompt: options.add_generation_prompt && final_message.is_none(),
+            reasoning_effort: options.reasoning_effort,
+            enable_thinking: options
+                .thinking
+                .map(|thinking| thinking != ThinkingMode::Disabled),
+            documents: &options.documents,
         };
 
-        let rendered_template = self.template.render(&inputs)?;
+        let Some(final_message) = final_message else {
+            traced!("render_template", messages = messages.len(), tools = tools.len(); {
+                self.template.render_to_write(&inputs, FmtWriter(out))
+            })?;
+
+            return Ok(());
+        };
 
-        // match final_message {
-        //     Some((role, text)) if role == "assistant" => {
-        //         if let Some(index) = rendered_template.rfind(&text) {
-        //             return Ok(rendered_template[..index + text.len()]
-        //                 .trim_end()
-        //                 .to_string());
-        //         }
-        //     }
-        //     _ => {}
-        // }
+        let mut rendered = String::new();
+        traced!("render_template", messages = messages.len(), tools = tools.len(); {
+            self.template.render_to_write(&inputs, FmtWriter(&mut rendered))
+        })?;
 
-        Ok(rendered_template)
+        let end = rendered
+            .rfind(&final_message)
+            .ok_or(RenderError::FinalMessageNotRendered)?
+            + final_message.len();
+        out.write_str(&rendered[..end])?;
+
+        Ok(())
     }
 }
 
+/// The trimmed text of the last message, or `None` when there is nothing to
+/// continue.
+pub(crate) fn final_message_text(messages: &[TemplateChatMessage]) -> Option<String> {
+    let text = messages.last()?.content.text();
+    let text = text.trim();
+
+    (!text.is_empty()).then(|| text.to_string())
+}
+
 #[derive(Deserialize)]
 pub struct NamedChatTemplate {
     name: String,
@@ -266,65 +427,179 @@ pub struct TokenizerConfig {
     pub add_bos_token: Option<bool>,
     pub add_eos_token: Option<bool>,
     pub guideline: Option<String>,
+    #[serde(default)]
+    pub added_tokens_decoder: BTreeMap<String, AddedToken>,
+}
+
+#[derive(Deserialize)]
+pub struct AddedToken {
+    pub content: String,
+}
+
+impl TokenizerConfig {
+    pub fn from_repo(repo: &CacheRepo) -> Result<Self, InitError> {
+        let tokenizer_config_string = std::fs::read_to_string(
+            repo.get(TOKENIZER_CONFIG)
+                .ok_or(InitError::ConfigNotFound(TOKENIZER_CONFIG))?,
+        )?;
+
+        Ok(serde_json::from_str(&tokenizer_config_string)?)
+    }
+
+    pub fn added_tokens(&self) -> Vec<String> {
+        self.added_tokens_decoder
+            .values()
+            .map(|token| token.content.clone())
+            .collect()
+    }
+
+    /// The added tokens by ID, skipping any whose ID isn't a number.
+    pub fn added_token_ids(&self) -> BTreeMap<u32, String> {
+        self.added_tokens_decoder
+            .iter()
+            .filter_map(|(id, token)| Some((id.parse().ok()?, token.content.clone())))
+            .collect()
+    }
 }
 
 #[derive(Deserialize)]
 pub struct ModelConfig {
     pub image_token_id: Option<u32>,
+    #[serde(default)]
+    pub architectures: Vec<String>,
 }
 
-#[derive(Serialize)]
+impl ModelConfig {
+    pub fn from_repo(repo: &CacheRepo) -> Result<Self, InitError> {
+        let model_config_string = std::fs::read_to_string(
+            repo.get(MODEL_CONFIG)
+                .ok_or(InitError::ConfigNotFound(MODEL_CONFIG))?,
+        )?;
+
+        Ok(serde_json::from_str(&model_config_string)?)
+    }
+}
+
+#[derive(Clone, Serialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
-pub enum ChatTemplateChunk {
-    Text { text: String },
-    Image { url: String },
+pub enum ChatTemplateChunk<'a> {
+    Text { text: Cow<'a, str> },
+    Image { url: Cow<'a, str> },
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 #[serde(untagged)]
-pub enum ChatTemplateContent {
-    Chunks(Vec<ChatTemplateChunk>),
-    Collapsed(String),
+pub enum ChatTemplateContent<'a> {
+    Chunks(Vec<ChatTemplateChunk<'a>>),
+    Collapsed(Cow<'a, str>),
 }
 
-#[derive(Serialize)]
-pub struct TemplateChatMessage {
-    pub role: String,
-    pub content: ChatTemplateContent,
-    pub name: Option<String>,
-    pub refusal: Option<String>,
-    pub tool_calls: Option<Vec<ChatToolCall>>,
-    pub tool_call_id: Option<String>,
+impl ChatTemplateContent<'_> {
+    /// The text chunks joined together, ignoring images.
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            ChatTemplateContent::Collapsed(text) => Cow::Borrowed(text),
+            ChatTemplateContent::Chunks(chunks) => chunks
+                .iter()
+                .filter_map(|chunk| match chunk {
+                    ChatTemplateChunk::Text { text } => Some(&**text),
+                    ChatTemplateChunk::Image { .. } => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A message as seen by the chat template. Converting from `&ChatMessages`
+/// borrows every string from the request, so the same request can be rendered
+/// repeatedly without cloning the conversation.
+#[derive(Clone, Serialize)]
+pub struct TemplateChatMessage<'a> {
+    pub role: Cow<'a, str>,
+    pub content: ChatTemplateContent<'a>,
+    pub name: Option<Cow<'a, str>>,
+    pub refusal: Option<Cow<'a, str>>,
+    pub tool_calls: Option<Cow<'a, [ChatToolCall]>>,
+    pub tool_call_id: Option<Cow<'a, str>>,
 }
 
-impl From<String> for ChatTemplateChunk {
+impl<'a> TemplateChatMessage<'a> {
+    fn new(role: &'static str, chunks: Vec<ChatTemplateChunk<'a>>) -> Self {
+        TemplateChatMessage {
+            role: Cow::Borrowed(role),
+            content: ChatTemplateContent::Chunks(chunks),
+            name: None,
+            refusal: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+}
+
+impl From<String> for ChatTemplateChunk<'static> {
     fn from(text: String) -> Self {
-        ChatTemplateChunk::Text { text }
+        ChatTemplateChunk::Text {
+            text: Cow::Owned(text),
+        }
     }
 }
 
-impl From<ChatUserChunk> for ChatTemplateChunk {
+impl<'a> From<&'a String> for ChatTemplateChunk<'a> {
+    fn from(text: &'a String) -> Self {
+        ChatTemplateChunk::Text {
+            text: Cow::Borrowed(text),
+        }
+    }
+}
+
+impl From<ChatUserChunk> for ChatTemplateChunk<'static> {
     fn from(chunk: ChatUserChunk) -> Self {
         match chunk {
-            ChatUserChunk::Text { text } => ChatTemplateChunk::Text { text },
+            ChatUserChunk::Text { text } => text.into(),
             ChatUserChunk::ImageUrl {
                 image_url: ChatImageUrl { url },
-            } => ChatTemplateChunk::Image { url },
+            } => ChatTemplateChunk::Image {
+                url: Cow::Owned(url),
+            },
         }
     }
 }
 
-impl From<ChatAssistantChunk> for ChatTemplateChunk {
+impl<'a> From<&'a ChatUserChunk> for ChatTemplateChunk<'a> {
+    fn from(chunk: &'a ChatUserChunk) -> Self {
+        match chunk {
+            ChatUserChunk::Text { text } => text.into(),
+            ChatUserChunk::ImageUrl {
+                image_url: ChatImageUrl { url },
+            } => ChatTemplateChunk::Image {
+                url: Cow::Borrowed(url),
+            },
+        }
+    }
+}
+
+impl From<ChatAssistantChunk> for ChatTemplateChunk<'static> {
     fn from(chunk: ChatAssistantChunk) -> Self {
         match chunk {
-            ChatAssistantChunk::Text { text } => ChatTemplateChunk::Text { text },
-            ChatAssistantChunk::Refusal { refusal } => ChatTemplateChunk::Text { text: refusal },
+            ChatAssistantChunk::Text { text } => text.into(),
+            ChatAssistantChunk::Refusal { refusal } => refusal.into(),
+        }
+    }
+}
+
+impl<'a> From<&'a ChatAssistantChunk> for ChatTemplateChunk<'a> {
+    fn from(chunk: &'a ChatAssistantChunk) -> Self {
+        match chunk {
+            ChatAssistantChunk::Text { text } => text.into(),
+            ChatAssistantChunk::Refusal { refusal } => refusal.into(),
         }
     }
 }
 
-impl<T: Into<ChatTemplateChunk>> From<ChatMessageContent<T>> for Vec<ChatTemplateChunk> {
+impl<T: Into<ChatTemplateChunk<'static>>> From<ChatMessageContent<T>>
+    for Vec<ChatTemplateChunk<'static>>
+{
     fn from(content: ChatMessageContent<T>) -> Self {
         match content {
             ChatMessageContent::SingleText(text) => vec![text.into()],
@@ -333,75 +608,113 @@ impl<T: Into<ChatTemplateChunk>> From<ChatMessageContent<T>> for Vec<ChatTemplat
     }
 }
 
-impl From<ChatMessages> for Vec<TemplateChatMessage> {
+impl<'a, T> From<&'a ChatMessageContent<T>> for Vec<ChatTemplateChunk<'a>>
+where
+    &'a T: Into<ChatTemplateChunk<'a>>,
+{
+    fn from(content: &'a ChatMessageContent<T>) -> Self {
+        match content {
+            ChatMessageContent::SingleText(text) => vec![text.into()],
+            ChatMessageContent::ManyChunks(chunks) => chunks.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ChatMessageVariant> for TemplateChatMessage<'static> {
+    fn from(message: ChatMessageVariant) -> Self {
+        match message {
+            ChatMessageVariant::Developer(msg) => TemplateChatMessage {
+                name: msg.name.map(Cow::Owned),
+                ..TemplateChatMessage::new("developer", msg.content.into())
+            },
+            ChatMessageVariant::System(msg) => TemplateChatMessage {
+                name: msg.name.map(Cow::Owned),
+                ..TemplateChatMessage::new("system", msg.content.into())
+            },
+            ChatMessageVariant::User(msg) => TemplateChatMessage {
+                name: msg.name.map(Cow::Owned),
+                ..TemplateChatMessage::new("user", msg.content.into())
+            },
+            ChatMessageVariant::Assistant(msg) => TemplateChatMessage {
+                name: msg.name.map(Cow::Owned),
+                refusal: msg.refusal.map(Cow::Owned),
+                tool_calls: msg.tool_calls.map(Cow::Owned),
+                ..TemplateChatMessage::new("assistant", msg.content.into())
+            },
+            ChatMessageVariant::Tool(msg) => TemplateChatMessage {
+                tool_call_id: Some(Cow::Owned(msg.tool_call_id)),
+                ..TemplateChatMessage::new("tool", msg.content.into())
+            },
+            ChatMessageVariant::Custom(msg) => TemplateChatMessage {
+                role: Cow::Owned(msg.role),
+                name: msg.name.map(Cow::Owned),
+                ..TemplateChatMessage::new("", msg.content.into())
+            },
+        }
+    }
+}
+
+impl<'a> From<&'a ChatMessageVariant> for TemplateChatMessage<'a> {
+    fn from(message: &'a ChatMessageVariant) -> Self {
+        match message {
+            ChatMessageVariant::Developer(msg) => TemplateChatMessage {
+                name: msg.name.as_deref().map(Cow::Borrowed),
+                ..TemplateChatMessage::new("developer", (&msg.content).into())
+            },
+            ChatMessageVariant::System(msg) => TemplateChatMessage {
+                name: msg.name.as_deref().map(Cow::Borrowed),
+                ..TemplateChatMessage::new("system", (&msg.content).into())
+            },
+            ChatMessageVariant::User(msg) => TemplateChatMessage {
+                name: msg.name.as_deref().map(Cow::Borrowed),
+                ..TemplateChatMessage::new("user", (&msg.content).into())
+            },
+            ChatMessageVariant::Assistant(msg) => TemplateChatMessage {
+                name: msg.name.as_deref().map(Cow::Borrowed),
+                refusal: msg.refusal.as_deref().map(Cow::Borrowed),
+                tool_calls: msg.tool_calls.as_deref().map(Cow::Borrowed),
+                ..TemplateChatMessage::new("assistant", (&msg.content).into())
+            },
+            ChatMessageVariant::Tool(msg) => TemplateChatMessage {
+                tool_call_id: Some(Cow::Borrowed(&msg.tool_call_id)),
+                ..TemplateChatMessage::new("tool", (&msg.content).into())
+            },
+            ChatMessageVariant::Custom(msg) => TemplateChatMessage {
+                role: Cow::Borrowed(&msg.role),
+                name: msg.name.as_deref().map(Cow::Borrowed),
+                ..TemplateChatMessage::new("", (&msg.content).into())
+            },
+        }
+    }
+}
+
+impl From<ChatMessages> for Vec<TemplateChatMessage<'static>> {
     fn from(messages: ChatMessages) -> Self {
         match messages {
-            ChatMessages::Content(s) => {
-                vec![TemplateChatMessage {
-                    content: ChatTemplateContent::Chunks(
-                        ChatMessageContent::<String>::SingleText(s).into(),
-                    ),
-                    role: "user".to_string(),
-                    name: None,
-                    refusal: None,
-                    tool_calls: None,
-                    tool_call_id: None,
-                }]
-            }
-            ChatMessages::Conversation(messages) => messages
-                .into_iter()
-                .map(|m| match m {
-                    ChatMessageVariant::Developer(msg) => TemplateChatMessage {
-                        content: ChatTemplateContent::Chunks(msg.content.into()),
-                        role: "developer".to_string(),
-                        name: msg.name,
-                        refusal: None,
-                        tool_calls: None,
-                        tool_call_id: None,
-                    },
-                    ChatMessageVariant::System(msg) => TemplateChatMessage {
-                        content: ChatTemplateContent::Chunks(msg.content.into()),
-                        role: "system".to_string(),
-                        name: msg.name,
-                        refusal: None,
-                        tool_calls: None,
-                        tool_call_id: None,
-                    },
-                    ChatMessageVariant::User(msg) => TemplateChatMessage {
-                        content: ChatTemplateContent::Chunks(msg.content.into()),
-                        role: "user".to_string(),
-                        name: msg.name,
-                        refusal: None,
-                        tool_calls: None,
-                        tool_call_id: None,
-                    },
-                    ChatMessageVariant::Assistant(msg) => TemplateChatMessage {
-                        content: ChatTemplateContent::Chunks(msg.content.into()),
-                        role: "assistant".to_string(),
-                        name: msg.name,
-                        refusal: msg.refusal,
-                        tool_calls: msg.tool_calls,
-                        tool_call_id: None,
-                    },
-                    ChatMessageVariant::Tool(msg) => TemplateChatMessage {
-                        content: ChatTemplateContent::Chunks(msg.content.into()),
-                        role: "tool".to_string(),
-                        name: None,
-                        refusal: None,
-                        tool_calls: None,
-                        tool_call_id: Some(msg.tool_call_id),
-                    },
-                })
-                .collect(),
+            ChatMessages::Content(s) => vec![TemplateChatMessage::new("user", vec![s.into()])],
+            ChatMessages::Conversation(messages) => messages.into_iter().map(Into::into).collect(),
         }
     }
 }
 
-#[derive(Serialize)]
+impl<'a> From<&'a ChatMessages> for Vec<TemplateChatMessage<'a>> {
+    fn from(messages: &'a ChatMessages) -> Self {
+        match messages {
+            ChatMessages::Content(s) => vec![TemplateChatMessage::new("user", vec![s.into()])],
+            ChatMessages::Conversation(messages) => messages.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
 pub struct TemplateTool {
     pub name: String,
     pub description: Option<String>,
     pub parameters: serde_json::Value,
+    /// A custom tool's own grammar for its raw input. Templates only see it
+    /// through `parameters`.
+    #[serde(skip)]
+    pub grammar: Option<CustomToolGrammar>,
 }
 
 impl From<ChatTool> for TemplateTool {
@@ -413,11 +726,13 @@ impl From<ChatTool> for TemplateTool {
                         name,
                         description,
                         parameters,
+                        ..
                     },
             } => TemplateTool {
                 name,
                 description,
                 parameters,
+                grammar: None,
             },
             ChatTool::Custom {
                 custom:
@@ -426,23 +741,31 @@ impl From<ChatTool> for TemplateTool {
                         description,
                         format,
                     },
-            } => TemplateTool {
-                name,
-                description,
-                parameters: match format {
-                    CustomToolFormat::Text => json!({ "type": "string" }),
-                    CustomToolFormat::Grammar {
-                        grammar: CustomToolGrammar { definition, syntax },
-                    } => match syntax {
-                        CustomToolSyntax::Lark => {
-                            json!({ "type": "string", "description": format!("a string that conforms to the following Lark grammar: {}", definition) })
-                        }
-                        CustomToolSyntax::Regex => {
-                            json!({ "type": "string", "pattern": definition })
-                        }
-                    },
-                },
-            },
+            } => {
+                let (parameters, grammar) = match format {
+                    CustomToolFormat::Text => (json!({ "type": "string" }), None),
+                    CustomToolFormat::Grammar { grammar } => {
+                        let CustomToolGrammar { definition, syntax } = &grammar;
+                        let parameters = match syntax {
+                            CustomToolSyntax::Lark => {
+                                json!({ "type": "string", "description": format!("a string that conforms to the following Lark grammar: {}", definition) })
+                            }
+                            CustomToolSyntax::Regex => {
+                                json!({ "type": "string", "pattern": definition })
+                            }
+                        };
+
+                        (parameters, Some(grammar))
+                    }
+                };
+
+                TemplateTool {
+                    name,
+                    description,
+                    parameters,
+                    grammar,
+                }
+            }
         }
     }
 }
@@ -0,0 +1,598 @@
+//! JSON schemas reduced to the structure a grammar can enforce, for grammar
+//! syntaxes without built-in JSON support like GBNF. Keywords that only narrow
+//! values further, like `minimum`, are dropped here and reported as warnings
+//! when the tools are validated.
+
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde_json::{Map, Value, json};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Any,
+    /// Matches nothing, like the `false` schema.
+    Unsatisfiable(String),
+    Null,
+    /// `Some` when only that value is allowed.
+    Boolean(Option<bool>),
+    Number(NumberSchema),
+    String(StringSchema),
+    Array(ArraySchema),
+    Object(ObjectSchema),
+    AnyOf(Vec<Schema>),
+    OneOf(Vec<Schema>),
+    Const(Value),
+    Enum(Vec<Value>),
+    /// A `$ref` that refers back to a schema it's part of, by reference.
+    Ref(String),
+    /// The schema behind a `$ref`, which the [`Schema::Ref`]s inside it with
+    /// the same reference refer back to.
+    Recursive(String, Box<Schema>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberSchema {
+    pub integer: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringSchema {
+    pub format: Option<String>,
+    pub pattern: Option<String>,
+    pub min_length: usize,
+    pub max_length: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArraySchema {
+    /// The leading items of a tuple, followed by any number of `items`.
+    pub prefix_items: Vec<Schema>,
+    pub items: Option<Box<Schema>>,
+    pub min_items: usize,
+    pub max_items: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectSchema {
+    pub properties: IndexMap<String, Schema>,
+    pub required: HashSet<String>,
+    /// `None` unless the schema allows other properties explicitly, so models
+    /// can't invent arguments.
+    pub additional_properties: Option<Box<Schema>>,
+}
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("{0} must be {1}")]
+    Invalid(&'static str, &'static str),
+
+    #[error("unknown type {0}")]
+    UnknownType(String),
+
+    #[error("$ref {0} doesn't point into the schema")]
+    UnresolvedRef(String),
+}
+
+/// Compiles a JSON schema into a [`Schema`], inlining local `$ref`s. A `$ref`
+/// inside what it points to becomes a [`Schema::Ref`].
+pub struct SchemaCompiler<'a> {
+    root: &'a Value,
+    /// The `$ref`s being inlined, innermost last.
+    resolving: Vec<&'a str>,
+    /// The `$ref`s being inlined that something inside them refers back to.
+    recursive: HashSet<String>,
+}
+
+impl<'a> SchemaCompiler<'a> {
+    pub fn compile(schema: &'a Value) -> Result<Schema, SchemaError> {
+        SchemaCompiler {
+            root: schema,
+            resolving: Vec::new(),
+            recursive: HashSet::new(),
+        }
+        .compile_schema(schema)
+    }
+
+    fn compile_schema(&mut self, schema: &'a Value) -> Result<Schema, SchemaError> {
+        let object = match schema {
+            Value::Bool(true) => return Ok(Schema::Any),
+            Value::Bool(false) => return Ok(Schema::Unsatisfiable("false schema".into())),
+            Value::Object(object) => object,
+            _ => return Err(SchemaError::Invalid("a schema", "an object or a boolean")),
+        };
+
+        if let Some(reference) = object.get("$ref") {
+            let reference = reference
+                .as_str()
+                .ok_or(SchemaError::Invalid("$ref", "a string"))?;
+
+            return self.compile_ref(reference);
+        }
+
+        if let Some(value) = object.get("const") {
+            return Ok(Schema::Const(value.clone()));
+        }
+
+        if let Some(values) = object.get("enum") {
+            let values = values
+                .as_array()
+                .ok_or(SchemaError::Invalid("enum", "an array"))?;
+
+            return Ok(Schema::Enum(values.clone()));
+        }
+
+        if let Some(schemas) = object.get("anyOf") {
+            return Ok(Schema::AnyOf(self.compile_all("anyOf", schemas)?));
+        }
+
+        if let Some(schemas) = object.get("oneOf") {
+            return Ok(Schema::OneOf(self.compile_all("oneOf", schemas)?));
+        }
+
+        if let Some(schemas) = object.get("allOf") {
+            return self.compile_all_of(object, schemas);
+        }
+
+        match object.get("type") {
+            Some(Value::String(ty)) => self.compile_type(ty, object),
+            Some(Value::Array(types)) => types
+                .iter()
+                .map(|ty| {
+                    let ty = ty
+                        .as_str()
+                        .ok_or(SchemaError::Invalid("type", "a string"))?;
+                    self.compile_type(ty, object)
+                })
+                .collect::<Result<_, _>>()
+                .map(Schema::AnyOf),
+            Some(_) => Err(SchemaError::Invalid("type", "a string or an array")),
+            None if ["properties", "additionalProperties", "required"]
+                .iter()
+                .any(|key| object.contains_key(*key)) =>
+            {
+                self.compile_type("object", object)
+            }
+            None if ["items", "prefixItems"]
+                .iter()
+                .any(|key| object.contains_key(*key)) =>
+            {
+                self.compile_type("array", object)
+            }
+            None => Ok(Schema::Any),
+        }
+    }
+
+    /// Compiles each schema with a compiler of its own, so the alternatives
+    /// of a large `anyOf`, like one per tool, can compile in parallel.
+    fn compile_all(
+        &mut self,
+        keyword: &'static str,
+        schemas: &'a Value,
+    ) -> Result<Vec<Schema>, SchemaError> {
+        let schemas = schemas
+            .as_array()
+            .ok_or(SchemaError::Invalid(keyword, "an array"))?;
+
+        let compile = |schema| {
+            let mut compiler = SchemaCompiler {
+                root: self.root,
+                resolving: self.resolving.clone(),
+                recursive: HashSet::new(),
+            };
+            let schema = compiler.compile_schema(schema)?;
+
+            Ok((schema, compiler.recursive))
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let compiled = schemas
+            .iter()
+            .map(compile)
+            .collect::<Result<Vec<_>, SchemaError>>()?;
+
+        // collect preserves the schemas' order
+        #[cfg(feature = "rayon")]
+        let compiled = schemas
+            .par_iter()
+            .map(compile)
+            .collect::<Result<Vec<_>, SchemaError>>()?;
+
+        Ok(compiled
+            .into_iter()
+            .map(|(schema, recursive)| {
+                self.recursive.extend(recursive);
+                schema
+            })
+            .collect())
+    }
+
+    fn compile_ref(&mut self, reference: &'a str) -> Result<Schema, SchemaError> {
+        if self.resolving.contains(&reference) {
+            self.recursive.insert(reference.to_string());
+            return Ok(Schema::Ref(reference.to_string()));
+        }
+
+        let target = self.target(reference)?;
+
+        self.resolving.push(reference);
+        let schema = self.compile_schema(target)?;
+        self.resolving.pop();
+
+        Ok(match self.recursive.remove(reference) {
+            true => Schema::Recursive(reference.to_string(), Box::new(schema)),
+            false => schema,
+        })
+    }
+
+    fn target(&self, reference: &str) -> Result<&'a Value, SchemaError> {
+        reference
+            .strip_prefix('#')
+            .and_then(|pointer| self.root.pointer(pointer))
+            .ok_or_else(|| SchemaError::UnresolvedRef(reference.to_string()))
+    }
+
+    /// Merges the schemas' properties and required keys into one object
+    /// schema. Their other keywords only fill in ones not set already.
+    fn compile_all_of(
+        &mut self,
+        object: &'a Map<String, Value>,
+        schemas: &'a Value,
+    ) -> Result<Schema, SchemaError> {
+        let mut merged = object.clone();
+        merged.remove("allOf");
+
+        for schema in schemas
+            .as_array()
+            .ok_or(SchemaError::Invalid("allOf", "an array"))?
+        {
+            let schema = match schema.get("$ref").and_then(Value::as_str) {
+                Some(reference) => self.target(reference)?,
+                None => schema,
+            };
+
+            let Value::Object(schema) = schema else {
+                continue;
+            };
+
+            for (key, value) in schema {
+                match (key.as_str(), merged.get_mut(key), value) {
+                    ("properties", Some(Value::Object(properties)), Value::Object(more)) => {
+                        properties.extend(more.clone());
+                    }
+                    ("required", Some(Value::Array(required)), Value::Array(more)) => {
+                        required.extend(more.iter().cloned());
+                    }
+                    (_, None, _) => {
+                        merged.insert(key.clone(), value.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // the merged schema is a temporary, so it gets a compiler of its own
+        let merged = Value::Object(merged);
+        let mut compiler = SchemaCompiler {
+            root: self.root,
+            resolving: self.resolving.clone(),
+            recursive: HashSet::new(),
+        };
+
+        let schema = compiler.compile_schema(&merged);
+        self.recursive.extend(compiler.recursive);
+
+        schema
+    }
+
+    fn compile_type(
+        &mut self,
+        ty: &str,
+        object: &'a Map<String, Value>,
+    ) -> Result<Schema, SchemaError> {
+        let count = |key: &'static str| match object.get(key) {
+            Some(value) => value
+                .as_u64()
+                .map(|count| Some(count as usize))
+                .ok_or(SchemaError::Invalid(key, "a non-negative integer")),
+            None => Ok(None),
+        };
+
+        Ok(match ty {
+            "null" => Schema::Null,
+            "boolean" => Schema::Boolean(None),
+            "integer" => Schema::Number(NumberSchema { integer: true }),
+            "number" => Schema::Number(NumberSchema { integer: false }),
+            "string" => Schema::String(StringSchema {
+                format: object
+                    .get("format")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                pattern: object
+                    .get("pattern")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                min_length: count("minLength")?.unwrap_or(0),
+                max_length: count("maxLength")?,
+            }),
+            "array" => {
+                // draft 2019-09 and older write tuples as an array of `items`
+                let (prefix_items, items) = match (object.get("prefixItems"), object.get("items")) {
+                    (Some(prefix_items), items) => (Some(prefix_items), items),
+                    (None, Some(Value::Array(_))) => (object.get("items"), None),
+                    (None, items) => (None, items),
+                };
+
+                Schema::Array(ArraySchema {
+                    prefix_items: match prefix_items {
+                        Some(prefix_items) => self.compile_all("prefixItems", prefix_items)?,
+                        None => Vec::new(),
+                    },
+                    items: items
+                        .map(|items| self.compile_schema(items).map(Box::new))
+                        .transpose()?,
+                    min_items: count("minItems")?.unwrap_or(0),
+                    max_items: count("maxItems")?,
+                })
+            }
+            "object" => {
+                let mut properties = IndexMap::new();
+                if let Some(schemas) = object.get("properties") {
+                    let schemas = schemas
+                        .as_object()
+                        .ok_or(SchemaError::Invalid("properties", "an object"))?;
+
+                    for (name, schema) in schemas {
+                        properties.insert(name.clone(), self.compile_schema(schema)?);
+                    }
+                }
+
+                let required = match object.get("required") {
+                    Some(required) => required
+                        .as_array()
+                        .ok_or(SchemaError::Invalid("required", "an array"))?
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect(),
+                    None => HashSet::new(),
+                };
+
+                let additional_properties = match object.get("additionalProperties") {
+                    None | Some(Value::Bool(false)) => None,
+                    Some(schema) => Some(Box::new(self.compile_schema(schema)?)),
+                };
+
+                Schema::Object(ObjectSchema {
+                    properties,
+                    required,
+                    additional_properties,
+                })
+            }
+            ty => return Err(SchemaError::UnknownType(ty.to_string())),
+        })
+    }
+}
+
+/// Tightens a schema the way OpenAI's strict function calling does: every
+/// object requires all of its properties and allows no others, so generated
+/// arguments always deserialize into the caller's types.
+pub fn strict(schema: &mut Value) {
+    let Value::Object(object) = schema else {
+        return;
+    };
+
+    let is_object = object.contains_key("properties")
+        || match object.get("type") {
+            Some(Value::String(ty)) => ty == "object",
+            Some(Value::Array(types)) => types.iter().any(|ty| ty == "object"),
+            _ => false,
+        };
+
+    if is_object {
+        if let Some(Value::Object(properties)) = object.get("properties") {
+            let required = properties.keys().cloned().map(Value::String).collect();
+            object.insert("required".into(), Value::Array(required));
+        }
+
+        // a schema for the extra properties keeps them typed, anything else closes them
+        if !matches!(object.get("additionalProperties"), Some(Value::Object(_))) {
+            object.insert("additionalProperties".into(), Value::Bool(false));
+        }
+    }
+
+    for (keyword, value) in object.iter_mut() {
+        match (keyword.as_str(), value) {
+            (
+                "properties" | "patternProperties" | "$defs" | "definitions",
+                Value::Object(schemas),
+            ) => {
+                schemas.values_mut().for_each(strict);
+            }
+            ("items" | "prefixItems" | "anyOf" | "oneOf" | "allOf", Value::Array(schemas)) => {
+                schemas.iter_mut().for_each(strict)
+            }
+            ("items" | "additionalProperties" | "not" | "contains", schema) => strict(schema),
+            _ => {}
+        }
+    }
+}
+
+/// Replaces every local `$ref` with a copy of what it points to and drops the
+/// definitions, so the schema keeps its meaning once nested in another one,
+/// like a tool call's arguments. Recursive definitions can't be flattened, so
+/// they're kept under `$defs` instead, named after `name` so several schemas'
+/// definitions can share the root of whatever they're nested in. See
+/// [`take_defs`].
+pub fn inline_refs(schema: &Value, name: &str) -> Result<Value, SchemaError> {
+    let mut inliner = Inliner {
+        root: schema,
+        name,
+        resolving: Vec::new(),
+        recursive: IndexMap::new(),
+    };
+
+    let mut inlined = inliner.inline(schema)?;
+    let defs = inliner
+        .recursive
+        .into_values()
+        .collect::<Map<String, Value>>();
+
+    // a schema that's only a recursive reference is spelled out once, so its
+    // properties are still at the root
+    if let Some(definition) = inlined
+        .as_object()
+        .filter(|object| object.len() == 1)
+        .and_then(|object| object.get("$ref")?.as_str()?.strip_prefix("#/$defs/"))
+        .and_then(|name| defs.get(&name.replace("~1", "/").replace("~0", "~")))
+    {
+        inlined = definition.clone();
+    }
+    extend_defs(&mut inlined, defs);
+
+    Ok(inlined)
+}
+
+/// Takes the `$defs` [`inline_refs`] kept out of `schema`, to move them to
+/// the root of the schema it's nested in.
+pub fn take_defs(schema: &mut Value) -> Map<String, Value> {
+    match schema
+        .as_object_mut()
+        .and_then(|object| object.remove("$defs"))
+    {
+        Some(Value::Object(defs)) => defs,
+        _ => Map::new(),
+    }
+}
+
+/// Adds `defs` to `schema`'s `$defs`, if there are any.
+pub fn extend_defs(schema: &mut Value, defs: Map<String, Value>) {
+    if let (false, Value::Object(object)) = (defs.is_empty(), schema) {
+        match object
+            .entry("$defs")
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(existing) => existing.extend(defs),
+            existing => *existing = Value::Object(defs),
+        }
+    }
+}
+
+struct Inliner<'a> {
+    root: &'a Value,
+    name: &'a str,
+    /// The `$ref`s being inlined, innermost last.
+    resolving: Vec<&'a str>,
+    /// The `$ref`s something inside them refers back to, with the names of
+    /// their definitions and, once inlined, the definitions themselves.
+    recursive: IndexMap<&'a str, (String, Value)>,
+}
+
+impl<'a> Inliner<'a> {
+    fn inline(&mut self, schema: &'a Value) -> Result<Value, SchemaError> {
+        let object = match schema {
+            Value::Object(object) => object,
+            Value::Array(schemas) => {
+                return schemas
+                    .iter()
+                    .map(|schema| self.inline(schema))
+                    .collect::<Result<_, _>>()
+                    .map(Value::Array);
+            }
+            value => return Ok(value.clone()),
+        };
+
+        let mut inlined = Map::new();
+        for (keyword, value) in object {
+            let value = match keyword.as_str() {
+                "$ref" | "$defs" | "definitions" => continue,
+                "properties" | "patternProperties" | "dependentSchemas" => match value {
+                    Value::Object(schemas) => Value::Object(
+                        schemas
+                            .iter()
+                            .map(|(name, schema)| Ok((name.clone(), self.inline(schema)?)))
+                            .collect::<Result<_, SchemaError>>()?,
+                    ),
+                    value => value.clone(),
+                },
+                // values rather than schemas
+                "const" | "enum" | "default" | "examples" => value.clone(),
+                _ => self.inline(value)?,
+            };
+
+            inlined.insert(keyword.clone(), value);
+        }
+
+        let Some(reference) = object.get("$ref") else {
+            return Ok(Value::Object(inlined));
+        };
+        let reference = reference
+            .as_str()
+            .ok_or(SchemaError::Invalid("$ref", "a string"))?;
+
+        let target = match self.recursive.get(reference) {
+            Some((name, _)) => def_ref(name),
+            None if self.resolving.contains(&reference) => {
+                let name = self.def_name(reference);
+                let target = def_ref(&name);
+                // the definition is filled in once its own inlining is done
+                self.recursive.insert(reference, (name, Value::Null));
+
+                target
+            }
+            None => {
+                let target = reference
+                    .strip_prefix('#')
+                    .and_then(|pointer| self.root.pointer(pointer))
+                    .ok_or_else(|| SchemaError::UnresolvedRef(reference.to_string()))?;
+
+                self.resolving.push(reference);
+                let target = self.inline(target)?;
+                self.resolving.pop();
+
+                match self.recursive.get_mut(reference) {
+                    Some((name, definition)) => {
+                        *definition = target;
+                        def_ref(name)
+                    }
+                    None => target,
+                }
+            }
+        };
+
+        // keywords beside the reference, usually a description, are added to its
+        // target unless they'd override part of it
+        Ok(match target {
+            target if inlined.is_empty() => target,
+            Value::Object(mut target) if inlined.keys().all(|key| !target.contains_key(key)) => {
+                target.extend(inlined);
+                Value::Object(target)
+            }
+            target => json!({ "allOf": [target, inlined] }),
+        })
+    }
+
+    /// A name for a recursive definition that's unique within the schema,
+    /// like `tree-Node` for `#/$defs/Node` in a schema named `tree`.
+    fn def_name(&self, reference: &str) -> String {
+        let name = match reference.rsplit_once('/') {
+            Some((_, segment)) if !segment.is_empty() => format!("{}-{segment}", self.name),
+            _ => self.name.to_string(),
+        };
+
+        match self.recursive.values().any(|(taken, _)| *taken == name) {
+            true => format!("{name}-{}", self.recursive.len()),
+            false => name,
+        }
+    }
+}
+
+/// A `$ref` to one of the `$defs` at the root.
+fn def_ref(name: &str) -> Value {
+    let name = name.replace('~', "~0").replace('/', "~1");
+
+    json!({ "$ref": format!("#/$defs/{name}") })
+}
@@ -0,0 +1,227 @@
+//! Helpers for validating a config against a corpus of conversations, the same
+//! way the crate's own tests do. Enabled by the `test-utils` feature.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    Acquiesce,
+    parse::{ParseResult, ReasoningDelta, ToolCallDelta},
+    render::{
+        RenderError, RenderOptions, RenderResult,
+        schema::{ChatMessages, ChatTool, ChatToolChoice},
+    },
+};
+
+/// Set to re-record snapshots instead of comparing against them.
+pub static UPDATE_SNAPSHOTS_ENV: &str = "ACQUIESCE_UPDATE_SNAPSHOTS";
+
+/// One line of a messages.jsonl corpus.
+#[derive(Clone, Deserialize)]
+pub struct TestCase {
+    pub messages: ChatMessages,
+    pub tools: Vec<ChatTool>,
+    #[serde(default)]
+    pub tool_choice: ChatToolChoice,
+    /// A recorded model output to replay through the parser.
+    #[serde(default)]
+    pub completion: Option<String>,
+    /// The arguments text of each tool call the completion should produce, in
+    /// call order.
+    #[serde(default)]
+    pub expected_tool_calls: Option<Vec<String>>,
+}
+
+/// Everything the parser emitted while replaying a completion.
+#[derive(Debug, Default, PartialEq)]
+pub struct Replay {
+    pub content: String,
+    /// One entry per thinking block.
+    pub reasoning: Vec<String>,
+    pub tool_calls: Vec<String>,
+    /// The IDs of the calls that had one, by index.
+    pub tool_call_ids: BTreeMap<usize, String>,
+    /// The names of the calls written outside the call, by index.
+    pub tool_call_names: BTreeMap<usize, String>,
+    pub rejected: Vec<(String, &'static str)>,
+    pub complete: bool,
+}
+
+/// Reads a corpus with one [`TestCase`] per line, skipping blank lines.
+pub fn load_corpus(path: impl AsRef<Path>) -> Result<Vec<TestCase>, CorpusError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut test_cases = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let test_case = serde_json::from_str(&line).map_err(|source| CorpusError::InvalidCase {
+            line: index + 1,
+            source,
+        })?;
+
+        test_cases.push(test_case);
+    }
+
+    Ok(test_cases)
+}
+
+/// Renders a test case into a single text snapshot of its prompt, grammar,
+/// warnings and stop sequences.
+pub fn render_snapshot(
+    acquiesce: &Acquiesce,
+    test_case: &TestCase,
+    options: &RenderOptions,
+) -> Result<String, RenderError> {
+    let RenderResult {
+        prompt,
+        grammar,
+        warnings,
+        stop,
+        ..
+    } = acquiesce.render(
+        &test_case.messages,
+        test_case.tools.clone(),
+        test_case.tool_choice.clone(),
+        options,
+    )?;
+
+    let mut snapshot = format!("--- prompt\n{prompt}\n");
+
+    match grammar {
+        Some(grammar) => write!(snapshot, "--- grammar\n{grammar}\n")?,
+        None => writeln!(snapshot, "--- grammar: none")?,
+    }
+
+    for warning in warnings {
+        writeln!(snapshot, "--- warning: {warning}")?;
+    }
+
+    for stop in stop {
+        writeln!(snapshot, "--- stop: {stop:?}")?;
+    }
+
+    Ok(snapshot)
+}
+
+/// Compares `actual` against the snapshot file at `path`, panicking on a
+/// mismatch. The file is (re)written instead when it doesn't exist yet or
+/// [`UPDATE_SNAPSHOTS_ENV`] is set.
+#[track_caller]
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+
+    if std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() || !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+
+        std::fs::write(path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(path).unwrap();
+
+    if expected != actual {
+        let line = expected
+            .lines()
+            .zip(actual.lines())
+            .take_while(|(expected, actual)| expected == actual)
+            .count();
+
+        panic!(
+            "snapshot {} differs from line {}, rerun with {UPDATE_SNAPSHOTS_ENV}=1 to accept \
+             the new output\n--- expected\n{expected}\n--- actual\n{actual}",
+            path.display(),
+            line + 1,
+        );
+    }
+}
+
+/// Feeds a completion through the config's parser one character at a time, as
+/// a streaming server would. Returns `None` if the config has no parser.
+pub fn replay_completion(acquiesce: &Acquiesce, completion: &str) -> Option<Replay> {
+    let parser = acquiesce.parser()?;
+
+    let mut replay = Replay::default();
+    let mut tool_calls = BTreeMap::<usize, String>::new();
+
+    for result in parser.parse_iter(completion.chars().map(String::from)) {
+        match result {
+            ParseResult::Content(content) => replay.content.push_str(&content),
+            ParseResult::Reasoning(ReasoningDelta { index, delta }) => {
+                replay
+                    .reasoning
+                    .resize(replay.reasoning.len().max(index + 1), String::new());
+                replay.reasoning[index].push_str(&delta);
+            }
+            ParseResult::ToolCall(ToolCallDelta { index, delta }) => {
+                tool_calls.entry(index).or_default().push_str(&delta)
+            }
+            ParseResult::ToolCallId(ToolCallDelta { index, delta }) => replay
+                .tool_call_ids
+                .entry(index)
+                .or_default()
+                .push_str(&delta),
+            ParseResult::ToolCallName(ToolCallDelta { index, delta }) => replay
+                .tool_call_names
+                .entry(index)
+                .or_default()
+                .push_str(&delta),
+            ParseResult::Rejected(text, expected) => replay.rejected.push((text, expected)),
+            ParseResult::Complete => replay.complete = true,
+        }
+    }
+
+    replay.tool_calls = tool_calls.into_values().collect();
+
+    Some(replay)
+}
+
+/// Replays the test case's completion and asserts that the parser accepts all
+/// of it and produces exactly the expected tool calls. Cases without a
+/// completion are ignored.
+#[track_caller]
+pub fn assert_tool_calls(acquiesce: &Acquiesce, test_case: &TestCase) {
+    let Some(completion) = &test_case.completion else {
+        return;
+    };
+
+    let replay = replay_completion(acquiesce, completion).expect("config has no parser");
+
+    assert!(
+        replay.rejected.is_empty(),
+        "parser rejected {:?} in completion {completion:?}",
+        replay.rejected
+    );
+
+    if let Some(expected_tool_calls) = &test_case.expected_tool_calls {
+        assert_eq!(
+            &replay.tool_calls, expected_tool_calls,
+            "unexpected tool calls for completion {completion:?}"
+        );
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CorpusError {
+    #[error("failed to read corpus: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid case on line {line}: {source}")]
+    InvalidCase {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
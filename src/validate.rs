@@ -0,0 +1,155 @@
+//! Checks a config up front, so every mistake in it is reported at once
+//! instead of one at a time from inside `render`.
+
+use thiserror::Error;
+
+use crate::{Config, Lexeme, OrderedLexemes, ToolCall, ToolCalls};
+
+/// One problem with a config. The first field locates it, like
+/// `tool_calls.tool_call.delimiter`.
+#[derive(Debug, Error)]
+pub enum ConfigDiagnostic {
+    #[error("{0} is empty")]
+    EmptyLexemes(&'static str),
+
+    #[error("{0} has an invalid regex: {1}")]
+    Regex(&'static str, String),
+
+    #[error("{0} has an invalid json schema: {1}")]
+    JsonSchema(&'static str, String),
+
+    #[error("{0} {2:?} collides with the tool call marker in {1}")]
+    ThinkingCollision(&'static str, &'static str, String),
+}
+
+impl OrderedLexemes {
+    fn diagnose(&self, at: &'static str, diagnostics: &mut Vec<ConfigDiagnostic>) {
+        if self.0.is_empty() {
+            diagnostics.push(ConfigDiagnostic::EmptyLexemes(at));
+        }
+
+        for lexeme in &self.0 {
+            match lexeme {
+                Lexeme::Text(s) | Lexeme::Token(s) | Lexeme::Whitespace { whitespace: s }
+                    if s.is_empty() =>
+                {
+                    diagnostics.push(ConfigDiagnostic::EmptyLexemes(at));
+                }
+                Lexeme::Regex { pattern } => {
+                    if let Err(e) = regex::Regex::new(pattern) {
+                        diagnostics.push(ConfigDiagnostic::Regex(at, e.to_string()));
+                    }
+                }
+                Lexeme::JsonSchema(schema) => {
+                    if let Err(e) = jsonschema::meta::validate(schema) {
+                        diagnostics.push(ConfigDiagnostic::JsonSchema(at, e.to_string()));
+                    }
+                }
+                Lexeme::Choice { choices } => {
+                    if choices.is_empty() {
+                        diagnostics.push(ConfigDiagnostic::EmptyLexemes(at));
+                    }
+
+                    for choice in choices {
+                        choice.diagnose(at, diagnostics);
+                    }
+                }
+                Lexeme::Optional { optional } => optional.diagnose(at, diagnostics),
+                Lexeme::Text(_)
+                | Lexeme::Token(_)
+                | Lexeme::TokenId(_)
+                | Lexeme::Whitespace { .. } => {}
+            }
+        }
+    }
+
+    /// The literal text the sequence starts with, if it starts with one.
+    fn marker(&self) -> Option<&str> {
+        match self.0.first()? {
+            Lexeme::Text(s) | Lexeme::Token(s) if !s.is_empty() => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Config<T> {
+    /// Every problem with this config. Empty when it's valid.
+    pub fn validate(&self) -> Vec<ConfigDiagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let Config::Components {
+            thinking,
+            tool_calls,
+            ..
+        } = self
+        else {
+            return diagnostics;
+        };
+
+        let mut tool_lexemes = Vec::new();
+        let tool_call = match tool_calls {
+            Some(ToolCalls::ToolCall { tool_call }) => Some(tool_call),
+            Some(ToolCalls::ToolCallsSection {
+                prefix,
+                tool_call,
+                suffix,
+                ..
+            }) => {
+                tool_lexemes.push(("tool_calls.prefix", Some(prefix)));
+                tool_lexemes.push(("tool_calls.suffix", suffix.as_ref()));
+                Some(tool_call)
+            }
+            None => None,
+        };
+
+        if let Some(ToolCall::NamedParameters {
+            prefix,
+            id,
+            delimiter,
+            suffix,
+            ..
+        }) = tool_call
+        {
+            tool_lexemes.extend([
+                ("tool_calls.tool_call.prefix", prefix.as_ref()),
+                ("tool_calls.tool_call.id", id.as_ref()),
+                ("tool_calls.tool_call.delimiter", delimiter.as_ref()),
+                ("tool_calls.tool_call.suffix", suffix.as_ref()),
+            ]);
+        }
+
+        let mut markers = Vec::new();
+        for (at, lexemes) in tool_lexemes {
+            if let Some(lexemes) = lexemes {
+                lexemes.diagnose(at, &mut diagnostics);
+                markers.extend(lexemes.marker().map(|marker| (at, marker)));
+            }
+        }
+
+        if let Some(thinking) = thinking {
+            for (at, lexemes) in [
+                ("thinking.prefix", &thinking.prefix),
+                ("thinking.suffix", &thinking.suffix),
+            ] {
+                lexemes.diagnose(at, &mut diagnostics);
+
+                let Some(marker) = lexemes.marker() else {
+                    continue;
+                };
+
+                // only the markers that open a tool call or section are ambiguous
+                diagnostics.extend(
+                    markers
+                        .iter()
+                        .filter(|(tool_at, _)| tool_at.ends_with("prefix"))
+                        .filter(|(_, tool)| tool.starts_with(marker) || marker.starts_with(tool))
+                        .map(|(tool_at, _)| {
+                            ConfigDiagnostic::ThinkingCollision(at, tool_at, marker.to_string())
+                        }),
+                );
+            }
+        }
+
+        diagnostics
+    }
+}
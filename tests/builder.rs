@@ -0,0 +1,24 @@
+use acquiesce::{Lexeme, builder::AcquiesceBuilder, configs::get_preset};
+
+#[test]
+fn builder_matches_preset() {
+    let repr = AcquiesceBuilder::new()
+        .thinking(
+            Lexeme::Token("<think>".into()),
+            [
+                Lexeme::Token("</think>".into()),
+                Lexeme::Whitespace {
+                    whitespace: "\n".into(),
+                },
+            ]
+            .as_slice(),
+        )
+        .tool_section([Lexeme::Token("<tool_call>".into()), "\n".into()].as_slice())
+        .json_object("name", "arguments")
+        .section_suffix(["\n".into(), Lexeme::Token("</tool_call>".into())].as_slice())
+        .section_per_call()
+        .stop("<|im_end|>")
+        .build();
+
+    assert_eq!(repr.to_string(), get_preset("qwen3").unwrap().to_string());
+}
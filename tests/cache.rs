@@ -0,0 +1,25 @@
+use std::cell::Cell;
+
+use acquiesce::{Acquiesce, AcquiesceRepr};
+
+#[test]
+fn get_or_load_reloads_on_revision_change() {
+    let loads = Cell::new(0);
+    let loader = || {
+        loads.set(loads.get() + 1);
+
+        AcquiesceRepr::infer_default("kimi-k2")
+            .unwrap()
+            .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+    };
+
+    Acquiesce::get_or_load("moonshotai/Kimi-K2", "a", loader).unwrap();
+    Acquiesce::get_or_load("moonshotai/Kimi-K2", "a", loader).unwrap();
+    assert_eq!(loads.get(), 1);
+
+    Acquiesce::get_or_load("moonshotai/Kimi-K2", "b", loader).unwrap();
+    assert_eq!(loads.get(), 2);
+
+    Acquiesce::get_or_load("moonshotai/Kimi-K2-Thinking", "b", loader).unwrap();
+    assert_eq!(loads.get(), 3);
+}
@@ -0,0 +1,19 @@
+#![cfg(feature = "json-schema")]
+
+use acquiesce::{config_schema::schema, configs::PRESETS};
+use serde_json::Value;
+
+#[test]
+fn presets_match_the_schema() {
+    let validator = jsonschema::validator_for(&schema()).unwrap();
+
+    for (name, preset) in PRESETS {
+        let config = serde_json::from_str::<Value>(&preset().to_string()).unwrap();
+        let errors = validator
+            .iter_errors(&config)
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+
+        assert!(errors.is_empty(), "{name}: {errors:?}");
+    }
+}
@@ -0,0 +1,43 @@
+#![cfg(any(feature = "yaml", feature = "toml"))]
+
+use acquiesce::{AcquiesceRepr, configs::get_preset};
+
+#[cfg(feature = "yaml")]
+#[test]
+fn yaml_config() {
+    let repr = AcquiesceRepr::from_yaml(
+        "
+version: v1
+extends: qwen3
+config:
+  thinking: null
+",
+    )
+    .unwrap();
+
+    assert_eq!(
+        repr.to_string(),
+        get_preset("qwen3")
+            .unwrap()
+            .merge(&serde_json::json!({ "thinking": null }))
+            .unwrap()
+            .to_string()
+    );
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn toml_config() {
+    let repr = AcquiesceRepr::from_toml(
+        r#"
+version = "v1"
+
+[config]
+type = "harmony"
+builtin_tools = ["python"]
+"#,
+    )
+    .unwrap();
+
+    assert!(repr.to_string().contains("python"));
+}
@@ -0,0 +1,85 @@
+use acquiesce::{AcquiesceRepr, configs::get_preset, gguf::GgufMetadata};
+
+fn string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend((s.len() as u64).to_le_bytes());
+    buf.extend(s.as_bytes());
+}
+
+fn gguf() -> Vec<u8> {
+    let mut buf = b"GGUF".to_vec();
+    buf.extend(3u32.to_le_bytes());
+    buf.extend(0u64.to_le_bytes());
+    buf.extend(6u64.to_le_bytes());
+
+    string(&mut buf, "general.architecture");
+    buf.extend(8u32.to_le_bytes());
+    string(&mut buf, "qwen3");
+
+    string(&mut buf, "general.rope_freq_base");
+    buf.extend(6u32.to_le_bytes());
+    buf.extend(1e6f32.to_le_bytes());
+
+    string(&mut buf, "tokenizer.ggml.tokens");
+    buf.extend(9u32.to_le_bytes());
+    buf.extend(8u32.to_le_bytes());
+    buf.extend(3u64.to_le_bytes());
+    for token in ["hello", "<|im_start|>", "<|im_end|>"] {
+        string(&mut buf, token);
+    }
+
+    string(&mut buf, "tokenizer.ggml.token_type");
+    buf.extend(9u32.to_le_bytes());
+    buf.extend(5u32.to_le_bytes());
+    buf.extend(3u64.to_le_bytes());
+    for token_type in [1i32, 3, 3] {
+        buf.extend(token_type.to_le_bytes());
+    }
+
+    string(&mut buf, "tokenizer.ggml.eos_token_id");
+    buf.extend(4u32.to_le_bytes());
+    buf.extend(2u32.to_le_bytes());
+
+    string(&mut buf, "tokenizer.chat_template");
+    buf.extend(8u32.to_le_bytes());
+    string(&mut buf, "{{ messages[0].content }}");
+
+    buf
+}
+
+#[test]
+fn reads_gguf_metadata() {
+    let metadata = GgufMetadata::from_reader(gguf().as_slice()).unwrap();
+
+    assert_eq!(metadata.architecture.as_deref(), Some("qwen3"));
+    assert_eq!(
+        metadata.chat_template.as_deref(),
+        Some("{{ messages[0].content }}")
+    );
+    assert_eq!(metadata.bos_token, None);
+    assert_eq!(metadata.eos_token.as_deref(), Some("<|im_end|>"));
+    assert_eq!(
+        metadata.added_tokens,
+        [(1, "<|im_start|>".into()), (2, "<|im_end|>".into())].into()
+    );
+
+    assert_eq!(
+        AcquiesceRepr::infer_from_gguf(&metadata)
+            .unwrap()
+            .to_string(),
+        get_preset("qwen3").unwrap().to_string()
+    );
+    assert!(
+        AcquiesceRepr::infer_from_gguf(&metadata)
+            .unwrap()
+            .resolve_from_gguf(&metadata)
+            .is_ok()
+    );
+}
+
+#[test]
+fn rejects_truncated_gguf() {
+    let gguf = gguf();
+
+    assert!(GgufMetadata::from_reader(&gguf[..gguf.len() - 4]).is_err());
+    assert!(GgufMetadata::from_reader(&b"GGML"[..]).is_err());
+}
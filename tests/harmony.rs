@@ -0,0 +1,190 @@
+use acquiesce::parse::{ParseResult, ReasoningDelta, ToolCallDelta};
+use acquiesce::render::schema::{ChatMessages, ChatTool, ChatToolChoice};
+use acquiesce::render::{ReasoningEffort, RenderOptions};
+use acquiesce::{Acquiesce, AcquiesceRepr, HarmonyBuiltinTool, HarmonyChannel, HarmonyOptions};
+
+#[test]
+fn harmony_system_message_options() {
+    let config = r#"{
+        "version": "v1",
+        "config": {
+            "type": "harmony",
+            "builtin_tools": ["python", "browser"],
+            "model_identity": "You are a helpful assistant.",
+            "knowledge_cutoff": "2025-01"
+        }
+    }"#
+    .parse::<AcquiesceRepr>()
+    .unwrap();
+
+    let AcquiesceRepr::Harmony(options) = config else {
+        panic!("expected a harmony config");
+    };
+    assert!(options.builtin_tools.contains(&HarmonyBuiltinTool::Browser));
+
+    let messages = serde_json::from_str::<ChatMessages>(
+        r#"[
+            {"role": "system", "content": "Answer tersely."},
+            {"role": "user", "content": "What's the weather in Paris?"}
+        ]"#,
+    )
+    .unwrap();
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{
+            "type": "function",
+            "function": {
+                "name": "get_weather",
+                "description": "Current weather for a city.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "city": {"type": "string"},
+                        "unit": {"enum": ["c", "f"], "default": "c"}
+                    },
+                    "required": ["city"]
+                }
+            }
+        }]"#,
+    )
+    .unwrap();
+
+    let prompt = Acquiesce::Harmony(options)
+        .render(
+            messages,
+            tools,
+            ChatToolChoice::Auto,
+            &RenderOptions::default(),
+        )
+        .unwrap()
+        .prompt;
+
+    assert!(prompt.starts_with(
+        "<|start|>system<|message|>You are a helpful assistant.\nKnowledge cutoff: 2025-01\n"
+    ));
+    assert!(prompt.find("## browser").unwrap() < prompt.find("## python").unwrap());
+    assert!(
+        prompt.contains("Calls to these tools must go to the commentary channel: 'functions'.")
+    );
+    assert!(prompt.contains("# Instructions\n\nAnswer tersely.\n\n# Tools"));
+    assert!(prompt.contains(
+        "// Current weather for a city.\ntype get_weather = (_: {\ncity: string,\nunit?: \"c\" | \"f\", // default: \"c\"\n}) => any;\n"
+    ));
+    assert!(prompt.ends_with(
+        "<|start|>user<|message|>What's the weather in Paris?<|end|><|start|>assistant"
+    ));
+
+    let default = Acquiesce::Harmony(HarmonyOptions::default())
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            Vec::new(),
+            ChatToolChoice::Auto,
+            &RenderOptions {
+                reasoning_effort: Some(ReasoningEffort::High),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .prompt;
+
+    assert!(default.contains("\n\nReasoning: high\n\n"));
+    assert!(!default.contains("# Tools"));
+    assert!(!default.contains("<|start|>developer"));
+}
+
+#[test]
+fn harmony_channels_parse_into_deltas() {
+    let parse = |completion: &str| {
+        let parser = Acquiesce::Harmony(HarmonyOptions::default())
+            .parser()
+            .unwrap();
+
+        parser
+            .parse_iter(completion.chars().map(String::from))
+            .map(|result| match result {
+                ParseResult::Content(delta) => format!("content:{delta}"),
+                ParseResult::Reasoning(ReasoningDelta { index, delta }) => {
+                    format!("reasoning[{index}]:{delta}")
+                }
+                ParseResult::ToolCall(ToolCallDelta { index, delta }) => {
+                    format!("tool_call[{index}]:{delta}")
+                }
+                ParseResult::ToolCallName(ToolCallDelta { index, delta }) => {
+                    format!("tool_call_name[{index}]:{delta}")
+                }
+                ParseResult::ToolCallId(_) => unreachable!(),
+                ParseResult::Rejected(text, _) => format!("rejected:{text}"),
+                ParseResult::Complete => "complete".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .concat()
+    };
+
+    let answer = parse(
+        "<|channel|>analysis<|message|>Easy.<|end|><|start|>assistant<|channel|>final<|message|>Hi!<|return|>",
+    );
+    assert_eq!(
+        answer,
+        "reasoning[0]:Ereasoning[0]:areasoning[0]:sreasoning[0]:yreasoning[0]:.\
+         content:Hcontent:icontent:!complete"
+    );
+
+    let call = parse(
+        "<|channel|>analysis<|message|>Look it up.<|end|>\
+         <|start|>assistant<|channel|>commentary<|message|>Checking.<|end|>\
+         <|start|>assistant<|channel|>commentary to=functions.get_weather <|constrain|>json<|message|>{}<|call|>",
+    );
+    assert!(call.starts_with("reasoning[0]:L"));
+    assert!(call.contains("content:."));
+    assert!(call.ends_with("tool_call_name[0]:get_weathertool_call[0]:{tool_call[0]:}complete"));
+}
+
+#[test]
+fn harmony_channels_and_cutoff_per_request() {
+    let mut options = HarmonyOptions::default();
+    options.knowledge_cutoff = Some("2025-01".to_string());
+    let acquiesce = Acquiesce::Harmony(options);
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let render = |options: &RenderOptions| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Auto,
+                options,
+            )
+            .unwrap()
+    };
+
+    let all = render(&RenderOptions::default());
+    assert!(all.prompt.contains("Knowledge cutoff: 2025-01\n"));
+    assert!(
+        all.prompt
+            .contains("# Valid channels: analysis, commentary, final.")
+    );
+    let grammar = all.grammar.unwrap();
+    assert!(grammar.contains("analysis"), "{grammar}");
+    assert!(grammar.contains("commentary to=functions."), "{grammar}");
+
+    let no_analysis = render(&RenderOptions {
+        knowledge_cutoff: Some("2025-06".to_string()),
+        harmony_channels: Some(vec![HarmonyChannel::Final, HarmonyChannel::Commentary]),
+        ..Default::default()
+    });
+    assert!(no_analysis.prompt.contains("Knowledge cutoff: 2025-06\n"));
+    assert!(
+        no_analysis
+            .prompt
+            .contains("# Valid channels: commentary, final.")
+    );
+    assert!(!no_analysis.grammar.unwrap().contains("analysis"));
+
+    let final_only = render(&RenderOptions {
+        harmony_channels: Some(vec![HarmonyChannel::Final]),
+        ..Default::default()
+    });
+    assert!(!final_only.prompt.contains("functions"));
+    assert!(final_only.grammar.is_none());
+}
@@ -0,0 +1,70 @@
+use std::sync::{Arc, Mutex};
+
+use acquiesce::AcquiesceRepr;
+use acquiesce::hooks::Hooks;
+use acquiesce::render::RenderOptions;
+use acquiesce::render::schema::{ChatMessages, ChatTool, ChatToolChoice};
+
+#[test]
+fn render_and_warning_hooks() {
+    let mut acquiesce = r#"{
+        "version": "v1",
+        "config": {
+            "type": "components",
+            "chat_template": null,
+            "thinking": null,
+            "tool_calls": {
+                "type": "tool_call",
+                "tool_call": { "type": "json_object", "name_key": "name", "argument_key": "arguments" }
+            }
+        }
+    }"#
+    .parse::<AcquiesceRepr>()
+    .unwrap()
+    .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+    .unwrap();
+
+    let renders = Arc::new(Mutex::new(Vec::new()));
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+
+    acquiesce.set_hooks(Hooks {
+        on_render: Some({
+            let renders = renders.clone();
+            Arc::new(move |meta| renders.lock().unwrap().push(meta.clone()))
+        }),
+        on_warning: Some({
+            let warnings = warnings.clone();
+            Arc::new(move |warning| warnings.lock().unwrap().push(warning.to_string()))
+        }),
+        ..Default::default()
+    });
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+
+    let result = acquiesce
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Auto,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+    let renders = renders.lock().unwrap();
+    assert_eq!(renders.len(), 1);
+    assert_eq!(renders[0].tools, 1);
+    assert_eq!(renders[0].prompt_len, Some(2));
+    assert_eq!(
+        renders[0].grammar_len,
+        result.grammar.as_ref().map(String::len)
+    );
+    assert_eq!(renders[0].warnings, 1);
+
+    assert_eq!(
+        *warnings.lock().unwrap(),
+        ["chat template does not render tools"]
+    );
+}
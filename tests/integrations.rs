@@ -0,0 +1,70 @@
+use acquiesce::AcquiesceRepr;
+use acquiesce::integrations::{LlamaCppRequest, MistralRsRequest};
+use acquiesce::render::RenderOptions;
+use acquiesce::render::schema::{ChatMessages, ChatTool, ChatToolChoice};
+
+#[test]
+fn engine_requests() {
+    let acquiesce = r#"{
+        "version": "v1",
+        "config": {
+            "type": "components",
+            "chat_template": null,
+            "thinking": null,
+            "tool_calls": {
+                "type": "tool_call",
+                "tool_call": { "type": "json_object", "name_key": "name", "argument_key": "arguments" }
+            }
+        }
+    }"#
+    .parse::<AcquiesceRepr>()
+    .unwrap()
+    .resolve_from_options(
+        "{{ messages[0].content }}".to_string(),
+        None,
+        Some("</s>".to_string()),
+        false,
+    )
+    .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+
+    let mut llama_cpp = LlamaCppRequest::default();
+    acquiesce
+        .render_into(
+            &mut llama_cpp,
+            ChatMessages::Content("hi".to_string()),
+            tools.clone(),
+            ChatToolChoice::None,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+    assert_eq!(llama_cpp.prompt, "hi");
+    assert!(llama_cpp.grammar.is_none());
+    assert_eq!(llama_cpp.stop, ["</s>"]);
+
+    let mut mistral_rs = MistralRsRequest::default();
+    acquiesce
+        .render_into(
+            &mut mistral_rs,
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Required,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+    let body = serde_json::to_value(&mistral_rs).unwrap();
+    assert_eq!(body["grammar"]["type"], "lark");
+    assert!(
+        body["grammar"]["value"]
+            .as_str()
+            .unwrap()
+            .starts_with("start:")
+    );
+    assert_eq!(body["stop"], serde_json::json!(["</s>"]));
+}
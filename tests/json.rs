@@ -0,0 +1,204 @@
+//! Expected outputs were produced by CPython's `json.dumps`, since chat
+//! templates written for transformers depend on its exact formatting.
+
+use acquiesce::render::json::JsonFormatter;
+use serde_json::{Value, json};
+
+fn dumps(value: &Value, ensure_ascii: bool) -> String {
+    JsonFormatter {
+        ensure_ascii,
+        ..JsonFormatter::python()
+    }
+    .serialize(value)
+    .unwrap()
+}
+
+fn dumps_pretty(value: &Value) -> String {
+    JsonFormatter {
+        sort_keys: true,
+        ensure_ascii: true,
+        python_compat: true,
+        ..JsonFormatter::pretty(2)
+    }
+    .serialize(value)
+    .unwrap()
+}
+
+#[test]
+fn floats() {
+    let value = json!([
+        1.0,
+        -0.0,
+        0.1,
+        100.0,
+        1.23456,
+        1e15,
+        1e16,
+        123456789012345678.0,
+        0.0001,
+        1e-5,
+        1.5e-7,
+        2.5e300,
+        5e-324
+    ]);
+
+    assert_eq!(
+        dumps(&value, true),
+        "[1.0, -0.0, 0.1, 100.0, 1.23456, 1000000000000000.0, 1e+16, 1.2345678901234568e+17, 0.0001, 1e-05, 1.5e-07, 2.5e+300, 5e-324]"
+    );
+    assert_eq!(dumps_pretty(&json!([1.0, 1e-5])), "[\n  1.0,\n  1e-05\n]");
+}
+
+#[test]
+fn integers() {
+    let value = json!([0, -1, 9007199254740993u64, 18446744073709551615u64]);
+
+    assert_eq!(
+        dumps(&value, true),
+        "[0, -1, 9007199254740993, 18446744073709551615]"
+    );
+}
+
+#[test]
+fn ascii_escapes() {
+    let value = json!([
+        "quote \" backslash \\ slash / \u{8}\u{c}\n\r\t",
+        "\u{0}\u{1f}\u{7f}\u{85}"
+    ]);
+
+    assert_eq!(
+        dumps(&value, true),
+        r#"["quote \" backslash \\ slash / \b\f\n\r\t", "\u0000\u001f\u007f\u0085"]"#
+    );
+    assert_eq!(
+        dumps(&value, false),
+        "[\"quote \\\" backslash \\\\ slash / \\b\\f\\n\\r\\t\", \"\\u0000\\u001f\u{7f}\u{85}\"]"
+    );
+}
+
+#[test]
+fn non_ascii() {
+    let value = json!(["café", "日本語", "😀", "\u{2028}\u{2029}"]);
+
+    assert_eq!(
+        dumps(&value, true),
+        r#"["caf\u00e9", "\u65e5\u672c\u8a9e", "\ud83d\ude00", "\u2028\u2029"]"#
+    );
+    assert_eq!(
+        dumps(&value, false),
+        "[\"café\", \"日本語\", \"😀\", \"\u{2028}\u{2029}\"]"
+    );
+}
+
+#[test]
+fn nested() {
+    let value = json!({ "a": {}, "b": [1, { "c": null }], "d": [], "e": true });
+
+    assert_eq!(
+        dumps(&value, true),
+        r#"{"a": {}, "b": [1, {"c": null}], "d": [], "e": true}"#
+    );
+    assert_eq!(
+        dumps_pretty(&value),
+        "{\n  \"a\": {},\n  \"b\": [\n    1,\n    {\n      \"c\": null\n    }\n  ],\n  \"d\": [],\n  \"e\": true\n}"
+    );
+}
+
+#[test]
+fn html_safe() {
+    let value = json!({ "html": "</script><b>&amp;</b>", "js": "a\u{2028}b\u{2029}c" });
+
+    assert_eq!(
+        JsonFormatter {
+            sort_keys: true,
+            ..JsonFormatter::html_safe()
+        }
+        .serialize(&value)
+        .unwrap(),
+        r#"{"html":"\u003c/script\u003e\u003cb\u003e\u0026amp;\u003c/b\u003e","js":"a\u2028b\u2029c"}"#
+    );
+}
+
+#[test]
+fn canonical_numbers() {
+    // RFC 8785 appendix B
+    let cases = [
+        (0x0000000000000000, "0"),
+        (0x8000000000000000, "0"),
+        (0x0000000000000001, "5e-324"),
+        (0x7fefffffffffffff, "1.7976931348623157e+308"),
+        (0x4340000000000000, "9007199254740992"),
+        (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+        (0x444b1ae4d6e2ef50, "1e+21"),
+        (0x44b52d02c7e14af6, "1e+23"),
+        (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+        (0x3eb0c6f7a0b5ed8d, "0.000001"),
+        (0x41b3de4355555555, "333333333.3333333"),
+        (0xc0fe240000000000, "-123456"),
+    ];
+
+    for (bits, expected) in cases {
+        let value = json!(f64::from_bits(bits));
+        assert_eq!(
+            JsonFormatter::canonical().serialize(&value).unwrap(),
+            expected,
+            "{bits:#018x}"
+        );
+    }
+}
+
+#[test]
+fn canonical_key_order() {
+    // RFC 8785 section 3.2.3
+    let value = json!({
+        "€": "Euro Sign",
+        "\r": "Carriage Return",
+        "\u{fb33}": "Hebrew Letter Dalet With Dagesh",
+        "1": "One",
+        "😀": "Emoji: Grinning Face",
+        "\u{80}": "Control",
+        "ö": "Latin Small Letter O With Diaeresis"
+    });
+
+    assert_eq!(
+        JsonFormatter::canonical().serialize(&value).unwrap(),
+        "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",\"ö\":\"Latin Small Letter O With Diaeresis\",\"€\":\"Euro Sign\",\"😀\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+    );
+}
+
+#[test]
+fn serialize_streams_like_display() {
+    let value = json!({
+        "z": [1.0, -0.0, 1e-5, 9007199254740993u64, null, true],
+        "a": { "nested": {}, "empty": [], "text": "</script>\u{0}\u{7f}\u{85}\u{2028}é😀\"\\/" }
+    });
+
+    for formatter in [
+        JsonFormatter::default(),
+        JsonFormatter::compact(),
+        JsonFormatter::pretty(2),
+        JsonFormatter::python(),
+        JsonFormatter::html_safe(),
+        JsonFormatter {
+            escape_solidus: true,
+            ..JsonFormatter::pretty(4)
+        },
+        JsonFormatter {
+            sort_keys: false,
+            ..JsonFormatter::canonical()
+        },
+    ] {
+        let streamed = formatter.serialize(&value).unwrap();
+
+        assert_eq!(streamed, formatter.display(&value).to_string());
+        assert_eq!(streamed, formatter.display_serialized(&value).to_string());
+    }
+
+    // values that aren't a `Value`, with number types it doesn't have
+    assert_eq!(
+        JsonFormatter::python()
+            .serialize(&("a", 0.5f32, 3u32, -7i8))
+            .unwrap(),
+        r#"["a", 0.5, 3, -7]"#
+    );
+}
@@ -0,0 +1,104 @@
+use acquiesce::{
+    AcquiesceRepr, InitError,
+    configs::{get_preset, list_presets},
+    migrate::{CURRENT_VERSION, migrate},
+    render::{
+        RenderOptions,
+        schema::{ChatMessages, ChatTool, ChatToolChoice},
+    },
+};
+use serde_json::json;
+
+#[test]
+fn reports_defaulted_and_ignored_fields() {
+    let migrated = migrate(
+        r#"{
+            "version": "v2",
+            "config": {
+                "type": "components",
+                "chat_template": null,
+                "tool_calls": null,
+                "stop": ["<|end|>"],
+                "sampling": { "temperature": 0.6 }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(migrated.version, CURRENT_VERSION + 1);
+    assert_eq!(migrated.defaulted, ["/config/thinking"]);
+    assert_eq!(migrated.ignored, ["/config/sampling"]);
+}
+
+#[test]
+fn rejects_unknown_versions() {
+    for config in [
+        r#"{ "config": { "type": "harmony" } }"#,
+        r#"{ "version": "v0", "config": { "type": "harmony" } }"#,
+        r#"{ "version": "latest", "config": { "type": "harmony" } }"#,
+    ] {
+        assert!(matches!(
+            config.parse::<AcquiesceRepr>(),
+            Err(InitError::UnsupportedVersion(_))
+        ));
+    }
+}
+
+#[test]
+fn extends_merges_over_the_preset() {
+    let repr = r#"{
+        "version": "v1",
+        "extends": "qwen3",
+        "config": { "thinking": null, "stop": ["<|endoftext|>"] }
+    }"#
+    .parse::<AcquiesceRepr>()
+    .unwrap();
+
+    let expected = get_preset("qwen3")
+        .unwrap()
+        .merge(&json!({ "thinking": null, "stop": ["<|endoftext|>"] }))
+        .unwrap();
+
+    assert_eq!(repr.to_string(), expected.to_string());
+    assert!(!repr.to_string().contains("<think>"));
+    assert!(repr.to_string().contains("<tool_call>"));
+
+    assert!(matches!(
+        r#"{ "version": "v1", "extends": "llama5" }"#.parse::<AcquiesceRepr>(),
+        Err(InitError::UnknownPreset(_))
+    ));
+}
+
+#[test]
+fn empty_overrides_keep_the_preset() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let render = |repr: AcquiesceRepr| {
+        let result = repr
+            .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+            .unwrap()
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Auto,
+                &RenderOptions::default(),
+            )
+            .unwrap();
+
+        (result.prompt, result.grammar)
+    };
+
+    for preset in list_presets() {
+        let repr = get_preset(preset).unwrap();
+        let merged = repr.merge(&json!({})).unwrap();
+        let extended = format!(r#"{{ "version": "v1", "extends": "{preset}" }}"#)
+            .parse::<AcquiesceRepr>()
+            .unwrap();
+
+        let expected = render(repr);
+        assert_eq!(render(merged), expected, "{preset}");
+        assert_eq!(render(extended), expected, "{preset}");
+    }
+}
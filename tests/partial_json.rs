@@ -0,0 +1,89 @@
+use acquiesce::json::PartialJson;
+
+fn feed(chunk: &str) -> PartialJson {
+    let mut state = PartialJson::default();
+    assert!(state.consume_str(chunk).is_none());
+    state
+}
+
+#[test]
+fn pointer_into_streaming_arguments() {
+    let state = feed(r#"{"name": "search", "arguments": {"query": "rust partial js"#);
+
+    let name = state.get("/name").unwrap();
+    assert_eq!(name.as_str(), Some("search"));
+    assert!(name.is_complete());
+
+    let query = state.get("/arguments/query").unwrap();
+    assert_eq!(query.as_str(), Some("rust partial js"));
+    assert!(!query.is_complete());
+
+    assert!(!state.get("").unwrap().is_complete());
+    assert!(state.get("/arguments/limit").is_none());
+}
+
+#[test]
+fn pointer_escapes_and_indices() {
+    let state = feed(r#"{"a/b": [10, 2"#);
+
+    assert_eq!(state.get("/a~1b/0").unwrap().as_str(), Some("10"));
+    assert_eq!(state.get("/a~1b/1").unwrap().as_str(), Some("2"));
+    assert!(state.get("/a~1b/01").is_none());
+    assert!(state.get("/a~1b/2").is_none());
+}
+
+#[test]
+fn completed_snapshots_parse() {
+    let input = r#"{"name": "search", "arguments": {"query": "a \"b\"", "limit": -12.5e+3, "tags": [true, null]}}"#;
+
+    for end in (0..=input.len()).filter(|&end| input.is_char_boundary(end)) {
+        let state = feed(&input[..end]);
+
+        if let Some(snapshot) = state.to_completed_string() {
+            serde_json::from_str::<serde_json::Value>(&snapshot)
+                .unwrap_or_else(|e| panic!("{snapshot:?} after {end} bytes: {e}"));
+        }
+    }
+
+    assert_eq!(
+        feed(r#"{"a": [1, "tw"#).to_completed_string().as_deref(),
+        Some(r#"{"a":[1,"tw"]}"#)
+    );
+    assert_eq!(
+        feed(r#"{"a": 1.5e"#).to_completed_string().as_deref(),
+        Some(r#"{"a":1.5}"#)
+    );
+    assert_eq!(
+        feed(r#"{"a": 1, "b": fa"#).to_completed_string().as_deref(),
+        Some(r#"{"a":1,"b":false}"#)
+    );
+    assert_eq!(
+        feed(r#"{"a": -"#).to_completed_string().as_deref(),
+        Some("{}")
+    );
+}
+
+#[test]
+fn display_writes_the_snapshot() {
+    assert_eq!(feed(r#"{"a": [1, "tw"#).to_string(), r#"{"a":[1,"tw"]}"#);
+    assert_eq!(feed("  ").to_string(), "");
+}
+
+#[test]
+fn to_value_closes_like_snapshots() {
+    let state = feed(r#"{"a": [1, "tw"], "b": 2.5, "c": tr"#);
+
+    assert_eq!(
+        state.to_value(),
+        Some(serde_json::json!({ "a": [1, "tw"], "b": 2.5, "c": true }))
+    );
+}
+
+#[cfg(feature = "arbitrary_precision")]
+#[test]
+fn to_value_preserves_big_integers() {
+    let state = feed(r#"{"id": 123456789012345678901234567890}"#);
+    let value = state.to_value().unwrap();
+
+    assert_eq!(value["id"].to_string(), "123456789012345678901234567890");
+}
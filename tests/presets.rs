@@ -0,0 +1,573 @@
+use std::sync::Arc;
+
+use acquiesce::configs::{get_preset, list_presets};
+use acquiesce::render::schema::{ChatMessages, ChatTool, ChatToolChoice};
+use acquiesce::render::{GrammarSyntax, RenderOptions};
+use acquiesce::{AcquiesceRepr, ThinkingMode};
+use llguidance::api::TopLevelGrammar;
+use llguidance::toktrie::{ApproximateTokEnv, TokEnv, TokRxInfo, TokTrie};
+use llguidance::{Matcher, ParserFactory};
+
+/// Whether llguidance matches all of `completion` against the Lark `grammar`,
+/// with a byte-level tokenizer that also has `special_tokens`, which are
+/// tokenized as one token wherever they appear.
+fn accepts(grammar: &str, special_tokens: &[&str], completion: &str) -> bool {
+    let words = (0..=255)
+        .map(|byte| vec![byte])
+        .chain(
+            special_tokens
+                .iter()
+                .map(|token| [&[TokTrie::SPECIAL_TOKEN_MARKER], token.as_bytes()].concat()),
+        )
+        .collect::<Vec<_>>();
+    let info = TokRxInfo::new(words.len() as u32, words.len() as u32 - 1);
+    let tok_env: TokEnv = Arc::new(ApproximateTokEnv::new(TokTrie::from(&info, &words)));
+
+    let parser = ParserFactory::new_simple(&tok_env)
+        .unwrap()
+        .create_parser(TopLevelGrammar::from_lark(grammar.to_string()));
+    let mut matcher = Matcher::new(parser);
+    let trie = tok_env.tok_trie();
+    let tokens =
+        trie.tokenize_with_special(completion, |text| trie.greedy_tokenize(text.as_bytes()));
+
+    matcher.try_consume_tokens(&tokens).unwrap() == tokens.len() && matcher.is_accepting().unwrap()
+}
+
+#[test]
+fn infer_default_matches_presets() {
+    for (model, preset) in [
+        ("moonshotai/Kimi-K2-Instruct", "kimi_k2"),
+        ("NousResearch/Hermes-3-Llama-3.1-8B", "hermes"),
+        ("Qwen/Qwen3-8B", "qwen3"),
+        ("Qwen/Qwen3-Coder-30B-A3B-Instruct", "qwen3_coder"),
+        ("mistralai/Mistral-Small-3.2-24B-Instruct-2506", "mistral"),
+        ("deepseek-ai/DeepSeek-R1-0528", "deepseek_r1"),
+        ("microsoft/phi-4", "phi4"),
+        ("google/gemma-3-27b-it", "gemma3"),
+        ("zai-org/GLM-4.5-Air", "glm4"),
+        ("CohereLabs/c4ai-command-r7b-12-2024", "command_r"),
+        ("ibm-granite/granite-3.3-8b-instruct", "granite"),
+        ("nvidia/Llama-3.1-Nemotron-Nano-8B-v1", "nemotron"),
+        ("meetkai/functionary-medium-v3.1", "functionary"),
+        ("Salesforce/Llama-xLAM-2-8b-fc-r", "xlam"),
+        ("ai21labs/AI21-Jamba-Mini-1.6", "jamba"),
+        ("MiniMaxAI/MiniMax-M1-80k", "minimax"),
+        ("MiniMaxAI/MiniMax-M2", "minimax_m2"),
+        ("ByteDance-Seed/Seed-OSS-36B-Instruct", "seed_oss"),
+        ("internlm/internlm2_5-7b-chat", "internlm"),
+    ] {
+        assert_eq!(
+            AcquiesceRepr::infer_default(model).unwrap().to_string(),
+            get_preset(preset).unwrap().to_string(),
+            "{model}"
+        );
+    }
+}
+
+#[test]
+fn infer_falls_back_to_architectures() {
+    let architectures = ["Qwen2ForCausalLM".to_string()];
+
+    assert_eq!(
+        AcquiesceRepr::infer("someone/my-finetune", &architectures)
+            .unwrap()
+            .to_string(),
+        get_preset("qwen3").unwrap().to_string()
+    );
+    // the name still wins when it is recognized
+    assert_eq!(
+        AcquiesceRepr::infer("NousResearch/Hermes-2-Pro", &architectures)
+            .unwrap()
+            .to_string(),
+        get_preset("hermes").unwrap().to_string()
+    );
+    assert!(AcquiesceRepr::infer("someone/my-finetune", &[]).is_err());
+}
+
+#[test]
+fn infer_from_template_markers() {
+    let qwen3 = "{{ '<|im_start|>' }}{{ '<tool_call>' }}{{ '<think>' }}";
+    assert_eq!(
+        AcquiesceRepr::infer_from_template(qwen3)
+            .unwrap()
+            .to_string(),
+        get_preset("qwen3").unwrap().to_string()
+    );
+
+    // Qwen2.5 calls tools the same way but doesn't think
+    let qwen2_5 = AcquiesceRepr::infer_from_template("<|im_start|><tool_call>")
+        .unwrap()
+        .to_string();
+    assert!(qwen2_5.contains("tool_call"));
+    assert!(!qwen2_5.contains("<think>"));
+
+    let thinking_only = AcquiesceRepr::infer_from_template("<think>{{ reasoning }}</think>")
+        .unwrap()
+        .to_string();
+    assert!(thinking_only.contains("<think>"));
+    assert!(thinking_only.contains(r#""tool_calls": null"#));
+
+    assert!(AcquiesceRepr::infer_from_template("{{ messages }}").is_err());
+}
+
+#[test]
+fn infer_from_added_tokens() {
+    let tokens = [
+        "<|im_start|>",
+        "<tool_call>",
+        "</tool_call>",
+        "<think>",
+        "</think>",
+    ]
+    .map(String::from);
+    assert_eq!(
+        AcquiesceRepr::infer_from_tokens(&tokens)
+            .unwrap()
+            .to_string(),
+        get_preset("qwen3").unwrap().to_string()
+    );
+
+    let thinking_only = AcquiesceRepr::infer_from_tokens(&["<think>".into(), "</think>".into()])
+        .unwrap()
+        .to_string();
+    assert!(thinking_only.contains(r#""tool_calls": null"#));
+
+    assert!(AcquiesceRepr::infer_from_tokens(&["<|endoftext|>".into()]).is_err());
+}
+
+#[test]
+fn roles_are_renamed_for_the_template() {
+    let acquiesce = get_preset("gemma3")
+        .unwrap()
+        .resolve_from_options(
+            "{% for message in messages %}{{ message.role }} {% endfor %}".to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let messages = serde_json::from_str::<ChatMessages>(
+        r#"[
+            {"role": "developer", "content": "Be brief."},
+            {"role": "user", "content": "What's the weather?"},
+            {"role": "assistant", "content": "", "tool_calls": [
+                {"id": "call_0", "type": "function", "function": {"name": "weather", "arguments": "{}"}}
+            ]},
+            {"role": "tool", "content": "Sunny.", "tool_call_id": "call_0"}
+        ]"#,
+    )
+    .unwrap();
+
+    let result = acquiesce
+        .render(
+            messages,
+            Vec::new(),
+            ChatToolChoice::None,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+    assert_eq!(result.prompt, "system user assistant user ");
+}
+
+#[test]
+fn thinking_toggle() {
+    let acquiesce = get_preset("nemotron")
+        .unwrap()
+        .resolve_from_options(
+            "{% for message in messages %}{{ message.content }}{% endfor %}{{ tools | length }}"
+                .to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let thinks = |system: &str| {
+        let messages = serde_json::from_value::<ChatMessages>(serde_json::json!([
+            {"role": "system", "content": system},
+            {"role": "user", "content": "hi"},
+        ]))
+        .unwrap();
+
+        acquiesce
+            .render(
+                messages,
+                tools.clone(),
+                ChatToolChoice::Auto,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+            .contains("<think>")
+    };
+
+    assert!(thinks("detailed thinking on"));
+    assert!(!thinks("detailed thinking off"));
+    assert!(!thinks("Be brief."));
+}
+
+#[test]
+fn thinking_mode() {
+    let acquiesce = get_preset("qwen3")
+        .unwrap()
+        .resolve_from_options(
+            "{{ enable_thinking is defined and enable_thinking }}".to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let render = |thinking| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Auto,
+                &RenderOptions {
+                    thinking,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+    };
+
+    let forced = render(None);
+    assert_eq!(forced.prompt, "true");
+    assert!(forced.grammar.unwrap().contains("<think>"));
+
+    let disabled = render(Some(ThinkingMode::Disabled));
+    assert_eq!(disabled.prompt, "false");
+    assert!(!disabled.grammar.unwrap().contains("<think>"));
+
+    let optional = render(Some(ThinkingMode::Optional)).grammar.unwrap();
+    assert!(optional.contains("<think>"), "{optional}");
+    assert!(optional.contains("optional_thinking"), "{optional}");
+}
+
+#[test]
+fn key_value_tag_arguments() {
+    let acquiesce = get_preset("qwen3_coder")
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "get_weather", "parameters": {
+            "type": "object",
+            "properties": { "city": { "type": "string" }, "days": { "type": "integer" } },
+            "required": ["city"]
+        }}}]"#,
+    )
+    .unwrap();
+
+    let render = |tool_choice| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                tool_choice,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+    let grammar = render(ChatToolChoice::Required);
+
+    assert!(grammar.contains(r#""<parameter=""#), "{grammar}");
+    assert!(grammar.contains(r#""city""#), "{grammar}");
+    assert!(grammar.contains(r#""days""#), "{grammar}");
+
+    // string values run up to the parameter's suffix, newline and all
+    let special_tokens = ["<tool_call>", "</tool_call>", "<|im_end|>"];
+    let call = "<tool_call>\n<function=get_weather>\n<parameter=city>\nParis\n</parameter>\n\
+                </function>\n</tool_call>";
+    for tool_choice in [ChatToolChoice::Required, ChatToolChoice::Auto] {
+        let grammar = render(tool_choice);
+
+        assert!(accepts(&grammar, &special_tokens, call), "{grammar}");
+        assert!(accepts(
+            &grammar,
+            &special_tokens,
+            "<tool_call>\n<function=get_weather>\n<parameter=city>\na < b\n</parameter>\n\
+             <parameter=days>\n3\n</parameter>\n</function>\n</tool_call>"
+        ));
+        assert!(!accepts(
+            &grammar,
+            &special_tokens,
+            "<tool_call>\n<function=get_weather>\n<parameter=city>\nParis\n</function>\n</tool_call>"
+        ));
+    }
+
+    // only the parameter the schema doesn't require is optional, besides the
+    // newlines between parallel calls' sections
+    assert_eq!(
+        grammar
+            .lines()
+            .filter(|line| line.ends_with('?') && !line.starts_with("whitespace"))
+            .count(),
+        1,
+        "{grammar}"
+    );
+}
+
+#[test]
+fn thinking_then_tool_calls() {
+    let acquiesce = get_preset("qwen3")
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "get_weather", "parameters": {
+            "type": "object", "properties": { "city": { "type": "string" } }, "required": ["city"]
+        }}}]"#,
+    )
+    .unwrap();
+    let render = |tool_choice| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                tool_choice,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    // Qwen3 leaves a blank line after its thinking
+    let special_tokens = [
+        "<think>",
+        "</think>",
+        "<tool_call>",
+        "</tool_call>",
+        "<|im_end|>",
+    ];
+    let call = "<think>\nEasy.\n</think>\n\n<tool_call>\n\
+                {\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}\n</tool_call>";
+    for tool_choice in [ChatToolChoice::Required, ChatToolChoice::Auto] {
+        let grammar = render(tool_choice);
+
+        assert!(accepts(&grammar, &special_tokens, call), "{grammar}");
+    }
+
+    let grammar = render(ChatToolChoice::Auto);
+    assert!(accepts(
+        &grammar,
+        &special_tokens,
+        "<think>\nEasy.\n</think>\n\nIt's sunny."
+    ));
+}
+
+#[test]
+fn minimax_m2_invoke_blocks() {
+    let acquiesce = get_preset("minimax_m2")
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "get_weather", "parameters": {
+            "type": "object",
+            "properties": { "city": { "type": "string" }, "days": { "type": "integer" } },
+            "required": ["city"]
+        }}}]"#,
+    )
+    .unwrap();
+    let render = |tool_choice| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                tool_choice,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    let grammar = render(ChatToolChoice::Required);
+    assert!(grammar.contains(r#""<minimax:tool_call>\n""#), "{grammar}");
+    assert!(grammar.contains(r#""<invoke name=\"""#), "{grammar}");
+    assert!(grammar.contains(r#""<parameter name=\"""#), "{grammar}");
+
+    // both calls share one section
+    let calls = "<think>\nEasy.\n</think>\n\n<minimax:tool_call>\n\
+                 <invoke name=\"get_weather\">\n<parameter name=\"city\">Paris</parameter>\n\
+                 <parameter name=\"days\">3</parameter>\n</invoke>\n\
+                 <invoke name=\"get_weather\">\n<parameter name=\"city\">Rome</parameter>\n\
+                 </invoke>\n</minimax:tool_call>";
+    for tool_choice in [ChatToolChoice::Required, ChatToolChoice::Auto] {
+        let grammar = render(tool_choice);
+
+        assert!(accepts(&grammar, &[], calls), "{grammar}");
+        assert!(!accepts(
+            &grammar,
+            &[],
+            "<minimax:tool_call>\n<invoke name=\"get_weather\">\n\
+             <parameter name=\"city\">Paris</invoke>\n</minimax:tool_call>"
+        ));
+    }
+}
+
+#[test]
+fn tool_call_id_lexeme() {
+    let acquiesce = get_preset("kimi_k2")
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "get_weather", "parameters": {
+            "type": "object", "properties": {}
+        }}}]"#,
+    )
+    .unwrap();
+
+    let grammar = acquiesce
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Required,
+            &RenderOptions::default(),
+        )
+        .unwrap()
+        .grammar
+        .unwrap();
+
+    assert!(grammar.contains("/[0-9]+/"), "{grammar}");
+
+    // configs without an id slot don't write one
+    assert!(!get_preset("glm4").unwrap().to_string().contains(r#""id""#));
+}
+
+#[test]
+fn preset_grammars_compile() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "weather", "parameters": {
+            "type": "object",
+            "properties": {"city": {"type": "string"}, "days": {"type": "integer"}},
+            "required": ["city"]
+        }}}]"#,
+    )
+    .unwrap();
+    let messages =
+        serde_json::from_str::<ChatMessages>(r#"[{"role": "user", "content": "hi"}]"#).unwrap();
+
+    for preset in list_presets() {
+        let acquiesce = get_preset(preset)
+            .unwrap()
+            .resolve_from_options(
+                "{% for message in messages %}{{ message.content }}{% endfor %}".to_string(),
+                None,
+                None,
+                false,
+            )
+            .unwrap();
+
+        for grammar_syntax in [
+            GrammarSyntax::Lark,
+            GrammarSyntax::GBNF,
+            GrammarSyntax::Ebnf,
+            GrammarSyntax::LLGuidance,
+        ] {
+            for tool_choice in [ChatToolChoice::Auto, ChatToolChoice::Required] {
+                let options = RenderOptions {
+                    grammar_syntax,
+                    validate_grammar: true,
+                    ..Default::default()
+                };
+
+                if let Err(e) =
+                    acquiesce.render(messages.clone(), tools.clone(), tool_choice, &options)
+                {
+                    panic!("{preset}: {e}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn special_tokens_are_matched_as_tokens() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = |preset, grammar_syntax| {
+        get_preset(preset)
+            .unwrap()
+            .resolve_from_options(String::new(), None, None, false)
+            .unwrap()
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    grammar_syntax,
+                    validate_grammar: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    // llguidance only takes special tokens in rules
+    let lark = grammar("phi4", GrammarSyntax::Lark);
+    assert!(lark.contains("sequence0: <|tool_call|>\n"), "{lark}");
+
+    let gbnf = grammar("phi4", GrammarSyntax::GBNF);
+    assert!(gbnf.contains("::= <|tool_call|>\n"), "{gbnf}");
+
+    let ebnf = grammar("phi4", GrammarSyntax::Ebnf);
+    assert!(ebnf.contains(r#"::= "<|tool_call|>""#), "{ebnf}");
+
+    // tokens without a name in angle brackets fall back to their text
+    let lark = grammar("mistral", GrammarSyntax::Lark);
+    assert!(lark.contains(r#"SEQUENCE0: "[TOOL_CALLS]""#), "{lark}");
+}
+
+#[test]
+fn presets_round_trip_through_json() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = |repr: AcquiesceRepr| {
+        repr.resolve_from_options(String::new(), None, None, false)
+            .unwrap()
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Auto,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .grammar
+    };
+
+    for preset in list_presets() {
+        let repr = get_preset(preset).unwrap();
+        let parsed = repr.to_string().parse::<AcquiesceRepr>().unwrap();
+
+        assert_eq!(grammar(parsed), grammar(repr), "{preset}");
+    }
+}
@@ -0,0 +1,136 @@
+use acquiesce::parse::{ParseResult, ReasoningDelta};
+use acquiesce::render::RenderOptions;
+use acquiesce::render::schema::{ChatMessages, ChatTool, ChatToolChoice};
+use acquiesce::{AcquiesceRepr, Lexeme, builder::AcquiesceBuilder};
+
+fn acquiesce(interleaved: bool) -> acquiesce::Acquiesce {
+    let builder = AcquiesceBuilder::new().thinking("<think>", "</think>");
+    let builder = match interleaved {
+        true => builder.interleaved_thinking(),
+        false => builder,
+    };
+
+    builder
+        .tool_section(Lexeme::Token("<tool_call>".into()))
+        .json_object("name", "arguments")
+        .build()
+        .resolve_from_options("{{ tools | length }}".to_string(), None, None, false)
+        .unwrap()
+}
+
+#[test]
+fn interleaved_thinking_grammar() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = |acquiesce: acquiesce::Acquiesce| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    assert!(!grammar(acquiesce(false)).contains("rounds"));
+
+    let interleaved = grammar(acquiesce(true));
+    assert!(interleaved.contains("rounds"), "{interleaved}");
+}
+
+/// The reasoning segments and content the config's parser splits a completion
+/// into, fed one character at a time.
+fn parse(repr: AcquiesceRepr, completion: &str) -> (Vec<String>, String) {
+    let parser = repr
+        .resolve_from_options(String::new(), None, None, false)
+        .unwrap()
+        .parser()
+        .unwrap();
+
+    let mut segments = Vec::<String>::new();
+    let mut content = String::new();
+    for result in parser.parse_iter(completion.chars().map(String::from)) {
+        match result {
+            ParseResult::Content(delta) => content.push_str(&delta),
+            ParseResult::Reasoning(ReasoningDelta { index, delta }) => {
+                if segments.len() == index {
+                    segments.push(String::new());
+                }
+                segments[index].push_str(&delta);
+            }
+            _ => panic!("unexpected parse result"),
+        }
+    }
+
+    (segments, content)
+}
+
+#[test]
+fn reasoning_segments() {
+    let thinking = |interleaved: bool| {
+        AcquiesceBuilder::new()
+            .thinking("<think>", "</think>")
+            .build()
+            .merge(&serde_json::json!({ "thinking": { "interleaved": interleaved } }))
+            .unwrap()
+    };
+
+    let completion = "<think>a</think>b<thin<think>c</think>d";
+
+    assert_eq!(
+        parse(thinking(false), completion),
+        (vec!["a".into()], "b<thin<think>c</think>d".into())
+    );
+    assert_eq!(
+        parse(thinking(true), completion),
+        (vec!["a".into(), "c".into()], "b<thind".into())
+    );
+}
+
+#[test]
+fn choice_markers() {
+    let prefix = serde_json::json!({
+        "thinking": { "prefix": { "choices": ["<think>", ["[THINK]", "\n"]] } }
+    });
+    let thinking = AcquiesceBuilder::new()
+        .thinking("<think>", "</think>")
+        .build()
+        .merge(&prefix)
+        .unwrap();
+
+    assert_eq!(
+        parse(thinking, "[THINK]\na</think>b"),
+        (vec!["a".into()], "b".into())
+    );
+
+    let acquiesce = AcquiesceBuilder::new()
+        .thinking("<think>", "</think>")
+        .tool_section(Lexeme::Token("<tool_call>".into()))
+        .build()
+        .merge(&prefix)
+        .unwrap()
+        .resolve_from_options("{{ tools | length }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = acquiesce
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Required,
+            &RenderOptions::default(),
+        )
+        .unwrap()
+        .grammar
+        .unwrap();
+    assert!(grammar.contains(r#""<think>""#), "{grammar}");
+    assert!(grammar.contains(r#""[THINK]""#), "{grammar}");
+}
@@ -1,11 +1,8 @@
 use acquiesce::AcquiesceRepr;
-use acquiesce::render::schema::{ChatMessages, ChatTool, ChatToolChoice};
-use acquiesce::render::{GrammarSyntax, RenderResult};
+use acquiesce::render::{GrammarSyntax, RenderOptions, RenderResult};
+use acquiesce::testing::{TestCase, load_corpus};
 use hf_hub::Cache;
 use hf_hub::api::sync::Api;
-use serde::Deserialize;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 
 const TEST_MODELS: &[&str] = &[
     // "zai-org/GLM-4.6-FP8",
@@ -24,28 +21,11 @@ const TEST_MODELS: &[&str] = &[
 
 const TEST_CORPUS_PATH: &str = "tests/messages.jsonl";
 
-#[derive(Clone, Deserialize)]
-struct TestCase {
-    messages: ChatMessages,
-    tools: Vec<ChatTool>,
-    #[serde(default)]
-    tool_choice: ChatToolChoice,
-}
-
 #[test]
 fn test_render_corpus() {
     let api = Api::new().unwrap();
 
-    let file = File::open(TEST_CORPUS_PATH).unwrap();
-    let reader = BufReader::new(file);
-
-    let test_cases = reader
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            serde_json::from_str::<TestCase>(&line).unwrap()
-        })
-        .collect::<Vec<_>>();
+    let test_cases = load_corpus(TEST_CORPUS_PATH).unwrap();
 
     for model in TEST_MODELS {
         println!("Testing model: {model}\n\n");
@@ -66,6 +46,7 @@ fn test_render_corpus() {
             messages,
             tools,
             tool_choice,
+            ..
         } in test_cases.clone()
         {
             let RenderResult {
@@ -73,26 +54,33 @@ fn test_render_corpus() {
                 ..
             } = acquiesce
                 .render(
-                    messages.clone(),
+                    &messages,
                     tools.clone(),
                     tool_choice.clone(),
-                    true,
-                    true,
-                    GrammarSyntax::Lark,
+                    &RenderOptions {
+                        parallel_tool_calls: true,
+                        mixed_content_tool_calls: true,
+                        grammar_syntax: GrammarSyntax::Lark,
+                        ..Default::default()
+                    },
                 )
                 .unwrap();
 
             let RenderResult {
                 prompt,
                 grammar: gbnf_grammar,
+                ..
             } = acquiesce
                 .render(
-                    messages,
+                    &messages,
                     tools,
                     tool_choice,
-                    true,
-                    true,
-                    GrammarSyntax::GBNF,
+                    &RenderOptions {
+                        parallel_tool_calls: true,
+                        mixed_content_tool_calls: true,
+                        grammar_syntax: GrammarSyntax::GBNF,
+                        ..Default::default()
+                    },
                 )
                 .unwrap();
 
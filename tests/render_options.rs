@@ -0,0 +1,758 @@
+use acquiesce::parse::{ParseResult, Parser, ToolCallDelta};
+use acquiesce::render::schema::{
+    ChatCompletionRequest, ChatMessages, ChatResponseFormat, ChatTool, ChatToolChoice,
+};
+use acquiesce::render::{
+    GrammarSyntax, ReasoningEffort, RenderError, RenderOptions, RenderWarning, ToolValidationError,
+};
+use acquiesce::{AcquiesceRepr, ThinkingLimit};
+
+const CONFIG: &str = r#"{
+    "version": "v1",
+    "config": {
+        "type": "components",
+        "chat_template": null,
+        "thinking": {
+            "prefix": "<think>",
+            "suffix": "</think>",
+            "budget": { "low": 256 }
+        },
+        "tool_calls": {
+            "type": "tool_call",
+            "tool_call": { "type": "json_object", "name_key": "name", "argument_key": "arguments" }
+        }
+    }
+}"#;
+
+#[test]
+fn reasoning_effort_reaches_template_and_grammar() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options(
+            "{{ messages[0].content }}|{{ reasoning_effort | default('unset') }}".to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let render = |reasoning_effort| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Auto,
+                &RenderOptions {
+                    reasoning_effort,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+    };
+
+    let low = render(Some(ReasoningEffort::Low));
+    assert_eq!(low.prompt, "hi|low");
+    assert!(
+        low.grammar
+            .unwrap()
+            .contains("[max_tokens=256]: THINKING_TEXT")
+    );
+
+    let high = render(Some(ReasoningEffort::High));
+    assert_eq!(high.prompt, "hi|high");
+    assert!(!high.grammar.unwrap().contains("max_tokens"));
+
+    assert_eq!(render(None).prompt, "hi|unset");
+}
+
+#[test]
+fn thinking_limit() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let render = |thinking_limit, grammar_syntax| {
+        acquiesce.render(
+            ChatMessages::Content("hi".to_string()),
+            tools.clone(),
+            ChatToolChoice::Auto,
+            &RenderOptions {
+                reasoning_effort: Some(ReasoningEffort::Low),
+                thinking_limit,
+                grammar_syntax,
+                validate_grammar: true,
+                ..Default::default()
+            },
+        )
+    };
+    let grammar = |thinking_limit, grammar_syntax| {
+        render(thinking_limit, grammar_syntax)
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    // the request's limit replaces the config's budget for the effort
+    let tokens = grammar(Some(ThinkingLimit::Tokens(32)), GrammarSyntax::Lark);
+    assert!(
+        tokens.contains("[max_tokens=32]: THINKING_TEXT"),
+        "{tokens}"
+    );
+
+    // characters bound the thinking in every syntax
+    let characters = grammar(Some(ThinkingLimit::Characters(64)), GrammarSyntax::Lark);
+    assert!(!characters.contains("max_tokens"), "{characters}");
+    assert!(
+        characters.contains(r"THINKING_TEXT0: /(?s:.){0,64}<\/think>/"),
+        "{characters}"
+    );
+
+    let characters = grammar(Some(ThinkingLimit::Characters(64)), GrammarSyntax::GBNF);
+    assert!(
+        characters.contains("thinking_text0 ::= (.){0,64}"),
+        "{characters}"
+    );
+
+    // GBNF can't count tokens, so the budget only comes back as a warning
+    let tokens = render(None, GrammarSyntax::GBNF).unwrap();
+    assert!(
+        tokens.warnings.iter().any(|warning| matches!(
+            warning,
+            RenderWarning::UnenforcedThinkingLimit { max_tokens: 256 }
+        )),
+        "{:?}",
+        tokens.warnings
+    );
+
+    // and won't unroll a character limit past its bound
+    let too_long = ThinkingLimit::MAX_GBNF_CHARACTERS + 1;
+    assert!(matches!(
+        render(Some(ThinkingLimit::Characters(too_long)), GrammarSyntax::GBNF),
+        Err(RenderError::ThinkingLimitTooLarge(max_chars)) if max_chars == too_long
+    ));
+    render(
+        Some(ThinkingLimit::Characters(too_long)),
+        GrammarSyntax::Lark,
+    )
+    .unwrap();
+}
+
+#[test]
+fn response_format_and_kwargs() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options(
+            "{{ messages[0].content }}|{{ greeting }}|{{ add_generation_prompt }}".to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let response_format = serde_json::from_str::<ChatResponseFormat>(
+        r#"{"type": "json_schema", "json_schema": {"name": "answer", "schema": {"type": "object", "properties": {"answer": {"type": "string"}}}}}"#,
+    )
+    .unwrap();
+    let options = RenderOptions {
+        response_format,
+        add_generation_prompt: false,
+        kwargs: serde_json::json!({ "greeting": "hello", "add_generation_prompt": true })
+            .as_object()
+            .unwrap()
+            .clone(),
+        ..Default::default()
+    };
+
+    let result = acquiesce
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            Vec::new(),
+            ChatToolChoice::Auto,
+            &options,
+        )
+        .unwrap();
+
+    assert_eq!(result.prompt, "hi|hello|false");
+    assert!(
+        result
+            .grammar
+            .unwrap()
+            .contains(r#"%json {"type":"object","properties":{"answer":{"type":"string"}}}"#)
+    );
+
+    let text = acquiesce
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            Vec::new(),
+            ChatToolChoice::Auto,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+
+    assert!(text.grammar.is_none());
+}
+
+#[test]
+fn stop_sequences() {
+    let acquiesce = r#"{
+        "version": "v1",
+        "config": {
+            "type": "components",
+            "chat_template": null,
+            "thinking": { "prefix": "<think>", "suffix": "</think>" },
+            "tool_calls": {
+                "type": "tool_calls_section",
+                "prefix": "<tool_calls>",
+                "tool_call": { "type": "json_object", "name_key": "name", "argument_key": "arguments" },
+                "suffix": "</tool_calls>"
+            },
+            "stop": ["<|im_end|>", "</s>"]
+        }
+    }"#
+    .parse::<AcquiesceRepr>()
+    .unwrap()
+    .resolve_from_options(
+        "{{ messages[0].content }}".to_string(),
+        None,
+        Some("</s>".to_string()),
+        false,
+    )
+    .unwrap();
+
+    let render = |tool_choice, options| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                Vec::new(),
+                tool_choice,
+                &options,
+            )
+            .unwrap()
+            .stop
+    };
+
+    assert_eq!(
+        render(ChatToolChoice::Auto, RenderOptions::default()),
+        ["</s>", "<|im_end|>"]
+    );
+    assert_eq!(
+        render(
+            ChatToolChoice::None,
+            RenderOptions {
+                reasoning_only: true,
+                ..Default::default()
+            }
+        ),
+        ["</s>", "<tool_calls>", "</think>", "<|im_end|>"]
+    );
+}
+
+#[test]
+fn prompt_prefix() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options(
+            "{% for message in messages %}<{{ message.role }}>{{ message.content }}{% endfor %}{% if add_generation_prompt %}<assistant>{% endif %}".to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let render = |messages: &str| {
+        acquiesce
+            .render(
+                serde_json::from_str::<ChatMessages>(messages).unwrap(),
+                Vec::new(),
+                ChatToolChoice::Auto,
+                &RenderOptions {
+                    prompt_prefix: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+    };
+
+    let first = render(
+        r#"[{"role": "system", "content": "Be brief."}, {"role": "user", "content": "hi"}]"#,
+    );
+    let second = render(
+        r#"[{"role": "system", "content": "Be brief."}, {"role": "user", "content": "bye"}]"#,
+    );
+    let first_prefix = first.prefix.unwrap();
+
+    assert_eq!(&first.prompt[..first_prefix.len], "<system>Be brief.");
+    assert_eq!(first_prefix, second.prefix.unwrap());
+
+    let other = render(
+        r#"[{"role": "system", "content": "Be verbose."}, {"role": "user", "content": "hi"}]"#,
+    );
+    assert_ne!(first_prefix.key, other.prefix.unwrap().key);
+
+    assert!(
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                Vec::new(),
+                ChatToolChoice::Auto,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .prefix
+            .is_none()
+    );
+}
+
+#[test]
+fn chat_completion_request() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options(
+            "{{ messages[0].content }}|{{ reasoning_effort }}|{{ greeting }}|\
+             {%- for document in documents %}{{ document.title }}{% endfor %}"
+                .to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let request = serde_json::from_str::<ChatCompletionRequest>(
+        r#"{
+            "model": "test",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}],
+            "tool_choice": "required",
+            "parallel_tool_calls": false,
+            "reasoning_effort": "low",
+            "temperature": 0.2,
+            "stream": true,
+            "stream_options": {"include_usage": true},
+            "chat_template_kwargs": {"greeting": "hello"},
+            "documents": [{"title": "a"}, {"title": "b"}]
+        }"#,
+    )
+    .unwrap();
+
+    assert!(!request.render_options().parallel_tool_calls);
+
+    let result = acquiesce.render_request(&request).unwrap();
+    assert_eq!(result.prompt, "hi|low|hello|ab");
+    assert!(
+        result
+            .grammar
+            .unwrap()
+            .contains("[max_tokens=256]: THINKING_TEXT")
+    );
+
+    let minimal = serde_json::from_str::<ChatCompletionRequest>(
+        r#"{"messages": [{"role": "user", "content": "hi"}], "tools": null}"#,
+    )
+    .unwrap();
+    assert!(
+        acquiesce
+            .render_request(&minimal)
+            .unwrap()
+            .grammar
+            .is_none()
+    );
+}
+
+#[test]
+fn tool_validation_errors_are_aggregated() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[
+            {"type": "function", "function": {"name": "search", "parameters": {"type": 1}}},
+            {"type": "function", "function": {"name": "search", "parameters": {"type": "object"}}},
+            {"type": "function", "function": {"name": "", "parameters": {"type": "object"}}},
+            {"type": "custom", "custom": {"name": "pattern", "format": {
+                "type": "grammar", "grammar": {"syntax": "regex", "definition": "("}
+            }}}
+        ]"#,
+    )
+    .unwrap();
+
+    let Err(RenderError::ToolValidation(errors)) = acquiesce.render(
+        ChatMessages::Content("hi".to_string()),
+        tools,
+        ChatToolChoice::Auto,
+        &RenderOptions::default(),
+    ) else {
+        panic!("expected tool validation errors");
+    };
+
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            ToolValidationError::EmptyName,
+            ToolValidationError::DuplicateName(duplicate),
+            ToolValidationError::JsonSchema(schema, _),
+            ToolValidationError::Regex(regex, _),
+        ] if duplicate == "search" && schema == "search" && regex == "pattern"
+    ));
+}
+
+#[test]
+fn custom_lark_validation_is_cached() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let render = |definition: &str| {
+        let tools = vec![
+            serde_json::from_value::<ChatTool>(serde_json::json!({
+                "type": "custom", "custom": {"name": "add", "format": {
+                    "type": "grammar", "grammar": {"syntax": "lark", "definition": definition}
+                }}
+            }))
+            .unwrap(),
+        ];
+
+        acquiesce.render(
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Auto,
+            &RenderOptions::default(),
+        )
+    };
+
+    // the second time around the outcome comes from the cache
+    for _ in 0..2 {
+        assert!(render("start: NUMBER \"+\" NUMBER\nNUMBER: /[0-9]+/").is_ok());
+
+        let Err(RenderError::ToolValidation(errors)) = render("start: missing") else {
+            panic!("expected a tool validation error");
+        };
+        assert!(
+            matches!(errors.as_slice(), [ToolValidationError::Lark(name, _)] if name == "add"),
+            "{errors:?}"
+        );
+    }
+}
+
+#[test]
+fn custom_roles() {
+    let acquiesce = r#"{
+        "version": "v1",
+        "config": {
+            "type": "components",
+            "chat_template": null,
+            "thinking": null,
+            "tool_calls": null,
+            "roles": { "critic": "user" },
+            "custom_roles": ["ipython"]
+        }
+    }"#
+    .parse::<AcquiesceRepr>()
+    .unwrap()
+    .resolve_from_options(
+        "{% for m in messages %}<{{ m.role }}>{{ m.content }}{% endfor %}".to_string(),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+
+    let render = |messages: &str| {
+        acquiesce.render(
+            serde_json::from_str::<ChatMessages>(messages).unwrap(),
+            Vec::new(),
+            ChatToolChoice::Auto,
+            &RenderOptions::default(),
+        )
+    };
+
+    let prompt = render(
+        r#"[
+            {"role": "user", "content": "hi"},
+            {"role": "ipython", "content": "42"},
+            {"role": "critic", "content": "ok"}
+        ]"#,
+    )
+    .unwrap()
+    .prompt;
+    assert_eq!(prompt, "<user>hi<ipython>42<user>ok");
+
+    assert!(matches!(
+        render(r#"[{"role": "observation", "content": "42"}]"#),
+        Err(RenderError::UnknownRole(role)) if role == "observation"
+    ));
+}
+
+#[test]
+fn allowed_tools_narrow_the_grammar() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options("{{ tools | length }}".to_string(), None, None, false)
+        .unwrap();
+
+    let request = serde_json::from_str::<ChatCompletionRequest>(
+        r#"{
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [
+                {"type": "function", "function": {"name": "noop", "parameters": {
+                    "type": "object", "properties": {"verbose": {"type": "boolean"}}
+                }}},
+                {"type": "function", "function": {"name": "echo", "parameters": {
+                    "type": "object", "properties": {"text": {"type": "string"}}
+                }}}
+            ],
+            "tool_choice": {"type": "allowed_tools", "allowed_tools": {
+                "mode": "required",
+                "tools": [{"type": "function", "function": {"name": "echo"}}]
+            }}
+        }"#,
+    )
+    .unwrap();
+
+    let result = acquiesce.render_request(&request).unwrap();
+    assert_eq!(result.prompt, "2");
+
+    let grammar = result.grammar.unwrap();
+    assert!(
+        grammar.contains("text") && !grammar.contains("verbose"),
+        "{grammar}"
+    );
+}
+
+#[test]
+fn strict_tools_close_their_schemas() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options("{{ tools | length }}".to_string(), None, None, false)
+        .unwrap();
+
+    let request = serde_json::from_str::<ChatCompletionRequest>(
+        r#"{
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"type": "function", "function": {"name": "weather", "strict": true, "parameters": {
+                "type": "object",
+                "properties": {
+                    "city": {"type": "string"},
+                    "units": {"type": "array", "items": {"type": "object", "properties": {"name": {"type": "string"}}}}
+                }
+            }}}]
+        }"#,
+    )
+    .unwrap();
+
+    let grammar = acquiesce.render_request(&request).unwrap().grammar.unwrap();
+    for closed in [r#""required":["city","units"]"#, r#""required":["name"]"#] {
+        assert!(grammar.contains(closed), "{grammar}");
+    }
+    // both objects in the arguments, plus the call around them
+    assert_eq!(
+        grammar.matches(r#""additionalProperties":false"#).count(),
+        3
+    );
+}
+
+#[test]
+fn max_tool_calls() {
+    let acquiesce = CONFIG
+        .replace(
+            r#""type": "tool_call","#,
+            r#""type": "tool_calls_section", "prefix": "<tool_call>", "suffix": "</tool_call>","#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = acquiesce
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            tools.clone(),
+            ChatToolChoice::Required,
+            &RenderOptions {
+                max_tool_calls: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .grammar
+        .unwrap();
+    assert!(
+        grammar.contains("tool_choice1: tool_choice0{1,2}"),
+        "{grammar}"
+    );
+
+    // a cap of zero would render as `{1,0}`
+    assert!(matches!(
+        acquiesce.render(
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Required,
+            &RenderOptions {
+                max_tool_calls: Some(0),
+                ..Default::default()
+            },
+        ),
+        Err(RenderError::ZeroMaxToolCalls)
+    ));
+
+    // one call per token
+    let mut calls = 0;
+    let parser = Parser::new(move |token: String| {
+        calls += 1;
+        vec![ParseResult::ToolCall(ToolCallDelta {
+            index: calls - 1,
+            delta: token,
+        })]
+    });
+
+    let results = parser
+        .with_max_tool_calls(2)
+        .parse_iter(["{}", "{}", "{}", "{}"].map(String::from).into_iter())
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        results.as_slice(),
+        [
+            ParseResult::ToolCall(ToolCallDelta { index: 0, .. }),
+            ParseResult::ToolCall(ToolCallDelta { index: 1, .. }),
+            ParseResult::Complete,
+            ParseResult::Rejected(_, "end of completion"),
+        ]
+    ));
+}
+
+#[test]
+fn mixed_content_tool_calls() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options("{{ messages[0].content }}".to_string(), None, None, false)
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = |tool_choice, mixed_content_tool_calls| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                tool_choice,
+                &RenderOptions {
+                    mixed_content_tool_calls,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    // content or calls, but not both
+    let auto = grammar(ChatToolChoice::Auto, false);
+    assert!(
+        auto.contains("content_or_calls0: TEXT0 | tool_choice0"),
+        "{auto}"
+    );
+
+    let required = grammar(ChatToolChoice::Required, false);
+    assert!(
+        required.starts_with("start: thinking0 tool_choice0\n"),
+        "{required}"
+    );
+
+    // text before the calls and between rounds of them
+    let mixed = grammar(ChatToolChoice::Required, true);
+    for rule in [
+        "round0: optional_content0 tool_choice0",
+        "calls0: tool_choice0 rounds0",
+        "calls1: optional_content0 calls0",
+    ] {
+        assert!(mixed.contains(rule), "{mixed}");
+    }
+
+    let parser = Parser::new(|token| vec![ParseResult::Content(token)]).without_content();
+    let results = parser
+        .parse_iter(["\n", "hi"].map(String::from).into_iter())
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        results.as_slice(),
+        [
+            ParseResult::Content(_),
+            ParseResult::Rejected(_, "tool call")
+        ]
+    ));
+}
+
+#[test]
+fn continue_final_message() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options(
+            "{% for message in messages %}<{{ message.role }}>{{ message.content }}</{{ message.role }}>{% endfor %}\
+             {%- if add_generation_prompt %}<assistant>{% endif %}"
+                .to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let render = |messages: &str| {
+        acquiesce
+            .render(
+                serde_json::from_str::<ChatMessages>(messages).unwrap(),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    continue_final_message: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+    };
+
+    let continued = render(
+        r#"[{"role": "user", "content": "hi"}, {"role": "assistant", "content": "Let me check. "}]"#,
+    );
+    assert_eq!(continued.prompt, "<user>hi</user><assistant>Let me check.");
+
+    // past the thinking, and the started text can run on before the call
+    let grammar = continued.grammar.unwrap();
+    assert!(grammar.starts_with("start: calls1\n"), "{grammar}");
+    assert!(!grammar.contains("<think>"), "{grammar}");
+
+    // nothing to continue, so a new message starts as usual
+    let fresh = render(r#"[{"role": "user", "content": "hi"}]"#);
+    assert_eq!(fresh.prompt, "<user>hi</user><assistant>");
+    assert!(fresh.grammar.unwrap().starts_with("start: thinking0 "));
+}
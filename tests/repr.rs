@@ -0,0 +1,840 @@
+use acquiesce::render::schema::{ChatMessages, ChatResponseFormat, ChatTool, ChatToolChoice};
+use acquiesce::render::{GrammarSyntax, GrammarTrigger, RenderError, RenderOptions, validate_gbnf};
+use acquiesce::{Acquiesce, AcquiesceRepr};
+use llguidance::api::TopLevelGrammar;
+use llguidance::toktrie::ApproximateTokEnv;
+use llguidance::{Matcher, ParserFactory};
+
+const CONFIG: &str = r#"{
+    "version": "v1",
+    "config": {
+        "type": "components",
+        "chat_template": null,
+        "thinking": { "prefix": "<think>", "suffix": "</think>" },
+        "tool_calls": {
+            "type": "tool_call",
+            "tool_call": { "type": "json_object", "name_key": "name", "argument_key": "arguments" }
+        },
+        "stop": ["<|im_end|>"]
+    }
+}"#;
+
+fn resolve(repr: AcquiesceRepr) -> Acquiesce {
+    repr.resolve_from_options(
+        "{{ messages[0].content }}".to_string(),
+        Some("<s>".to_string()),
+        Some("</s>".to_string()),
+        false,
+    )
+    .unwrap()
+}
+
+#[test]
+fn to_repr_round_trips() {
+    let repr = CONFIG.parse::<AcquiesceRepr>().unwrap();
+    let acquiesce = resolve(repr.clone());
+
+    assert_eq!(acquiesce.to_repr().to_string(), repr.to_string());
+
+    let resolved = serde_json::to_value(&acquiesce).unwrap();
+    assert_eq!(
+        resolved["chat_template"],
+        serde_json::json!({
+            "source_hash": "a087613a74eb6b18",
+            "bos_token": "<s>",
+            "eos_token": "</s>",
+            "multimodal": false,
+        })
+    );
+    assert_eq!(
+        serde_json::to_value(resolve(acquiesce.to_repr())).unwrap(),
+        resolved
+    );
+}
+
+#[test]
+fn token_ids_resolve_to_tokens() {
+    let repr = CONFIG
+        .replace(
+            r#"{ "prefix": "<think>", "suffix": "</think>" }"#,
+            r#"{ "prefix": 151667, "suffix": 151668 }"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+
+    // without tools, the response format is what gives the request a grammar
+    let grammar = |acquiesce: &Acquiesce, grammar_syntax| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                Vec::new(),
+                ChatToolChoice::None,
+                &RenderOptions {
+                    grammar_syntax,
+                    response_format: ChatResponseFormat::JsonObject,
+                    ..Default::default()
+                },
+            )
+            .map(|result| result.grammar.unwrap())
+    };
+
+    let unresolved = resolve(repr.clone());
+    assert!(
+        grammar(&unresolved, GrammarSyntax::Lark)
+            .unwrap()
+            .contains("<[151667]>")
+    );
+    assert!(
+        grammar(&unresolved, GrammarSyntax::GBNF)
+            .unwrap()
+            .contains("<[151667]>")
+    );
+    // XGrammar has no syntax for token IDs
+    assert!(matches!(
+        grammar(&unresolved, GrammarSyntax::Ebnf),
+        Err(RenderError::UnresolvedTokenId(151667))
+    ));
+
+    let token_ids = [(151667, "<think>".into()), (151668, "</think>".into())].into();
+    let resolved = resolve(repr.with_token_ids(&token_ids));
+    let lark = grammar(&resolved, GrammarSyntax::Lark).unwrap();
+    assert!(
+        lark.contains("<think>") && !lark.contains("<[151667]>"),
+        "{lark}"
+    );
+
+    // the same Lark grammar, wrapped for llguidance
+    let llguidance = grammar(&resolved, GrammarSyntax::LLGuidance).unwrap();
+    let llguidance = serde_json::from_str::<serde_json::Value>(&llguidance).unwrap();
+    assert_eq!(llguidance["grammars"][0]["lark_grammar"], lark.as_str());
+}
+
+/// Whether llguidance matches all of `completion` against the Lark `grammar`.
+fn accepts(grammar: &str, completion: &str) -> bool {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let parser = ParserFactory::new_simple(&tok_env)
+        .unwrap()
+        .create_parser(TopLevelGrammar::from_lark(grammar.to_string()));
+    let mut matcher = Matcher::new(parser);
+    let tokens = tok_env.tokenize_bytes(completion.as_bytes());
+
+    matcher.try_consume_tokens(&tokens).unwrap() == tokens.len() && matcher.is_accepting().unwrap()
+}
+
+#[test]
+fn json_tool_calls_match_name_and_arguments() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[
+            {"type": "function", "function": {"name": "get_weather", "parameters": {
+                "type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]
+            }}},
+            {"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}
+        ]"#,
+    )
+    .unwrap();
+    let grammar = |tool_call: &str| {
+        let repr = CONFIG
+            .replace(
+                r#""thinking": { "prefix": "<think>", "suffix": "</think>" },"#,
+                "",
+            )
+            .replace(r#""type": "json_object""#, tool_call)
+            .parse::<AcquiesceRepr>()
+            .unwrap();
+
+        resolve(repr)
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    let object = grammar(r#""type": "json_object""#);
+    for call in [
+        r#"{"name": "get_weather", "arguments": {"city": "Paris"}}"#,
+        r#"{"name": "noop", "arguments": {}}"#,
+    ] {
+        assert!(accepts(&object, call), "{call}\n{object}");
+    }
+    for call in [
+        // the name key's own name, an unknown tool, another tool's arguments
+        r#"{"name": "name", "arguments": {"city": "Paris"}}"#,
+        r#"{"name": "get_time", "arguments": {}}"#,
+        r#"{"name": "get_weather", "arguments": {}}"#,
+        r#"{"name": "get_weather"}"#,
+        r#"{"name": "noop", "arguments": {}, "id": 1}"#,
+    ] {
+        assert!(!accepts(&object, call), "{call}\n{object}");
+    }
+
+    let array = grammar(r#""type": "json_array""#);
+    assert!(accepts(
+        &array,
+        r#"[{"name": "get_weather", "arguments": {"city": "Paris"}}, {"name": "noop", "arguments": {}}]"#
+    ));
+    assert!(!accepts(&array, "[]"));
+}
+
+#[test]
+fn per_call_sections_repeat_for_parallel_calls() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = |per_call: bool| {
+        let repr = CONFIG
+            .replace(
+                r#""thinking": { "prefix": "<think>", "suffix": "</think>" },"#,
+                "",
+            )
+            .replace(
+                r#""type": "tool_call","#,
+                &format!(
+                    r#""type": "tool_calls_section", "prefix": "<tool_call>",
+                    "suffix": "</tool_call>", "per_call": {per_call},"#
+                ),
+            )
+            .parse::<AcquiesceRepr>()
+            .unwrap();
+
+        resolve(repr)
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    parallel_tool_calls: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    let call = r#"{"name": "noop", "arguments": {}}"#;
+    let shared = format!("<tool_call>{call}{call}</tool_call>");
+    let own = format!("<tool_call>{call}</tool_call>\n<tool_call>{call}</tool_call>");
+
+    let per_call = grammar(true);
+    assert!(accepts(&per_call, &own), "{per_call}");
+    assert!(!accepts(&per_call, &shared), "{per_call}");
+
+    let section = grammar(false);
+    assert!(accepts(&section, &shared), "{section}");
+    assert!(!accepts(&section, &own), "{section}");
+}
+
+#[test]
+fn schema_refs_are_inlined() {
+    let repr = CONFIG
+        .replace(
+            r#""thinking": { "prefix": "<think>", "suffix": "</think>" },"#,
+            "",
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    let acquiesce = resolve(repr);
+
+    // as Pydantic writes a model with a nested one
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r##"[{"type": "function", "function": {"name": "book", "parameters": {
+            "$defs": {
+                "City": {"type": "object", "properties": {"name": {"type": "string"}}, "required": ["name"]}
+            },
+            "type": "object",
+            "properties": {
+                "to": {"$ref": "#/$defs/City", "description": "Where to"},
+                "via": {"type": "array", "items": {"$ref": "#/$defs/City"}}
+            },
+            "required": ["to"]
+        }}}]"##,
+    )
+    .unwrap();
+    let grammar = |grammar_syntax| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    grammar_syntax,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    let lark = grammar(GrammarSyntax::Lark);
+    assert!(!lark.contains("$ref") && !lark.contains("$defs"), "{lark}");
+    assert!(accepts(
+        &lark,
+        r#"{"name": "book", "arguments": {"to": {"name": "Oslo"}, "via": [{"name": "Bergen"}]}}"#
+    ));
+    assert!(!accepts(
+        &lark,
+        r#"{"name": "book", "arguments": {"to": {"city": "Oslo"}}}"#
+    ));
+
+    let gbnf = grammar(GrammarSyntax::GBNF);
+    assert!(gbnf.contains(r#""\"name\"""#), "{gbnf}");
+}
+
+#[test]
+fn recursive_schema_refs_are_kept() {
+    let repr = CONFIG
+        .replace(
+            r#""thinking": { "prefix": "<think>", "suffix": "</think>" },"#,
+            "",
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    let acquiesce = resolve(repr);
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r##"[{"type": "function", "function": {"name": "tree", "parameters": {
+            "$defs": {"Node": {
+                "type": "object",
+                "properties": {"children": {"type": "array", "items": {"$ref": "#/$defs/Node"}}},
+                "required": ["children"]
+            }},
+            "$ref": "#/$defs/Node"
+        }}}]"##,
+    )
+    .unwrap();
+    let grammar = |grammar_syntax| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    grammar_syntax,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    // the definition moves to the root of the call, where the reference points
+    let lark = grammar(GrammarSyntax::Lark);
+    assert!(lark.contains(r##""$ref":"#/$defs/tree-Node""##), "{lark}");
+    assert!(accepts(
+        &lark,
+        r#"{"name": "tree", "arguments": {"children": [{"children": [{"children": []}]}]}}"#
+    ));
+    assert!(!accepts(
+        &lark,
+        r#"{"name": "tree", "arguments": {"children": [{}]}}"#
+    ));
+
+    let gbnf = grammar(GrammarSyntax::GBNF);
+    validate_gbnf(&gbnf).unwrap();
+    // the array's items refer back to the object holding it
+    for rule in [
+        "tool_choice-0-arguments-children-item0 ::= tool_choice-0-arguments-children-item1",
+        r#"tool_choice-0-arguments-children-item-children0 ::= "[" space0 (tool_choice-0-arguments-children-item0"#,
+    ] {
+        assert!(gbnf.contains(rule), "{gbnf}");
+    }
+}
+
+#[test]
+fn custom_tool_grammars_constrain_their_input() {
+    let repr = CONFIG
+        .replace(
+            r#""thinking": { "prefix": "<think>", "suffix": "</think>" },"#,
+            "",
+        )
+        .replace(
+            r#"{ "type": "json_object", "name_key": "name", "argument_key": "arguments" }"#,
+            r#"{ "type": "named_parameters", "prefix": "<tool_call>", "delimiter": ":", "arguments": "json_object", "suffix": "</tool_call>" }"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[
+            {"type": "custom", "custom": {"name": "add", "format": {"type": "grammar", "grammar": {
+                "syntax": "lark", "definition": "start: NUMBER \"+\" NUMBER\nNUMBER: /[0-9]+/"
+            }}}},
+            {"type": "custom", "custom": {"name": "zip", "format": {"type": "grammar", "grammar": {
+                "syntax": "regex", "definition": "[0-9]{5}"
+            }}}}
+        ]"#,
+    )
+    .unwrap();
+
+    let acquiesce = resolve(repr);
+    let grammar = |grammar_syntax| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    grammar_syntax,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    let lark = grammar(GrammarSyntax::Lark);
+    for call in ["add:1+2", "zip:12345"] {
+        let call = format!("<tool_call>{call}</tool_call>");
+        assert!(accepts(&lark, &call), "{call}\n{lark}");
+    }
+    for call in ["add:1*2", "add:\"1+2\"", "zip:1234"] {
+        let call = format!("<tool_call>{call}</tool_call>");
+        assert!(!accepts(&lark, &call), "{call}\n{lark}");
+    }
+
+    // the regex carries over, the Lark grammar can't
+    let gbnf = grammar(GrammarSyntax::GBNF);
+    assert!(gbnf.contains("input0 ::= ([0-9]){5}"), "{gbnf}");
+}
+
+#[test]
+fn text_stops_at_markers() {
+    let repr = CONFIG
+        .replace(
+            r#"{ "type": "json_object", "name_key": "name", "argument_key": "arguments" }"#,
+            r#"{ "type": "named_parameters", "prefix": "<tool_call>", "delimiter": ":", "arguments": "json_object", "suffix": "</tool_call>" }"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+
+    let acquiesce = resolve(repr);
+    let grammar = |grammar_syntax, mixed_content_tool_calls| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Auto,
+                &RenderOptions {
+                    grammar_syntax,
+                    mixed_content_tool_calls,
+                    validate_grammar: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    let call = "<tool_call>noop:{}</tool_call>";
+    let lark = grammar(GrammarSyntax::Lark, false);
+    for completion in [
+        "<think>a</think>{\"not\": \"a call\"}".to_string(),
+        format!("<think>a</think>{call}"),
+    ] {
+        assert!(accepts(&lark, &completion), "{completion}\n{lark}");
+    }
+    for completion in [
+        format!("<think>a</think>b{call}"),
+        "<think>a</think><tool_call>noop:[]</tool_call>".to_string(),
+    ] {
+        assert!(!accepts(&lark, &completion), "{completion}\n{lark}");
+    }
+
+    let mixed = grammar(GrammarSyntax::Lark, true);
+    for completion in [
+        "<think>a</think>b".to_string(),
+        format!("<think>a</think>b{call}"),
+    ] {
+        assert!(accepts(&mixed, &completion), "{completion}\n{mixed}");
+    }
+    assert!(!accepts(
+        &mixed,
+        "<think>a</think>b<tool_call>noop:[]</tool_call>"
+    ));
+
+    // GBNF has no negation, so the text is spelled out as states
+    let gbnf = grammar(GrammarSyntax::GBNF, false);
+    assert!(gbnf.contains("[^<] text1"), "{gbnf}");
+}
+
+#[test]
+fn shared_rules_are_hoisted() {
+    let repr = CONFIG
+        .replace(
+            r#""thinking": { "prefix": "<think>", "suffix": "</think>" },"#,
+            "",
+        )
+        .replace(
+            r#"{ "type": "json_object", "name_key": "name", "argument_key": "arguments" }"#,
+            r#"{ "type": "named_parameters", "prefix": "<tool_call>", "delimiter": ":", "arguments": "json_object", "suffix": "</tool_call>" }"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    let point = r#"{"type": "object", "properties": {"x": {"type": "integer"}}}"#;
+    let tools = serde_json::from_str::<Vec<ChatTool>>(&format!(
+        r#"[
+            {{"type": "function", "function": {{"name": "move", "parameters": {{
+                "type": "object", "properties": {{"to": {point}}}
+            }}}}}},
+            {{"type": "function", "function": {{"name": "look", "parameters": {{
+                "type": "object", "properties": {{"at": {point}}}
+            }}}}}}
+        ]"#
+    ))
+    .unwrap();
+
+    let acquiesce = resolve(repr);
+    let grammar = |grammar_syntax| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    grammar_syntax,
+                    validate_grammar: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    // the prefix and suffix are matched once around the tools
+    let lark = grammar(GrammarSyntax::Lark);
+    assert_eq!(lark.matches(r#""<tool_call>""#).count(), 1, "{lark}");
+    assert!(
+        lark.contains("tool_choices1: sequence0 tool_choices0 sequence2"),
+        "{lark}"
+    );
+    for call in [r#"move:{"to": {"x": 1}}"#, r#"look:{"at": {"x": 2}}"#] {
+        let call = format!("<tool_call>{call}</tool_call>");
+        assert!(accepts(&lark, &call), "{call}\n{lark}");
+    }
+    assert!(!accepts(
+        &lark,
+        r#"<tool_call>look:{"at": {"x": "1"}}</tool_call>"#
+    ));
+
+    // the point schema is converted once, whatever its property is called
+    let gbnf = grammar(GrammarSyntax::GBNF);
+    assert!(gbnf.contains("parameters-to0 ::="), "{gbnf}");
+    assert!(!gbnf.contains("parameters-at0 ::="), "{gbnf}");
+}
+
+#[test]
+fn literals_are_escaped() {
+    let repr = CONFIG
+        .replace(
+            r#""thinking": { "prefix": "<think>", "suffix": "</think>" },"#,
+            "",
+        )
+        .replace(
+            r#"{ "type": "json_object", "name_key": "name", "argument_key": "arguments" }"#,
+            r#"{ "type": "named_parameters", "prefix": "<tool_call>", "delimiter": "\n", "arguments": "json_object", "suffix": "</tool_call>" }"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    let names = [
+        "say \"hi\"",
+        "back\\slash",
+        "new\nline",
+        "tab\tbed",
+        "del\u{7f}",
+    ];
+    let mut tools = names
+        .iter()
+        .map(|name| {
+            serde_json::from_value::<ChatTool>(serde_json::json!({
+                "type": "function",
+                "function": {"name": name, "parameters": {"type": "object"}}
+            }))
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    tools.push(
+        serde_json::from_str(
+            r#"{"type": "custom", "custom": {"name": "ratio", "format": {"type": "grammar", "grammar": {
+                "syntax": "regex", "definition": "[0-9]+/[0-9]+"
+            }}}}"#,
+        )
+        .unwrap(),
+    );
+
+    let acquiesce = resolve(repr);
+    let grammar = |grammar_syntax| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    grammar_syntax,
+                    validate_grammar: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    for grammar_syntax in [
+        GrammarSyntax::GBNF,
+        GrammarSyntax::Ebnf,
+        GrammarSyntax::LLGuidance,
+    ] {
+        grammar(grammar_syntax);
+    }
+
+    let lark = grammar(GrammarSyntax::Lark);
+    for name in names {
+        let call = format!("<tool_call>{name}\n{{}}</tool_call>");
+        assert!(accepts(&lark, &call), "{call}\n{lark}");
+    }
+    assert!(
+        accepts(&lark, "<tool_call>ratio\n1/2</tool_call>"),
+        "{lark}"
+    );
+    assert!(
+        !accepts(&lark, "<tool_call>say hi\n{}</tool_call>"),
+        "{lark}"
+    );
+}
+
+#[test]
+fn optional_and_whitespace_lexemes() {
+    let repr = CONFIG
+        .replace(
+            r#""type": "tool_call","#,
+            r#""type": "tool_calls_section",
+            "prefix": ["<tool_call>", { "whitespace": " \n" }],
+            "suffix": [{ "optional": " " }, "</tool_call>"],"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let grammar = resolve(repr)
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Required,
+            &RenderOptions::default(),
+        )
+        .unwrap()
+        .grammar
+        .unwrap();
+
+    assert!(grammar.contains(r"WHITESPACE0: /[ \n]+/"), "{grammar}");
+    assert!(grammar.contains("whitespace0: WHITESPACE0?"), "{grammar}");
+    assert!(grammar.contains("optional0: sequence"), "{grammar}");
+}
+
+#[test]
+fn json_schemas_convert_to_gbnf() {
+    let repr = CONFIG
+        .replace(
+            r#"{ "type": "json_object", "name_key": "name", "argument_key": "arguments" }"#,
+            r#"{ "type": "named_parameters", "prefix": "<tool_call>", "delimiter": "\n", "arguments": "json_object", "suffix": "</tool_call>" }"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r##"[{"type": "function", "function": {"name": "weather", "parameters": {
+            "type": "object",
+            "properties": {
+                "city": { "$ref": "#/$defs/city" },
+                "unit": { "enum": ["celsius", "fahrenheit"] },
+                "days": { "type": "array", "items": { "type": "integer" }, "maxItems": 7 }
+            },
+            "required": ["city"],
+            "$defs": { "city": { "type": "string", "pattern": "^[A-Z][a-z]+$" } }
+        }}}]"##,
+    )
+    .unwrap();
+
+    let acquiesce = resolve(repr);
+    let grammar = |grammar_syntax| {
+        acquiesce
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Required,
+                &RenderOptions {
+                    grammar_syntax,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .grammar
+            .unwrap()
+    };
+
+    let gbnf = grammar(GrammarSyntax::GBNF);
+    // XGrammar reads the same rules
+    assert_eq!(grammar(GrammarSyntax::Ebnf), gbnf);
+
+    let grammar = gbnf;
+
+    assert!(grammar.contains(r#""\"city\"""#), "{grammar}");
+    assert!(grammar.contains(r#"[A-Z] ([a-z])+"#), "{grammar}");
+    assert!(
+        grammar.contains(r#""\"celsius\"" | "\"fahrenheit\"""#),
+        "{grammar}"
+    );
+    assert!(grammar.contains("integer0"), "{grammar}");
+}
+
+#[test]
+fn grammars_validate_before_returning() {
+    let acquiesce = resolve(CONFIG.parse().unwrap());
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+
+    for grammar_syntax in [
+        GrammarSyntax::Lark,
+        GrammarSyntax::GBNF,
+        GrammarSyntax::LLGuidance,
+    ] {
+        let result = acquiesce.render(
+            ChatMessages::Content("hi".to_string()),
+            tools.clone(),
+            ChatToolChoice::Auto,
+            &RenderOptions {
+                grammar_syntax,
+                validate_grammar: true,
+                ..Default::default()
+            },
+        );
+        if let Err(e) = result {
+            panic!("{e}");
+        }
+    }
+
+    let error = |grammar: &str| match validate_gbnf(grammar) {
+        Err(RenderError::GrammarCompilation(e)) => e,
+        Err(e) => panic!("{e}"),
+        Ok(()) => panic!("{grammar}"),
+    };
+
+    assert_eq!(
+        error("root ::= call\ncall ::= \"<call>\" args\n"),
+        "rule call: refers to undefined rule args"
+    );
+    assert_eq!(error("root ::= (\"a\" | \"b\"\n"), "rule root: unclosed (");
+    assert_eq!(error("root ::= [\\-]\n"), "rule root: unknown escape \\-");
+    assert_eq!(error("call ::= \"a\"\n"), "rule root: not defined");
+    assert!(validate_gbnf("root ::= \"a\" x{1,3} [^\\x5E\\]]*\nx ::= .\n").is_ok());
+}
+
+#[test]
+fn structural_tags_wrap_each_tool() {
+    let repr = CONFIG
+        .replace(
+            r#""type": "tool_call","#,
+            r#""type": "tool_calls_section",
+            "prefix": ["<tool_call>", { "whitespace": "\n" }],
+            "suffix": "</tool_call>","#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[
+            {"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}},
+            {"type": "function", "function": {"name": "echo", "parameters": {"type": "string"}}}
+        ]"#,
+    )
+    .unwrap();
+    let grammar = resolve(repr)
+        .render(
+            ChatMessages::Content("hi".to_string()),
+            tools,
+            ChatToolChoice::Auto,
+            &RenderOptions {
+                grammar_syntax: GrammarSyntax::StructuralTags,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .grammar
+        .unwrap();
+
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&grammar).unwrap(),
+        serde_json::json!({
+            "type": "structural_tag",
+            "structures": [
+                {
+                    "begin": r#"<tool_call>{"name": "noop", "arguments": "#,
+                    "schema": { "type": "object" },
+                    "end": "}</tool_call>",
+                },
+                {
+                    "begin": r#"<tool_call>{"name": "echo", "arguments": "#,
+                    "schema": { "type": "string" },
+                    "end": "}</tool_call>",
+                },
+            ],
+            "triggers": ["<tool_call>"],
+        })
+    );
+}
+
+#[test]
+fn lazy_grammar_triggers() {
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let triggers = |repr: AcquiesceRepr| {
+        resolve(repr)
+            .render(
+                ChatMessages::Content("hi".to_string()),
+                tools.clone(),
+                ChatToolChoice::Auto,
+                &RenderOptions::default(),
+            )
+            .unwrap()
+            .triggers
+    };
+
+    let section = CONFIG
+        .replace(
+            r#""type": "tool_call","#,
+            r#""type": "tool_calls_section",
+            "prefix": [{ "optional": "\n" }, "<tool_call>"],"#,
+        )
+        .parse::<AcquiesceRepr>()
+        .unwrap();
+    assert_eq!(
+        triggers(section),
+        [
+            GrammarTrigger::Text("\n".into()),
+            GrammarTrigger::Text("<tool_call>".into())
+        ]
+    );
+
+    // bare JSON calls have no marker to wait for
+    assert!(triggers(CONFIG.parse().unwrap()).is_empty());
+}
@@ -0,0 +1,28 @@
+use acquiesce::render::template::{ChatTemplate, TemplateIntrospection};
+
+#[test]
+fn introspect() {
+    let template = ChatTemplate::from_options(
+        "{%- if tools %}{{ tools | tojson }}{% endif %}\
+         {%- for message in messages %}\
+         {%- if message.reasoning_content %}<think>{{ message.reasoning_content }}</think>{% endif %}\
+         {{- message.content }}\
+         {%- endfor %}\
+         {%- if add_generation_prompt %}<assistant>{% endif %}"
+            .to_string(),
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+
+    assert_eq!(
+        template.introspect(),
+        TemplateIntrospection {
+            tools: true,
+            add_generation_prompt: true,
+            reasoning_content: true,
+            ..Default::default()
+        }
+    );
+}
@@ -0,0 +1,44 @@
+use acquiesce::AcquiesceRepr;
+use acquiesce::render::RenderOptions;
+use acquiesce::testing::{assert_snapshot, load_corpus, render_snapshot};
+
+const CONFIG: &str = r#"{
+    "version": "v1",
+    "config": {
+        "type": "components",
+        "chat_template": null,
+        "thinking": null,
+        "tool_calls": {
+            "type": "tool_call",
+            "tool_call": { "type": "json_object", "name_key": "name", "argument_key": "arguments" }
+        }
+    }
+}"#;
+
+#[test]
+fn corpus_snapshot() {
+    let acquiesce = CONFIG
+        .parse::<AcquiesceRepr>()
+        .unwrap()
+        .resolve_from_options(
+            "{% for message in messages %}<{{ message.role }}>{{ message.content }}{% endfor %}"
+                .to_string(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+    let test_cases =
+        load_corpus(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/messages.jsonl")).unwrap();
+
+    let snapshot = render_snapshot(&acquiesce, &test_cases[6], &RenderOptions::default()).unwrap();
+
+    assert_snapshot(
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/snapshots/corpus_snapshot.txt"
+        ),
+        &snapshot,
+    );
+}
@@ -0,0 +1,103 @@
+use acquiesce::format::{ToolCallFormat, register_tool_call_format};
+use acquiesce::parse::{ParseResult, Parser, ToolCallDelta};
+use acquiesce::render::schema::{ChatMessages, ChatTool, ChatToolChoice};
+use acquiesce::render::template::TemplateTool;
+use acquiesce::render::{RenderError, RenderOptions};
+use acquiesce::{AcquiesceRepr, Lexeme, OrderedLexemes};
+
+/// `[[name]] {arguments}`, with everything after the opening brackets treated
+/// as a single tool call.
+struct Brackets;
+
+impl ToolCallFormat for Brackets {
+    fn tool_call(&self, tool: &TemplateTool) -> OrderedLexemes {
+        [
+            Lexeme::Token("[[".into()),
+            Lexeme::Text(format!("{}]] ", tool.name).into()),
+            Lexeme::JsonSchema(tool.parameters.clone()),
+        ]
+        .as_slice()
+        .into()
+    }
+
+    fn parser(&self) -> Parser {
+        let mut in_call = false;
+
+        Parser::new(move |token: String| {
+            if in_call {
+                return vec![ParseResult::ToolCall(ToolCallDelta {
+                    index: 0,
+                    delta: token,
+                })];
+            }
+
+            in_call = token == "[[";
+
+            if in_call {
+                Vec::new()
+            } else {
+                vec![ParseResult::Content(token)]
+            }
+        })
+    }
+}
+
+fn acquiesce(format: &str) -> acquiesce::Acquiesce {
+    format!(
+        r#"{{
+            "version": "v1",
+            "config": {{
+                "type": "components",
+                "chat_template": null,
+                "thinking": null,
+                "tool_calls": {{
+                    "type": "tool_call",
+                    "tool_call": {{ "type": "external", "name": "{format}" }}
+                }}
+            }}
+        }}"#
+    )
+    .parse::<AcquiesceRepr>()
+    .unwrap()
+    .resolve_from_options("{{ tools | length }}".to_string(), None, None, false)
+    .unwrap()
+}
+
+#[test]
+fn external_tool_call_format() {
+    register_tool_call_format("brackets", Brackets);
+
+    let tools = serde_json::from_str::<Vec<ChatTool>>(
+        r#"[{"type": "function", "function": {"name": "noop", "parameters": {"type": "object"}}}]"#,
+    )
+    .unwrap();
+    let render = |acquiesce: &acquiesce::Acquiesce| {
+        acquiesce.render(
+            ChatMessages::Content("hi".to_string()),
+            tools.clone(),
+            ChatToolChoice::Required,
+            &RenderOptions::default(),
+        )
+    };
+
+    let brackets = acquiesce("brackets");
+    let grammar = render(&brackets).unwrap().grammar.unwrap();
+    assert!(grammar.contains(r#""noop]] ""#));
+
+    let results = brackets
+        .parser()
+        .unwrap()
+        .parse_iter(["hi", "[[", "noop]] ", "{}"].map(String::from).into_iter())
+        .collect::<Vec<_>>();
+    assert!(matches!(&results[0], ParseResult::Content(content) if content == "hi"));
+    assert!(
+        matches!(&results[1], ParseResult::ToolCall(ToolCallDelta { delta, .. }) if delta == "noop]] ")
+    );
+
+    let unregistered = acquiesce("unregistered");
+    assert!(matches!(
+        render(&unregistered),
+        Err(RenderError::UnknownToolCallFormat(name)) if name == "unregistered"
+    ));
+    assert!(unregistered.parser().is_none());
+}
@@ -0,0 +1,152 @@
+use acquiesce::Acquiesce;
+use acquiesce::configs::{get_preset, list_presets};
+use acquiesce::testing::{Replay, replay_completion};
+
+fn preset(name: &str) -> Acquiesce {
+    get_preset(name)
+        .unwrap()
+        .resolve_from_options(String::new(), None, None, false)
+        .unwrap()
+}
+
+fn replay(name: &str, completion: &str) -> Replay {
+    let replay = replay_completion(&preset(name), completion).unwrap();
+    assert!(
+        replay.rejected.is_empty(),
+        "{name} rejected {:?}",
+        replay.rejected
+    );
+
+    replay
+}
+
+#[test]
+fn every_preset_parses() {
+    for name in list_presets() {
+        replay_completion(&preset(name), "Hello there.").unwrap();
+    }
+}
+
+#[test]
+fn json_object_sections() {
+    let replay = replay(
+        "qwen3",
+        "<think>\nEasy.\n</think>\n\nLet me check.\n<tool_call>\n\
+         {\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\", \"days\": [1, 2]}}\n\
+         </tool_call>\n<tool_call>\n{\"arguments\": {}, \"name\": \"get_time\"}\n</tool_call>",
+    );
+
+    assert_eq!(replay.reasoning, ["\nEasy.\n"]);
+    assert_eq!(replay.content, "\n\nLet me check.\n\n");
+    assert_eq!(
+        replay.tool_call_names.into_values().collect::<Vec<_>>(),
+        ["get_weather", "get_time"]
+    );
+    assert_eq!(
+        replay.tool_calls,
+        [r#"{"city":"Paris","days":[1,2]}"#, "{}"]
+    );
+}
+
+#[test]
+fn json_arrays() {
+    let mistral = replay(
+        "mistral",
+        r#"[TOOL_CALLS][{"name": "a", "arguments": {}}, {"name": "b", "arguments": {"x": 1}}]"#,
+    );
+    assert_eq!(mistral.content, "");
+    assert_eq!(
+        mistral.tool_call_names.into_values().collect::<Vec<_>>(),
+        ["a", "b"]
+    );
+    assert_eq!(mistral.tool_calls, ["{}", r#"{"x":1}"#]);
+
+    // xLAM's calls have no marker, so only a completion opening with one has them
+    let xlam = replay(
+        "xlam",
+        r#" [{"name": "get_weather", "arguments": {"city": "Paris"}}]"#,
+    );
+    assert_eq!(xlam.tool_calls, [r#"{"city":"Paris"}"#]);
+
+    let content = replay("xlam", r#"Use [{"name": "a"}]"#);
+    assert_eq!(content.content, r#"Use [{"name": "a"}]"#);
+    assert!(content.tool_calls.is_empty());
+}
+
+#[test]
+fn named_parameters_with_ids() {
+    let replay = replay(
+        "kimi_k2",
+        "Checking.<|tool_calls_section_begin|>\
+         <|tool_call_begin|>functions.get_weather:0<|tool_call_argument_begin|>{\"city\": \"Paris\"}<|tool_call_end|>\
+         <|tool_call_begin|>functions.get_time:1<|tool_call_argument_begin|>{}<|tool_call_end|>\
+         <|tool_calls_section_end|>",
+    );
+
+    assert_eq!(replay.content, "Checking.");
+    assert_eq!(
+        replay.tool_call_names.into_values().collect::<Vec<_>>(),
+        ["get_weather", "get_time"]
+    );
+    assert_eq!(
+        replay.tool_call_ids.into_values().collect::<Vec<_>>(),
+        [":0", ":1"]
+    );
+    assert_eq!(replay.tool_calls, [r#"{"city": "Paris"}"#, "{}"]);
+}
+
+#[test]
+fn key_value_tags() {
+    let replay = replay(
+        "qwen3_coder",
+        "<tool_call>\n<function=get_weather>\n\
+         <parameter=city>\nParis\n</parameter>\n<parameter=days>\n3\n</parameter>\n\
+         </function>\n</tool_call>",
+    );
+
+    assert_eq!(
+        replay.tool_call_names.into_values().collect::<Vec<_>>(),
+        ["get_weather"]
+    );
+    assert_eq!(replay.tool_calls, [r#"{"city":"Paris","days":3}"#]);
+}
+
+#[test]
+fn invoke_blocks_in_one_section() {
+    let replay = replay(
+        "minimax_m2",
+        "<think>\nEasy.\n</think>\n\n<minimax:tool_call>\n\
+         <invoke name=\"get_weather\">\n<parameter name=\"city\">Paris</parameter>\n\
+         <parameter name=\"days\">3</parameter>\n</invoke>\n\
+         <invoke name=\"get_time\">\n</invoke>\n</minimax:tool_call>",
+    );
+
+    assert_eq!(replay.reasoning, ["\nEasy.\n"]);
+    assert_eq!(
+        replay.tool_call_names.into_values().collect::<Vec<_>>(),
+        ["get_weather", "get_time"]
+    );
+    assert_eq!(replay.tool_calls, [r#"{"city":"Paris","days":3}"#, "{}"]);
+}
+
+#[test]
+fn calls_in_one_per_call_section_are_rejected() {
+    let replay = replay_completion(
+        &preset("hermes"),
+        "<tool_call>\n{\"name\": \"a\", \"arguments\": {}}\n\
+         {\"name\": \"b\", \"arguments\": {}}\n</tool_call>",
+    )
+    .unwrap();
+
+    assert_eq!(replay.tool_calls, ["{}"]);
+    assert_eq!(replay.rejected.len(), 1);
+}
+
+#[test]
+fn unexpected_text_in_a_call_is_rejected() {
+    let replay =
+        replay_completion(&preset("qwen3"), "<tool_call>\n{\"name\": get_weather}").unwrap();
+
+    assert_eq!(replay.rejected.len(), 1);
+    assert!(replay.tool_call_names.is_empty());
+}
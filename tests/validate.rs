@@ -0,0 +1,50 @@
+use acquiesce::{AcquiesceRepr, configs::PRESETS, validate::ConfigDiagnostic};
+
+#[test]
+fn presets_are_valid() {
+    for (name, preset) in PRESETS {
+        let diagnostics = preset().validate();
+        assert!(diagnostics.is_empty(), "{name}: {diagnostics:?}");
+    }
+}
+
+#[test]
+fn diagnostics_are_collected() {
+    let repr = r#"{
+        "version": "v1",
+        "config": {
+            "type": "components",
+            "chat_template": null,
+            "thinking": { "prefix": "<tool>", "suffix": "" },
+            "tool_calls": {
+                "type": "tool_calls_section",
+                "prefix": "<tool>",
+                "tool_call": {
+                    "type": "named_parameters",
+                    "prefix": { "pattern": "(" },
+                    "delimiter": { "type": "object", "required": 1 },
+                    "arguments": "json_object",
+                    "suffix": null
+                },
+                "suffix": null
+            }
+        }
+    }"#
+    .parse::<AcquiesceRepr>()
+    .unwrap();
+
+    let diagnostics = repr.validate();
+
+    assert!(
+        matches!(
+            diagnostics.as_slice(),
+            [
+                ConfigDiagnostic::Regex("tool_calls.tool_call.prefix", _),
+                ConfigDiagnostic::JsonSchema("tool_calls.tool_call.delimiter", _),
+                ConfigDiagnostic::ThinkingCollision("thinking.prefix", "tool_calls.prefix", _),
+                ConfigDiagnostic::EmptyLexemes("thinking.suffix"),
+            ]
+        ),
+        "{diagnostics:?}"
+    );
+}